@@ -0,0 +1,42 @@
+//! Compares the throughput of deserializing a month of positions one at a time and on rayon's thread pool
+//! (`cargo bench --features parallel --bench positions`).
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+/// Returns a JSON array of `n` positions, one every 5 seconds, similar to a busy month of an aircraft
+fn positions(n: usize) -> Vec<u8> {
+    let start = time::macros::datetime!(2023 - 01 - 01 00:00 UTC);
+    let positions = (0..n)
+        .map(|i| {
+            let datetime = start + time::Duration::seconds(5 * i as i64);
+            let datetime = datetime
+                .format(&time::format_description::well_known::Rfc3339)
+                .unwrap();
+            let latitude = 55.0 + (i % 1000) as f64 * 0.001;
+            let longitude = 12.0 + (i % 1000) as f64 * 0.001;
+            match i % 10 {
+                0 => format!(r#"{{"datetime":"{datetime}","latitude":{latitude},"longitude":{longitude}}}"#),
+                _ => format!(
+                    r#"{{"datetime":"{datetime}","latitude":{latitude},"longitude":{longitude},"altitude":{}}}"#,
+                    (i % 400) * 100
+                ),
+            }
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", positions.join(",")).into_bytes()
+}
+
+fn decode(c: &mut Criterion) {
+    let data = positions(200_000);
+    let mut group = c.benchmark_group("decode_positions");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("sequential", |b| {
+        b.iter(|| flights::icao_to_trace::decode_positions(&data, false).count())
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| flights::icao_to_trace::decode_positions(&data, true).count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
@@ -0,0 +1,177 @@
+//! Contains the implementation to compute anonymized download statistics of the public datasets from the
+//! [server access logs](https://docs.aws.amazon.com/AmazonS3/latest/userguide/LogFormat.html) of the object store.
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use time::{macros::format_description, OffsetDateTime};
+
+use crate::fs::BlobStorageProvider;
+
+static DATABASE: &str = "access/v1/data.csv";
+
+/// A request of an access log, restricted to the fields used to compute [`Downloads`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub datetime: OffsetDateTime,
+    /// The IP address of the client (never written to a dataset)
+    pub remote_ip: String,
+    /// The operation (e.g. `REST.GET.OBJECT`)
+    pub operation: String,
+    /// The key of the object (e.g. `leg/v2/all/year=2023/data.csv`)
+    pub key: String,
+    /// The HTTP status
+    pub status: u16,
+    /// The number of bytes sent to the client
+    pub bytes_sent: u64,
+}
+
+/// Splits a line of an access log into its fields, where `[...]` and `"..."` are a single field
+fn fields(line: &str) -> Vec<&str> {
+    let mut fields = vec![];
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        let (field, remaining) = match rest.as_bytes()[0] {
+            b'[' => rest[1..].split_once(']').unwrap_or((&rest[1..], "")),
+            b'"' => rest[1..].split_once('"').unwrap_or((&rest[1..], "")),
+            _ => rest.split_once(' ').unwrap_or((rest, "")),
+        };
+        fields.push(field);
+        rest = remaining.trim_start();
+    }
+    fields
+}
+
+/// Returns the [`Request`] of a line of an access log, or `None` when the line is not a valid request
+pub fn parse_line(line: &str) -> Option<Request> {
+    let fields = fields(line);
+    let [_owner, _bucket, datetime, remote_ip, _requester, _id, operation, key, _uri, status, _error, bytes_sent, ..] =
+        fields[..]
+    else {
+        return None;
+    };
+    let format = format_description!(
+        "[day]/[month repr:short]/[year]:[hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+    );
+    Some(Request {
+        datetime: OffsetDateTime::parse(datetime, format).ok()?,
+        remote_ip: remote_ip.to_string(),
+        operation: operation.to_string(),
+        key: key.to_string(),
+        status: status.parse().ok()?,
+        // `-` when no bytes were sent
+        bytes_sent: bytes_sent.parse().unwrap_or(0),
+    })
+}
+
+/// The downloads of a file of a dataset on a month
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Downloads {
+    /// The month (e.g. `2023-01`)
+    pub month: String,
+    /// The key of the file (e.g. `leg/v2/all/year=2023/data.csv`)
+    pub key: String,
+    /// The number of successful downloads (including partial downloads)
+    pub downloads: usize,
+    /// The number of distinct clients (IP addresses) that downloaded it
+    pub clients: usize,
+    /// The number of bytes sent
+    pub bytes_sent: u64,
+}
+
+/// Returns the [`Downloads`] of each month and key from `requests`, ordered by month and key.
+/// Only successful `GET`s of objects are downloads. IP addresses are only counted, so that the result is anonymized.
+pub fn downloads(requests: impl Iterator<Item = Request>) -> Vec<Downloads> {
+    let mut by_key = HashMap::<(String, String), (usize, HashSet<String>, u64)>::new();
+    for request in requests
+        .filter(|request| request.operation == "REST.GET.OBJECT")
+        .filter(|request| request.status == 200 || request.status == 206)
+    {
+        let month = crate::serde::month_to_part(request.datetime.date());
+        let (downloads, clients, bytes_sent) = by_key.entry((month, request.key)).or_default();
+        *downloads += 1;
+        clients.insert(request.remote_ip);
+        *bytes_sent += request.bytes_sent;
+    }
+    let mut downloads = by_key
+        .into_iter()
+        .map(
+            |((month, key), (downloads, clients, bytes_sent))| Downloads {
+                month,
+                key,
+                downloads,
+                clients: clients.len(),
+                bytes_sent,
+            },
+        )
+        .collect::<Vec<_>>();
+    downloads.sort_unstable_by(|a, b| (&a.month, &a.key).cmp(&(&b.month, &b.key)));
+    downloads
+}
+
+/// Computes the [`Downloads`] from all access logs under `prefix` of `client` and writes them to
+/// `access/v1/data.csv`.
+/// # Error
+/// Errors if the logs cannot be listed or read, or the result cannot be written
+pub async fn etl_access_logs(
+    client: &dyn BlobStorageProvider,
+    prefix: &str,
+) -> Result<(), Box<dyn Error>> {
+    let logs = client.list(prefix).await?;
+    log::info!("access logs: {}", logs.len());
+
+    let logs = futures::stream::iter(logs.iter().map(|key| client.maybe_get(key)))
+        .buffered(100)
+        .try_collect::<Vec<_>>()
+        .await?;
+    let requests = logs
+        .iter()
+        .flatten()
+        .flat_map(|data| {
+            String::from_utf8_lossy(data)
+                .lines()
+                .map(parse_line)
+                .collect::<Vec<_>>()
+        })
+        .flatten();
+
+    let downloads = downloads(requests);
+    client
+        .put(DATABASE, crate::csv::serialize(downloads.iter()))
+        .await?;
+    log::info!("Written {DATABASE}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn work() {
+        let logs = r#"79a59df900b9 private-jets [06/Feb/2024:00:00:38 +0000] 192.0.2.3 - 3E57427F3EXAMPLE REST.GET.OBJECT leg/v2/all/year=2023/data.csv "GET /leg/v2/all/year=2023/data.csv HTTP/1.1" 200 - 1000 1000 70 10 "-" "curl/8.0" -
+79a59df900b9 private-jets [07/Feb/2024:10:00:00 +0000] 192.0.2.3 - 3E57427F3EXAMPLE REST.GET.OBJECT leg/v2/all/year=2023/data.csv "GET /leg/v2/all/year=2023/data.csv HTTP/1.1" 206 - 500 1000 70 10 "-" "curl/8.0" -
+79a59df900b9 private-jets [07/Feb/2024:11:00:00 +0000] 198.51.100.7 - 3E57427F3EXAMPLE REST.GET.OBJECT leg/v2/all/year=2023/data.csv "GET /leg/v2/all/year=2023/data.csv HTTP/1.1" 200 - 1000 1000 70 10 "-" "Mozilla/5.0 (X11; Linux x86_64)" -
+79a59df900b9 private-jets [07/Feb/2024:12:00:00 +0000] 198.51.100.7 - 3E57427F3EXAMPLE REST.GET.OBJECT leg/v2/all/year=2019/data.csv "GET /leg/v2/all/year=2019/data.csv HTTP/1.1" 404 NoSuchKey 300 - 70 10 "-" "curl/8.0" -
+79a59df900b9 private-jets [01/Mar/2024:00:00:00 +0000] 198.51.100.7 - 3E57427F3EXAMPLE REST.PUT.OBJECT leg/v2/status.json "PUT /leg/v2/status.json HTTP/1.1" 200 - - 100 70 10 "-" "aws-sdk-rust" -
+not a log line"#;
+        let requests = logs.lines().filter_map(parse_line).collect::<Vec<_>>();
+        assert_eq!(requests.len(), 5);
+        assert_eq!(requests[0].key, "leg/v2/all/year=2023/data.csv");
+        assert_eq!(requests[4].bytes_sent, 0);
+
+        assert_eq!(
+            downloads(requests.into_iter()),
+            vec![Downloads {
+                month: "2024-02".to_string(),
+                key: "leg/v2/all/year=2023/data.csv".to_string(),
+                downloads: 3,
+                clients: 2,
+                bytes_sent: 2500,
+            }]
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Contains the implementation of the daily activity calendar of aircrafts.
+//! Each day of an aircraft is classified as an [`Activity`], serialized as a single character,
+//! so that a year of an aircraft is a string with one character per day (e.g. `UUIIFIF...`).
+use std::{collections::HashSet, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime};
+
+use crate::trace_month::first_of_next_month;
+
+/// The activity of an aircraft on a given day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Activity {
+    /// At least one leg took place (even if partially) on the day
+    Flew,
+    /// The aircraft was observed by ADS-B, but no leg took place on the day
+    Idle,
+    /// No position of the aircraft was observed on the day
+    Unknown,
+}
+
+impl Activity {
+    pub fn as_char(&self) -> char {
+        match self {
+            Self::Flew => 'F',
+            Self::Idle => 'I',
+            Self::Unknown => 'U',
+        }
+    }
+
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'F' => Some(Self::Flew),
+            'I' => Some(Self::Idle),
+            'U' => Some(Self::Unknown),
+            _ => None,
+        }
+    }
+}
+
+/// The activity of an aircraft on each day of a month
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MonthActivity {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The month in ISO 8601 (e.g. `2023-01`)
+    pub month: String,
+    /// One [`Activity`] character per day of the month
+    pub days: String,
+}
+
+/// The activity of an aircraft on each day of a year
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct YearActivity {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The year
+    pub year: i32,
+    /// Number of days the aircraft flew
+    pub days_flew: usize,
+    /// Number of days the aircraft was observed but did not fly
+    pub days_idle: usize,
+    /// Number of days without observations of the aircraft
+    pub days_unknown: usize,
+    /// One [`Activity`] character per day of the year
+    pub days: String,
+}
+
+crate::schema!(YearActivity {
+    icao_number: "The ICAO number",
+    year: "The year",
+    days_flew: "The number of days the aircraft flew (`F`)",
+    days_idle: "The number of days the aircraft was observed but did not fly (`I`)",
+    days_unknown: "The number of days without observations of the aircraft (`U`), including months not processed",
+    days: "The classification of each day of the year (one character per day, starting on January 1st)",
+});
+
+/// Returns the [`MonthActivity`] of `icao_number` on the month starting at `month`, given the
+/// dates when the aircraft was observed and the `(start, end)` of its legs.
+pub fn month_activity(
+    icao_number: Arc<str>,
+    month: Date,
+    observed: impl Iterator<Item = Date>,
+    legs: impl Iterator<Item = (OffsetDateTime, OffsetDateTime)>,
+) -> MonthActivity {
+    let observed = observed.collect::<HashSet<_>>();
+    let flew = legs
+        .flat_map(|(start, end)| crate::DateIter {
+            from: start.date(),
+            to: end.date().next_day().expect("date to not be the last date"),
+            increment: time::Duration::days(1),
+        })
+        .collect::<HashSet<_>>();
+
+    let days = crate::DateIter {
+        from: month,
+        to: first_of_next_month(&month),
+        increment: time::Duration::days(1),
+    }
+    .map(|day| {
+        if flew.contains(&day) {
+            Activity::Flew
+        } else if observed.contains(&day) {
+            Activity::Idle
+        } else {
+            Activity::Unknown
+        }
+    })
+    .map(|activity| activity.as_char())
+    .collect();
+
+    MonthActivity {
+        icao_number,
+        month: crate::serde::month_to_part(month),
+        days,
+    }
+}
+
+/// Returns the [`YearActivity`] of `icao_number` on `year` from its [`MonthActivity`]s.
+/// Months without a [`MonthActivity`] are [`Activity::Unknown`].
+pub fn year_activity(
+    icao_number: Arc<str>,
+    year: i32,
+    months: impl Iterator<Item = MonthActivity>,
+) -> YearActivity {
+    let mut months = months
+        .filter(|m| crate::serde::parse_month(&m.month).year() == year)
+        .collect::<Vec<_>>();
+    months.sort_unstable_by(|a, b| a.month.cmp(&b.month));
+    let mut months = months.into_iter().peekable();
+
+    let mut days = String::new();
+    for month in (1..=12u8).map(|m| time::Month::try_from(m).unwrap()) {
+        let length = month.length(year) as usize;
+        match months.next_if(|m| crate::serde::parse_month(&m.month).month() == month) {
+            Some(activity) => days.push_str(&activity.days),
+            None => days.extend(std::iter::repeat_n(Activity::Unknown.as_char(), length)),
+        }
+    }
+
+    let count = |activity: Activity| days.chars().filter(|c| *c == activity.as_char()).count();
+    YearActivity {
+        icao_number,
+        year,
+        days_flew: count(Activity::Flew),
+        days_idle: count(Activity::Idle),
+        days_unknown: count(Activity::Unknown),
+        days,
+    }
+}
+
+/// An aircraft resuming flights after months without flights (often indicating a sale or a new operator)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reactivation {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The last day it flew before the inactivity (e.g. `2023-01-31`)
+    pub last_flight: String,
+    /// The first day it flew after the inactivity
+    pub resumed: String,
+    /// Number of whole calendar months without flights between `last_flight` and `resumed`
+    pub idle_months: i32,
+}
+
+fn month_index(date: Date) -> i32 {
+    date.year() * 12 + date.month() as i32
+}
+
+/// Returns the [`Reactivation`]s of `icao_number` with at least `min_idle_months` whole calendar months without flights,
+/// given its [`YearActivity`]s.
+pub fn reactivations(
+    icao_number: Arc<str>,
+    years: impl Iterator<Item = YearActivity>,
+    min_idle_months: i32,
+) -> Vec<Reactivation> {
+    let mut years = years.collect::<Vec<_>>();
+    years.sort_unstable_by_key(|year| year.year);
+
+    let flew = years
+        .iter()
+        .flat_map(|year| {
+            year.days.chars().enumerate().filter_map(|(day, c)| {
+                (Activity::from_char(c) == Some(Activity::Flew))
+                    .then(|| Date::from_ordinal_date(year.year, day as u16 + 1).ok())
+                    .flatten()
+            })
+        })
+        .collect::<Vec<_>>();
+
+    flew.windows(2)
+        .filter_map(|w| {
+            let idle_months = month_index(w[1]) - month_index(w[0]) - 1;
+            (idle_months >= min_idle_months).then(|| Reactivation {
+                icao_number: icao_number.clone(),
+                last_flight: w[0].to_string(),
+                resumed: w[1].to_string(),
+                idle_months,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::{date, datetime};
+
+    use super::*;
+
+    #[test]
+    fn month() {
+        let activity = month_activity(
+            "aa".into(),
+            date!(2023 - 02 - 01),
+            [date!(2023 - 02 - 02), date!(2023 - 02 - 03)].into_iter(),
+            [(
+                datetime!(2023 - 02 - 03 23:00 UTC),
+                datetime!(2023 - 02 - 04 01:00 UTC),
+            )]
+            .into_iter(),
+        );
+        assert_eq!(activity.month, "2023-02");
+        assert_eq!(activity.days, format!("UIFF{}", "U".repeat(24)));
+    }
+
+    #[test]
+    fn year() {
+        let month = MonthActivity {
+            icao_number: "aa".into(),
+            month: "2023-02".to_string(),
+            days: format!("UIFF{}", "U".repeat(24)),
+        };
+        let activity = year_activity("aa".into(), 2023, [month].into_iter());
+        assert_eq!(activity.days.len(), 365);
+        assert_eq!(&activity.days[31..35], "UIFF");
+        assert_eq!(activity.days_flew, 2);
+        assert_eq!(activity.days_idle, 1);
+        assert_eq!(activity.days_unknown, 362);
+        assert_eq!(Activity::from_char('F'), Some(Activity::Flew));
+    }
+
+    #[test]
+    fn reactivation() {
+        let month = |month: &str, days: &str| MonthActivity {
+            icao_number: "aa".into(),
+            month: month.to_string(),
+            days: days.to_string(),
+        };
+        let years = [
+            year_activity(
+                "aa".into(),
+                2022,
+                [month("2022-11", &format!("F{}", "I".repeat(29)))].into_iter(),
+            ),
+            year_activity(
+                "aa".into(),
+                2023,
+                [
+                    month("2023-06", &format!("IF{}", "I".repeat(28))),
+                    month("2023-07", &format!("F{}", "I".repeat(30))),
+                ]
+                .into_iter(),
+            ),
+        ];
+
+        let reactivations = reactivations("aa".into(), years.into_iter(), 6);
+
+        assert_eq!(
+            reactivations,
+            vec![Reactivation {
+                icao_number: "aa".into(),
+                last_flight: "2022-11-01".to_string(),
+                resumed: "2023-06-02".to_string(),
+                idle_months: 6,
+            }]
+        );
+    }
+}
@@ -1,7 +1,11 @@
 //! Contains the implementation to extract the database of all aircrafts available in ADS-B exchange
 //! The database contains "current" status.
 use std::error::Error;
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Mutex;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use async_recursion::async_recursion;
 use futures::{StreamExt, TryStreamExt};
@@ -15,6 +19,7 @@ use crate::csv;
 use crate::fs::BlobStorageProvider;
 
 static DATABASE: &'static str = "aircraft/db/";
+static INDEX: &str = "aircraft/index/";
 
 /// [`HashMap`] between tail number (e.g. "OY-TWM") and an [`Aircraft`]
 pub type Aircrafts = HashMap<Arc<str>, Aircraft>;
@@ -32,6 +37,38 @@ pub struct Aircraft {
     pub model: String,
     /// The country in ISO 3166 of the aircraft
     pub country: Option<Arc<str>>,
+    /// The year the aircraft was manufactured (not available in older snapshots)
+    #[serde(default)]
+    pub manufacture_year: Option<u16>,
+}
+
+impl Aircraft {
+    /// The country of the aircraft derived from the allocation block of its ICAO number
+    /// (see `M-country-of-registration`), independent of registry data.
+    pub fn allocation_country(&self) -> Option<Arc<str>> {
+        crate::country::allocation_country(&self.icao_number)
+    }
+
+    /// The registry country of the aircraft, falling back to [`Aircraft::allocation_country`] when missing.
+    pub fn country_or_allocation(&self) -> Option<Arc<str>> {
+        self.country.clone().or_else(|| self.allocation_country())
+    }
+
+    /// The country claimed by the registration of the aircraft: that of the nationality mark of its tail number
+    /// (e.g. `OY-` for Denmark), see [`crate::country::tail_number_country`].
+    pub fn tail_number_country(&self) -> Option<Arc<str>> {
+        crate::country::tail_number_country(&self.tail_number)
+    }
+
+    /// Whether the [`Aircraft::tail_number_country`] of the aircraft differs from its
+    /// [`Aircraft::allocation_country`] (e.g. re-registered in another country while keeping its ICAO number).
+    /// Aircraft whose countries are unknown in either are not in conflict.
+    pub fn country_conflict(&self) -> bool {
+        match (self.tail_number_country(), self.allocation_country()) {
+            (Some(registration), Some(allocation)) => registration != allocation,
+            _ => false,
+        }
+    }
 }
 
 fn pk_to_blob_name(date: &time::Date) -> String {
@@ -48,6 +85,18 @@ fn blob_name_to_pk(blob: &str) -> time::Date {
     .unwrap()
 }
 
+/// Returns the prefix of the shard of the index containing `icao_number`
+fn shard(icao_number: &str) -> String {
+    icao_number
+        .get(..2)
+        .unwrap_or(icao_number)
+        .to_ascii_lowercase()
+}
+
+fn shard_to_blob_name(date: &time::Date, prefix: &str) -> String {
+    format!("{INDEX}date={date}/prefix={prefix}/data.csv")
+}
+
 fn url(prefix: &str) -> String {
     format!("https://globe.adsbexchange.com/db-current/{prefix}.js")
 }
@@ -129,6 +178,11 @@ async fn extract_aircrafts_prefix(
                     let tail_number = std::mem::take(&mut data[0])?;
                     let type_designator = std::mem::take(&mut data[1])?;
                     let model = std::mem::take(&mut data[3])?;
+                    // entries are `[registration, type, flags, description, owner, year]`
+                    let manufacture_year = data
+                        .get(5)
+                        .and_then(|x| x.as_deref())
+                        .and_then(|x| x.parse().ok());
                     let country = country_ranges
                         .country(&icao_number)
                         .expect("Data from adsb-b to be a valid hex");
@@ -139,6 +193,7 @@ async fn extract_aircrafts_prefix(
                         type_designator,
                         model,
                         country: country.cloned(),
+                        manufacture_year,
                     })
                 });
             acc.extend(items);
@@ -160,7 +215,141 @@ async fn load(
 pub async fn etl_aircrafts(client: &dyn BlobStorageProvider) -> Result<(), Box<dyn Error>> {
     let now = time::OffsetDateTime::now_utc().date();
     let aircraft = extract_aircrafts().await?;
-    load(aircraft, &now, client).await
+    load(aircraft, &now, client).await?;
+    let aircrafts = read(now, client).await?;
+    write_index(&now, &aircrafts, client).await?;
+    Ok(())
+}
+
+/// Returns the shards of the index of `aircrafts`: one per prefix of two hex characters (including empty ones),
+/// so that a missing shard means that the index of the snapshot was not built
+fn index(aircrafts: &Aircrafts) -> HashMap<String, Aircrafts> {
+    let hex = (b'0'..=b'9').chain(b'a'..=b'f');
+    let mut shards = hex
+        .clone()
+        .flat_map(|a| {
+            hex.clone()
+                .map(move |b| String::from_utf8(vec![a, b]).unwrap())
+        })
+        .map(|prefix| (prefix, Aircrafts::default()))
+        .collect::<HashMap<_, _>>();
+    for (icao_number, aircraft) in aircrafts {
+        shards
+            .entry(shard(icao_number))
+            .or_default()
+            .insert(icao_number.clone(), aircraft.clone());
+    }
+    shards
+}
+
+/// Writes the index of the snapshot of `aircrafts` of `date`, used by [`LazyAircrafts`]
+pub async fn write_index(
+    date: &Date,
+    aircrafts: &Aircrafts,
+    client: &dyn BlobStorageProvider,
+) -> Result<(), std::io::Error> {
+    let tasks = index(aircrafts)
+        .into_iter()
+        .map(|(prefix, aircrafts)| async move {
+            let contents = csv::serialize(aircrafts.values());
+            client
+                .put(&shard_to_blob_name(date, &prefix), contents)
+                .await
+        });
+    futures::stream::iter(tasks)
+        .buffer_unordered(50)
+        .try_collect::<Vec<_>>()
+        .await?;
+    log::info!("{INDEX}date={date}/ - written");
+    Ok(())
+}
+
+/// The snapshots of aircrafts, loaded on demand: only the shards of the index containing the looked up
+/// ICAO numbers are read, instead of every snapshot at once as in [`read_all`].
+/// Snapshots without index are read at once and their index is written when `client` can be written to.
+pub struct LazyAircrafts<'a> {
+    client: &'a dyn BlobStorageProvider,
+    dates: Vec<Date>,
+    shards: Mutex<HashMap<(Date, String), Arc<Aircrafts>>>,
+}
+
+impl<'a> LazyAircrafts<'a> {
+    /// Returns a new [`LazyAircrafts`] of the snapshots in `client`
+    pub async fn new(client: &'a dyn BlobStorageProvider) -> Result<Self, std::io::Error> {
+        let mut dates = client
+            .list(DATABASE)
+            .await?
+            .into_iter()
+            .map(|key| blob_name_to_pk(&key))
+            .collect::<Vec<_>>();
+        dates.sort_unstable();
+        Ok(Self {
+            client,
+            dates,
+            shards: Default::default(),
+        })
+    }
+
+    /// The dates of the snapshots
+    pub fn dates(&self) -> &[Date] {
+        &self.dates
+    }
+
+    async fn shard(&self, date: Date, prefix: String) -> Result<Arc<Aircrafts>, std::io::Error> {
+        if let Some(shard) = self.shards.lock().unwrap().get(&(date, prefix.clone())) {
+            return Ok(shard.clone());
+        }
+        let blob_name = shard_to_blob_name(&date, &prefix);
+        if let Some(data) = self.client.maybe_get(&blob_name).await? {
+            let shard = csv::deserialize::<Aircraft>(&data)
+                .map(|x| x.map(|x| (x.icao_number.clone(), x)))
+                .collect::<Result<Aircrafts, _>>()?;
+            let shard = Arc::new(shard);
+            self.shards
+                .lock()
+                .unwrap()
+                .insert((date, prefix), shard.clone());
+            return Ok(shard);
+        }
+
+        log::warn!("{blob_name} does not exist; reading the snapshot of {date}");
+        let aircrafts = read(date, self.client).await?;
+        if self.client.can_put() {
+            write_index(&date, &aircrafts, self.client).await?;
+        }
+        let mut shards = self.shards.lock().unwrap();
+        for (shard_prefix, aircrafts) in index(&aircrafts) {
+            shards.insert((date, shard_prefix), Arc::new(aircrafts));
+        }
+        Ok(shards.get(&(date, prefix)).cloned().unwrap_or_default())
+    }
+
+    /// Returns the [`Aircraft`] of `icao_number` in the snapshot of `date`, if any
+    pub async fn get(
+        &self,
+        date: Date,
+        icao_number: &str,
+    ) -> Result<Option<Aircraft>, std::io::Error> {
+        let shard = self.shard(date, shard(icao_number)).await?;
+        Ok(shard.get(icao_number).cloned())
+    }
+
+    /// Returns the [`Aircrafts`] of each snapshot, restricted to `icao_numbers`, in the same format as [`read_all`]
+    pub async fn read(
+        &self,
+        icao_numbers: &HashSet<Arc<str>>,
+    ) -> Result<HashMap<Date, Aircrafts>, std::io::Error> {
+        let mut result = HashMap::<Date, Aircrafts>::new();
+        for date in &self.dates {
+            let entry = result.entry(*date).or_default();
+            for icao_number in icao_numbers {
+                if let Some(aircraft) = self.get(*date, icao_number).await? {
+                    entry.insert(icao_number.clone(), aircraft);
+                }
+            }
+        }
+        Ok(result)
+    }
 }
 
 pub async fn read(
@@ -217,6 +406,28 @@ mod test {
         //assert!(extract_aircrafts().await.unwrap().len() > 400000);
     }
 
+    #[test]
+    fn allocation_country() {
+        let mut aircraft = Aircraft {
+            icao_number: "458d6b".into(),
+            tail_number: "OY-GFS".into(),
+            type_designator: "F2TH".into(),
+            model: "Something".into(),
+            country: None,
+            manufacture_year: None,
+        };
+        assert_eq!(aircraft.country_or_allocation(), Some("Denmark".into()));
+        assert!(!aircraft.country_conflict());
+
+        aircraft.country = Some("Sweden".into());
+        assert_eq!(aircraft.country_or_allocation(), Some("Sweden".into()));
+        // registered in Denmark
+        assert!(!aircraft.country_conflict());
+
+        aircraft.tail_number = "SE-RMT".into();
+        assert!(aircraft.country_conflict());
+    }
+
     #[tokio::test]
     async fn load_works() {
         let original = Aircraft {
@@ -225,6 +436,7 @@ mod test {
             type_designator: "F2TH".into(),
             model: "Something".into(),
             country: Some("UK".into()),
+            manufacture_year: Some(2007),
         };
         let date = date!(2023 - 01 - 01);
         load(vec![original.clone()], &date, &crate::fs::LocalDisk)
@@ -239,4 +451,45 @@ mod test {
         )]);
         assert_eq!(data, expected);
     }
+
+    #[tokio::test]
+    async fn lazy() {
+        let aircraft = |icao_number: &str| Aircraft {
+            icao_number: icao_number.into(),
+            tail_number: "OY-GFS".into(),
+            type_designator: "F2TH".into(),
+            model: "Something".into(),
+            country: None,
+            manufacture_year: None,
+        };
+        let root = std::env::temp_dir().join("test_aircraft_lazy");
+        let _ = std::fs::remove_dir_all(&root);
+        let client = crate::fs_local::LocalDisk::new(&root);
+        let date = date!(2023 - 01 - 01);
+        load(vec![aircraft("459cd3"), aircraft("45ab01")], &date, &client)
+            .await
+            .unwrap();
+
+        // without index, the snapshot is read and its index written
+        let aircrafts = LazyAircrafts::new(&client).await.unwrap();
+        assert_eq!(aircrafts.dates(), &[date]);
+        assert_eq!(
+            aircrafts.get(date, "459cd3").await.unwrap(),
+            Some(aircraft("459cd3"))
+        );
+        assert!(root
+            .join("aircraft/index/date=2023-01-01/prefix=45/data.csv")
+            .exists());
+        assert!(root
+            .join("aircraft/index/date=2023-01-01/prefix=00/data.csv")
+            .exists());
+
+        // with index
+        let aircrafts = LazyAircrafts::new(&client).await.unwrap();
+        assert_eq!(aircrafts.get(date, "000000").await.unwrap(), None);
+        let icao_numbers = HashSet::from(["45ab01".into(), "000000".into()]);
+        let read = aircrafts.read(&icao_numbers).await.unwrap();
+        assert_eq!(read[&date].len(), 1);
+        assert_eq!(read[&date]["45ab01"], aircraft("45ab01"));
+    }
 }
@@ -0,0 +1,411 @@
+//! Contains the implementation to detect the same physical aircraft (airframe) registered under more than one ICAO number.
+//!
+//! An airframe may appear under two ICAO numbers when it is re-registered (e.g. moved to another country) or when
+//! sources are merged, which would split (or double-count) its legs. This module resolves ICAO numbers into airframes,
+//! represented by a canonical ICAO number.
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    aircraft::{Aircraft, Aircrafts},
+    fs::BlobStorageProvider,
+};
+
+static ROOT: &str = "airframe/v1/";
+static DATABASE: &str = "airframe/v1/data.csv";
+
+/// [`HashMap`] between an ICAO number and the canonical ICAO number of its airframe.
+/// ICAO numbers that are their own airframe are not present.
+pub type MergeMap = HashMap<Arc<str>, Arc<str>>;
+
+/// An entry of the [`MergeMap`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Merge {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The canonical ICAO number of the airframe
+    pub airframe: Arc<str>,
+}
+
+crate::schema!(Merge {
+    icao_number: "The ICAO number",
+    airframe: "The canonical ICAO number of the airframe",
+});
+
+/// [`HashMap`] between an ICAO number and the first and last months with positions of the aircraft
+pub type Activity = HashMap<Arc<str>, (Date, Date)>;
+
+/// [`HashMap`] between an ICAO number and the serial number of its airframe (e.g. `525-0123`)
+pub type Serials = HashMap<Arc<str>, Arc<str>>;
+
+/// The maximum number of months between the last positions of an ICAO number and the first positions of another
+/// with the same tail number for them to be the same airframe when the year of manufacture of either is unknown
+pub static MAX_GAP_MONTHS: i32 = 3;
+
+/// A serial number of an airframe contributed by the community, in `src/airframes_serials.csv`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct SerialNumber {
+    icao_number: Arc<str>,
+    serial_number: Arc<str>,
+}
+
+/// Returns the [`Serials`] in `src/airframes_serials.csv`
+pub fn load_serials() -> Result<Serials, std::io::Error> {
+    parse_serials(&std::fs::read("src/airframes_serials.csv")?)
+}
+
+/// Returns the [`Serials`] in `data`, a CSV in the format of `src/airframes_serials.csv`
+pub fn parse_serials(data: &[u8]) -> Result<Serials, std::io::Error> {
+    crate::csv::deserialize::<SerialNumber>(data)
+        .map(|x| {
+            x.map(|x| {
+                let icao_number = x.icao_number.to_ascii_lowercase().into();
+                let serial_number = x.serial_number.trim().to_ascii_uppercase().into();
+                (icao_number, serial_number)
+            })
+        })
+        .collect()
+}
+
+/// Returns the [`Activity`] from the set of `(icao_number, month)` with positions
+pub fn activity(months: impl Iterator<Item = (Arc<str>, Date)>) -> Activity {
+    months.fold(Activity::new(), |mut acc, (icao_number, month)| {
+        acc.entry(icao_number)
+            .and_modify(|(first, last)| {
+                *first = (*first).min(month);
+                *last = (*last).max(month);
+            })
+            .or_insert((month, month));
+        acc
+    })
+}
+
+/// Returns the number of months from the end of the earlier of `a` and `b` to the start of the later, or `None`
+/// when they overlap by more than one month (an airframe transmits a single ICAO number at a time, except on
+/// the month it is re-registered)
+fn gap_months(a: (Date, Date), b: (Date, Date)) -> Option<i32> {
+    let (earlier, later) = if a.0 <= b.0 { (a, b) } else { (b, a) };
+    let months = |date: Date| date.year() * 12 + date.month() as i32;
+    let gap = months(later.0) - months(earlier.1);
+    (gap >= 0).then_some(gap)
+}
+
+/// Whether `a` and `b`, two ICAO numbers with the same tail number, are the same airframe
+fn same_airframe(a: &Aircraft, b: &Aircraft, serials: &Serials, activity: &Activity) -> bool {
+    if a.type_designator != b.type_designator {
+        return false;
+    }
+    let gap = match (activity.get(&a.icao_number), activity.get(&b.icao_number)) {
+        (Some(a), Some(b)) => match gap_months(*a, *b) {
+            Some(gap) => Some(gap),
+            // both flew at the same time
+            None => return false,
+        },
+        _ => None,
+    };
+    if let (Some(a), Some(b)) = (serials.get(&a.icao_number), serials.get(&b.icao_number)) {
+        return a == b;
+    }
+    match (a.manufacture_year, b.manufacture_year) {
+        (Some(a), Some(b)) => a == b,
+        // a tail number re-used by another airframe is only told apart by the time between their activity
+        _ => gap.is_some_and(|gap| gap <= MAX_GAP_MONTHS),
+    }
+}
+
+/// Pushes `aircraft` to `entries`, unless an aircraft with its ICAO number is already in it
+fn push<'a>(entries: &mut Vec<&'a Aircraft>, aircraft: &'a Aircraft) {
+    if !entries
+        .iter()
+        .any(|a| a.icao_number == aircraft.icao_number)
+    {
+        entries.push(aircraft)
+    }
+}
+
+/// Returns the [`MergeMap`] from the snapshots of aircrafts, the serial numbers of airframes and the activity of
+/// ICAO numbers.
+/// # Implementation
+/// Two ICAO numbers are the same airframe (see `M-airframes`) when they did not fly at the same time and either
+/// * they have the same type designator and serial number, or
+/// * in any snapshot, they have the same tail number and type designator, no different serial numbers, and
+///   * the same year of manufacture when known for both, or
+///   * at most [`MAX_GAP_MONTHS`] months between their activity otherwise.
+///
+/// ICAO numbers with the same tail number are compared to the next more recent one with it, so that an airframe
+/// re-registered more than once is resolved into a single airframe.
+/// The canonical ICAO number is the one in the most recent snapshot.
+pub fn merge_map(
+    aircrafts: &HashMap<Date, Aircrafts>,
+    serials: &Serials,
+    activity: &Activity,
+) -> MergeMap {
+    let mut snapshots = aircrafts.iter().collect::<Vec<_>>();
+    // most recent first
+    snapshots.sort_unstable_by(|a, b| b.0.cmp(a.0));
+
+    // tail number -> aircrafts with it, most recent first
+    let mut by_tail_number = HashMap::<String, Vec<&Aircraft>>::new();
+    // (type designator, serial number) -> aircrafts with it, most recent first
+    let mut by_serial_number = HashMap::<(&str, &str), Vec<&Aircraft>>::new();
+    for (_, aircrafts) in snapshots {
+        let mut aircrafts = aircrafts.values().collect::<Vec<_>>();
+        aircrafts.sort_unstable_by(|a, b| a.icao_number.cmp(&b.icao_number));
+        for aircraft in aircrafts {
+            if let Some(serial) = serials.get(&aircraft.icao_number) {
+                let key = (aircraft.type_designator.as_str(), serial.as_ref());
+                push(by_serial_number.entry(key).or_default(), aircraft);
+            }
+            let tail_number = aircraft.tail_number.trim().to_ascii_uppercase();
+            if !tail_number.is_empty() {
+                push(by_tail_number.entry(tail_number).or_default(), aircraft);
+            }
+        }
+    }
+    let mut by_serial_number = by_serial_number.into_values().collect::<Vec<_>>();
+    by_serial_number.sort_unstable_by(|a, b| a[0].icao_number.cmp(&b[0].icao_number));
+    let mut by_tail_number = by_tail_number.into_iter().collect::<Vec<_>>();
+    by_tail_number.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let overlap = |a: &Aircraft, b: &Aircraft| match (
+        activity.get(&a.icao_number),
+        activity.get(&b.icao_number),
+    ) {
+        (Some(a), Some(b)) => gap_months(*a, *b).is_none(),
+        _ => false,
+    };
+
+    let mut merges = MergeMap::new();
+    // serial numbers first, since they also match airframes re-registered with another tail number
+    for aircrafts in by_serial_number {
+        let Some((canonical, others)) = aircrafts.split_first() else {
+            continue;
+        };
+        for other in others {
+            if !overlap(canonical, other) {
+                merges
+                    .entry(other.icao_number.clone())
+                    .or_insert_with(|| canonical.icao_number.clone());
+            }
+        }
+    }
+    // each ICAO number is compared to the next more recent one with the tail number, so that an airframe
+    // re-registered more than once is resolved through the chain below
+    for (_, aircrafts) in by_tail_number {
+        for pair in aircrafts.windows(2) {
+            let (newer, older) = (pair[0], pair[1]);
+            if same_airframe(newer, older, serials, activity) {
+                merges
+                    .entry(older.icao_number.clone())
+                    .or_insert_with(|| newer.icao_number.clone());
+            }
+        }
+    }
+
+    // resolve chains (a -> b -> c) into (a -> c)
+    merges
+        .keys()
+        .map(|icao_number| {
+            let mut airframe = icao_number;
+            let mut hops = 0;
+            while let Some(next) = merges.get(airframe) {
+                airframe = next;
+                hops += 1;
+                if hops > merges.len() {
+                    // cycle: keep the direct mapping
+                    airframe = &merges[icao_number];
+                    break;
+                }
+            }
+            (icao_number.clone(), airframe.clone())
+        })
+        .filter(|(icao_number, airframe)| icao_number != airframe)
+        .collect()
+}
+
+/// Returns the canonical ICAO number of the airframe of `icao_number`
+pub fn airframe<'a>(merges: &'a MergeMap, icao_number: &'a Arc<str>) -> &'a Arc<str> {
+    merges.get(icao_number).unwrap_or(icao_number)
+}
+
+/// Computes the [`MergeMap`] from the database of aircrafts, the serial numbers in `src/airframes_serials.csv` and
+/// the months with positions of each ICAO number, and writes it to `client`.
+pub async fn etl_airframes(client: &dyn BlobStorageProvider) -> Result<(), std::io::Error> {
+    let aircrafts = crate::aircraft::read_all(client).await?;
+    let serials = load_serials()?;
+    let months = crate::icao_to_trace::list_months_positions(client).await?;
+    let activity = activity(months.into_iter());
+    let mut merges = merge_map(&aircrafts, &serials, &activity)
+        .into_iter()
+        .map(|(icao_number, airframe)| Merge {
+            icao_number,
+            airframe,
+        })
+        .collect::<Vec<_>>();
+    merges.sort_unstable_by(|a, b| a.icao_number.cmp(&b.icao_number));
+    log::info!(
+        "ICAO numbers merged into another airframe: {}",
+        merges.len()
+    );
+
+    client
+        .put(DATABASE, crate::csv::serialize(merges.into_iter()))
+        .await?;
+    log::info!("Written {DATABASE}");
+    crate::schema::write::<Merge>(ROOT, crate::units::Units::Metric, client).await?;
+    Ok(())
+}
+
+/// Returns the [`MergeMap`] written by [`etl_airframes`], or an empty one if it was never written.
+pub async fn read(client: &dyn BlobStorageProvider) -> Result<MergeMap, std::io::Error> {
+    let Some(data) = client.maybe_get(DATABASE).await? else {
+        return Ok(Default::default());
+    };
+    crate::csv::deserialize::<Merge>(&data)
+        .map(|merge| merge.map(|merge| (merge.icao_number, merge.airframe)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+
+    fn aircraft(icao_number: &str, tail_number: &str, manufacture_year: Option<u16>) -> Aircraft {
+        Aircraft {
+            icao_number: icao_number.into(),
+            tail_number: tail_number.to_string(),
+            type_designator: "F2TH".to_string(),
+            model: "FALCON 2000".to_string(),
+            country: None,
+            manufacture_year,
+        }
+    }
+
+    fn snapshot(aircrafts: Vec<Aircraft>) -> Aircrafts {
+        aircrafts
+            .into_iter()
+            .map(|a| (a.icao_number.clone(), a))
+            .collect()
+    }
+
+    #[test]
+    fn work() {
+        let aircrafts = HashMap::from([
+            (
+                date!(2023 - 01 - 01),
+                snapshot(vec![
+                    aircraft("aa", "OY-ABC", Some(2007)),
+                    aircraft("cc", "OY-XYZ", Some(2010)),
+                ]),
+            ),
+            (
+                date!(2024 - 01 - 01),
+                snapshot(vec![
+                    // re-registered under a new ICAO number
+                    aircraft("bb", "oy-abc", None),
+                    // a different airframe re-using the tail number
+                    aircraft("dd", "OY-XYZ", Some(2015)),
+                ]),
+            ),
+        ]);
+
+        let activity = Activity::from([
+            ("aa".into(), (date!(2019 - 01 - 01), date!(2023 - 06 - 01))),
+            ("bb".into(), (date!(2023 - 07 - 01), date!(2024 - 01 - 01))),
+        ]);
+
+        let merges = merge_map(&aircrafts, &Serials::new(), &activity);
+
+        assert_eq!(merges, HashMap::from([("aa".into(), "bb".into())]));
+        assert_eq!(airframe(&merges, &"aa".into()).as_ref(), "bb");
+        assert_eq!(airframe(&merges, &"cc".into()).as_ref(), "cc");
+    }
+
+    #[test]
+    fn many_icao_numbers() {
+        let aircrafts = HashMap::from([
+            (
+                date!(2022 - 01 - 01),
+                snapshot(vec![aircraft("aa", "OY-ABC", None)]),
+            ),
+            (
+                date!(2023 - 01 - 01),
+                snapshot(vec![
+                    aircraft("bb", "OY-ABC", None),
+                    aircraft("aa", "D-ABCD", None),
+                ]),
+            ),
+            (
+                date!(2024 - 01 - 01),
+                snapshot(vec![aircraft("cc", "OY-ABC", None)]),
+            ),
+        ]);
+
+        let activity = Activity::from([
+            ("aa".into(), (date!(2021 - 01 - 01), date!(2022 - 06 - 01))),
+            // flew under both ICAO numbers on the month it was re-registered
+            ("bb".into(), (date!(2022 - 06 - 01), date!(2023 - 06 - 01))),
+            ("cc".into(), (date!(2023 - 07 - 01), date!(2024 - 01 - 01))),
+        ]);
+
+        let merges = merge_map(&aircrafts, &Serials::new(), &activity);
+
+        assert_eq!(
+            merges,
+            HashMap::from([("aa".into(), "cc".into()), ("bb".into(), "cc".into())])
+        );
+    }
+
+    #[test]
+    fn serials_and_activity() {
+        let aircrafts = HashMap::from([
+            (
+                date!(2023 - 01 - 01),
+                snapshot(vec![
+                    aircraft("ee", "OY-ABC", None),
+                    aircraft("gg", "OY-GGG", None),
+                    aircraft("ii", "OY-III", Some(2010)),
+                    aircraft("kk", "OY-KKK", Some(2010)),
+                ]),
+            ),
+            (
+                date!(2024 - 01 - 01),
+                snapshot(vec![
+                    // re-registered with another ICAO number and tail number
+                    aircraft("ff", "D-ABCD", None),
+                    // a tail number re-used by another airframe two years later
+                    aircraft("hh", "OY-GGG", None),
+                    // another airframe flying at the same time
+                    aircraft("jj", "OY-III", Some(2010)),
+                    // another airframe by its serial number
+                    aircraft("ll", "OY-KKK", Some(2010)),
+                ]),
+            ),
+        ]);
+        let serials = parse_serials(
+            b"icao_number,serial_number,source
+EE,525-0123,https://example.com
+ff,525-0123,https://example.com
+kk,525-0200,https://example.com
+ll,525-0201,https://example.com
+",
+        )
+        .unwrap();
+        let activity = Activity::from([
+            ("ee".into(), (date!(2019 - 01 - 01), date!(2023 - 03 - 01))),
+            ("ff".into(), (date!(2023 - 03 - 01), date!(2024 - 01 - 01))),
+            ("gg".into(), (date!(2019 - 01 - 01), date!(2021 - 01 - 01))),
+            ("hh".into(), (date!(2023 - 01 - 01), date!(2024 - 01 - 01))),
+            ("ii".into(), (date!(2019 - 01 - 01), date!(2023 - 06 - 01))),
+            ("jj".into(), (date!(2023 - 01 - 01), date!(2024 - 01 - 01))),
+        ]);
+
+        let merges = merge_map(&aircrafts, &serials, &activity);
+
+        assert_eq!(merges, HashMap::from([("ee".into(), "ff".into())]));
+    }
+}
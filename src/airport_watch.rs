@@ -0,0 +1,239 @@
+//! Contains the arrivals and departures of legs at airports of interest (`M-airport-watch`), e.g. Roskilde (`EKRK`)
+//! and Kastrup (`EKCH`) for Danish reporting.
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{airports::Airports, etl::legs::LegOut, Error};
+
+/// The radius in km of a [`Watch`] when it is not set
+pub static DEFAULT_RADIUS: f64 = 5.0;
+
+/// An airport of interest and the radius around it where legs arrive at or depart from it.
+/// It is written as `{ident}:{radius in km}` (e.g. `EKRK:5`) or `{ident}` for a radius of [`DEFAULT_RADIUS`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watch {
+    /// The identifier of the airport in the database of airports (e.g. `EKRK`)
+    pub airport: String,
+    /// The radius in km
+    pub radius: f64,
+}
+
+impl std::str::FromStr for Watch {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (airport, radius) = match s.split_once(':') {
+            Some((airport, radius)) => {
+                let radius = radius
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|radius| *radius > 0.0)
+                    .ok_or_else(|| format!("radius of `{s}` must be a positive number of km"))?;
+                (airport, radius)
+            }
+            None => (s, DEFAULT_RADIUS),
+        };
+        if airport.is_empty() {
+            return Err(format!("`{s}` must be `{{ident}}:{{radius}}`"));
+        }
+        Ok(Self {
+            airport: airport.to_uppercase(),
+            radius,
+        })
+    }
+}
+
+/// An arrival or departure of a leg at an airport of interest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Movement {
+    /// The identifier of the airport (e.g. `EKRK`)
+    pub airport: Arc<str>,
+    /// `arrival` or `departure`
+    pub movement: Arc<str>,
+    /// The ICAO number of the aircraft
+    pub icao_number: Arc<str>,
+    /// The tail number of the aircraft
+    pub tail_number: Option<Arc<str>>,
+    /// The model of the aircraft
+    pub aircraft_model: Option<Arc<str>>,
+    /// The owner of the aircraft (see `M-owners`)
+    pub owner: Option<Arc<str>>,
+    /// The operator of the aircraft (see `M-owners`)
+    pub operator: Option<Arc<str>>,
+    /// When the leg ended (arrivals) or started (departures)
+    #[serde(with = "time::serde::rfc3339")]
+    pub datetime: time::OffsetDateTime,
+    /// The distance in km between the airport and the last (arrivals) or first (departures) position of the leg
+    pub distance: f64,
+}
+
+crate::schema!(Movement {
+    airport: "The identifier of the airport (e.g. `EKRK`)",
+    movement: "`arrival` or `departure`",
+    icao_number: "The ICAO number of the aircraft",
+    tail_number: "The tail number of the aircraft",
+    aircraft_model: "The model of the aircraft",
+    owner: "The owner of the aircraft",
+    operator: "The operator of the aircraft",
+    datetime: "When the leg ended (arrivals) or started (departures)",
+    distance("km"): "The distance between the airport and the last (arrivals) or first (departures) position of the leg",
+});
+
+impl Movement {
+    fn new(airport: &Arc<str>, movement: &str, leg: &LegOut, distance: f64) -> Self {
+        Self {
+            airport: airport.clone(),
+            movement: movement.into(),
+            icao_number: leg.icao_number.clone(),
+            tail_number: leg.tail_number.clone(),
+            aircraft_model: leg.aircraft_model.clone(),
+            owner: leg.owner.clone(),
+            operator: leg.operator.clone(),
+            datetime: if movement == "arrival" {
+                leg.end
+            } else {
+                leg.start
+            },
+            distance,
+        }
+    }
+}
+
+/// Returns the [`Movement`]s of `legs` at the airports of `watches`, ordered by datetime:
+/// * a leg departs from an airport when it starts on the ground within the radius of the airport
+/// * a leg arrives at an airport when it ends on the ground within the radius of the airport
+///
+/// # Error
+/// Errors if an airport of `watches` is not in `airports`
+pub fn movements<'a>(
+    watches: &[Watch],
+    airports: &Airports,
+    legs: impl Iterator<Item = &'a LegOut>,
+) -> Result<Vec<Movement>, Error> {
+    let watches = watches
+        .iter()
+        .map(|watch| {
+            let airport = airports
+                .airports()
+                .iter()
+                .find(|airport| airport.ident == watch.airport)
+                .ok_or_else(|| {
+                    Error::MissingData(format!("airport {} does not exist", watch.airport))
+                })?;
+            Ok((
+                Arc::<str>::from(watch.airport.as_str()),
+                airport.pos(),
+                watch.radius,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let mut movements = legs
+        .flat_map(|leg| {
+            watches.iter().flat_map(move |(airport, pos, radius)| {
+                let departure = crate::distance(*pos, (leg.start_lat, leg.start_lon));
+                let departure = (leg.start_on_ground == Some(true) && departure <= *radius)
+                    .then(|| Movement::new(airport, "departure", leg, departure));
+                let arrival = crate::distance(*pos, (leg.end_lat, leg.end_lon));
+                let arrival = (leg.end_on_ground == Some(true) && arrival <= *radius)
+                    .then(|| Movement::new(airport, "arrival", leg, arrival));
+                departure.into_iter().chain(arrival)
+            })
+        })
+        .collect::<Vec<_>>();
+    movements.sort_unstable_by(|a, b| {
+        (a.datetime, &a.airport, &a.icao_number).cmp(&(b.datetime, &b.airport, &b.icao_number))
+    });
+    Ok(movements)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::airports::Airport;
+
+    use super::*;
+
+    #[test]
+    fn work() {
+        assert_eq!(
+            "ekrk:10".parse::<Watch>(),
+            Ok(Watch {
+                airport: "EKRK".to_string(),
+                radius: 10.0
+            })
+        );
+        assert_eq!("EKCH".parse::<Watch>().unwrap().radius, DEFAULT_RADIUS);
+        assert!("EKCH:-1".parse::<Watch>().is_err());
+
+        let airport = |ident: &str, latitude_deg: f64, longitude_deg: f64| Airport {
+            ident: ident.to_string(),
+            airport_type: "medium_airport".to_string(),
+            name: ident.to_string(),
+            latitude_deg,
+            longitude_deg,
+            elevation_ft: None,
+            iso_country: "DK".to_string(),
+            municipality: None,
+            scheduled_service: "no".to_string(),
+            iata_code: None,
+        };
+        let airports = Airports::new(vec![
+            airport("EKRK", 55.5856, 12.1314),
+            airport("EKCH", 55.6179, 12.656),
+        ]);
+        let leg = |start: &str, from: (f64, f64), to: (f64, f64), end_on_ground: bool| {
+            serde_json::from_value::<LegOut>(serde_json::json!({
+                "icao_number": "459cd3",
+                "owner": "Owner A/S",
+                "start": start,
+                "start_lat": from.0,
+                "start_lon": from.1,
+                "start_altitude": 0.0,
+                "end": start,
+                "end_lat": to.0,
+                "end_lon": to.1,
+                "end_altitude": 0.0,
+                "duration": 1.5,
+                "distance": 1000.0,
+                "great_circle_distance": 1000.0,
+                "midpoint_lat": 52.3,
+                "midpoint_lon": 7.5,
+                "hours_above_30000": 1.0,
+                "hours_above_40000": 0.0,
+                "diverted": false,
+                "start_on_ground": true,
+                "end_on_ground": end_on_ground,
+                "taxi_out_minutes": 0.0,
+                "taxi_in_minutes": 0.0,
+                "commercial_alternative_exists": true,
+            }))
+            .unwrap()
+        };
+        let legs = [
+            // from Roskilde to Paris
+            leg("2024-06-03T10:00:00Z", (55.59, 12.13), (49.0, 2.5), true),
+            // from Paris to Kastrup
+            leg("2024-06-04T10:00:00Z", (49.0, 2.5), (55.62, 12.65), true),
+            // lost coverage near Kastrup
+            leg("2024-06-05T10:00:00Z", (49.0, 2.5), (55.62, 12.65), false),
+        ];
+        let watches = ["EKRK:5", "EKCH:5"].map(|x| x.parse::<Watch>().unwrap());
+
+        let result = movements(&watches, &airports, legs.iter()).unwrap();
+        assert_eq!(result.len(), 2);
+        assert_eq!(
+            (result[0].airport.as_ref(), result[0].movement.as_ref()),
+            ("EKRK", "departure")
+        );
+        assert_eq!(result[0].owner.as_deref(), Some("Owner A/S"));
+        assert_eq!(
+            (result[1].airport.as_ref(), result[1].movement.as_ref()),
+            ("EKCH", "arrival")
+        );
+        assert!(result[1].distance < 1.0);
+
+        let unknown = ["EKXX".parse::<Watch>().unwrap()];
+        assert!(movements(&unknown, &airports, legs.iter()).is_err());
+    }
+}
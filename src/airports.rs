@@ -0,0 +1,485 @@
+//! Contains the implementation to extract and query the database of airports from [OurAirports](https://ourairports.com/data/).
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    fs::{self, BlobStorageProvider},
+    legs::Leg,
+};
+
+static DATABASE: &str = "airport/ourairports/data.csv";
+
+fn url() -> &'static str {
+    "https://davidmegginson.github.io/ourairports-data/airports.csv"
+}
+
+/// An in-memory representation of an airport
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Airport {
+    /// The identifier of the airport, its ICAO code when it has one (e.g. `EKCH`)
+    pub ident: String,
+    /// The type of airport (e.g. `large_airport`, `heliport`, `closed`)
+    #[serde(rename = "type")]
+    pub airport_type: String,
+    /// The name of the airport (e.g. `Copenhagen Kastrup Airport`)
+    pub name: String,
+    /// The latitude in ISO 6709 decimal
+    pub latitude_deg: f64,
+    /// The longitude in ISO 6709 decimal
+    pub longitude_deg: f64,
+    /// The elevation in feet
+    pub elevation_ft: Option<f64>,
+    /// The country in ISO 3166-1 alpha-2 (e.g. `DK`)
+    pub iso_country: String,
+    /// The municipality
+    pub municipality: Option<String>,
+    /// Whether the airport has scheduled airline service (`yes` or `no`)
+    pub scheduled_service: String,
+    /// The IATA code
+    pub iata_code: Option<String>,
+}
+
+impl Airport {
+    pub fn pos(&self) -> (f64, f64) {
+        (self.latitude_deg, self.longitude_deg)
+    }
+}
+
+/// A correction of an [`Airport`] of OurAirports (e.g. a misplaced airfield or heliport) contributed by the
+/// community, in `src/airports_overrides.csv`
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct AirportOverride {
+    /// The identifier of the airport (e.g. `EKRK`); airports not in OurAirports are added
+    pub icao: String,
+    /// The name of the airport
+    pub name: String,
+    /// The latitude in ISO 6709 decimal
+    pub latitude: f64,
+    /// The longitude in ISO 6709 decimal
+    pub longitude: f64,
+    /// The elevation in feet
+    pub elevation_ft: Option<f64>,
+    /// The country in ISO 3166-1 alpha-2 (e.g. `DK`)
+    pub country: String,
+    /// the source of the correction
+    pub source: String,
+    /// the date of when the source was retrieved
+    pub date: String,
+}
+
+/// Returns all [`AirportOverride`]s in `src/airports_overrides.csv`
+/// # Error
+/// Errors if the file cannot be read or is not a valid CSV
+pub fn load_airport_overrides() -> Result<Vec<AirportOverride>, std::io::Error> {
+    parse_airport_overrides(&std::fs::read("src/airports_overrides.csv")?)
+}
+
+/// Returns all [`AirportOverride`]s in `data`, a CSV in the format of `src/airports_overrides.csv`
+/// # Error
+/// Errors if `data` is not a valid CSV
+pub fn parse_airport_overrides(data: &[u8]) -> Result<Vec<AirportOverride>, std::io::Error> {
+    crate::csv::deserialize(data).collect()
+}
+
+/// Replaces the name, position, elevation and country of the airports of `overrides` in `airports`, and adds those
+/// not in `airports` (as `small_airport`s without scheduled service)
+pub fn apply_overrides(airports: &mut Vec<Airport>, overrides: &[AirportOverride]) {
+    let index = airports
+        .iter()
+        .enumerate()
+        .map(|(i, airport)| (airport.ident.clone(), i))
+        .collect::<HashMap<_, _>>();
+    for o in overrides {
+        let airport = match index.get(&o.icao) {
+            Some(i) => &mut airports[*i],
+            None => {
+                airports.push(Airport {
+                    ident: o.icao.clone(),
+                    airport_type: "small_airport".to_string(),
+                    name: o.name.clone(),
+                    latitude_deg: o.latitude,
+                    longitude_deg: o.longitude,
+                    elevation_ft: o.elevation_ft,
+                    iso_country: o.country.clone(),
+                    municipality: None,
+                    scheduled_service: "no".to_string(),
+                    iata_code: None,
+                });
+                continue;
+            }
+        };
+        airport.name = o.name.clone();
+        airport.latitude_deg = o.latitude;
+        airport.longitude_deg = o.longitude;
+        airport.elevation_ft = o.elevation_ft;
+        airport.iso_country = o.country.clone();
+    }
+}
+
+/// A set of [`Airport`]s indexed by location
+#[derive(Debug, Clone, PartialEq)]
+pub struct Airports {
+    airports: Vec<Airport>,
+    /// map of (floor(latitude), floor(longitude)) to the airports in that cell
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+fn cell((latitude, longitude): (f64, f64)) -> (i32, i32) {
+    (latitude.floor() as i32, longitude.floor() as i32)
+}
+
+/// Maximum distance in km between a position and an airport for the airport's elevation
+/// to be used as the ground elevation of the position
+static ELEVATION_MAX_DISTANCE: f64 = 20.0;
+
+/// Maximum distance in km between the first (last) position of a leg and its departure (arrival) airport
+static AIRPORT_MAX_DISTANCE: f64 = 10.0;
+/// Maximum distance in km between a position and an airport for the position to be an approach to it
+static APPROACH_MAX_DISTANCE: f64 = 5.0;
+/// Maximum height in feet of a position for it to be an approach
+static APPROACH_HEIGHT: f64 = 1500.0;
+/// Minimum climb in feet after an approach for it to be a go-around
+static GO_AROUND_CLIMB: f64 = 1000.0;
+/// Maximum height in feet of the first (last) position of a leg for it to be on the ground
+static ON_GROUND_HEIGHT: f64 = 500.0;
+/// Maximum ground speed in km/h of the first (last) position of a leg for it to be on the ground (~80 knots,
+/// below the rotation speed of jets)
+static ON_GROUND_SPEED: f64 = 150.0;
+/// Maximum time between the first (last) two positions of a leg for their ground speed to be estimated
+static ON_GROUND_MAX_GAP: time::Duration = time::Duration::minutes(5);
+
+impl Airports {
+    pub fn new(airports: Vec<Airport>) -> Self {
+        let cells = airports.iter().enumerate().fold(
+            HashMap::<_, Vec<_>>::new(),
+            |mut acc, (i, airport)| {
+                acc.entry(cell(airport.pos())).or_default().push(i);
+                acc
+            },
+        );
+        Self { airports, cells }
+    }
+
+    /// All airports
+    pub fn airports(&self) -> &[Airport] {
+        &self.airports
+    }
+
+    /// Returns the closest (non-closed) [`Airport`] to `(latitude, longitude)` within `max_distance` km,
+    /// and its distance in km.
+    pub fn closest(&self, pos: (f64, f64), max_distance: f64) -> Option<(&Airport, f64)> {
+        self.closest_with(pos, max_distance, |_| true)
+    }
+
+    /// Returns the closest (non-closed) [`Airport`] matching `filter` to `(latitude, longitude)` within
+    /// `max_distance` km, and its distance in km.
+    pub fn closest_with(
+        &self,
+        pos: (f64, f64),
+        max_distance: f64,
+        filter: impl Fn(&Airport) -> bool,
+    ) -> Option<(&Airport, f64)> {
+        let (lat, lon) = cell(pos);
+        let lat_cells = (max_distance / 111.0).ceil() as i32;
+        // the width of a cell decreases with the latitude
+        let lon_cells = (max_distance / (111.0 * pos.0.to_radians().cos().max(0.01)))
+            .ceil()
+            .min(180.0) as i32;
+
+        (lat - lat_cells..=lat + lat_cells)
+            .flat_map(|lat| (lon - lon_cells..=lon + lon_cells).map(move |lon| (lat, lon)))
+            .filter_map(|(lat, lon)| {
+                // wrap around the anti-meridian
+                self.cells.get(&(lat, (lon + 180).rem_euclid(360) - 180))
+            })
+            .flatten()
+            .map(|i| &self.airports[*i])
+            .filter(|airport| airport.airport_type != "closed" && filter(airport))
+            .map(|airport| (airport, crate::distance(pos, airport.pos())))
+            .filter(|(_, distance)| *distance <= max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    /// Returns the airport of the start or end of a leg at `(latitude, longitude)`: the closest
+    /// (non-closed) [`Airport`] within 10 km, and its distance in km.
+    /// Returns `None` when there is no such airport (e.g. a private airstrip missing from OurAirports).
+    pub fn closest_airport(&self, latitude: f64, longitude: f64) -> Option<(&Airport, f64)> {
+        self.closest((latitude, longitude), AIRPORT_MAX_DISTANCE)
+    }
+
+    /// Returns the elevation of the ground in feet at `(latitude, longitude)`, estimated as the
+    /// elevation of the closest airport within 20 km (and 0 otherwise).
+    pub fn elevation(&self, pos: (f64, f64)) -> f64 {
+        self.closest(pos, ELEVATION_MAX_DISTANCE)
+            .and_then(|(airport, _)| airport.elevation_ft)
+            .unwrap_or(0.0)
+    }
+
+    /// Returns whether `endpoint`, the first (last) position of a leg with `adjacent` the next (previous) one,
+    /// was observed on the ground: it is within 10 km of an airport, below 500 feet of height, and either reported
+    /// on the ground or slower than 150 km/h.
+    fn on_ground(&self, endpoint: &crate::Position, adjacent: Option<&crate::Position>) -> bool {
+        let (latitude, longitude) = endpoint.pos();
+        if self.closest_airport(latitude, longitude).is_none()
+            || endpoint.altitude() - self.elevation(endpoint.pos()) > ON_GROUND_HEIGHT
+        {
+            return false;
+        }
+        if endpoint.grounded() {
+            return true;
+        }
+        adjacent.is_some_and(|adjacent| {
+            let gap = (adjacent.datetime() - endpoint.datetime()).abs();
+            gap > time::Duration::ZERO
+                && gap <= ON_GROUND_MAX_GAP
+                && endpoint.distace(adjacent) / (gap.as_seconds_f64() / 60.0 / 60.0)
+                    < ON_GROUND_SPEED
+        })
+    }
+
+    /// Returns whether the start of `leg` was observed on the ground at an airport, as opposed to
+    /// the start of the coverage of the aircraft at altitude (see `M-on-ground`)
+    pub fn start_on_ground(&self, leg: &Leg) -> bool {
+        let positions = leg.positions();
+        self.on_ground(leg.from(), positions.get(1))
+    }
+
+    /// Returns whether the end of `leg` was observed on the ground at an airport, as opposed to
+    /// a loss of coverage of the aircraft at altitude (see `M-on-ground`)
+    pub fn end_on_ground(&self, leg: &Leg) -> bool {
+        let positions = leg.positions();
+        let adjacent = positions.len().checked_sub(2).map(|i| &positions[i]);
+        self.on_ground(leg.to(), adjacent)
+    }
+
+    /// Returns whether `leg` was diverted: during its descent, it approached an airport other than
+    /// its arrival airport and climbed again (a go-around) before landing elsewhere.
+    /// Returns `false` when the arrival airport is unknown.
+    pub fn diverted(&self, leg: &Leg) -> bool {
+        let (latitude, longitude) = leg.to().pos();
+        let Some((arrival, _)) = self.closest_airport(latitude, longitude) else {
+            return false;
+        };
+        let positions = leg.positions();
+        // the descent starts at the highest position of the leg
+        let top = positions
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.flying())
+            .max_by(|a, b| a.1.altitude().total_cmp(&b.1.altitude()))
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+        let descent = &positions[top..];
+
+        let height = |p: &crate::Position| p.altitude() - self.elevation(p.pos());
+        let Some(approach) = descent.iter().position(|p| {
+            p.flying()
+                && height(p) < APPROACH_HEIGHT
+                && self
+                    .closest(p.pos(), APPROACH_MAX_DISTANCE)
+                    .is_some_and(|(airport, _)| airport.ident != arrival.ident)
+        }) else {
+            return false;
+        };
+        let approach_height = height(&descent[approach]);
+        descent[approach..]
+            .iter()
+            .any(|p| p.flying() && height(p) > approach_height + GO_AROUND_CLIMB)
+    }
+}
+
+async fn extract() -> Result<Vec<u8>, std::io::Error> {
+    Ok(reqwest::get(url())
+        .await
+        .map_err(std::io::Error::other)?
+        .bytes()
+        .await
+        .map_err(std::io::Error::other)?
+        .to_vec())
+}
+
+/// Returns [`Airports`] from [OurAirports](https://ourairports.com/data/), with the corrections of
+/// `src/airports_overrides.csv` (see [`load_airport_overrides`]).
+/// # Implementation
+/// The dataset is cached in `client` (or on local disk when `client` cannot be written to)
+/// the first time it is used.
+pub async fn airports(client: &dyn BlobStorageProvider) -> Result<Airports, std::io::Error> {
+    let data =
+        fs::cached_call(DATABASE, extract(), client, fs::CacheAction::ReadFetchWrite).await?;
+    let mut airports = crate::csv::deserialize::<Airport>(&data).collect::<Result<Vec<_>, _>>()?;
+    let overrides = load_airport_overrides()?;
+    log::info!("airport overrides: {}", overrides.len());
+    apply_overrides(&mut airports, &overrides);
+    Ok(Airports::new(airports))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn airport(ident: &str, pos: (f64, f64), elevation_ft: f64) -> Airport {
+        Airport {
+            ident: ident.to_string(),
+            airport_type: "medium_airport".to_string(),
+            name: ident.to_string(),
+            latitude_deg: pos.0,
+            longitude_deg: pos.1,
+            elevation_ft: Some(elevation_ft),
+            iso_country: "XX".to_string(),
+            municipality: None,
+            scheduled_service: "no".to_string(),
+            iata_code: None,
+        }
+    }
+
+    #[test]
+    fn closest() {
+        let airports = Airports::new(vec![
+            airport("KASE", (39.2232, -106.8690), 7820.0),
+            airport("EKCH", (55.6179, 12.6560), 17.0),
+            airport("EKRK", (55.5856, 12.1314), 146.0),
+        ]);
+
+        let (airport, distance) = airports.closest((55.62, 12.60), 50.0).unwrap();
+        assert_eq!(airport.ident, "EKCH");
+        assert!(distance < 5.0);
+
+        assert!(airports.closest((56.5, 12.60), 50.0).is_none());
+        assert_eq!(
+            airports.closest_airport(55.59, 12.13).unwrap().0.ident,
+            "EKRK"
+        );
+        assert!(airports.closest_airport(55.75, 12.40).is_none());
+        assert_eq!(airports.elevation((39.22, -106.86)), 7820.0);
+        assert_eq!(airports.elevation((39.52, -106.86)), 0.0);
+    }
+
+    #[test]
+    fn diverted() {
+        let airports = Airports::new(vec![
+            airport("EKCH", (55.6179, 12.6560), 17.0),
+            airport("EKBI", (55.7403, 9.1518), 247.0),
+            airport("EKYT", (57.0928, 9.8492), 10.0),
+        ]);
+        let position = |minutes: i64, pos: (f64, f64), altitude: Option<f64>| crate::Position {
+            datetime: time::macros::datetime!(2023 - 01 - 01 10:00 UTC)
+                + time::Duration::minutes(minutes),
+            latitude: pos.0,
+            longitude: pos.1,
+            altitude,
+            altitude_source: None,
+        };
+        let leg = |positions: Vec<crate::Position>| {
+            crate::legs::legs_with_elevation(positions.into_iter(), |p| airports.elevation(p.pos()))
+                .next()
+                .unwrap()
+        };
+
+        // EKCH -> go-around at EKBI -> EKYT
+        let diverted = leg(vec![
+            position(0, (55.6179, 12.6560), None),
+            position(4, (55.65, 12.0), Some(20000.0)),
+            position(8, (55.70, 10.0), Some(30000.0)),
+            position(12, (55.73, 9.30), Some(5000.0)),
+            position(16, (55.74, 9.16), Some(1000.0)),
+            position(20, (55.80, 9.20), Some(3000.0)),
+            position(24, (56.50, 9.50), Some(8000.0)),
+            position(28, (57.08, 9.84), Some(500.0)),
+            position(32, (57.0928, 9.8492), None),
+        ]);
+        assert!(airports.diverted(&diverted));
+
+        // EKCH -> EKYT overflying EKBI
+        let direct = leg(vec![
+            position(0, (55.6179, 12.6560), None),
+            position(4, (55.65, 12.0), Some(20000.0)),
+            position(8, (55.70, 10.0), Some(30000.0)),
+            position(12, (55.74, 9.16), Some(20000.0)),
+            position(16, (56.50, 9.50), Some(8000.0)),
+            position(20, (57.08, 9.84), Some(500.0)),
+            position(24, (57.0928, 9.8492), None),
+        ]);
+        assert!(!airports.diverted(&direct));
+    }
+
+    #[test]
+    fn on_ground() {
+        let airports = Airports::new(vec![airport("EKCH", (55.6179, 12.6560), 17.0)]);
+        let position = |minutes: i64, pos: (f64, f64), altitude: Option<f64>| crate::Position {
+            datetime: time::macros::datetime!(2023 - 01 - 01 10:00 UTC)
+                + time::Duration::minutes(minutes),
+            latitude: pos.0,
+            longitude: pos.1,
+            altitude,
+            altitude_source: None,
+        };
+        let leg = |positions: Vec<crate::Position>| {
+            crate::legs::legs_with_elevation(positions.into_iter(), |p| airports.elevation(p.pos()))
+                .next()
+                .unwrap()
+        };
+
+        // departing EKCH on the ground; the last position at altitude (loss of coverage)
+        let truncated = leg(vec![
+            position(0, (55.6179, 12.6560), None),
+            position(4, (55.65, 12.0), Some(20000.0)),
+            position(8, (55.70, 10.0), Some(30000.0)),
+            position(20, (55.62, 12.50), Some(25000.0)),
+            position(21, (55.62, 12.55), Some(25000.0)),
+        ]);
+        assert!(airports.start_on_ground(&truncated));
+        assert!(!airports.end_on_ground(&truncated));
+
+        // arriving at EKCH slowly at a low altitude, without being reported on the ground
+        let landed = leg(vec![
+            position(0, (55.70, 10.0), None),
+            position(4, (55.65, 12.0), Some(20000.0)),
+            position(8, (55.70, 12.3), Some(30000.0)),
+            position(12, (55.62, 12.60), Some(2000.0)),
+            position(16, (55.618, 12.650), Some(100.0)),
+            position(17, (55.6179, 12.6560), Some(50.0)),
+        ]);
+        assert!(!airports.start_on_ground(&landed));
+        assert!(airports.end_on_ground(&landed));
+    }
+
+    #[test]
+    fn deserialize() {
+        let data = br#""id","ident","type","name","latitude_deg","longitude_deg","elevation_ft","continent","iso_country","iso_region","municipality","scheduled_service","gps_code","iata_code","local_code","home_link","wikipedia_link","keywords"
+2429,"EKCH","large_airport","Copenhagen Kastrup Airport",55.617900848389,12.656000137329,17,"EU","DK","DK-84","Copenhagen","yes","EKCH","CPH",,"https://www.cph.dk/en/","https://en.wikipedia.org/wiki/Copenhagen_Airport","Kastrup"
+"#;
+        let airports = crate::csv::deserialize::<Airport>(data)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(airports[0].ident, "EKCH");
+        assert_eq!(airports[0].elevation_ft, Some(17.0));
+        assert_eq!(airports[0].iata_code.as_deref(), Some("CPH"));
+    }
+
+    #[test]
+    fn overrides() {
+        assert!(load_airport_overrides().is_ok());
+
+        let data = b"icao,name,latitude,longitude,elevation_ft,country,source,date
+EKCH,Copenhagen Airport,55.618,12.656,17,DK,https://www.cph.dk,2024-05-01
+EKXX,Hospital Heliport,55.69,12.57,,DK,https://example.com,2024-05-01
+";
+        let overrides = parse_airport_overrides(data).unwrap();
+        let mut airports = vec![
+            airport("EKCH", (55.6179, 12.6560), 17.0),
+            airport("EKRK", (55.5856, 12.1314), 146.0),
+        ];
+        apply_overrides(&mut airports, &overrides);
+        assert_eq!(airports.len(), 3);
+        assert_eq!(airports[0].name, "Copenhagen Airport");
+        assert_eq!(airports[0].iso_country, "DK");
+        assert_eq!(airports[1].name, "EKRK");
+        assert_eq!(airports[2].ident, "EKXX");
+        assert_eq!(airports[2].elevation_ft, None);
+
+        let airports = Airports::new(airports);
+        let (airport, _) = airports.closest_airport(55.69, 12.57).unwrap();
+        assert_eq!(airport.ident, "EKXX");
+    }
+}
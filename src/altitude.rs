@@ -0,0 +1,287 @@
+//! Contains the normalization of the altitudes of positions (`M-altitudes`): ADS-B reports a barometric altitude
+//! (pressure altitude, relative to the standard pressure of 1013.25 hPa) and, for most transponders, a geometric
+//! altitude (GNSS height), which sources of positions mix.
+//!
+//! Like winds (see [`crate::wind`]), METAR observations are not retrieved by this crate: their QNH is expected to be
+//! stored, per month, as CSV at `metar/qnh/month={month}/data.csv` with the columns of [`QnhRecord`].
+use std::{collections::HashMap, sync::Arc};
+
+use futures::lock::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{fs::BlobStorageProvider, Position};
+
+static DATABASE: &str = "metar/qnh/";
+
+/// The standard pressure in hPa, the reference of barometric altitudes
+static STANDARD_PRESSURE: f64 = 1013.25;
+/// The change in feet of the barometric altitude per hPa near the ground
+static FEET_PER_HPA: f64 = 27.0;
+/// Barometric altitudes at or above it (in feet) are not corrected: flight levels are flown relative to
+/// the standard pressure. The highest transition altitude in use (North America)
+static TRANSITION_ALTITUDE: f64 = 18000.0;
+/// The maximum distance in km between a position and the station whose QNH corrects it
+static MAX_STATION_DISTANCE: f64 = 100.0;
+/// The maximum time in seconds between a position and the observation whose QNH corrects it
+static MAX_OBSERVATION_AGE: i64 = 3600;
+
+fn pk_to_blob_name(month: time::Date) -> String {
+    let month = crate::serde::month_to_part(month);
+    format!("{DATABASE}month={month}/data.csv")
+}
+
+/// The source of the altitude of a [`Position`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AltitudeSource {
+    /// The barometric altitude reported by the aircraft
+    Barometric,
+    /// The geometric (GNSS) altitude reported by the aircraft
+    Geometric,
+    /// The barometric altitude corrected with the QNH of a nearby METAR observation
+    QnhCorrected,
+}
+
+impl crate::schema::Type for AltitudeSource {
+    const NAME: &'static str = "string";
+}
+
+impl AltitudeSource {
+    /// The name of the source (e.g. `barometric`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Barometric => "barometric",
+            Self::Geometric => "geometric",
+            Self::QnhCorrected => "qnh-corrected",
+        }
+    }
+}
+
+impl std::str::FromStr for AltitudeSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "barometric" => Ok(Self::Barometric),
+            "geometric" => Ok(Self::Geometric),
+            "qnh-corrected" => Ok(Self::QnhCorrected),
+            other => Err(format!(
+                "altitude source `{other}` must be `barometric`, `geometric` or `qnh-corrected`"
+            )),
+        }
+    }
+}
+
+/// Which of the altitudes of a position is preferred when both are reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Preference {
+    /// The barometric altitude, falling back to the geometric one
+    #[default]
+    Barometric,
+    /// The geometric altitude, falling back to the barometric one
+    Geometric,
+}
+
+impl std::str::FromStr for Preference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "barometric" => Ok(Self::Barometric),
+            "geometric" => Ok(Self::Geometric),
+            other => Err(format!(
+                "altitude `{other}` must be `barometric` or `geometric`"
+            )),
+        }
+    }
+}
+
+/// Returns the altitude of a position with `barometric` and `geometric` altitudes according to `preference`,
+/// falling back to the other altitude when the preferred one is not reported, and its source
+pub fn select(
+    barometric: Option<f64>,
+    geometric: Option<f64>,
+    preference: Preference,
+) -> Option<(f64, AltitudeSource)> {
+    let barometric = barometric.map(|x| (x, AltitudeSource::Barometric));
+    let geometric = geometric.map(|x| (x, AltitudeSource::Geometric));
+    match preference {
+        Preference::Barometric => barometric.or(geometric),
+        Preference::Geometric => geometric.or(barometric),
+    }
+}
+
+/// Returns the source of the altitudes of the flying `positions` of a leg: their [`AltitudeSource`] when they all
+/// have the same, `mixed` otherwise, and `None` when all are on the ground
+pub fn leg_source(positions: &[Position]) -> Option<&'static str> {
+    let mut sources = positions.iter().filter_map(|p| p.altitude_source());
+    let first = sources.next()?;
+    Some(if sources.all(|source| source == first) {
+        first.as_str()
+    } else {
+        "mixed"
+    })
+}
+
+/// Returns the correction in feet of a barometric altitude near the ground under `qnh` (in hPa)
+fn correction(qnh: f64) -> f64 {
+    (qnh - STANDARD_PRESSURE) * FEET_PER_HPA
+}
+
+/// An observation of the QNH of a METAR station
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QnhRecord {
+    /// The ICAO code of the station (e.g. `EKCH`)
+    pub station: Arc<str>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub datetime: time::OffsetDateTime,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// The QNH in hPa
+    pub qnh: f64,
+}
+
+/// A set of [`QnhRecord`]s, indexed by hour
+#[derive(Debug, Clone, PartialEq)]
+pub struct QnhGrid {
+    /// hour since epoch -> records observed within the hour
+    records: HashMap<i64, Vec<QnhRecord>>,
+}
+
+impl QnhGrid {
+    /// Returns a new [`QnhGrid`] from `records`
+    pub fn new(records: impl Iterator<Item = QnhRecord>) -> Self {
+        let mut by_hour = HashMap::<_, Vec<_>>::new();
+        for record in records {
+            by_hour
+                .entry(record.datetime.unix_timestamp().div_euclid(3600))
+                .or_default()
+                .push(record);
+        }
+        Self { records: by_hour }
+    }
+
+    /// Returns the [`QnhRecord`] closest to `position` within 100 km and 1 hour, if any
+    pub fn qnh(&self, position: &Position) -> Option<&QnhRecord> {
+        let timestamp = position.datetime().unix_timestamp();
+        let hour = timestamp.div_euclid(3600);
+        (hour - 1..=hour + 1)
+            .filter_map(|hour| self.records.get(&hour))
+            .flatten()
+            .filter(|record| {
+                (record.datetime.unix_timestamp() - timestamp).abs() <= MAX_OBSERVATION_AGE
+            })
+            .map(|record| {
+                let distance = crate::distance(position.pos(), (record.latitude, record.longitude));
+                (record, distance)
+            })
+            .filter(|(_, distance)| *distance <= MAX_STATION_DISTANCE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(record, _)| record)
+    }
+
+    /// Corrects the barometric altitude of `position` below the transition altitude with the QNH of the closest
+    /// observation (see [`QnhGrid::qnh`]). Positions on the ground, with other sources or without a nearby
+    /// observation are returned unchanged.
+    pub fn correct(&self, mut position: Position) -> Position {
+        let barometric = position.altitude_source() == Some(AltitudeSource::Barometric);
+        if !barometric || position.altitude() >= TRANSITION_ALTITUDE {
+            return position;
+        }
+        if let Some(record) = self.qnh(&position) {
+            position.altitude = position.altitude.map(|x| x + correction(record.qnh));
+            position.altitude_source = Some(AltitudeSource::QnhCorrected);
+        }
+        position
+    }
+}
+
+/// In-memory cache of the [`QnhGrid`] of each month
+#[derive(Default)]
+pub struct Qnhs {
+    months: Mutex<HashMap<time::Date, Option<Arc<QnhGrid>>>>,
+}
+
+impl Qnhs {
+    /// Returns the [`QnhGrid`] of `month`, or `None` if no observations exist for it.
+    /// # Implementation
+    /// The observations are read from `client` on the first call for a given month, and kept in memory.
+    pub async fn month(
+        &self,
+        month: time::Date,
+        client: &dyn BlobStorageProvider,
+    ) -> Result<Option<Arc<QnhGrid>>, std::io::Error> {
+        let mut months = self.months.lock().await;
+        if let Some(grid) = months.get(&month) {
+            return Ok(grid.clone());
+        }
+        let grid = match client.maybe_get(&pk_to_blob_name(month)).await? {
+            Some(data) => {
+                let records =
+                    crate::csv::deserialize::<QnhRecord>(&data).collect::<Result<Vec<_>, _>>()?;
+                Some(Arc::new(QnhGrid::new(records.into_iter())))
+            }
+            None => {
+                log::warn!("no QNH for month {month}");
+                None
+            }
+        };
+        months.insert(month, grid.clone());
+        Ok(grid)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn work() {
+        assert_eq!(
+            select(Some(1000.0), Some(1100.0), Preference::Barometric),
+            Some((1000.0, AltitudeSource::Barometric))
+        );
+        assert_eq!(
+            select(Some(1000.0), Some(1100.0), Preference::Geometric),
+            Some((1100.0, AltitudeSource::Geometric))
+        );
+        assert_eq!(
+            select(None, Some(1100.0), Preference::Barometric),
+            Some((1100.0, AltitudeSource::Geometric))
+        );
+        assert_eq!(select(None, None, Preference::Geometric), None);
+
+        let grid = QnhGrid::new(std::iter::once(QnhRecord {
+            station: "EKCH".into(),
+            datetime: datetime!(2023 - 01 - 01 10:20 UTC),
+            latitude: 55.6179,
+            longitude: 12.656,
+            qnh: 993.25,
+        }));
+        let position = |datetime, latitude, altitude| Position {
+            datetime,
+            latitude,
+            longitude: 12.6,
+            altitude: Some(altitude),
+            altitude_source: None,
+        };
+
+        // 20 hPa below the standard pressure: the aircraft is 540 ft lower than its barometric altitude
+        let corrected = grid.correct(position(datetime!(2023 - 01 - 01 10:00 UTC), 55.6, 2000.0));
+        assert_eq!(corrected.altitude(), 1460.0);
+        assert_eq!(
+            corrected.altitude_source(),
+            Some(AltitudeSource::QnhCorrected)
+        );
+        // too far in time, in space or above the transition altitude
+        for uncorrected in [
+            position(datetime!(2023 - 01 - 01 12:00 UTC), 55.6, 2000.0),
+            position(datetime!(2023 - 01 - 01 10:00 UTC), 57.6, 2000.0),
+            position(datetime!(2023 - 01 - 01 10:00 UTC), 55.6, 35000.0),
+        ] {
+            assert_eq!(grid.correct(uncorrected.clone()), uncorrected);
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! Contains the conversion of positions and legs to and from [Apache Arrow](https://arrow.apache.org/)
+//! [`RecordBatch`]es (feature `arrow`), so that they can be handed over in memory to dataframe libraries
+//! (e.g. Polars or DataFusion) without writing them to a dataset and parsing it back.
+//!
+//! The columns of a batch are those of the [`Schema`] of its rows, with the Arrow type of their type
+//! (see [`data_type`]).
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Float64Type, Int64Type, TimestampMicrosecondType, UInt64Type},
+    Array, ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, StringArray,
+    TimestampMicrosecondArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, TimeUnit};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::{etl::legs::LegOut, schema::Schema, Position};
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// Returns the Arrow type of the type `type_` of a column (see [`crate::schema::Type::NAME`]):
+/// integers are widened to 64 bits and datetimes are timestamps in microseconds in UTC
+pub fn data_type(type_: &str) -> DataType {
+    match type_ {
+        "f64" => DataType::Float64,
+        "bool" => DataType::Boolean,
+        "i32" => DataType::Int64,
+        "u8" | "u16" | "u32" | "u64" => DataType::UInt64,
+        "datetime" => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        _ => DataType::Utf8,
+    }
+}
+
+/// Returns the Arrow schema of rows `S`
+pub fn schema<S: Schema>() -> arrow_schema::Schema {
+    arrow_schema::Schema::new(
+        S::fields()
+            .into_iter()
+            .map(|field| Field::new(field.name, data_type(field.type_), field.nullable))
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn timestamp(value: &Value) -> Option<i64> {
+    let datetime = OffsetDateTime::parse(value.as_str()?, &Rfc3339).ok()?;
+    Some((datetime.unix_timestamp_nanos() / 1000) as i64)
+}
+
+/// Returns the column of type `type_` of the serialized `rows`
+fn column(type_: &DataType, values: Vec<&Value>) -> ArrayRef {
+    match type_ {
+        DataType::Float64 => Arc::new(
+            values
+                .into_iter()
+                .map(Value::as_f64)
+                .collect::<Float64Array>(),
+        ),
+        DataType::Boolean => Arc::new(
+            values
+                .into_iter()
+                .map(Value::as_bool)
+                .collect::<BooleanArray>(),
+        ),
+        DataType::Int64 => Arc::new(
+            values
+                .into_iter()
+                .map(Value::as_i64)
+                .collect::<Int64Array>(),
+        ),
+        DataType::UInt64 => Arc::new(
+            values
+                .into_iter()
+                .map(Value::as_u64)
+                .collect::<UInt64Array>(),
+        ),
+        DataType::Timestamp(..) => Arc::new(
+            values
+                .into_iter()
+                .map(timestamp)
+                .collect::<TimestampMicrosecondArray>()
+                .with_timezone("UTC"),
+        ),
+        _ => Arc::new(
+            values
+                .into_iter()
+                .map(Value::as_str)
+                .collect::<StringArray>(),
+        ),
+    }
+}
+
+/// Returns `rows` as a [`RecordBatch`] with the [`schema`] of `S`
+/// # Error
+/// Errors if a row cannot be serialized
+pub fn to_batch<S: Schema + Serialize>(rows: &[S]) -> Result<RecordBatch, std::io::Error> {
+    let schema = schema::<S>();
+    let rows = rows
+        .iter()
+        .map(serde_json::to_value)
+        .collect::<Result<Vec<_>, _>>()?;
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| {
+            let values = rows
+                .iter()
+                .map(|row| row.get(field.name()).unwrap_or(&Value::Null))
+                .collect();
+            column(field.data_type(), values)
+        })
+        .collect();
+    RecordBatch::try_new(Arc::new(schema), columns).map_err(std::io::Error::other)
+}
+
+/// Returns the value of the row `index` of `array`, as serialized by rows
+fn value(array: &dyn Array, index: usize) -> Option<Value> {
+    if array.is_null(index) {
+        return Some(Value::Null);
+    }
+    Some(match array.data_type() {
+        DataType::Float64 => {
+            let value = array.as_primitive_opt::<Float64Type>()?.value(index);
+            serde_json::Number::from_f64(value).map_or(Value::Null, Value::Number)
+        }
+        DataType::Boolean => Value::Bool(array.as_boolean_opt()?.value(index)),
+        DataType::Int64 => array.as_primitive_opt::<Int64Type>()?.value(index).into(),
+        DataType::UInt64 => array.as_primitive_opt::<UInt64Type>()?.value(index).into(),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => {
+            let micros = array
+                .as_primitive_opt::<TimestampMicrosecondType>()?
+                .value(index);
+            let datetime = OffsetDateTime::from_unix_timestamp_nanos(micros as i128 * 1000).ok()?;
+            Value::String(datetime.format(&Rfc3339).ok()?)
+        }
+        DataType::Utf8 => Value::String(array.as_string_opt::<i32>()?.value(index).to_string()),
+        _ => return None,
+    })
+}
+
+/// Returns the rows `S` of `batch`, whose columns are matched by name against the [`schema`] of `S`
+/// # Error
+/// Errors if a column is missing or of another type, or if a row is not a valid `S`
+pub fn from_batch<S: Schema + DeserializeOwned>(
+    batch: &RecordBatch,
+) -> Result<Vec<S>, std::io::Error> {
+    let columns = schema::<S>()
+        .fields()
+        .iter()
+        .map(|field| {
+            let column = batch
+                .column_by_name(field.name())
+                .ok_or_else(|| invalid(format!("column `{}` is missing", field.name())))?;
+            if column.data_type() != field.data_type() {
+                return Err(invalid(format!(
+                    "column `{}` must be of type {} but is {}",
+                    field.name(),
+                    field.data_type(),
+                    column.data_type()
+                )));
+            }
+            Ok((field.name().clone(), column))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    (0..batch.num_rows())
+        .map(|index| {
+            let row = columns
+                .iter()
+                .map(|(name, column)| {
+                    let value = value(column.as_ref(), index)
+                        .ok_or_else(|| invalid(format!("column `{name}` is invalid")))?;
+                    Ok((name.clone(), value))
+                })
+                .collect::<Result<serde_json::Map<_, _>, std::io::Error>>()?;
+            Ok(serde_json::from_value(Value::Object(row))?)
+        })
+        .collect()
+}
+
+/// Returns `positions` as a [`RecordBatch`]
+pub fn positions_to_batch(positions: &[Position]) -> Result<RecordBatch, std::io::Error> {
+    to_batch(positions)
+}
+
+/// Returns the [`Position`]s of `batch`
+/// # Error
+/// Errors if `batch` does not have the columns of positions
+pub fn batch_to_positions(batch: &RecordBatch) -> Result<Vec<Position>, std::io::Error> {
+    from_batch(batch)
+}
+
+/// Returns `legs` as a [`RecordBatch`] with the columns of the database of legs
+pub fn legs_to_batch(legs: &[LegOut]) -> Result<RecordBatch, std::io::Error> {
+    to_batch(legs)
+}
+
+/// Returns the [`LegOut`]s of `batch`
+/// # Error
+/// Errors if `batch` does not have the columns of legs
+pub fn batch_to_legs(batch: &RecordBatch) -> Result<Vec<LegOut>, std::io::Error> {
+    from_batch(batch)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let positions = serde_json::from_value::<Vec<Position>>(serde_json::json!([
+            {"datetime": "2023-01-01T10:00:00Z", "latitude": 55.6, "longitude": 12.6},
+            {"datetime": "2023-01-01T10:10:00.5Z", "latitude": 55.0, "longitude": 12.0, "altitude": 30000.0},
+        ]))
+        .unwrap();
+        let batch = positions_to_batch(&positions).unwrap();
+        assert_eq!(batch.num_rows(), 2);
+        assert_eq!(
+            batch.schema().field(0).data_type(),
+            &DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+        );
+        assert_eq!(batch.column_by_name("altitude").unwrap().null_count(), 1);
+        assert_eq!(batch_to_positions(&batch).unwrap(), positions);
+
+        let legs = serde_json::from_value::<Vec<LegOut>>(serde_json::json!([{
+            "icao_number": "459cd3",
+            "start": "2023-01-01T10:00:00Z",
+            "start_lat": 55.6,
+            "start_lon": 12.6,
+            "start_altitude": 0.0,
+            "end": "2023-01-01T11:30:00Z",
+            "end_lat": 49.0,
+            "end_lon": 2.5,
+            "end_altitude": 0.0,
+            "duration": 1.5,
+            "distance": 1000.0,
+            "great_circle_distance": 1000.0,
+            "midpoint_lat": 52.3,
+            "midpoint_lon": 7.5,
+            "hours_above_30000": 1.0,
+            "hours_above_40000": 0.0,
+            "co2_emissions": 100.0,
+            "diverted": false,
+            "start_on_ground": true,
+            "end_on_ground": true,
+            "taxi_out_minutes": 0.0,
+            "taxi_in_minutes": 0.0,
+            "commercial_alternative_exists": true,
+        }]))
+        .unwrap();
+        let batch = legs_to_batch(&legs).unwrap();
+        assert_eq!(batch.num_columns(), schema::<LegOut>().fields().len());
+        assert_eq!(
+            serde_json::to_value(batch_to_legs(&batch).unwrap()).unwrap(),
+            serde_json::to_value(&legs).unwrap()
+        );
+
+        // a batch of positions does not have the columns of legs
+        assert!(batch_to_legs(&positions_to_batch(&positions).unwrap()).is_err());
+    }
+}
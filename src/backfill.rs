@@ -0,0 +1,276 @@
+//! Contains the implementation of the backfill of ICAO numbers with positions but without an entry in any
+//! snapshot of the database of aircrafts (`M-backfill`), whose positions are otherwise excluded from legs.
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aircraft::{Aircraft, LazyAircrafts},
+    fs::BlobStorageProvider,
+};
+
+static DATABASE: &str = "aircraft/unmatched/";
+
+/// The default registry, queried with the ICAO number in place of `{icao_number}`
+pub static HEXDB: &str = "https://hexdb.io/api/v1/aircraft/{icao_number}";
+
+/// A source of registry entries of aircrafts
+#[async_trait]
+pub trait Registry: Sync {
+    /// The name of the registry, written to [`Backfilled::source`]
+    fn name(&self) -> &str;
+    /// Returns the [`Aircraft`] of `icao_number`, if the registry has it
+    async fn lookup(&self, icao_number: &str) -> Result<Option<Aircraft>, Box<dyn Error>>;
+}
+
+/// A registry of aircrafts served over http, one aircraft per request (e.g. [`HEXDB`])
+pub struct HttpRegistry {
+    url: String,
+}
+
+/// The response of [`HttpRegistry`]
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HttpEntry {
+    registration: String,
+    #[serde(rename = "ICAOTypeCode")]
+    icao_type_code: String,
+    #[serde(rename = "Type")]
+    model: String,
+}
+
+/// Returns the [`Aircraft`] of `icao_number` from the response of a [`HttpRegistry`]
+fn from_http(icao_number: &str, data: &[u8]) -> Result<Aircraft, serde_json::Error> {
+    let entry = serde_json::from_slice::<HttpEntry>(data)?;
+    Ok(Aircraft {
+        icao_number: icao_number.into(),
+        tail_number: entry.registration,
+        type_designator: entry.icao_type_code,
+        model: entry.model,
+        country: None,
+        manufacture_year: None,
+    })
+}
+
+#[async_trait]
+impl Registry for HttpRegistry {
+    fn name(&self) -> &str {
+        &self.url
+    }
+
+    async fn lookup(&self, icao_number: &str) -> Result<Option<Aircraft>, Box<dyn Error>> {
+        let url = self.url.replace("{icao_number}", icao_number);
+        let response = reqwest::get(&url).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let data = response.error_for_status()?.bytes().await?;
+        Ok(Some(from_http(icao_number, &data)?))
+    }
+}
+
+/// A registry of aircrafts in a CSV file with the columns of [`Aircraft`]
+pub struct CsvRegistry {
+    path: String,
+    aircrafts: HashMap<Arc<str>, Aircraft>,
+}
+
+impl CsvRegistry {
+    /// Returns a new [`CsvRegistry`] from the CSV `data` read from `path`
+    pub fn new(path: String, data: &[u8]) -> Result<Self, std::io::Error> {
+        let aircrafts = crate::csv::deserialize::<Aircraft>(data)
+            .map(|x| x.map(|x| (x.icao_number.to_ascii_lowercase().into(), x)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { path, aircrafts })
+    }
+}
+
+#[async_trait]
+impl Registry for CsvRegistry {
+    fn name(&self) -> &str {
+        &self.path
+    }
+
+    async fn lookup(&self, icao_number: &str) -> Result<Option<Aircraft>, Box<dyn Error>> {
+        Ok(self.aircrafts.get(icao_number).cloned())
+    }
+}
+
+/// Initializes a [`Registry`] from `source`, either
+/// * `http(s)://...` with `{icao_number}` (a [`HttpRegistry`] like [`HEXDB`]), or
+/// * the path to a CSV file (a [`CsvRegistry`])
+pub fn registry(source: &str) -> Result<Box<dyn Registry>, Box<dyn Error>> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        if !source.contains("{icao_number}") {
+            return Err(format!("{source} does not contain {{icao_number}}").into());
+        }
+        Ok(Box::new(HttpRegistry {
+            url: source.to_string(),
+        }))
+    } else {
+        let data = std::fs::read(source)?;
+        Ok(Box::new(CsvRegistry::new(source.to_string(), &data)?))
+    }
+}
+
+/// An ICAO number with positions but without an entry in the database of aircrafts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Backfilled {
+    pub icao_number: Arc<str>,
+    /// The number of months with positions
+    pub months: usize,
+    /// The number of positions excluded from legs
+    pub positions: usize,
+    /// The tail number in the registry, if found
+    pub tail_number: Option<String>,
+    /// The ICAO number of the aircraft model in the registry, if found
+    pub type_designator: Option<String>,
+    /// The model in the registry, if found
+    pub model: Option<String>,
+    /// The registry the aircraft was found in, if found
+    pub source: Option<String>,
+}
+
+/// The summary of the positions of a year excluded from legs because of missing registry entries
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    /// The number of ICAO numbers with positions but without an entry in the database of aircrafts
+    pub icao_numbers: usize,
+    /// The number of their positions, excluded from legs
+    pub positions: usize,
+    /// The number of those ICAO numbers found in the registry
+    pub found: usize,
+    /// The number of positions of ICAO numbers found in the registry
+    pub positions_found: usize,
+}
+
+/// Returns the months with positions of each ICAO number with positions but in no snapshot of `aircrafts`
+pub async fn unmatched(
+    aircrafts: &LazyAircrafts<'_>,
+    client: &(dyn BlobStorageProvider + Sync),
+) -> Result<BTreeMap<Arc<str>, Vec<time::Date>>, Box<dyn Error>> {
+    let positions = crate::icao_to_trace::indexed_client(client, false).await?;
+    let mut by_icao = BTreeMap::<Arc<str>, Vec<time::Date>>::new();
+    for (icao_number, month) in crate::icao_to_trace::list_months_positions(&positions).await? {
+        by_icao.entry(icao_number).or_default().push(month);
+    }
+
+    let mut unmatched = BTreeMap::new();
+    for (icao_number, mut months) in by_icao {
+        let mut matched = false;
+        for date in aircrafts.dates() {
+            if aircrafts.get(*date, &icao_number).await?.is_some() {
+                matched = true;
+                break;
+            }
+        }
+        if !matched {
+            months.sort_unstable();
+            unmatched.insert(icao_number, months);
+        }
+    }
+    Ok(unmatched)
+}
+
+/// Returns the number of positions of `icao_number` on `month`
+async fn count_positions(
+    icao_number: &str,
+    month: time::Date,
+    client: &dyn BlobStorageProvider,
+) -> Result<usize, std::io::Error> {
+    let data = crate::icao_to_trace::get_month_positions_json(icao_number, month, client).await?;
+    let positions =
+        crate::icao_to_trace::positions_from_json(&data).try_fold(0, |acc, x| x.map(|_| acc + 1));
+    positions
+}
+
+/// Looks up the ICAO numbers with positions but without an entry in the database of aircrafts in `registry`
+/// and writes them to `aircraft/unmatched/data.csv` (see [`Backfilled`]), together with the [`Summary`] of each
+/// year to `aircraft/unmatched/summary.json`.
+/// Returns the summary of each year.
+pub async fn etl_backfill(
+    registry: &dyn Registry,
+    concurrency: usize,
+    client: &(dyn BlobStorageProvider + Sync),
+) -> Result<BTreeMap<i32, Summary>, Box<dyn Error>> {
+    let aircrafts = LazyAircrafts::new(client).await?;
+    let unmatched = unmatched(&aircrafts, client).await?;
+    log::info!("unmatched icao numbers: {}", unmatched.len());
+
+    let tasks = unmatched.iter().map(|(icao_number, months)| async move {
+        let mut positions = BTreeMap::<i32, usize>::new();
+        for month in months {
+            *positions.entry(month.year()).or_default() +=
+                count_positions(icao_number, *month, client).await?;
+        }
+        let aircraft = registry.lookup(icao_number).await?;
+        Ok::<_, Box<dyn Error>>((icao_number, months.len(), positions, aircraft))
+    });
+    let results = futures::stream::iter(tasks)
+        .buffered(concurrency)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let mut summary = BTreeMap::<i32, Summary>::new();
+    let mut rows = vec![];
+    for (icao_number, months, positions, aircraft) in results {
+        for (year, positions) in &positions {
+            let year = summary.entry(*year).or_default();
+            year.icao_numbers += 1;
+            year.positions += positions;
+            if aircraft.is_some() {
+                year.found += 1;
+                year.positions_found += positions;
+            }
+        }
+        rows.push(Backfilled {
+            icao_number: icao_number.clone(),
+            months,
+            positions: positions.values().sum(),
+            source: aircraft.as_ref().map(|_| registry.name().to_string()),
+            tail_number: aircraft.as_ref().map(|x| x.tail_number.clone()),
+            type_designator: aircraft.as_ref().map(|x| x.type_designator.clone()),
+            model: aircraft.map(|x| x.model),
+        });
+    }
+
+    let key = format!("{DATABASE}data.csv");
+    client.put(&key, crate::csv::serialize(rows.iter())).await?;
+    log::info!("Written {key}");
+
+    let key = format!("{DATABASE}summary.json");
+    let data = serde_json::to_vec(&summary).map_err(std::io::Error::other)?;
+    client.put(&key, data).await?;
+    log::info!("Written {key}");
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn registries() {
+        let aircraft = from_http(
+            "459cd3",
+            br#"{"ModeS":"459CD3","Registration":"OY-GFS","Manufacturer":"Dassault","ICAOTypeCode":"F2TH","Type":"Falcon 2000","RegisteredOwners":"","OperatorFlagCode":""}"#,
+        )
+        .unwrap();
+        assert_eq!(aircraft.tail_number, "OY-GFS");
+        assert_eq!(aircraft.type_designator, "F2TH");
+        assert_eq!(aircraft.model, "Falcon 2000");
+
+        let data = crate::csv::serialize(std::iter::once(&aircraft));
+        let registry = CsvRegistry::new("registry.csv".to_string(), &data).unwrap();
+        assert_eq!(registry.lookup("459cd3").await.unwrap(), Some(aircraft));
+        assert_eq!(registry.lookup("45d2ed").await.unwrap(), None);
+
+        assert!(super::registry("https://example.com/aircraft").is_err());
+    }
+}
@@ -0,0 +1,48 @@
+use std::error::Error;
+
+use clap::Parser;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Lists the ICAO numbers with positions but without an entry in the database of aircrafts (whose positions
+are excluded from legs), looks them up in a registry, and writes them to `aircraft/unmatched/data.csv` together with
+a summary of the excluded positions of each year (`aircraft/unmatched/summary.json`), see `M-backfill`."#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// The registry to look up ICAO numbers in: an url with `{icao_number}` returning the aircraft as JSON
+    /// (in the format of hexdb.io) or the path to a CSV file with the columns of the database of aircrafts
+    #[arg(long, default_value = flights::backfill::HEXDB)]
+    registry: String,
+    /// The maximum number of ICAO numbers looked up concurrently
+    #[arg(long, default_value_t = 10)]
+    concurrency: usize,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs::client_from_args(&cli.storage).await?;
+
+    let registry = flights::backfill::registry(&cli.registry)?;
+    let summary =
+        flights::backfill::etl_backfill(registry.as_ref(), cli.concurrency, client.as_ref())
+            .await?;
+    for (year, summary) in summary {
+        log::info!(
+            "year={year}: {} positions of {} icao numbers excluded; {} icao numbers found in the registry",
+            summary.positions,
+            summary.icao_numbers,
+            summary.found
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use std::error::Error;
+
+use clap::Parser;
+use flights::etl::legs::Roots;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Bootstraps a new deployment according to `M-bootstrap`: validates the credentials of the storage,
+uploads the bundled reference data (models and overrides of airports), caches the database of airports, and writes the
+catalog of the datasets and an empty status of the datasets of legs."#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// The version of the datasets of legs to bootstrap (see `M-versions`)
+    #[arg(long, default_value = "v2")]
+    dataset_version: String,
+    /// Whether to skip caching the database of airports (which is downloaded from the internet)
+    #[arg(long, default_value_t = false)]
+    skip_airports: bool,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs::client_from_args(&cli.storage).await?;
+
+    let roots = Roots::new(&cli.dataset_version);
+    let report = flights::bootstrap::bootstrap(&roots, client.as_ref()).await?;
+    if !cli.skip_airports {
+        flights::airports::airports(client.as_ref()).await?;
+        log::info!("Cached the database of airports");
+    }
+    log::info!(
+        "Bootstrapped: {} blobs written, {} kept",
+        report.written.len(),
+        report.kept.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use std::error::Error;
+
+use clap::Parser;
+use flights::compression::Compression;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Recompresses the CSVs under a prefix (e.g. the monthly partitions of legs at `leg/v2/data/`)
+in place according to `M-compression`: each CSV is written with the suffix of the compression and the original deleted."#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// The prefixes of the CSVs to recompress
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "leg/v2/data/,leg/v2/all/,leg/v2/by_country/"
+    )]
+    prefixes: Vec<String>,
+    /// The compression to recompress to: `none`, `gzip` or `zstd`
+    #[arg(long, default_value = "zstd")]
+    compression: Compression,
+    /// The maximum number of blobs recompressed concurrently
+    #[arg(long, default_value_t = 100)]
+    concurrency: usize,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs::client_from_args(&cli.storage).await?;
+
+    for prefix in &cli.prefixes {
+        flights::compression::recompress(prefix, cli.compression, cli.concurrency, client.as_ref())
+            .await?;
+    }
+    Ok(())
+}
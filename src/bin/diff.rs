@@ -0,0 +1,64 @@
+use std::{collections::BTreeMap, error::Error, io::Write};
+
+use clap::Parser;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Compares two runs of a dataset partitioned by month and ICAO number (e.g. `leg/v2/` and `leg/v3/`)
+and writes to stdout, as CSV, the number of added, removed and changed rows of every changed ICAO number and month"#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    /// The token to the remote storage
+    #[arg(long)]
+    access_key: String,
+    /// The token to the remote storage
+    #[arg(long)]
+    secret_access_key: String,
+    /// The root of the old run (e.g. `leg/v2/`)
+    #[arg(long)]
+    old: String,
+    /// The root of the new run (e.g. `leg/v3/`)
+    #[arg(long)]
+    new: String,
+    /// The comma-separated columns that identify a row within a month and ICAO number
+    #[arg(long, default_value = "start")]
+    key: String,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs_s3::client(cli.access_key, cli.secret_access_key).await;
+
+    let key = cli.key.split(',').collect::<Vec<_>>();
+    let diff = flights::diff::diff(&cli.old, &cli.new, &key, &client).await?;
+
+    // summary per month
+    let by_month = diff.iter().fold(
+        BTreeMap::<&str, (usize, usize, usize, usize)>::new(),
+        |mut acc, d| {
+            let entry = acc.entry(&d.month).or_default();
+            entry.0 += d.added;
+            entry.1 += d.removed;
+            entry.2 += d.changed;
+            entry.3 += d.unchanged;
+            acc
+        },
+    );
+    for (month, (added, removed, changed, unchanged)) in by_month {
+        log::info!(
+            "month={month} added={added} removed={removed} changed={changed} unchanged={unchanged}"
+        );
+    }
+
+    let changed = diff.into_iter().filter(|d| d.is_changed());
+    std::io::stdout().write_all(&flights::csv::serialize(changed))?;
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use std::error::Error;
+
+use clap::Parser;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Builds the dataset of anonymized downloads of the public datasets according to `M-downloads`,
+from the server access logs of the remote storage (when logging is enabled on it)."#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    /// The token to the remote storage
+    #[arg(long)]
+    access_key: String,
+    /// The token to the remote storage
+    #[arg(long)]
+    secret_access_key: String,
+    /// The prefix the server access logs are written to
+    #[arg(long, default_value = "logs/")]
+    logs_prefix: String,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs_s3::client(cli.access_key, cli.secret_access_key).await;
+
+    flights::access_log::etl_access_logs(&client, &cli.logs_prefix).await
+}
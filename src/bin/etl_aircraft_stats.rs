@@ -0,0 +1,56 @@
+use std::error::Error;
+
+use clap::Parser;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Builds the dataset of yearly statistics of each aircraft according to `M-aircraft-year`
+(number of legs, hours, distance, emissions, airports, short legs, and countries of registration and operation),
+of each country according to `M-country-year` and of each model according to `M-model-year`, and the quarterly
+statistics of each aircraft according to `M-aircraft-quarter`, from the public dataset of legs."#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// The first year to compute
+    #[arg(long, default_value_t = 2019)]
+    from: i32,
+    /// The last year to compute (inclusive)
+    #[arg(long, default_value_t = 2024)]
+    to: i32,
+    /// Countries with fewer aircrafts than this on a basis are not published (see `M-suppression`)
+    #[arg(long, default_value_t = 0)]
+    min_aircrafts: usize,
+    /// Countries with fewer legs than this on a basis are not published (see `M-suppression`)
+    #[arg(long, default_value_t = 0)]
+    min_legs: usize,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs::client_from_args(&cli.storage).await?;
+
+    let seats = flights::model::load_model_seats()?;
+    let changelog = flights::model::load_model_changelog()?;
+    let suppression = flights::stats::Suppression {
+        min_aircrafts: cli.min_aircrafts,
+        min_legs: cli.min_legs,
+    };
+    flights::stats::etl_aircraft_stats(
+        cli.from..=cli.to,
+        &seats,
+        &changelog,
+        suppression,
+        client.as_ref(),
+    )
+    .await?;
+    Ok(())
+}
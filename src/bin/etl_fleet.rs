@@ -0,0 +1,41 @@
+use std::error::Error;
+
+use clap::Parser;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Builds the dataset of the fleet of private jets according to `M-fleet`:
+when each aircraft was first and last observed, and its age,
+the aircrafts that were retired according to `M-retired`,
+and the map of ICAO numbers of the same airframe according to `M-airframes`."#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// Adds links to external references of each aircraft (registry record, photos and ADS-B history),
+    /// see `M-fleet-links`
+    #[arg(long, default_value_t = false)]
+    with_links: bool,
+    /// The minimum number of months without positions of an aircraft absent from the most recent snapshot of
+    /// aircrafts for it to be retired, see `M-retired`
+    #[arg(long, default_value_t = 12)]
+    retired_after_months: u32,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs::client_from_args(&cli.storage).await?;
+
+    flights::fleet::etl_fleet(client.as_ref(), cli.with_links).await?;
+    flights::retired::etl_retired(client.as_ref(), cli.retired_after_months).await?;
+    flights::airframes::etl_airframes(client.as_ref()).await?;
+    Ok(())
+}
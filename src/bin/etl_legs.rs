@@ -1,155 +1,78 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashSet,
     error::Error,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use clap::Parser;
-use futures::{StreamExt, TryStreamExt};
-use serde::Serialize;
+use futures::StreamExt;
 use simple_logger::SimpleLogger;
 
-use flights::{aircraft::Aircraft, fs::BlobStorageProvider, model::AircraftModel, Position};
-
-static DATABASE_ROOT: &'static str = "leg/v2/";
-static DATABASE: &'static str = "leg/v2/data/";
-
-#[derive(serde::Serialize, serde::Deserialize)]
-struct LegOut {
-    /// The ICAO number
-    icao_number: Arc<str>,
-    /// The tail number
-    tail_number: Arc<str>,
-    /// The aircraft model
-    aircraft_model: Arc<str>,
-    /// The start timestamp
-    #[serde(with = "time::serde::rfc3339")]
-    start: time::OffsetDateTime,
-    /// The start latitude
-    start_lat: f64,
-    /// The start longitude
-    start_lon: f64,
-    /// The start altitude in feet
-    start_altitude: f64,
-    /// The end timestamp
-    #[serde(with = "time::serde::rfc3339")]
-    end: time::OffsetDateTime,
-    /// The end latitude
-    end_lat: f64,
-    /// The end longitude
-    end_lon: f64,
-    /// The end altitude in feet
-    end_altitude: f64,
-    /// The duration of the flight in hours
-    duration: f64,
-    /// The total two-dimensional flown distance of the leg in km
-    distance: f64,
-    /// The great-circle distance of the leg in km
-    great_circle_distance: f64,
-    /// The time above 30.000 feet
-    hours_above_30000: f64,
-    /// The time above 40.000 feet
-    hours_above_40000: f64,
-    /// CO2 emissions in kg
-    co2_emissions: f64,
-}
+use flights::{
+    checkpoint::{Progress, State},
+    compression::Compression,
+    descriptor::Processing,
+    emissions::EmissionsConfig,
+    enrich::{Enricher, Enrichers},
+    etl::legs::{AggregateConfig, Context, Roots, TaskError, TimingReport, Warnings},
+    exclusion::Exclusions,
+    format::Format,
+    fs::BlobStorageProvider,
+    fs_s3::RetryPolicy,
+    legs::LegsConfig,
+    lock::Lock,
+    model::Category,
+    overlays::Overlays,
+    region::Region,
+    runs::{Metered, Outcome, Run},
+    timezone::Calendar,
+    units::Units,
+    wind::Winds,
+};
 
-#[derive(serde::Serialize)]
-struct Metadata {
-    icao_months_to_process: usize,
-    icao_months_processed: usize,
-    url: String,
+/// The minimum time between writes of the progress of a run
+static CHECKPOINT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+/// The time after which the lock of a run without heartbeats can be taken over by another run
+static LOCK_TTL: time::Duration = time::Duration::minutes(10);
+/// The time between heartbeats of the lock of a run
+static LOCK_HEARTBEAT: std::time::Duration = std::time::Duration::from_secs(60);
+/// Set when the process is asked to stop (SIGINT or SIGTERM); no task is started once set
+static STOPPING: AtomicBool = AtomicBool::new(false);
+/// The published datasets of `roots` in `calendar` compared by `--replay`
+fn published(roots: &Roots, calendar: Calendar) -> Vec<String> {
+    let legs = roots.aggregated_in(&roots.legs, calendar);
+    let activity = roots.aggregated(&roots.activity);
+    vec![
+        format!("{legs}all/"),
+        format!("{legs}by_country/"),
+        format!("{}unmatched_icaos.csv", roots.legs),
+        format!("{}all/", roots.aggregated_in(&roots.ground_times, calendar)),
+        format!("{activity}all/"),
+        format!("{activity}reactivations.csv"),
+    ]
 }
 
-async fn write_json(
-    client: &dyn BlobStorageProvider,
-    d: impl Serialize,
-    key: &str,
-) -> Result<(), Box<dyn Error>> {
-    let mut bytes: Vec<u8> = Vec::new();
-    serde_json::to_writer(&mut bytes, &d).map_err(std::io::Error::other)?;
-
-    Ok(client.put(key, bytes).await?)
+fn lock_blob_name(roots: &Roots) -> String {
+    format!("{}lock.json", roots.legs)
 }
 
-async fn write_csv(
-    items: impl Iterator<Item = impl Serialize>,
-    key: &str,
-    client: &dyn BlobStorageProvider,
-) -> Result<(), std::io::Error> {
-    let data_csv = flights::csv::serialize(items);
-    client.put(&key, data_csv).await?;
-    Ok(())
+fn run_pk_to_blob_name(roots: &Roots, run_id: &str) -> String {
+    format!("{}run/{run_id}.json", roots.legs)
 }
 
-fn transform<'a>(
-    icao_number: &'a Arc<str>,
-    aircraft: &'a Aircraft,
-    model: &'a AircraftModel,
-    positions: Vec<Position>,
-) -> impl Iterator<Item = LegOut> + 'a {
-    flights::legs::legs(positions.into_iter()).map(|leg| LegOut {
-        icao_number: icao_number.clone(),
-        tail_number: aircraft.tail_number.clone().into(),
-        aircraft_model: aircraft.model.clone().into(),
-        start: leg.from().datetime(),
-        start_lat: leg.from().latitude(),
-        start_lon: leg.from().longitude(),
-        start_altitude: leg.from().altitude(),
-        end: leg.to().datetime(),
-        end_lat: leg.to().latitude(),
-        end_lon: leg.to().longitude(),
-        end_altitude: leg.to().altitude(),
-        duration: leg.duration().as_seconds_f64() / 60.0 / 60.0,
-        distance: leg.distance(),
-        great_circle_distance: leg.great_circle_distance(),
-        hours_above_30000: leg
-            .positions()
-            .windows(2)
-            .filter_map(|w| {
-                (w[0].altitude() > 30000.0 && w[1].altitude() > 30000.0).then(|| {
-                    (w[1].datetime() - w[0].datetime()).whole_seconds() as f64 / 60.0 / 60.0
-                })
-            })
-            .sum::<f64>(),
-        hours_above_40000: leg
-            .positions()
-            .windows(2)
-            .filter_map(|w| {
-                (w[0].altitude() > 40000.0 && w[1].altitude() > 40000.0).then(|| {
-                    (w[1].datetime() - w[0].datetime()).whole_seconds() as f64 / 60.0 / 60.0
-                })
-            })
-            .sum::<f64>(),
-        co2_emissions: flights::emissions::leg_co2_kg(model.gph.into(), leg.duration()),
-    })
+fn runs_pk_to_blob_name(roots: &Roots, run: &Run) -> String {
+    format!("{}runs/{}.jsonl", roots.legs, run.started.unix_timestamp())
 }
 
-async fn write(
-    icao: &Arc<str>,
-    month: time::Date,
-    legs: impl Iterator<Item = impl Serialize>,
-    client: &dyn BlobStorageProvider,
-) -> Result<(), Box<dyn Error>> {
-    let key = pk_to_blob_name(icao, month);
-
-    write_csv(legs, &key, client).await?;
-    log::info!("Written {} {}", icao, month);
-    Ok(())
+fn slowest_pk_to_blob_name(roots: &Roots, run_id: &str) -> String {
+    format!("{}run/{run_id}/slowest.csv", roots.legs)
 }
 
-async fn read_u8(
-    icao: &Arc<str>,
-    month: time::Date,
-    client: &dyn BlobStorageProvider,
-) -> Result<Option<Vec<u8>>, std::io::Error> {
-    log::info!("Read icao={icao} month={month}");
-    client.maybe_get(&pk_to_blob_name(icao, month)).await
-}
-
-fn pk_to_blob_name(icao: &str, month: time::Date) -> String {
-    let month = flights::serde::month_to_part(month);
-    format!("{DATABASE}month={month}/icao_number={icao}/data.csv")
+fn errors_pk_to_blob_name(roots: &Roots, run_id: &str) -> String {
+    format!("{}errors/date={run_id}/errors.csv", roots.legs)
 }
 
 const ABOUT: &'static str = "Builds the database of all legs";
@@ -157,88 +80,275 @@ const ABOUT: &'static str = "Builds the database of all legs";
 #[derive(Parser, Debug)]
 #[command(author, version, about = ABOUT)]
 struct Cli {
-    /// The token to the remote storage
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// The minimum average ground speed of a leg in km/h (see `M-identify-legs`)
+    #[arg(long, default_value_t = 0.0)]
+    min_ground_speed: f64,
+    /// The maximum time in minutes between two positions close to the ground for the aircraft to be flying between them
+    #[arg(long, default_value_t = 5.0)]
+    max_time_gap: f64,
+    /// The minimum duration of a leg in minutes
+    #[arg(long, default_value_t = 5.0)]
+    min_duration: f64,
+    /// The minimum great-circle distance of a leg in km
+    #[arg(long, default_value_t = 3.0)]
+    min_distance: f64,
+    /// The height above the ground in feet below which a position is close to the ground
+    #[arg(long, default_value_t = 10000.0)]
+    landing_altitude_threshold: f64,
+    /// The ground speed in km/h above which an aircraft on the ground is on its takeoff roll or landing rollout (see `M-taxi`)
+    #[arg(long, default_value_t = 60.0)]
+    max_taxi_speed: f64,
+    /// The ground speed in km/h below which an aircraft on the ground is stationary
+    #[arg(long, default_value_t = 5.0)]
+    stationary_speed: f64,
+    /// The minimum time in minutes stationary on the ground for an aircraft to be parked
+    #[arg(long, default_value_t = 10.0)]
+    min_parked: f64,
+    /// The maximum number of retries of a request to the remote storage failing with a transient error
+    #[arg(long, default_value_t = 5)]
+    max_retries: u32,
+    /// Optional filter of the registration of the aircrafts to fetch (see `M-registry-filters`): comma-separated
+    /// countries of registration (e.g. `Denmark`), regions of owners in ISO 3166 (e.g. `GL` or `DK-81`) and prefixes
+    /// of tail numbers (e.g. `OY-H*`), each optionally prefixed by `!` to exclude them; defaults to whole world
+    #[arg(long)]
+    country: Option<flights::registry::filter::Filter>,
+    /// Optional comma-separated ICAO numbers to process (e.g. `45d2ed,459cd3`); defaults to all private jets.
+    /// Their aircrafts are looked up on demand instead of reading every snapshot of aircrafts, and their
+    /// aggregated datasets are written to `leg/v2/subset={icao_numbers}/` (ICAO numbers separated by `-`)
+    #[arg(
+        long,
+        alias = "icao",
+        value_delimiter = ',',
+        conflicts_with = "country"
+    )]
+    icao_numbers: Vec<String>,
+    /// Comma-separated categories of aircraft models to process: `jets`, `helicopters` and `turboprops`
+    /// (see `M-categories`). Replays process the models of the snapshot
+    #[arg(long, value_delimiter = ',', default_value = "jets")]
+    categories: Vec<Category>,
+    /// The first month to process (`YYYY-MM`)
+    #[arg(long, default_value = "2019-01", value_parser = flights::serde::try_parse_month)]
+    from: time::Date,
+    /// The last month to process (`YYYY-MM`)
+    #[arg(long, default_value = "2024-12", value_parser = flights::serde::try_parse_month)]
+    to: time::Date,
+    /// Optional comma-separated months to process (e.g. `2023-01,2023-05`) instead of `--from` to `--to`.
+    /// The yearly datasets of the years of the processed months are aggregated with all their months
+    #[arg(long, value_delimiter = ',', value_parser = flights::serde::try_parse_month, conflicts_with_all = ["from", "to"])]
+    months: Vec<time::Date>,
+    /// Optional message broker to publish every written leg to, as
+    /// `nats://host:port/subject`, `kafka://host:port/topic` or a webhook `https://host/path`
+    #[arg(long)]
+    events: Option<String>,
+    /// Optional bounding box `min_lon,min_lat,max_lon,max_lat`; only legs touching it are written, to
+    /// `leg/v2/region={bbox}/` instead of the datasets of all legs
+    #[arg(long, conflicts_with = "region")]
+    bbox: Option<String>,
+    /// Optional GeoJSON file with a (multi)polygon; only legs touching it are written, to
+    /// `leg/v2/region={file name without extension}/` instead of the datasets of all legs
+    #[arg(long)]
+    region: Option<std::path::PathBuf>,
+    /// Optional GeoJSON file of exclusion zones and aircrafts of emergency aviation (e.g. air ambulances);
+    /// legs matching them are tagged with `excluded_reason` (see `M-exclusions`)
+    #[arg(long)]
+    exclusions: Option<std::path::PathBuf>,
+    /// Whether legs matching `--exclusions` are not written instead of tagged
+    #[arg(long, requires = "exclusions")]
+    drop_excluded: bool,
+    /// Optional GeoJSON file of overlays (e.g. Natura 2000 sites) with which legs are annotated by the enricher
+    /// `overlays` (see `M-overlays`)
+    #[arg(long)]
+    overlays: Option<std::path::PathBuf>,
+    /// The property of the features of `--overlays` with their name
+    #[arg(long, default_value = "name", requires = "overlays")]
+    overlay_name_property: String,
+    /// Whether to enrich legs with winds aloft from ERA5 subsets stored at `wind/era5/month={month}/data.csv`
+    #[arg(long)]
+    with_winds: bool,
+    /// The resolution in degrees of the grid of the ERA5 subsets
+    #[arg(long, default_value_t = 1.0)]
+    winds_resolution: f64,
+    /// Whether to correct barometric altitudes below the transition altitude with the QNH of METAR observations
+    /// stored at `metar/qnh/month={month}/data.csv` (see `M-altitudes`)
+    #[arg(long)]
+    with_qnh: bool,
+    /// The stages adding columns to legs, in order: `airports` (`M-leg-airports`), `countries` (`M-leg-countries`),
+    /// `owners` (`M-owners`), `timezones` (`M-local-times`) and `overlays` (`M-overlays`, requires `--overlays`).
+    /// Columns of stages not selected are empty
+    #[arg(long, value_delimiter = ',', default_value = "airports,countries")]
+    enrichers: Vec<String>,
+    /// Whether to write the altitude profile of every leg (for thumbnails) to `leg/v2/profile/`
+    #[arg(long)]
+    with_profiles: bool,
+    /// Optional JSON file with the density and CO2 emission factor of each fuel; defaults to `M-co2-emissions`
     #[arg(long)]
-    access_key: String,
-    /// The token to the remote storage
+    emissions_config: Option<std::path::PathBuf>,
+    /// Whether to compute the emissions of a business class passenger flying each leg on a commercial flight
+    /// (`commercial_co2_emissions`, see `M-co2-emissions`) with the backend `commercial_backend` of `--emissions-config`
     #[arg(long)]
-    secret_access_key: String,
-    /// Optional country to fetch from (in ISO 3166); defaults to whole world
+    with_emissions: bool,
+    /// Optional CSV file with the columns `from_airport,to_airport,co2_per_km`, required by the commercial backend `route-table`
     #[arg(long)]
-    country: Option<String>,
+    commercial_routes: Option<std::path::PathBuf>,
+    /// The units of distances and CO2 emissions of the yearly datasets: `metric` (km and kg) or `aviation` (nm and lb).
+    /// Datasets in units other than `metric` are written to `all/units={distance}-{mass}/`
+    #[arg(long, default_value = "metric")]
+    units: Units,
+    /// The calendar in which legs are assigned to years in the yearly datasets: `utc`, an IANA time zone
+    /// (e.g. `America/New_York`) or `local` (the local time at departure, requires the enricher `timezones`).
+    /// Datasets in calendars other than `utc` are written to `timezone={name}/` (see `M-local-months`)
+    #[arg(long, default_value = "utc")]
+    timezone: Calendar,
+    /// The minimum number of whole months without flights for an aircraft resuming flights to be a reactivation
+    #[arg(long, default_value_t = 6)]
+    reactivation_months: i32,
+    /// Optional webhook (`https://host/path`) or message broker (as in `--events`) to publish reactivations to;
+    /// only reactivations not in `reactivations.csv` of previous runs are published
+    #[arg(long)]
+    notify: Option<String>,
+    /// The file format of the monthly and yearly datasets of legs: `csv` or `parquet` (requires feature `parquet`).
+    /// Datasets are written to `data.{format}`
+    #[arg(long, default_value = "csv")]
+    format: Format,
+    /// The compression of the monthly and yearly datasets of legs: `none`, `gzip` or `zstd` (see `M-compression`).
+    /// Compressed datasets are written to `data.{format}.gz` or `data.{format}.zst`
+    #[arg(long, default_value = "none")]
+    compression: Compression,
+    /// Optional directory of a snapshot to replay instead of reading from `--backend` (see `M-replay`).
+    /// Nothing is written; the run fails if the datasets it computes differ from those in the snapshot
+    #[arg(long, conflicts_with_all = ["events", "notify"])]
+    replay: Option<std::path::PathBuf>,
+    /// Whether to deserialize the positions of each month on a thread pool of all cores (requires feature `parallel`)
+    #[arg(long)]
+    parallel_decode: bool,
+    /// The maximum number of months of aircrafts processed concurrently
+    #[arg(long, default_value_t = 400)]
+    concurrency: usize,
+    /// The maximum number of monthly datasets read concurrently when aggregating
+    #[arg(long, default_value_t = 1000)]
+    aggregate_concurrency: usize,
+    /// The maximum number of years aggregated concurrently; each holds the legs of its year in memory.
+    /// A year that fails to aggregate does not stop the others
+    #[arg(long, default_value_t = 2)]
+    year_concurrency: usize,
+    /// Optional directory of the local disk where the positions read are cached (see `M-positions-cache`),
+    /// so that repeated runs (e.g. with other thresholds) do not download them again
+    #[arg(long)]
+    cache_dir: Option<std::path::PathBuf>,
+    /// The maximum size in GB of the cache of positions; the least recently used positions are removed above it
+    #[arg(long, default_value_t = 100)]
+    cache_size: u64,
+    /// The maximum number of positions not in the cache downloaded concurrently
+    #[arg(long, default_value_t = 50)]
+    cache_downloads: usize,
+    /// The maximum time in seconds to process a month of an aircraft; tasks taking longer are
+    /// cancelled and requeued after all other tasks
+    #[arg(long, default_value_t = 600)]
+    task_timeout: u64,
+    /// The maximum number of times a timed out (or rate limited) task is requeued
+    #[arg(long, default_value_t = 2)]
+    max_requeues: u32,
+    /// Optional maximum number of months of aircrafts started by the run (requeued ones included); once reached,
+    /// no task is started and the run stops as interrupted after the tasks in flight (see `M-quotas`)
+    #[arg(long)]
+    max_partitions: Option<usize>,
+    /// Optional maximum number of bytes read from the storage by the run (positions read from the cache excluded);
+    /// once reached, no task is started and the run stops as interrupted after the tasks in flight (see `M-quotas`)
+    #[arg(long)]
+    max_bytes: Option<usize>,
+    /// The number of slowest months of aircrafts of the run written to `leg/v2/run/{run_id}/slowest.csv`
+    /// (see `M-manifests`)
+    #[arg(long, default_value_t = 100)]
+    slowest: usize,
+    /// The version of the datasets of legs to write (see `M-versions`); versions other than `v2` are written
+    /// to `leg/{version}/` without overwriting the published datasets, and are published with `etl_validate --promote`
+    #[arg(long, default_value = "v2")]
+    dataset_version: String,
+    /// Whether to rebuild the index of existing positions (`index/position/keys.txt`), from which the positions
+    /// of ICAO numbers not in the database of aircrafts are listed, from a full listing of the storage
+    #[arg(long)]
+    refresh_index: bool,
+    /// Optional identifier of a stopped run to resume; tasks it completed (`leg/v2/run/{run_id}.json`) are skipped
+    #[arg(long)]
+    resume: Option<String>,
+    /// Whether to fail the run, before aggregating, when there are soft warnings (failed or timed out months of aircrafts,
+    /// ICAO numbers not in the database of aircrafts and legs without airports), see `M-strict`
+    #[arg(long)]
+    strict: bool,
+    /// The maximum ratio of failed (or timed out) months of aircrafts over those executed; runs above it fail
+    /// without aggregating the datasets. Failed months are written to `leg/v2/errors/date={run_id}/errors.csv`
+    #[arg(long, default_value_t = 0.05)]
+    max_failure_ratio: f64,
+    /// Whether to also aggregate the weeks of the months of the run into `leg/v2/weekly/data.csv` (see `M-weekly`),
+    /// e.g. to report the current month before it is closed with a frequent run of `--months {current month}`
+    #[arg(long)]
+    weekly: bool,
+    /// Airports of interest (`{ident}:{radius in km}`, e.g. `EKRK:5,EKCH:5`) whose arrivals and departures on the
+    /// months of the run are written to `leg/v2/airport_watch/month={month}/data.csv` (see `M-airport-watch`)
+    #[arg(long, value_delimiter = ',')]
+    airport_watch: Vec<flights::airport_watch::Watch>,
+    /// Whether to take over the lock (`leg/v2/lock.json`) held by another run whose heartbeat is recent.
+    /// Only use it when that run is known to have stopped
+    #[arg(long)]
+    steal_lock: bool,
 }
 
-async fn etl_task(
-    aircraft: &Aircraft,
-    model: &AircraftModel,
-    month: time::Date,
-    client: &dyn BlobStorageProvider,
-) -> Result<(), Box<dyn Error>> {
-    let icao_number = &aircraft.icao_number;
-    // extract
-    let positions =
-        flights::icao_to_trace::get_month_positions(&icao_number, month, client).await?;
-    // transform
-    let legs = transform(&icao_number, aircraft, model, positions);
-    // load
-    write(&icao_number, month, legs, client).await
+/// Confirms `lock` every [`LOCK_HEARTBEAT`] and stops the run when the lock is lost
+async fn heartbeat(lock: Lock, client: Arc<dyn BlobStorageProvider + Send + Sync>) {
+    loop {
+        tokio::time::sleep(LOCK_HEARTBEAT).await;
+        if let Err(e) = lock.heartbeat(client.as_ref()).await {
+            log::error!("lock lost ({e}); stopping: finishing in-flight tasks...");
+            STOPPING.store(true, Ordering::Relaxed);
+            return;
+        }
+    }
 }
 
-async fn aggregate(
-    required: impl Iterator<Item = (Arc<str>, time::Date)>,
-    client: &dyn BlobStorageProvider,
-) -> Result<(), Box<dyn Error>> {
-    // group by year
-    let required_by_year = required.fold(HashMap::<i32, HashSet<_>>::new(), |mut acc, v| {
-        acc.entry(v.1.year())
-            .and_modify(|entries| {
-                entries.insert(v.clone());
-            })
-            .or_insert(HashSet::from([v]));
-        acc
-    });
-
-    // run tasks by year
-    let mut metadata = HashMap::<i32, Metadata>::new();
-    for (year, completed) in required_by_year {
-        let tasks = completed
-            .iter()
-            .map(|(icao_number, date)| async move { read_u8(icao_number, *date, client).await });
-
-        log::info!("Gettings all legs for year={year}");
-        let legs = futures::stream::iter(tasks)
-            .buffered(1000)
-            .try_collect::<Vec<_>>()
-            .await?
-            .into_iter()
-            .flatten() // drop those that do not exist
-            .map(|content| {
-                flights::csv::deserialize::<LegOut>(&content)
-                    .collect::<Result<Vec<_>, _>>()
-                    .unwrap()
-            })
-            .flatten();
-
-        log::info!("Writing all legs for year={year}");
-        let key = format!("{DATABASE_ROOT}all/year={year}/data.csv");
-        write_csv(legs, &key, client).await?;
-        log::info!("Written {key}");
-        metadata.insert(
-            year,
-            Metadata {
-                icao_months_to_process: completed.len(),
-                icao_months_processed: completed.len(),
-                url: format!("https://private-jets.fra1.digitaloceanspaces.com/{key}"),
-            },
-        );
+/// Returns why a run that started `started` months of aircrafts and read `bytes_read` bytes reached the quotas
+/// `max_partitions` and `max_bytes` (see `M-quotas`), if it did
+fn reached_quota(
+    (max_partitions, max_bytes): (Option<usize>, Option<usize>),
+    started: usize,
+    bytes_read: usize,
+) -> Option<String> {
+    if let Some(max) = max_partitions.filter(|max| started >= *max) {
+        return Some(format!(
+            "started {started} months of aircrafts (--max-partitions {max})"
+        ));
+    }
+    if let Some(max) = max_bytes.filter(|max| bytes_read >= *max) {
+        return Some(format!("read {bytes_read} bytes (--max-bytes {max})"));
     }
+    None
+}
 
-    let key = format!("{DATABASE_ROOT}status.json");
-    write_json(client, metadata, &key).await?;
-    log::info!("status written");
+/// Writes the record of `run` with `outcome` and the bytes read and written through `metered` to `key` (see `M-runs`)
+async fn write_run<C>(
+    run: &mut Run,
+    outcome: Outcome,
+    metered: &Metered<C>,
+    key: &str,
+    client: &dyn BlobStorageProvider,
+) -> Result<(), std::io::Error> {
+    run.bytes_read = metered.bytes_read();
+    run.bytes_written = metered.bytes_written();
+    run.write(outcome, key, client).await?;
+    log::info!("Written {key}");
     Ok(())
 }
 
+/// Resolves when the process receives SIGINT (ctrl+c) or SIGTERM
+async fn shutdown_signal() -> std::io::Result<()> {
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<(), Box<dyn Error>> {
     SimpleLogger::new()
@@ -247,34 +357,588 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .unwrap();
 
     let cli = Cli::parse();
+    #[cfg(not(feature = "parallel"))]
+    if cli.parallel_decode {
+        return Err("--parallel-decode requires compiling with feature `parallel`".into());
+    }
+    if cli.timezone == Calendar::Departure && !cli.enrichers.iter().any(|x| x == "timezones") {
+        return Err("--timezone local requires the enricher `timezones`".into());
+    }
+    let mut icao_numbers = cli
+        .icao_numbers
+        .iter()
+        .map(|x| x.to_ascii_lowercase())
+        .collect::<Vec<_>>();
+    icao_numbers.sort_unstable();
+    icao_numbers.dedup();
+    // the legs of a region must not overwrite those of the whole world
+    let roots = match (&cli.bbox, &cli.region) {
+        (Some(bbox), _) => Roots::new(&cli.dataset_version).with_region(bbox),
+        (None, Some(path)) => {
+            let name = path.file_stem().and_then(|x| x.to_str());
+            let name = name.ok_or("--region must be a path to a file")?;
+            Roots::new(&cli.dataset_version).with_region(name)
+        }
+        (None, None) => Roots::new(&cli.dataset_version),
+    };
+    // the aggregates of a subset of aircrafts must not overwrite those of all aircrafts
+    let roots = &match icao_numbers.is_empty() {
+        true => roots,
+        false => roots.with_subset(&icao_numbers.join("-")),
+    };
+
+    // a replay reads from the snapshot and keeps writes in memory
+    let replay = cli.replay.as_ref().map(flights::replay::Snapshot::new);
+    let (backend, retries) = match &replay {
+        Some(_) => (None, None),
+        None => {
+            let retry = RetryPolicy {
+                max_retries: cli.max_retries,
+                ..Default::default()
+            };
+            let (client, retries) =
+                flights::fs::client_from_args_with_retry(&cli.storage, retry, false).await?;
+            (Some(client), retries)
+        }
+    };
+    let client: &(dyn BlobStorageProvider + Sync) = match (&replay, &backend) {
+        (Some(snapshot), _) => snapshot,
+        (None, backend) => backend.as_deref().expect("a backend exists without replay"),
+    };
+    let metered = Metered::new(client);
+    let cached = match &cli.cache_dir {
+        Some(dir) => Some(flights::fs_cache::CachedClient::new(
+            &metered,
+            dir,
+            "position/",
+            cli.cache_size * 1_000_000_000,
+            cli.cache_downloads,
+        )?),
+        None => None,
+    };
+    let client: &(dyn BlobStorageProvider + Sync) = match &cached {
+        Some(cached) => cached,
+        None => &metered,
+    };
+
+    // the model table is pinned to the snapshot on replays
+    let (models, model_overrides) = match &replay {
+        Some(snapshot) => (snapshot.models().await?, snapshot.model_overrides().await?),
+        None => (
+            flights::model::load_models(&cli.categories)?,
+            flights::model::load_model_overrides()?,
+        ),
+    };
+
+    let model_changelog = flights::model::load_model_changelog()?;
+
+    let mut progress = match cli.resume {
+        Some(run_id) => Progress::read(&run_pk_to_blob_name(roots, &run_id), client)
+            .await?
+            .ok_or_else(|| format!("run {run_id} does not exist"))?,
+        None => Progress::new(time::OffsetDateTime::now_utc().unix_timestamp().to_string()),
+    };
+    let progress_key = run_pk_to_blob_name(roots, &progress.run_id);
+    let mut run = Run::new(progress.run_id.clone());
+    let run_key = runs_pk_to_blob_name(roots, &run);
+
+    // a replay does not write to the backend, so it does not need to lock it
+    let lock = match &backend {
+        Some(backend) => {
+            let holder = format!("run {} (pid {})", progress.run_id, std::process::id());
+            let lock = Lock::acquire(
+                &lock_blob_name(roots),
+                holder,
+                LOCK_TTL,
+                cli.steal_lock,
+                backend.as_ref(),
+            )
+            .await?;
+            let heartbeat = tokio::spawn(heartbeat(lock.clone(), backend.clone()));
+            Some((lock, heartbeat))
+        }
+        None => None,
+    };
+    log::info!(
+        "run {}: {} tasks already completed",
+        progress.run_id,
+        progress.completed_tasks()
+    );
+
+    // the lock is released on every exit of the run, also when it fails; the run returns whether it finished
+    // (`false` when it was stopped)
+    let finished = async {
+        let events = match cli.events.as_deref() {
+            Some(url) => Some(flights::events::client(url).await?),
+            None => None,
+        };
+        let events = events.as_deref();
+
+        let notify = match cli.notify.as_deref() {
+            Some(url) => Some(flights::events::client(url).await?),
+            None => None,
+        };
+        let notify = notify.as_deref();
 
-    let client = flights::fs_s3::client(cli.access_key, cli.secret_access_key).await;
-    let client = &client;
+        let region = match (cli.bbox, cli.region) {
+            (Some(bbox), _) => Some(Region::from_bbox(&bbox)?),
+            (None, Some(path)) => Some(Region::from_geojson(&std::fs::read(path)?)?),
+            (None, None) => None,
+        };
+        let region = region.as_ref();
 
-    log::info!("computing required tasks...");
-    let required =
-        flights::private_jets_in_month((2019..2025).rev(), cli.country.as_deref(), client).await?;
-    log::info!("required : {}", required.len());
+        let exclusions = match cli.exclusions {
+            Some(path) => Some(Exclusions::from_geojson(&std::fs::read(path)?)?),
+            None => None,
+        };
+        let exclusions = exclusions.as_ref();
 
-    log::info!("executing required...");
-    let tasks = required
-        .clone()
-        .into_iter()
-        .map(|((_, month), (aircraft, model))| async move {
-            etl_task(&aircraft, &model, month, client).await
+        log::info!("loading airports...");
+        let airports = &flights::airports::airports(client).await?;
+
+        let countries = match cli.enrichers.iter().any(|name| name == "countries") {
+            true => {
+                log::info!("loading countries...");
+                Some(flights::geo::countries(client).await?)
+            }
+            false => None,
+        };
+        let owners = match cli.enrichers.iter().any(|name| name == "owners") {
+            true => {
+                log::info!("loading owners...");
+                Some(flights::owners::owners(client).await?)
+            }
+            false => None,
+        };
+        let time_zones = match cli.enrichers.iter().any(|name| name == "timezones") {
+            true => {
+                log::info!("loading time zones...");
+                Some(flights::timezone::time_zones(client).await?)
+            }
+            false => None,
+        };
+        let overlays = match &cli.overlays {
+            Some(path) => {
+                log::info!("loading overlays...");
+                let overlays =
+                    Overlays::from_geojson(&std::fs::read(path)?, &cli.overlay_name_property)?;
+                log::info!("{} overlays", overlays.len());
+                Some(overlays)
+            }
+            None => None,
+        };
+        let mut available = vec![airports as &dyn Enricher];
+        available.extend(
+            countries
+                .as_ref()
+                .map(|countries| countries as &dyn Enricher),
+        );
+        available.extend(owners.as_ref().map(|owners| owners as &dyn Enricher));
+        available.extend(time_zones.as_ref().map(|zones| zones as &dyn Enricher));
+        available.extend(overlays.as_ref().map(|overlays| overlays as &dyn Enricher));
+        let enrichers = &Enrichers::select(&available, &cli.enrichers)?;
+        log::info!("enrichers: {:?}", enrichers.names().collect::<Vec<_>>());
+
+        let winds = cli.with_winds.then(|| Winds::new(cli.winds_resolution));
+        let winds = winds.as_ref();
+        let qnhs = cli.with_qnh.then(flights::altitude::Qnhs::default);
+        let qnhs = qnhs.as_ref();
+
+        let emissions = &match cli.emissions_config {
+            Some(path) => EmissionsConfig::from_json(&std::fs::read(path)?)?,
+            None => EmissionsConfig::default(),
+        };
+        let model_phases = &flights::model::load_model_phases()?;
+        let commercial = if cli.with_emissions {
+            let routes = cli
+                .commercial_routes
+                .as_ref()
+                .map(std::fs::read)
+                .transpose()?;
+            Some(flights::commercial::backend(emissions, routes.as_deref())?)
+        } else {
+            None
+        };
+        let commercial = commercial.as_deref();
+
+        let selected = match cli.months.is_empty() {
+            true => flights::months_between(cli.from, cli.to).collect::<HashSet<_>>(),
+            false => cli.months.iter().copied().collect(),
+        };
+        if selected.is_empty() {
+            return Err(format!("--from {} must not be after --to {}", cli.from, cli.to).into());
+        }
+        // all months of the years of the selected months are required, so that their yearly datasets are complete
+        let years = selected
+            .iter()
+            .map(|month| month.year())
+            .collect::<HashSet<_>>();
+        let months = || flights::months_of_years(years.iter().copied());
+        log::info!("computing required tasks...");
+        let (required, swaps) = if cli.icao_numbers.is_empty() {
+            flights::private_jets_in_month_with_models(months(), cli.country.as_ref(), &models, client)
+                .await?
+        } else {
+            let icao_numbers = icao_numbers
+                .iter()
+                .map(|x| x.as_str().into())
+                .collect::<HashSet<_>>();
+            flights::private_jets_in_month_of(&icao_numbers, months(), &models, client).await?
+        };
+        log::info!("required : {}", required.len());
+        log::info!("months with a swap of aircraft: {}", swaps.len());
+
+        let unmatched = match (&cli.country, cli.icao_numbers.is_empty()) {
+            (None, true) => {
+                flights::etl::legs::unmatched(&required, &years, cli.refresh_index, roots, client)
+                    .await?
+            }
+            _ => {
+                log::warn!(
+                    "unmatched icao numbers are only computed without --country and --icao-numbers"
+                );
+                vec![]
+            }
+        };
+        log::info!("unmatched: {}", unmatched.len());
+
+        tokio::spawn(async {
+            match shutdown_signal().await {
+                Ok(()) => {
+                    log::warn!("stopping: finishing in-flight tasks...");
+                    STOPPING.store(true, Ordering::Relaxed);
+                }
+                Err(e) => log::error!("cannot listen to shutdown signals: {e}"),
+            }
         });
 
-    let _ = futures::stream::iter(tasks)
-        .buffered(400)
-        .map(|r| {
-            if let Err(e) = r {
-                log::error!("{e}");
+        let context = &Context {
+            client,
+            roots,
+            events,
+            region,
+            exclusions,
+            drop_excluded: cli.drop_excluded,
+            swaps: Some(&swaps),
+            airports,
+            enrichers,
+            winds,
+            qnhs,
+            emissions,
+            model_phases,
+            commercial,
+            profiles: cli.with_profiles,
+            format: cli.format,
+            compression: cli.compression,
+            legs: LegsConfig {
+                min_ground_speed: cli.min_ground_speed,
+                max_time_gap: time::Duration::seconds_f64(cli.max_time_gap * 60.0),
+                min_duration: time::Duration::seconds_f64(cli.min_duration * 60.0),
+                min_distance: cli.min_distance,
+                landing_altitude_threshold: cli.landing_altitude_threshold,
+                max_taxi_speed: cli.max_taxi_speed,
+                stationary_speed: cli.stationary_speed,
+                min_parked: time::Duration::seconds_f64(cli.min_parked * 60.0),
+            },
+            parallel_decode: cli.parallel_decode,
+        };
+
+        log::info!("executing required...");
+        let tasks = required
+            .keys()
+            .map(|(icao_number, month)| (icao_number, *month))
+            .chain(
+                unmatched
+                    .iter()
+                    .map(|(icao_number, month)| (icao_number, *month)),
+            )
+            .filter(|(_, month)| selected.contains(month));
+        let tasks = tasks.collect::<Vec<_>>();
+        let mut pending = required
+            .iter()
+            .map(|((icao_number, month), (aircraft, model))| {
+                (icao_number.clone(), *month, Some(aircraft), Some(model))
+            })
+            .chain(
+                unmatched
+                    .iter()
+                    .map(|(icao_number, month)| (icao_number.clone(), *month, None, None)),
+            )
+            .filter(|(_, month, _, _)| selected.contains(month))
+            .filter(|(icao_number, month, _, _)| !progress.is_completed(icao_number, *month))
+            .collect::<Vec<_>>();
+        log::info!("pending: {}", pending.len());
+        let executed = pending.len();
+        run.tasks_skipped = tasks.len() - pending.len();
+        let timeout = std::time::Duration::from_secs(cli.task_timeout);
+        let mut last_written = std::time::Instant::now();
+        let mut report = TimingReport::new(cli.slowest);
+        let mut warnings = Warnings {
+            unmatched: unmatched.len(),
+            missing_airports: enrichers.names().any(|x| x == "airports").then_some(0),
+            ..Default::default()
+        };
+        let quotas = (cli.max_partitions, cli.max_bytes);
+        let mut started = 0;
+        for requeue in 0..=cli.max_requeues {
+            if pending.is_empty() || STOPPING.load(Ordering::Relaxed) {
+                break;
+            }
+            if requeue > 0 {
+                log::warn!(
+                    "requeuing {} timed out or rate limited tasks ({requeue})",
+                    pending.len()
+                );
+            }
+            let tasks = std::mem::take(&mut pending)
+                .into_iter()
+                // tasks are started lazily, so none is started once stopping
+                .take_while(|_| {
+                    if STOPPING.load(Ordering::Relaxed) {
+                        return false;
+                    }
+                    if let Some(reason) = reached_quota(quotas, started, metered.bytes_read()) {
+                        log::warn!("stopping: {reason}; finishing in-flight tasks...");
+                        STOPPING.store(true, Ordering::Relaxed);
+                        return false;
+                    }
+                    started += 1;
+                    true
+                })
+                .map(|task| async move {
+                    let (icao_number, month, aircraft, model) = &task;
+                    let result = tokio::time::timeout(
+                        timeout,
+                        flights::etl::legs::process_icao_month(
+                            icao_number,
+                            aircraft.map(|x| x.as_ref()),
+                            model.map(|x| x.as_ref()),
+                            *month,
+                            context,
+                        ),
+                    )
+                    .await;
+                    (task, result)
+                });
+
+            let mut results = futures::stream::iter(tasks).buffered(cli.concurrency);
+            while let Some((task, result)) = results.next().await {
+                match result {
+                    Ok(Ok(manifest)) => {
+                        run.tasks_succeeded += 1;
+                        progress.complete(task.0, task.1);
+                        warnings.push(&manifest);
+                        report.push(manifest);
+                    }
+                    Ok(Err(e)) if e.is_retryable() => {
+                        log::warn!("{} {}: {e}", task.0, task.1);
+                        pending.push(task);
+                    }
+                    Ok(Err(e)) => {
+                        log::error!("{e}");
+                        warnings.failed.push(TaskError::new(task.0, task.1, e));
+                    }
+                    Err(_) => {
+                        log::warn!("{} {}: timed out after {timeout:?}", task.0, task.1);
+                        pending.push(task);
+                    }
+                }
+                if last_written.elapsed() > CHECKPOINT_INTERVAL {
+                    progress
+                        .write(State::Running, &progress_key, client)
+                        .await?;
+                    last_written = std::time::Instant::now();
+                }
+            }
+        }
+        if !pending.is_empty() {
+            log::error!(
+                "{} tasks timed out or were rate limited on every attempt",
+                pending.len()
+            );
+        }
+        warnings.timed_out = pending.len();
+        run.tasks_failed = warnings.failed.len();
+        run.tasks_timed_out = pending.len();
+        run.years = flights::runs::completeness(tasks.into_iter(), &progress);
+        log::info!(
+            "processed {} months of aircrafts ({} bytes of positions) in {:.0}s extracting, {:.0}s transforming and {:.0}s loading",
+            report.partitions,
+            report.source_bytes,
+            report.extract,
+            report.transform,
+            report.load
+        );
+        let key = slowest_pk_to_blob_name(roots, &progress.run_id);
+        client
+            .put(&key, flights::csv::serialize(report.slowest().into_iter()))
+            .await?;
+        log::info!("Written {key}");
+        let errors = warnings
+            .failed
+            .iter()
+            .cloned()
+            .chain(pending.iter().map(|task| {
+                TaskError::new(
+                    task.0.clone(),
+                    task.1,
+                    "timed out or rate limited on every attempt",
+                )
+            }));
+        let errors = errors.collect::<Vec<_>>();
+        let failure_ratio = errors.len() as f64 / executed.max(1) as f64;
+        if !errors.is_empty() {
+            let key = errors_pk_to_blob_name(roots, &progress.run_id);
+            client
+                .put(&key, flights::csv::serialize(errors.iter()))
+                .await?;
+            log::warn!(
+                "{} of {executed} months of aircrafts failed ({:.1}%); written to {key}",
+                errors.len(),
+                failure_ratio * 100.0
+            );
+        }
+        if let Some(events) = events {
+            events.flush().await?;
+        }
+        if STOPPING.load(Ordering::Relaxed) {
+            progress
+                .write(State::Interrupted, &progress_key, client)
+                .await?;
+            write_run(&mut run, Outcome::Interrupted, &metered, &run_key, client).await?;
+            log::warn!(
+                "run {} stopped after {} tasks; continue it with `--resume {}`",
+                progress.run_id,
+                progress.completed_tasks(),
+                progress.run_id
+            );
+            return Ok(false);
+        }
+        progress
+            .write(State::Completed, &progress_key, client)
+            .await?;
+        log::info!("execution completed");
+
+        let diagnostics = warnings.diagnostics();
+        for diagnostic in &diagnostics {
+            log::warn!("{diagnostic}");
+        }
+        if failure_ratio > cli.max_failure_ratio {
+            let error = format!(
+                "{} of {executed} months of aircrafts failed, above --max-failure-ratio {}; the datasets were not aggregated",
+                errors.len(),
+                cli.max_failure_ratio
+            );
+            run.error = Some(error.clone());
+            write_run(&mut run, Outcome::Failed, &metered, &run_key, client).await?;
+            return Err(error.into());
+        }
+        if cli.strict && !diagnostics.is_empty() {
+            write_run(&mut run, Outcome::Strict, &metered, &run_key, client).await?;
+            return Err(format!(
+                "strict: {} kinds of warnings; the datasets were not aggregated:\n{}",
+                diagnostics.len(),
+                diagnostics.join("\n")
+            )
+            .into());
+        }
+
+        log::info!("aggregating...");
+        let completed = required.into_keys().chain(unmatched);
+        let completed = completed.collect::<Vec<_>>();
+        let config = AggregateConfig {
+            emissions,
+            model_overrides: &model_overrides,
+            model_changelog: &model_changelog,
+            units: cli.units,
+            format: cli.format,
+            compression: cli.compression,
+            calendar: cli.timezone,
+            concurrency: cli.aggregate_concurrency,
+            year_concurrency: cli.year_concurrency,
+            processing: &Processing {
+                legs: context.legs,
+                qnh_correction: cli.with_qnh,
+                winds_resolution: cli.with_winds.then_some(cli.winds_resolution),
+            },
+            roots,
+            lock: lock.as_ref().map(|(lock, _)| lock),
+            client,
+        };
+        let aggregated = async {
+            flights::etl::legs::aggregate(completed.iter().cloned(), &config).await?;
+            if cli.weekly {
+                flights::etl::legs::aggregate_weeks(completed.iter().cloned(), &config).await?;
             }
-        })
-        .collect::<Vec<_>>()
+            if !cli.airport_watch.is_empty() {
+                flights::etl::legs::aggregate_airport_watch(
+                    completed.iter().cloned(),
+                    &cli.airport_watch,
+                    airports,
+                    &config,
+                )
+                .await?;
+            }
+            let activity = flights::etl::legs::aggregate_activity(
+                completed.into_iter(),
+                roots,
+                cli.aggregate_concurrency,
+                client,
+            )
+            .await?;
+            flights::etl::legs::reactivations(activity, cli.reactivation_months, notify, roots, client)
+                .await
+        }
         .await;
-    log::info!("execution completed");
+        // the record of a failed run is kept, so that the history of runs has no gaps
+        let outcome = match &aggregated {
+            Ok(()) => Outcome::Completed,
+            Err(e) => {
+                run.error = Some(e.to_string());
+                Outcome::Failed
+            }
+        };
+        write_run(&mut run, outcome, &metered, &run_key, client).await?;
+        aggregated?;
+        Ok::<_, Box<dyn Error>>(true)
+    }
+    .await;
+    let released = match lock {
+        Some((lock, heartbeat)) => {
+            heartbeat.abort();
+            lock.release(client).await
+        }
+        None => Ok(()),
+    };
+    // the error of the run takes precedence over that of releasing the lock
+    if !finished? {
+        released?;
+        return Ok(());
+    }
+    released?;
+
+    if let Some(retries) = retries {
+        log::info!(
+            "retried requests to the remote storage: {}",
+            retries.retried()
+        );
+    }
 
-    log::info!("aggregating...");
-    aggregate(required.into_keys(), client).await
+    if let Some(snapshot) = &replay {
+        let published = published(roots, cli.timezone);
+        let published = published.iter().map(|x| x.as_str()).collect::<Vec<_>>();
+        let mismatches = snapshot.verify(&published).await?;
+        for mismatch in &mismatches {
+            log::error!("{mismatch}");
+        }
+        if !mismatches.is_empty() {
+            return Err(format!(
+                "replay: {} datasets differ from the snapshot",
+                mismatches.len()
+            )
+            .into());
+        }
+        log::info!("replay: all datasets reproduced");
+    }
+    Ok(())
 }
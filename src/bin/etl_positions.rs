@@ -1,6 +1,16 @@
-use std::{collections::HashSet, error::Error};
+use std::{
+    collections::HashSet,
+    error::Error,
+    sync::{Arc, Mutex},
+};
 
 use clap::Parser;
+use flights::{
+    altitude::Preference,
+    fs::BlobStorageProvider,
+    merge_positions::{failover_positions, served_pk_to_blob_name, Adsbexchange, Coverage, Stored},
+    PositionsSource,
+};
 use futures::StreamExt;
 use simple_logger::SimpleLogger;
 
@@ -9,15 +19,32 @@ const ABOUT: &'static str = r#"Builds the database of all private jet positions
 #[derive(Parser, Debug)]
 #[command(author, version, about = ABOUT)]
 struct Cli {
-    /// The token to the remote storage
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// Optional filter of the registration of the aircrafts to fetch (see `M-registry-filters`): comma-separated
+    /// countries of registration (e.g. `Denmark`), regions of owners in ISO 3166 (e.g. `GL` or `DK-81`) and prefixes
+    /// of tail numbers (e.g. `OY-H*`), each optionally prefixed by `!` to exclude them; defaults to whole world
     #[arg(long)]
-    access_key: String,
-    /// The token to the remote storage
+    country: Option<flights::registry::filter::Filter>,
+    /// Whether to rebuild the index of existing positions from a full listing of the storage
     #[arg(long)]
-    secret_access_key: String,
-    /// Optional country to fetch from (in ISO 3166); defaults to whole world
-    #[arg(long)]
-    country: Option<String>,
+    refresh_index: bool,
+    /// Other sources of positions in the layout of the database of positions, as `{name}={directory}`
+    /// (e.g. `adsblol=./adsblol`). When set, the positions of each month are served by the source with the best
+    /// coverage of the aircraft, failing over to the others (see `M-source-selection`)
+    #[arg(long, value_parser = parse_source)]
+    source: Vec<(String, String)>,
+    /// The altitude of the positions fetched: `barometric` or `geometric`, falling back to the other when the
+    /// preferred one is not reported (see `M-altitudes`). Months of positions already fetched are not changed
+    #[arg(long, default_value = "barometric")]
+    altitude: Preference,
+}
+
+fn parse_source(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(name, directory)| (name.to_string(), directory.to_string()))
+        .ok_or_else(|| format!("source `{value}` must be `{{name}}={{directory}}`"))
 }
 
 #[tokio::main(flavor = "multi_thread")]
@@ -29,23 +56,92 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let cli = Cli::parse();
 
-    let client = flights::fs_s3::client(cli.access_key, cli.secret_access_key).await;
+    let client = flights::fs::client_from_args(&cli.storage).await?;
 
     let required =
-        flights::private_jets_in_month((2019..2025).rev(), cli.country.as_deref(), &client).await?;
+        flights::private_jets_in_month((2019..2025).rev(), cli.country.as_ref(), client.as_ref())
+            .await?;
 
     let required = required.keys().cloned().collect::<HashSet<_>>();
 
     log::info!("required : {}", required.len());
 
+    let client = flights::icao_to_trace::indexed_client(client.as_ref(), cli.refresh_index).await?;
     let completed = flights::icao_to_trace::list_months_positions(&client).await?;
     log::info!("completed: {}", completed.len());
     let mut todo = required.difference(&completed).collect::<Vec<_>>();
     todo.sort_unstable_by_key(|(icao_number, date)| (date, icao_number));
     log::info!("todo     : {}", todo.len());
 
+    if !cli.source.is_empty() {
+        failover(todo, &cli.source, cli.altitude, &client).await?;
+        client.flush().await?;
+        return Ok(());
+    }
+
+    let tasks = todo.into_iter().map(|(icao_number, month)| {
+        flights::icao_to_trace::month_positions_with(icao_number, *month, cli.altitude, &client)
+    });
+
+    futures::stream::iter(tasks)
+        // limit concurrent tasks
+        .buffered(10)
+        // continue if error
+        .map(|r| {
+            if let Err(e) = r {
+                log::error!("{e}");
+            }
+        })
+        .collect::<Vec<_>>()
+        .await;
+    client.flush().await?;
+    Ok(())
+}
+
+/// Serves the positions of `todo` from adsbexchange and the other `sources` (see `M-source-selection`), writing
+/// them to the database of positions, the source that served each of them, and the updated coverage of the sources
+async fn failover(
+    todo: Vec<&(Arc<str>, time::Date)>,
+    sources: &[(String, String)],
+    altitude: Preference,
+    client: &dyn BlobStorageProvider,
+) -> Result<(), Box<dyn Error>> {
+    let disks = sources
+        .iter()
+        .map(|(name, directory)| (name, flights::fs_local::LocalDisk::new(directory)))
+        .collect::<Vec<_>>();
+    let stored = disks
+        .iter()
+        .map(|(name, disk)| Stored {
+            name: name.to_string(),
+            client: disk,
+        })
+        .collect::<Vec<_>>();
+    let adsbexchange = Adsbexchange { client, altitude };
+    let sources = std::iter::once(&adsbexchange as &dyn PositionsSource)
+        .chain(stored.iter().map(|x| x as &dyn PositionsSource))
+        .collect::<Vec<_>>();
+
+    let coverage = Mutex::new(Coverage::read(client).await?);
     let tasks = todo.into_iter().map(|(icao_number, month)| {
-        flights::icao_to_trace::month_positions(icao_number, *month, &client)
+        let (sources, coverage, adsbexchange) = (&sources, &coverage, &adsbexchange);
+        async move {
+            let sources = coverage.lock().unwrap().order(sources, icao_number);
+            let (positions, served) = failover_positions(&sources, icao_number, *month).await?;
+            if served.source != adsbexchange.name() {
+                flights::icao_to_trace::put_month_positions(
+                    icao_number,
+                    *month,
+                    &positions,
+                    client,
+                )
+                .await?;
+            }
+            let key = served_pk_to_blob_name(icao_number, *month);
+            client.put(&key, serde_json::to_vec(&served)?).await?;
+            coverage.lock().unwrap().add(icao_number, &served);
+            Ok::<_, std::io::Error>(())
+        }
     });
 
     futures::stream::iter(tasks)
@@ -59,5 +155,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
         })
         .collect::<Vec<_>>()
         .await;
+    let coverage = coverage.into_inner().unwrap();
+    coverage.write(client).await?;
     Ok(())
 }
@@ -0,0 +1,52 @@
+use std::error::Error;
+
+use clap::Parser;
+use flights::etl::legs::Roots;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Validates the database of legs according to `M-quality-report` (overlapping legs, negative durations,
+endpoints over oceans at zero altitude, speeds above Mach 1 and duplicate legs) and writes a report of the
+offending partitions of each year.
+With `--promote`, the validated version of the datasets of legs is published (see `M-versions`)."#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// The first year to validate
+    #[arg(long, default_value_t = 2019)]
+    from: i32,
+    /// The last year to validate (inclusive)
+    #[arg(long, default_value_t = 2024)]
+    to: i32,
+    /// The version of the datasets of legs to validate (`leg/{version}/`)
+    #[arg(long, default_value = "v2")]
+    dataset_version: String,
+    /// Whether to publish the version once it passes the validation (writes `leg/status.json`)
+    #[arg(long)]
+    promote: bool,
+    /// The maximum share of legs with anomalies of each year for the version to be promoted
+    #[arg(long, default_value_t = 0.01)]
+    max_anomalies: f64,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs::client_from_args(&cli.storage).await?;
+
+    let roots = Roots::new(&cli.dataset_version);
+    let reports =
+        flights::validate::etl_validate(cli.from..=cli.to, &roots, client.as_ref()).await?;
+    if cli.promote {
+        flights::validate::promote(&roots, &reports, cli.max_anomalies, client.as_ref()).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use std::error::Error;
+
+use clap::Parser;
+use flights::fs::BlobStorageProvider;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Exports the datasets under the prefixes (by default the full history of legs, `leg/v2/`) to another
+storage according to `M-export`, e.g. to produce a mirror or an archive (such as a deposit in Zenodo).
+Exports are resumable: running it again copies only the blobs not yet copied or changed since.
+Destinations are a bucket of the remote storage, a directory of the local disk or a container of Azure Blob Storage;
+for other destinations (e.g. SFTP), export to a directory of the local disk and transfer it."#;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum Source {
+    /// The remote storage, read anonymously
+    Remote,
+    /// A directory of the local disk (see `--root`)
+    Local,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum Destination {
+    /// A bucket of the remote storage (requires `--bucket`, `--access-key` and `--secret-access-key`)
+    Remote,
+    /// A directory of the local disk (see `--destination-root`)
+    Local,
+    /// A container of Azure Blob Storage (requires `--azure-account`, `--azure-container` and
+    /// `--azure-sas-token` or `--azure-account-key`)
+    Azure,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    /// Where the datasets are read from
+    #[arg(long, value_enum, default_value_t = Source::Remote)]
+    source: Source,
+    /// The directory of the `local` source
+    #[arg(long, default_value = "database/")]
+    root: std::path::PathBuf,
+    /// Where the datasets are exported to
+    #[arg(long, value_enum)]
+    destination: Destination,
+    /// The directory of the `local` destination
+    #[arg(long, default_value = "export/")]
+    destination_root: std::path::PathBuf,
+    /// The bucket of the `remote` destination
+    #[arg(long)]
+    bucket: Option<String>,
+    /// The token to the remote storage (required by the `remote` destination)
+    #[arg(long)]
+    access_key: Option<String>,
+    /// The token to the remote storage (required by the `remote` destination)
+    #[arg(long)]
+    secret_access_key: Option<String>,
+    /// The storage account of the `azure` destination
+    #[arg(long)]
+    azure_account: Option<String>,
+    /// The container of the `azure` destination
+    #[arg(long)]
+    azure_container: Option<String>,
+    /// The SAS token of the container of the `azure` destination
+    #[arg(long)]
+    azure_sas_token: Option<String>,
+    /// The key of the storage account of the `azure` destination (used when there is no SAS token)
+    #[arg(long)]
+    azure_account_key: Option<String>,
+    /// The prefixes of the datasets to export
+    #[arg(long, value_delimiter = ',', default_value = "leg/v2/")]
+    prefixes: Vec<String>,
+    /// The maximum number of blobs copied concurrently
+    #[arg(long, default_value_t = 100)]
+    concurrency: usize,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let source: Box<dyn BlobStorageProvider + Send + Sync> = match cli.source {
+        Source::Remote => Box::new(flights::fs_s3::anonymous_client().await),
+        Source::Local => Box::new(flights::fs_local::LocalDisk::new(&cli.root)),
+    };
+
+    let destination: Box<dyn BlobStorageProvider + Send + Sync> = match cli.destination {
+        Destination::Remote => {
+            let (Some(bucket), Some(access_key), Some(secret_access_key)) =
+                (cli.bucket, cli.access_key, cli.secret_access_key)
+            else {
+                return Err(
+                    "the remote destination requires bucket, access_key and secret_access_key"
+                        .into(),
+                );
+            };
+            let mut client = flights::fs_s3::client(access_key, secret_access_key).await;
+            client.bucket = bucket;
+            Box::new(client)
+        }
+        Destination::Local => Box::new(flights::fs_local::LocalDisk::new(&cli.destination_root)),
+        Destination::Azure => {
+            let credential =
+                flights::fs_azure::Credential::new(cli.azure_sas_token, cli.azure_account_key);
+            let (Some(account), Some(container), Some(credential)) =
+                (cli.azure_account, cli.azure_container, credential)
+            else {
+                return Err("the azure destination requires azure_account, azure_container and azure_sas_token or azure_account_key".into());
+            };
+            Box::new(flights::fs_azure::client(account, container, credential))
+        }
+    };
+    if !destination.can_put() {
+        return Err("the destination is read-only; provide credentials that can write".into());
+    }
+
+    flights::export::export(
+        &cli.prefixes,
+        source.as_ref(),
+        destination.as_ref(),
+        cli.concurrency,
+    )
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use std::error::Error;
+
+use clap::Parser;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Exports the legs of a year (or of an aircraft on a year) of the public dataset of legs to GeoJSON
+according to `M-geojson`, one LineString per leg, written to `leg/v2/geojson/`."#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// The year to export
+    #[arg(long)]
+    year: i32,
+    /// Only exports the legs of this ICAO number
+    #[arg(long)]
+    icao_number: Option<String>,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs::client_from_args(&cli.storage).await?;
+
+    let icao_number = cli.icao_number.map(|x| x.to_ascii_lowercase());
+    flights::geojson::export_geojson(cli.year, icao_number.as_deref(), client.as_ref()).await?;
+    Ok(())
+}
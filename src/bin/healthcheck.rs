@@ -0,0 +1,56 @@
+use std::error::Error;
+
+use clap::Parser;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Probes every source of positions with a known ICAO number and date, verifies that its response
+can be parsed into positions, and reports its latency and schema. Exits with an error if any source is unhealthy"#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    /// The ICAO number to probe with
+    #[arg(long, default_value = "45d2ed")]
+    icao_number: String,
+    /// The date to probe with, in `yyyy-mm-dd`
+    #[arg(long, default_value = "2023-10-13")]
+    date: String,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+    let date = time::Date::parse(
+        &cli.date,
+        time::macros::format_description!("[year]-[month]-[day]"),
+    )?;
+
+    let sources = [flights::icao_to_trace::healthcheck(&cli.icao_number, date).await];
+
+    let mut unhealthy = 0;
+    for health in sources {
+        println!("{}", serde_json::to_string(&health)?);
+        match &health.error {
+            None => log::info!(
+                "{} is healthy: {} positions in {:.2}s ({})",
+                health.source,
+                health.positions,
+                health.latency,
+                health.schema
+            ),
+            Some(error) => {
+                log::error!("{} is unhealthy: {error}", health.source);
+                unhealthy += 1;
+            }
+        }
+    }
+    if unhealthy > 0 {
+        return Err(format!("{unhealthy} source(s) are unhealthy").into());
+    }
+    Ok(())
+}
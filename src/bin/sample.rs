@@ -0,0 +1,41 @@
+use std::{error::Error, io::Write};
+
+use clap::Parser;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Writes to stdout, as CSV, a reproducible random sample of the public dataset of legs, stratified by year and
+country of departure (see `M-sample`), to prototype analyses without downloading the whole dataset"#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    /// The fraction of the legs of each year and country to sample (at least one leg of each)
+    #[arg(long, default_value_t = 0.01)]
+    fraction: f64,
+    /// The seed of the random sample; the same seed samples the same legs
+    #[arg(long, default_value_t = 42)]
+    seed: u64,
+    /// The first year to sample
+    #[arg(long, default_value_t = 2019)]
+    from: i32,
+    /// The last year to sample (inclusive)
+    #[arg(long, default_value_t = 2024)]
+    to: i32,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let client = flights::fs_s3::anonymous_client().await;
+
+    let sample =
+        flights::sample::sample(cli.from..=cli.to, cli.fraction, cli.seed, &client).await?;
+    std::io::stdout().write_all(&sample)?;
+    Ok(())
+}
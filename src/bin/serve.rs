@@ -0,0 +1,44 @@
+use std::error::Error;
+
+use clap::Parser;
+use flights::etl::legs::Roots;
+use simple_logger::SimpleLogger;
+
+const ABOUT: &str = r#"Serves a read-only HTTP API over the datasets according to `M-api`: the legs of an
+aircraft (`/aircraft/{icao_number}/legs?from={date}&to={date}`), pages of the legs of a year in JSON or CSV
+(`/legs/year/{year}`), the totals of the yearly statistics of the aircrafts (`/stats/year/{year}`) and the status of
+the datasets of legs (`/status`)."#;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = ABOUT)]
+struct Cli {
+    // the `remote` backend is read anonymously without credentials
+    #[command(flatten)]
+    storage: flights::fs::StorageArgs,
+    /// The version of the datasets of legs to serve (see `M-versions`); by default the published one
+    #[arg(long)]
+    dataset_version: Option<String>,
+    /// The address to listen on
+    #[arg(long, default_value = "127.0.0.1:3000")]
+    address: String,
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() -> Result<(), Box<dyn Error>> {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .init()
+        .unwrap();
+
+    let cli = Cli::parse();
+
+    let (client, _) =
+        flights::fs::client_from_args_with_retry(&cli.storage, Default::default(), true).await?;
+    let roots = cli.dataset_version.as_deref().map(Roots::new);
+    let router = flights::server::router(client, roots);
+
+    let listener = tokio::net::TcpListener::bind(&cli.address).await?;
+    log::info!("Listening on {}", listener.local_addr()?);
+    axum::serve(listener, router).await?;
+    Ok(())
+}
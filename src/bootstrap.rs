@@ -0,0 +1,165 @@
+//! Contains the bootstrap of a new deployment (`M-bootstrap`): it validates the credentials of the storage, uploads
+//! the bundled reference data, and writes the catalog of the datasets and an empty status of the datasets of legs,
+//! so that the ETLs can run against an empty storage.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{etl::legs::Roots, fs::BlobStorageProvider, Error};
+
+/// The bundled reference data uploaded by [`bootstrap`], as `(file, blob name)`
+pub static REFERENCE_DATA: &[(&str, &str)] = &[
+    ("src/models.csv", "model/db/data.csv"),
+    ("src/models_helicopters.csv", "model/db/helicopters.csv"),
+    ("src/models_turboprops.csv", "model/db/turboprops.csv"),
+    ("src/models_overrides.csv", "model/db/overrides.csv"),
+    ("src/models_changelog.csv", "model/db/changelog.csv"),
+    ("src/models_seats.csv", "model/db/seats.csv"),
+    ("src/models_phases.csv", "model/db/phases.csv"),
+    ("src/airports_overrides.csv", "airport/overrides/data.csv"),
+];
+
+/// The blob written and deleted to validate the credentials of the storage
+static PROBE: &str = "bootstrap/probe";
+
+/// The prefixes of the datasets of a deployment, written to `{root of legs}catalog.json`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Catalog {
+    /// The version of the datasets of legs (e.g. `v2`)
+    pub version: String,
+    /// The prefix of each dataset, by its name (e.g. `positions`)
+    pub datasets: BTreeMap<String, String>,
+}
+
+impl Catalog {
+    /// Returns the [`Catalog`] of the datasets of `roots`
+    pub fn new(roots: &Roots) -> Self {
+        let datasets = [
+            ("positions", "position/".to_string()),
+            ("aircrafts", "aircraft/db/".to_string()),
+            ("models", "model/db/".to_string()),
+            ("airports", "airport/".to_string()),
+            ("legs", format!("{}data/", roots.legs)),
+            ("legs_by_year", format!("{}all/", roots.legs)),
+            ("legs_slim", format!("{}slim/", roots.legs)),
+            ("legs_by_country", format!("{}by_country/", roots.legs)),
+            ("legs_by_week", format!("{}weekly/", roots.legs)),
+            ("activity", roots.activity.clone()),
+            ("ground_times", roots.ground_times.clone()),
+            ("stats", "stats/v1/".to_string()),
+        ]
+        .into_iter()
+        .map(|(name, prefix)| (name.to_string(), prefix))
+        .collect();
+        Self {
+            version: roots.version.to_string(),
+            datasets,
+        }
+    }
+}
+
+/// The blobs written by [`bootstrap`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// The blobs written
+    pub written: Vec<String>,
+    /// The blobs that already existed and were kept
+    pub kept: Vec<String>,
+}
+
+/// Validates that `client` can write, read and delete blobs by writing and deleting a probe
+/// # Error
+/// Errors if the storage is read-only or any of the operations fails
+pub async fn validate_credentials(client: &dyn BlobStorageProvider) -> Result<(), Error> {
+    if !client.can_put() {
+        return Err(Error::Storage(std::io::Error::other(
+            "the storage is read-only; provide credentials that can write",
+        )));
+    }
+    let probe = b"bootstrap".to_vec();
+    client.put(PROBE, probe.clone()).await?;
+    if client.maybe_get(PROBE).await?.as_ref() != Some(&probe) {
+        return Err(Error::Storage(std::io::Error::other(format!(
+            "{PROBE} was written but could not be read back"
+        ))));
+    }
+    client.delete(PROBE).await?;
+    Ok(())
+}
+
+/// Bootstraps the storage `client` for the datasets of `roots`:
+/// * validates the credentials (see [`validate_credentials`])
+/// * uploads the [`REFERENCE_DATA`]
+/// * writes the [`Catalog`] of `roots` to `{root of legs}catalog.json`
+/// * writes an empty status of the datasets of legs to `{root of legs}status.json`, unless it exists
+///
+/// It is idempotent: running it again only updates the reference data and the catalog.
+/// # Error
+/// Errors if the credentials are invalid or a file of the reference data cannot be read
+pub async fn bootstrap(roots: &Roots, client: &dyn BlobStorageProvider) -> Result<Report, Error> {
+    validate_credentials(client).await?;
+    log::info!("credentials are valid");
+
+    let mut report = Report::default();
+    for (file, blob_name) in REFERENCE_DATA {
+        client.put(blob_name, std::fs::read(file)?).await?;
+        log::info!("Uploaded {file} to {blob_name}");
+        report.written.push(blob_name.to_string());
+    }
+
+    let key = format!("{}catalog.json", roots.legs);
+    let catalog = serde_json::to_vec_pretty(&Catalog::new(roots))?;
+    client.put(&key, catalog).await?;
+    log::info!("Written {key}");
+    report.written.push(key);
+
+    let key = format!("{}status.json", roots.legs);
+    if client.maybe_get(&key).await?.is_some() {
+        log::info!("{key} exists; kept");
+        report.kept.push(key);
+    } else {
+        client.put(&key, b"{}".to_vec()).await?;
+        log::info!("Written {key}");
+        report.written.push(key);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn work() {
+        let root = std::env::temp_dir().join("test_bootstrap");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let roots = Roots::default();
+
+        let report = bootstrap(&roots, &disk).await.unwrap();
+        assert_eq!(report.written.len(), REFERENCE_DATA.len() + 2);
+        assert!(report.kept.is_empty());
+        assert_eq!(disk.maybe_get(PROBE).await.unwrap(), None);
+        let status = disk.maybe_get("leg/v2/status.json").await.unwrap();
+        assert_eq!(status, Some(b"{}".to_vec()));
+        let catalog = disk
+            .maybe_get("leg/v2/catalog.json")
+            .await
+            .unwrap()
+            .unwrap();
+        let catalog = serde_json::from_slice::<Catalog>(&catalog).unwrap();
+        assert_eq!(catalog.datasets["legs"], "leg/v2/data/");
+        // the models can be read back
+        let models = disk.maybe_get("model/db/data.csv").await.unwrap().unwrap();
+        assert!(!crate::model::private_jet_models(&models, &[])
+            .unwrap()
+            .is_empty());
+
+        // an existing status is kept
+        disk.put("leg/v2/status.json", b"{\"2023\": {}}".to_vec())
+            .await
+            .unwrap();
+        let report = bootstrap(&roots, &disk).await.unwrap();
+        assert_eq!(report.kept, vec!["leg/v2/status.json".to_string()]);
+    }
+}
@@ -0,0 +1,117 @@
+//! Contains the implementation of the progress of long runs, so that a run that was stopped
+//! (e.g. killed or interrupted) can be resumed without repeating the tasks it completed.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fs::BlobStorageProvider;
+
+/// The state of a run
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    /// The run is executing tasks (or was killed while doing so)
+    Running,
+    /// The run was asked to stop and finished its in-flight tasks
+    Interrupted,
+    /// The run completed all its tasks
+    Completed,
+}
+
+/// The progress of a run, as the `(icao_number, month)` tasks it completed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Progress {
+    /// The identifier of the run
+    pub run_id: String,
+    pub state: State,
+    /// When the progress was last written
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated: time::OffsetDateTime,
+    /// The ICAO numbers of the completed tasks of each month (e.g. `2023-01`)
+    completed: BTreeMap<String, BTreeSet<Arc<str>>>,
+}
+
+impl Progress {
+    /// Returns the [`Progress`] of a new run without completed tasks
+    pub fn new(run_id: String) -> Self {
+        Self {
+            run_id,
+            state: State::Running,
+            updated: time::OffsetDateTime::now_utc(),
+            completed: Default::default(),
+        }
+    }
+
+    /// Whether the task `(icao_number, month)` was completed
+    pub fn is_completed(&self, icao_number: &str, month: time::Date) -> bool {
+        self.completed
+            .get(&crate::serde::month_to_part(month))
+            .is_some_and(|icao_numbers| icao_numbers.contains(icao_number))
+    }
+
+    /// Marks the task `(icao_number, month)` as completed
+    pub fn complete(&mut self, icao_number: Arc<str>, month: time::Date) {
+        self.completed
+            .entry(crate::serde::month_to_part(month))
+            .or_default()
+            .insert(icao_number);
+    }
+
+    /// The number of completed tasks
+    pub fn completed_tasks(&self) -> usize {
+        self.completed.values().map(|x| x.len()).sum()
+    }
+
+    /// Returns the [`Progress`] written at `key`, or `None` when there is none
+    /// # Error
+    /// Errors if the blob cannot be read or is not a valid progress
+    pub async fn read(
+        key: &str,
+        client: &dyn BlobStorageProvider,
+    ) -> Result<Option<Self>, std::io::Error> {
+        client
+            .maybe_get(key)
+            .await?
+            .map(|data| serde_json::from_slice(&data).map_err(std::io::Error::other))
+            .transpose()
+    }
+
+    /// Sets the state to `state` and writes the progress to `key`
+    /// # Error
+    /// Errors if the blob cannot be written
+    pub async fn write(
+        &mut self,
+        state: State,
+        key: &str,
+        client: &dyn BlobStorageProvider,
+    ) -> Result<(), std::io::Error> {
+        self.state = state;
+        self.updated = time::OffsetDateTime::now_utc();
+        let data = serde_json::to_vec(self).map_err(std::io::Error::other)?;
+        client.put(key, data).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+
+    #[test]
+    fn work() {
+        let mut progress = Progress::new("1".to_string());
+        progress.complete("45d2ed".into(), date!(2023 - 01 - 01));
+        progress.complete("45d2ed".into(), date!(2023 - 01 - 01));
+        progress.complete("45860d".into(), date!(2023 - 02 - 01));
+        assert_eq!(progress.completed_tasks(), 2);
+        assert!(progress.is_completed("45d2ed", date!(2023 - 01 - 01)));
+        assert!(!progress.is_completed("45d2ed", date!(2023 - 02 - 01)));
+
+        let data = serde_json::to_vec(&progress).unwrap();
+        assert_eq!(serde_json::from_slice::<Progress>(&data).unwrap(), progress);
+    }
+}
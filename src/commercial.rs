@@ -0,0 +1,354 @@
+//! Contains the backends computing the emissions of the commercial alternative of a leg (see `M-co2-emissions`),
+//! and whether the commercial alternative exists (see `M-commercial-alternative`).
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::airports::{Airport, Airports};
+
+/// A trip between two positions, for which the emissions of a commercial flight are computed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trip<'a> {
+    /// The great-circle distance in km
+    pub distance: f64,
+    /// The identifier of the departure airport, when known (see `M-leg-airports`)
+    pub from_airport: Option<&'a str>,
+    /// The identifier of the arrival airport, when known (see `M-leg-airports`)
+    pub to_airport: Option<&'a str>,
+}
+
+/// A method to compute the emissions of a business class passenger flying a [`Trip`] on a commercial flight
+pub trait CommercialEmissions: Sync {
+    /// The name of the backend, written to each leg (`commercial_co2_backend`)
+    fn name(&self) -> &'static str;
+    /// Returns the CO2 emissions in kg of `trip`, or `None` when this backend cannot compute them
+    fn co2_kg(&self, trip: &Trip) -> Option<f64>;
+}
+
+/// The backend used to compute the emissions of commercial flights, selected in
+/// [`EmissionsConfig::commercial_backend`](crate::emissions::EmissionsConfig::commercial_backend)
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, Hash, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CommercialBackend {
+    /// [`ClassBased`]
+    #[default]
+    ClassBased,
+    /// [`Myclimate`]
+    Myclimate,
+    /// [`RouteTable`]
+    RouteTable,
+}
+
+/// A constant factor of emissions per km of a business class passenger
+pub struct ClassBased {
+    /// The CO2 emitted in kg per km
+    pub co2_per_km: f64,
+}
+
+impl CommercialEmissions for ClassBased {
+    fn name(&self) -> &'static str {
+        "class-based"
+    }
+
+    fn co2_kg(&self, trip: &Trip) -> Option<f64> {
+        Some(trip.distance * self.co2_per_km)
+    }
+}
+
+/// The parameters of the formula of [`Myclimate`] for a haul
+#[derive(Serialize)]
+pub struct Haul {
+    /// the average number of seats
+    seats: f64,
+    /// the passenger load factor
+    load_factor: f64,
+    /// the share of emissions attributed to cargo
+    cargo_factor: f64,
+    /// the weight of a business class seat relative to an average seat
+    business_weight: f64,
+    /// the coefficients of the fuel burnt in kg as a function of the distance (`a x^2 + b x + c`)
+    fuel: (f64, f64, f64),
+}
+
+static SHORT_HAUL: Haul = Haul {
+    seats: 153.51,
+    load_factor: 0.82,
+    cargo_factor: 0.07,
+    business_weight: 1.26,
+    fuel: (0.0, 2.714, 1166.52),
+};
+
+static LONG_HAUL: Haul = Haul {
+    seats: 280.21,
+    load_factor: 0.82,
+    cargo_factor: 0.26,
+    business_weight: 1.54,
+    fuel: (0.0001, 7.104, 5044.93),
+};
+
+/// The emissions of a business class passenger according to the formula of
+/// [myclimate](https://www.myclimate.org/fileadmin/user_upload/myclimate_-_home/01_Information/01_About_myclimate/09_Calculation_principles/Documents/myclimate-flight-calculator-documentation_EN.pdf),
+/// without radiative forcing, so that they are comparable to the emissions of legs.
+pub struct Myclimate;
+
+impl Myclimate {
+    /// The detour of commercial flights in km, added to the great-circle distance
+    const DETOUR: f64 = 95.0;
+    /// The CO2 emitted in kg per kg of fuel burnt
+    const CO2_PER_KG: f64 = 3.15;
+    /// The CO2 emitted in kg per kg of fuel to produce the fuel
+    const PRE_PRODUCTION: f64 = 0.54;
+    /// The emissions in kg per km of the aircraft (its production and maintenance)
+    const AIRCRAFT_FACTOR: f64 = 0.00038;
+    /// The emissions in kg per flight of the airport infrastructure
+    const AIRPORT_INFRASTRUCTURE: f64 = 11.68;
+    /// The maximum distance in km of short haul trips
+    const SHORT_HAUL_MAX_DISTANCE: f64 = 1500.0;
+    /// The minimum distance in km of long haul trips
+    const LONG_HAUL_MIN_DISTANCE: f64 = 2500.0;
+
+    fn haul_co2_kg(haul: &Haul, distance: f64) -> f64 {
+        let x = distance + Self::DETOUR;
+        let (a, b, c) = haul.fuel;
+        let fuel = a * x * x + b * x + c;
+        fuel / (haul.seats * haul.load_factor)
+            * (1.0 - haul.cargo_factor)
+            * haul.business_weight
+            * (Self::CO2_PER_KG + Self::PRE_PRODUCTION)
+            + Self::AIRCRAFT_FACTOR * x
+            + Self::AIRPORT_INFRASTRUCTURE
+    }
+}
+
+impl CommercialEmissions for Myclimate {
+    fn name(&self) -> &'static str {
+        "myclimate"
+    }
+
+    /// Trips up to 1500 km are short haul and from 2500 km long haul; trips in between are linearly interpolated
+    fn co2_kg(&self, trip: &Trip) -> Option<f64> {
+        let short = Self::haul_co2_kg(&SHORT_HAUL, trip.distance);
+        let long = Self::haul_co2_kg(&LONG_HAUL, trip.distance);
+        let weight = ((trip.distance - Self::SHORT_HAUL_MAX_DISTANCE)
+            / (Self::LONG_HAUL_MIN_DISTANCE - Self::SHORT_HAUL_MAX_DISTANCE))
+            .clamp(0.0, 1.0);
+        Some(short * (1.0 - weight) + long * weight)
+    }
+}
+
+/// A row of the table of [`RouteTable`]
+#[derive(Deserialize)]
+struct Route {
+    from_airport: String,
+    to_airport: String,
+    co2_per_km: f64,
+}
+
+/// A table of factors of emissions per km of a business class passenger on each route, in both directions.
+/// Trips on routes not in the table, or without known airports, have no emissions.
+pub struct RouteTable {
+    routes: HashMap<(Arc<str>, Arc<str>), f64>,
+}
+
+impl RouteTable {
+    /// Returns a [`RouteTable`] from a CSV with the columns `from_airport,to_airport,co2_per_km`
+    pub fn from_csv(data: &[u8]) -> Result<Self, std::io::Error> {
+        let mut routes = HashMap::new();
+        for route in crate::csv::deserialize::<Route>(data) {
+            let route = route?;
+            let (from, to): (Arc<str>, Arc<str>) =
+                (route.from_airport.into(), route.to_airport.into());
+            routes.insert((from.clone(), to.clone()), route.co2_per_km);
+            routes.entry((to, from)).or_insert(route.co2_per_km);
+        }
+        Ok(Self { routes })
+    }
+}
+
+impl CommercialEmissions for RouteTable {
+    fn name(&self) -> &'static str {
+        "route-table"
+    }
+
+    fn co2_kg(&self, trip: &Trip) -> Option<f64> {
+        let key = (trip.from_airport?.into(), trip.to_airport?.into());
+        self.routes
+            .get(&key)
+            .map(|co2_per_km| trip.distance * co2_per_km)
+    }
+}
+
+/// Maximum distance in km between the start (end) of a leg and the airport its commercial alternative departs
+/// from (arrives at)
+pub static ALTERNATIVE_AIRPORT_MAX_DISTANCE: f64 = 100.0;
+/// Minimum distance in km between two airports for a scheduled commercial route between them to be plausible
+pub static MIN_ROUTE_DISTANCE: f64 = 150.0;
+/// Maximum distance in km of a scheduled commercial route between two medium airports
+pub static MAX_MEDIUM_ROUTE_DISTANCE: f64 = 2000.0;
+
+fn is_commercial(airport: &Airport) -> bool {
+    airport.scheduled_service == "yes"
+        && matches!(
+            airport.airport_type.as_str(),
+            "large_airport" | "medium_airport"
+        )
+}
+
+/// Returns whether a scheduled commercial route plausibly exists between the airports with scheduled service closest
+/// to `from` and `to` (see `M-commercial-alternative`).
+/// # Implementation
+/// The route exists when both airports exist within [`ALTERNATIVE_AIRPORT_MAX_DISTANCE`], they are at least
+/// [`MIN_ROUTE_DISTANCE`] apart and either is a large airport or they are at most [`MAX_MEDIUM_ROUTE_DISTANCE`] apart.
+pub fn alternative_exists(airports: &Airports, from: (f64, f64), to: (f64, f64)) -> bool {
+    let closest = |pos| airports.closest_with(pos, ALTERNATIVE_AIRPORT_MAX_DISTANCE, is_commercial);
+    let (Some((from, _)), Some((to, _))) = (closest(from), closest(to)) else {
+        return false;
+    };
+    let distance = crate::distance(from.pos(), to.pos());
+    let large = from.airport_type == "large_airport" || to.airport_type == "large_airport";
+    distance >= MIN_ROUTE_DISTANCE && (large || distance <= MAX_MEDIUM_ROUTE_DISTANCE)
+}
+
+/// The parameters of the backend of [`EmissionsConfig::commercial_backend`](crate::emissions::EmissionsConfig::commercial_backend),
+/// recorded in the descriptor of the methodology (see [`crate::descriptor`])
+#[derive(Serialize)]
+#[serde(tag = "backend", rename_all = "kebab-case")]
+pub enum Parameters {
+    ClassBased {
+        co2_per_km: f64,
+    },
+    Myclimate {
+        detour: f64,
+        co2_per_kg: f64,
+        pre_production: f64,
+        aircraft_factor: f64,
+        airport_infrastructure: f64,
+        short_haul_max_distance: f64,
+        long_haul_min_distance: f64,
+        short_haul: &'static Haul,
+        long_haul: &'static Haul,
+    },
+    /// The factors of each route are those of the table of `--commercial-routes`
+    RouteTable,
+}
+
+/// Returns the [`Parameters`] of the backend of `config`
+pub fn parameters(config: &crate::emissions::EmissionsConfig) -> Parameters {
+    match config.commercial_backend {
+        CommercialBackend::ClassBased => Parameters::ClassBased {
+            co2_per_km: config.commercial_co2_per_km,
+        },
+        CommercialBackend::Myclimate => Parameters::Myclimate {
+            detour: Myclimate::DETOUR,
+            co2_per_kg: Myclimate::CO2_PER_KG,
+            pre_production: Myclimate::PRE_PRODUCTION,
+            aircraft_factor: Myclimate::AIRCRAFT_FACTOR,
+            airport_infrastructure: Myclimate::AIRPORT_INFRASTRUCTURE,
+            short_haul_max_distance: Myclimate::SHORT_HAUL_MAX_DISTANCE,
+            long_haul_min_distance: Myclimate::LONG_HAUL_MIN_DISTANCE,
+            short_haul: &SHORT_HAUL,
+            long_haul: &LONG_HAUL,
+        },
+        CommercialBackend::RouteTable => Parameters::RouteTable,
+    }
+}
+
+/// Returns the [`CommercialEmissions`] of `config`. The backend [`CommercialBackend::RouteTable`] requires
+/// `routes`, the CSV of [`RouteTable::from_csv`].
+pub fn backend(
+    config: &crate::emissions::EmissionsConfig,
+    routes: Option<&[u8]>,
+) -> Result<Box<dyn CommercialEmissions>, std::io::Error> {
+    Ok(match config.commercial_backend {
+        CommercialBackend::ClassBased => Box::new(ClassBased {
+            co2_per_km: config.commercial_co2_per_km,
+        }),
+        CommercialBackend::Myclimate => Box::new(Myclimate),
+        CommercialBackend::RouteTable => {
+            let routes = routes.ok_or_else(|| {
+                std::io::Error::other("the backend `route-table` requires a table of routes")
+            })?;
+            Box::new(RouteTable::from_csv(routes)?)
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backends() {
+        let trip = Trip {
+            distance: 1000.0,
+            from_airport: Some("EKCH"),
+            to_airport: Some("LFPB"),
+        };
+        assert_eq!(ClassBased { co2_per_km: 0.2 }.co2_kg(&trip), Some(200.0));
+
+        // short haul, long haul and their interpolation
+        let short = Myclimate.co2_kg(&trip).unwrap();
+        assert!((short - 154.2).abs() < 0.1, "{short}");
+        let co2_kg = |distance| Myclimate.co2_kg(&Trip { distance, ..trip }).unwrap();
+        assert!(co2_kg(1500.0) < co2_kg(2000.0));
+        assert!(co2_kg(2000.0) < co2_kg(2500.0));
+
+        let table =
+            RouteTable::from_csv(b"from_airport,to_airport,co2_per_km\nLFPB,EKCH,0.3\n").unwrap();
+        assert_eq!(table.co2_kg(&trip), Some(300.0));
+        let unknown = Trip {
+            to_airport: None,
+            ..trip
+        };
+        assert_eq!(table.co2_kg(&unknown), None);
+    }
+
+    #[test]
+    fn alternative() {
+        let airport = |ident: &str, airport_type: &str, scheduled: &str, pos: (f64, f64)| Airport {
+            ident: ident.to_string(),
+            airport_type: airport_type.to_string(),
+            name: ident.to_string(),
+            latitude_deg: pos.0,
+            longitude_deg: pos.1,
+            elevation_ft: None,
+            iso_country: "XX".to_string(),
+            municipality: None,
+            scheduled_service: scheduled.to_string(),
+            iata_code: None,
+        };
+        let airports = Airports::new(vec![
+            airport("EKCH", "large_airport", "yes", (55.6179, 12.6560)),
+            airport("EKRK", "small_airport", "no", (55.5856, 12.1314)),
+            airport("EKBI", "medium_airport", "yes", (55.7403, 9.1518)),
+            airport("LFPG", "large_airport", "yes", (49.0097, 2.5479)),
+            airport("KASE", "medium_airport", "yes", (39.2232, -106.8690)),
+        ]);
+        // from a small airport next to Copenhagen to Paris
+        assert!(alternative_exists(
+            &airports,
+            (55.5856, 12.1314),
+            (49.0, 2.5)
+        ));
+        // from Copenhagen to Billund
+        assert!(alternative_exists(
+            &airports,
+            (55.6179, 12.6560),
+            (55.7403, 9.1518)
+        ));
+        // the same airport
+        assert!(!alternative_exists(
+            &airports,
+            (55.5856, 12.1314),
+            (55.6179, 12.6560)
+        ));
+        // too far for two medium airports
+        assert!(!alternative_exists(
+            &airports,
+            (55.7403, 9.1518),
+            (39.2232, -106.8690)
+        ));
+        // without an airport with scheduled service nearby
+        assert!(!alternative_exists(&airports, (55.6, 12.6), (0.0, 0.0)));
+    }
+}
@@ -0,0 +1,263 @@
+//! Contains the compressions of stored datasets (`M-compression`). Compressed blobs have the extension of
+//! their compression appended to their name (e.g. `data.csv.zst`) and are decompressed transparently on read
+//! (see [`crate::io::maybe_get`]).
+use std::io::{Read, Write};
+
+use futures::{StreamExt, TryStreamExt};
+
+use crate::fs::BlobStorageProvider;
+
+/// A compression of a blob
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// No compression
+    #[default]
+    None,
+    /// [gzip](https://datatracker.ietf.org/doc/html/rfc1952)
+    Gzip,
+    /// [Zstandard](https://datatracker.ietf.org/doc/html/rfc8878)
+    Zstd,
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "gzip" => Ok(Self::Gzip),
+            "zstd" => Ok(Self::Zstd),
+            other => Err(format!(
+                "compression `{other}` must be `none`, `gzip` or `zstd`"
+            )),
+        }
+    }
+}
+
+impl Compression {
+    /// All compressions
+    pub const ALL: [Self; 3] = [Self::None, Self::Gzip, Self::Zstd];
+
+    /// The suffix of blobs of this compression (e.g. `.zst`)
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Gzip => ".gz",
+            Self::Zstd => ".zst",
+        }
+    }
+
+    /// Returns the compression of the blob `blob_name` according to its suffix, and its name without it
+    pub fn of(blob_name: &str) -> (Self, &str) {
+        [Self::Gzip, Self::Zstd]
+            .into_iter()
+            .find_map(|compression| {
+                blob_name
+                    .strip_suffix(compression.extension())
+                    .map(|name| (compression, name))
+            })
+            .unwrap_or((Self::None, blob_name))
+    }
+
+    /// Returns `data` compressed
+    pub fn compress(&self, data: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+        match self {
+            Self::None => Ok(data),
+            Self::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+                encoder.write_all(&data)?;
+                encoder.finish()
+            }
+            Self::Zstd => zstd::encode_all(data.as_slice(), zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+
+    /// Returns `chunks` compressed as a single blob, compressing each chunk as it arrives so that
+    /// only the compressed output of a chunk is held in memory at a time. The first error of `chunks` is
+    /// returned and ends the blob.
+    pub fn compress_chunks<'a>(
+        &self,
+        chunks: impl Iterator<Item = Result<Vec<u8>, std::io::Error>> + Send + 'a,
+    ) -> Box<dyn Iterator<Item = Result<Vec<u8>, std::io::Error>> + Send + 'a> {
+        match self {
+            Self::None => Box::new(chunks),
+            Self::Gzip => Box::new(encode_chunks(
+                flate2::write::GzEncoder::new(vec![], flate2::Compression::default()),
+                |encoder| encoder.get_mut(),
+                |encoder| encoder.finish(),
+                chunks,
+            )),
+            Self::Zstd => {
+                match zstd::stream::write::Encoder::new(vec![], zstd::DEFAULT_COMPRESSION_LEVEL) {
+                    Ok(encoder) => Box::new(encode_chunks(
+                        encoder,
+                        |encoder| encoder.get_mut(),
+                        |encoder| encoder.finish(),
+                        chunks,
+                    )),
+                    Err(e) => Box::new(std::iter::once(Err(e))),
+                }
+            }
+        }
+    }
+}
+
+/// Writes each of `chunks` to `encoder` and yields what it has written to its `output` so far,
+/// followed by the remaining output on `finish`
+fn encode_chunks<'a, E: Write + Send + 'a>(
+    encoder: E,
+    output: fn(&mut E) -> &mut Vec<u8>,
+    finish: fn(E) -> Result<Vec<u8>, std::io::Error>,
+    mut chunks: impl Iterator<Item = Result<Vec<u8>, std::io::Error>> + Send + 'a,
+) -> impl Iterator<Item = Result<Vec<u8>, std::io::Error>> + Send + 'a {
+    let mut encoder = Some(encoder);
+    std::iter::from_fn(move || loop {
+        let Some(chunk) = chunks.next() else {
+            return match finish(encoder.take()?) {
+                Ok(rest) => (!rest.is_empty()).then_some(Ok(rest)),
+                Err(e) => Some(Err(e)),
+            };
+        };
+        let current = encoder.as_mut()?;
+        if let Err(e) = chunk.and_then(|chunk| current.write_all(&chunk)) {
+            encoder = None;
+            return Some(Err(e));
+        }
+        let written = std::mem::take(output(current));
+        if !written.is_empty() {
+            return Some(Ok(written));
+        }
+    })
+}
+
+/// Returns `data` decompressed, detecting its compression from its first bytes.
+/// Data that is not compressed is returned as is.
+pub fn decompress(data: Vec<u8>) -> Result<Vec<u8>, std::io::Error> {
+    match data.as_slice() {
+        [0x1f, 0x8b, ..] => {
+            let mut result = vec![];
+            flate2::read::MultiGzDecoder::new(data.as_slice()).read_to_end(&mut result)?;
+            Ok(result)
+        }
+        [0x28, 0xb5, 0x2f, 0xfd, ..] => zstd::decode_all(data.as_slice()),
+        _ => Ok(data),
+    }
+}
+
+/// Recompresses the CSVs under `prefix` (e.g. `leg/v2/data/`) with `compression` in place: each blob of another
+/// compression is written with the suffix of `compression` and then deleted (see [`crate::io::put`]),
+/// `concurrency` blobs at a time.
+/// Returns the number of recompressed blobs.
+pub async fn recompress(
+    prefix: &str,
+    compression: Compression,
+    concurrency: usize,
+    client: &dyn BlobStorageProvider,
+) -> Result<usize, std::io::Error> {
+    let keys = client.list(prefix).await?;
+    log::info!("{prefix}: {} blobs", keys.len());
+    let tasks = keys
+        .into_iter()
+        .filter(|key| {
+            let (current, name) = Compression::of(key);
+            current != compression && name.ends_with(".csv")
+        })
+        .map(|key| async move {
+            let Some(data) = client.maybe_get(&key).await? else {
+                return Ok(false);
+            };
+            let (_, name) = Compression::of(&key);
+            crate::io::put(name, decompress(data)?, compression, client).await?;
+            Ok::<_, std::io::Error>(true)
+        });
+    let recompressed = futures::stream::iter(tasks)
+        .buffer_unordered(concurrency)
+        .try_fold(0, |count, done| async move {
+            if count % 1000 == 999 {
+                log::info!("recompressed {} blobs", count + 1);
+            }
+            Ok(count + done as usize)
+        })
+        .await?;
+    log::info!("{prefix}: recompressed {recompressed} blobs");
+    Ok(recompressed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let data = b"icao_number,start\n459cd3,2023-01-01T10:00:00Z\n".repeat(100);
+        for compression in Compression::ALL {
+            let compressed = compression.compress(data.clone()).unwrap();
+            if compression != Compression::None {
+                assert!(compressed.len() < data.len());
+            }
+            assert_eq!(decompress(compressed).unwrap(), data);
+
+            let chunks = data.chunks(7).map(|x| Ok(x.to_vec())).collect::<Vec<_>>();
+            let compressed = compression
+                .compress_chunks(chunks.into_iter())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+                .concat();
+            assert_eq!(decompress(compressed).unwrap(), data);
+        }
+
+        assert_eq!(
+            Compression::of("a/data.csv.zst"),
+            (Compression::Zstd, "a/data.csv")
+        );
+        assert_eq!(
+            Compression::of("a/data.csv.gz"),
+            (Compression::Gzip, "a/data.csv")
+        );
+        assert_eq!(
+            Compression::of("a/data.csv"),
+            (Compression::None, "a/data.csv")
+        );
+    }
+
+    #[tokio::test]
+    async fn recompress_in_place() {
+        let root = std::env::temp_dir().join("test_recompress");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let data = b"icao_number\n459cd3\n".to_vec();
+        disk.put(
+            "leg/v2/data/month=2023-01/icao_number=459cd3/data.csv",
+            data.clone(),
+        )
+        .await
+        .unwrap();
+        disk.put("leg/v2/data/month=2023-01/manifest.json", b"{}".to_vec())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            recompress("leg/v2/data/", Compression::Zstd, 2, &disk)
+                .await
+                .unwrap(),
+            1
+        );
+        // idempotent
+        assert_eq!(
+            recompress("leg/v2/data/", Compression::Zstd, 2, &disk)
+                .await
+                .unwrap(),
+            0
+        );
+
+        let key = "leg/v2/data/month=2023-01/icao_number=459cd3/data.csv";
+        assert_eq!(disk.maybe_get(key).await.unwrap(), None);
+        assert_eq!(crate::io::maybe_get(key, &disk).await.unwrap(), Some(data));
+        assert!(disk
+            .maybe_get("leg/v2/data/month=2023-01/manifest.json")
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
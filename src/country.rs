@@ -1,4 +1,7 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, OnceLock},
+};
 
 #[derive(Debug, serde::Deserialize, Clone)]
 struct CountryRange {
@@ -55,6 +58,68 @@ impl CountryIcaoRanges {
     }
 }
 
+static RANGES: OnceLock<CountryIcaoRanges> = OnceLock::new();
+
+/// Returns the country of `icao_number` derived from ICAO's allocation of 24-bit addresses,
+/// or `None` when the number is not in hex format or is not allocated to any country.
+pub fn allocation_country(icao_number: &str) -> Option<Arc<str>> {
+    RANGES
+        .get_or_init(CountryIcaoRanges::new)
+        .country(icao_number)
+        .ok()
+        .flatten()
+        .cloned()
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+struct NationalityMark {
+    prefix: String,
+    country: Arc<str>,
+}
+
+static MARKS: OnceLock<Vec<NationalityMark>> = OnceLock::new();
+
+/// Returns the country of the nationality mark of `tail_number` (e.g. `OY-` for Denmark) in
+/// `src/nationality_marks.csv`, from ICAO Annex 7, or `None` when it has none (e.g. `B-`, shared by China and
+/// Taiwan). Unlike [`allocation_country`], this is the country claimed by the registration of the aircraft.
+pub fn tail_number_country(tail_number: &str) -> Option<Arc<str>> {
+    MARKS
+        .get_or_init(|| {
+            let data = std::fs::read("src/nationality_marks.csv")
+                .expect("src/nationality_marks.csv to exist");
+            crate::csv::deserialize::<NationalityMark>(&data)
+                .collect::<Result<_, _>>()
+                .expect("src/nationality_marks.csv to be deserializable")
+        })
+        .iter()
+        .filter(|mark| tail_number.starts_with(mark.prefix.as_str()))
+        .max_by_key(|mark| mark.prefix.len())
+        .map(|mark| mark.country.clone())
+}
+
+/// Tail number prefixes of registries whose ICAO numbers are allocated within the block of another country
+/// (e.g. the Isle of Man within the United Kingdom), where the allocation does not identify the registry.
+static OFFSHORE_REGISTRIES: [(&str, &str); 5] = [
+    ("M-", "Isle of Man"),
+    ("2-", "Guernsey"),
+    ("VP-B", "Bermuda"),
+    ("VQ-B", "Bermuda"),
+    ("VP-C", "Cayman Islands"),
+];
+
+/// Returns the country of registration of an aircraft (see `M-country-of-registration`): the registry of its
+/// `tail_number` when it is an offshore registry, and otherwise the [`allocation_country`] of its `icao_number`.
+pub fn registration_country(icao_number: &str, tail_number: Option<&str>) -> Option<Arc<str>> {
+    tail_number
+        .and_then(|tail_number| {
+            OFFSHORE_REGISTRIES
+                .iter()
+                .find(|(prefix, _)| tail_number.starts_with(prefix))
+        })
+        .map(|(_, country)| (*country).into())
+        .or_else(|| allocation_country(icao_number))
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -72,4 +137,33 @@ mod test {
         // exists in ads-b, but can't be assigned
         assert_eq!(CountryIcaoRanges::new().country("EA00CA"), Ok(None));
     }
+
+    #[test]
+    fn allocation() {
+        assert_eq!(allocation_country("458d6b"), Some("Denmark".into()));
+        assert_eq!(allocation_country("EA00CA"), None);
+        assert_eq!(allocation_country("not hex"), None);
+    }
+
+    #[test]
+    fn tail_number() {
+        assert_eq!(tail_number_country("OY-GFS"), Some("Denmark".into()));
+        assert_eq!(tail_number_country("N123AB"), Some("United States".into()));
+        // the longest prefix
+        assert_eq!(tail_number_country("VP-CAB"), Some("Cayman Islands".into()));
+        assert_eq!(tail_number_country("B-8888"), None);
+    }
+
+    #[test]
+    fn registration() {
+        assert_eq!(
+            registration_country("43e8c1", Some("M-ABCD")),
+            Some("Isle of Man".into())
+        );
+        assert_eq!(
+            registration_country("458d6b", Some("OY-ABC")),
+            Some("Denmark".into())
+        );
+        assert_eq!(registration_country("458d6b", None), Some("Denmark".into()));
+    }
 }
@@ -1,25 +1,47 @@
-use std::{collections::HashMap, error::Error, hash::Hash};
-
-use serde::Deserialize;
-
-/// Loads a CSV from disk into a HashMap based on the primary key of the type
-/// # Error
-/// Errors if the file cannot be read
-pub(crate) fn load<H: Hash + Eq, D: for<'de> Deserialize<'de>, PK: Fn(D) -> (H, D)>(
-    path: &str,
-    map: PK,
-) -> Result<HashMap<H, D>, Box<dyn Error>> {
-    let data = std::fs::read(path)?;
-
-    Ok(deserialize(&data).map(|x| x.unwrap()).map(map).collect())
+pub fn serialize(items: impl Iterator<Item = impl serde::Serialize>) -> Vec<u8> {
+    serialize_into(items, vec![]).unwrap()
 }
 
-pub fn serialize(items: impl Iterator<Item = impl serde::Serialize>) -> Vec<u8> {
-    let mut wtr = csv::Writer::from_writer(vec![]);
-    for leg in items {
-        wtr.serialize(leg).unwrap()
+/// Serializes `items` as CSV into `writer`, returning it
+pub fn serialize_into<W: std::io::Write>(
+    items: impl Iterator<Item = impl serde::Serialize>,
+    writer: W,
+) -> Result<W, std::io::Error> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    for item in items {
+        wtr.serialize(item)?
     }
-    wtr.into_inner().unwrap()
+    wtr.into_inner().map_err(|e| e.into_error())
+}
+
+/// Returns `items` serialized as CSV in chunks of at least `chunk_size` bytes (except the last), so that
+/// they are written (see [`crate::fs::BlobStorageProvider::put_stream`]) without serializing all of them at once
+pub fn serialize_chunks<'a, T: serde::Serialize + 'a>(
+    mut items: impl Iterator<Item = T> + 'a,
+    chunk_size: usize,
+) -> impl Iterator<Item = Vec<u8>> + 'a {
+    let mut writer = Some(csv::Writer::from_writer(vec![]));
+    std::iter::from_fn(move || {
+        let wtr = writer.as_mut()?;
+        let mut is_last = true;
+        for item in items.by_ref() {
+            wtr.serialize(item).unwrap();
+            if wtr.get_ref().len() >= chunk_size {
+                is_last = false;
+                break;
+            }
+        }
+        let chunk = writer.take()?.into_inner().unwrap();
+        if !is_last {
+            // the header is only written on the first chunk
+            writer = Some(
+                csv::WriterBuilder::new()
+                    .has_headers(false)
+                    .from_writer(vec![]),
+            );
+        }
+        (!chunk.is_empty()).then_some(chunk)
+    })
 }
 
 pub fn deserialize<'a, D: serde::de::DeserializeOwned + 'a>(
@@ -29,7 +51,36 @@ pub fn deserialize<'a, D: serde::de::DeserializeOwned + 'a>(
         .delimiter(b',')
         .from_reader(std::io::Cursor::new(data));
     rdr.into_deserialize().into_iter().map(|r| {
-        let record: D = r?;
+        // so that it is an [`Error::Decode`](crate::Error::Decode)
+        let record: D = r.map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
         Ok(record)
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct Row {
+        a: usize,
+        b: String,
+    }
+
+    #[test]
+    fn chunks() {
+        let rows = || {
+            (0..10000).map(|a| Row {
+                a,
+                b: "b".repeat(a % 7),
+            })
+        };
+
+        let chunks = serialize_chunks(rows(), 10 * 1024).collect::<Vec<_>>();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().rev().skip(1).all(|x| x.len() >= 10 * 1024));
+        assert_eq!(chunks.concat(), serialize(rows()));
+
+        assert_eq!(serialize_chunks(std::iter::empty::<Row>(), 1).count(), 0);
+    }
+}
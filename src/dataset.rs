@@ -0,0 +1,489 @@
+//! Contains the implementation to query the public dataset of legs (`leg/{version}/all/year={year}/data.csv`, see
+//! [`Roots`]) without their trajectories: by region, and by filters with cursor pagination (see [`LegsQuery`]).
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use crate::{
+    etl::legs::{Roots, DEFAULT_VERSION},
+    fs::BlobStorageProvider,
+    region::Region,
+};
+
+/// The maximum number of legs of a [`Page`] when [`LegsQuery::limit`] is not set
+pub static DEFAULT_LIMIT: usize = 1000;
+
+/// Maximum distance in km between consecutive points of the great-circle path of a leg
+/// used to check whether it crosses a [`Region`]
+static STEP: f64 = 5.0;
+
+/// A leg of the public dataset of legs, restricted to the columns needed to query it
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct DatasetLeg {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The start timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: time::OffsetDateTime,
+    /// The start latitude
+    pub start_lat: f64,
+    /// The start longitude
+    pub start_lon: f64,
+    /// The end timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: time::OffsetDateTime,
+    /// The end latitude
+    pub end_lat: f64,
+    /// The end longitude
+    pub end_lon: f64,
+    /// The aircraft model, when known
+    #[serde(default)]
+    pub aircraft_model: Option<Arc<str>>,
+    /// The identifier of the departure airport, when known
+    #[serde(default)]
+    pub from_airport_icao: Option<Arc<str>>,
+    /// The identifier of the arrival airport, when known
+    #[serde(default)]
+    pub to_airport_icao: Option<Arc<str>>,
+}
+
+impl From<crate::etl::legs::LegOut> for DatasetLeg {
+    fn from(leg: crate::etl::legs::LegOut) -> Self {
+        Self {
+            icao_number: leg.icao_number,
+            start: leg.start,
+            start_lat: leg.start_lat,
+            start_lon: leg.start_lon,
+            end: leg.end,
+            end_lat: leg.end_lat,
+            end_lon: leg.end_lon,
+            aircraft_model: leg.aircraft_model,
+            from_airport_icao: leg.from_airport_icao,
+            to_airport_icao: leg.to_airport_icao,
+        }
+    }
+}
+
+/// Returns the `(latitude, longitude)` at `fraction` of the great circle from `from` to `to`
+pub(crate) fn intermediate(from: (f64, f64), to: (f64, f64), fraction: f64) -> (f64, f64) {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+    let (distance, _) = crate::region::angular_distance_bearing((lon1, lat1), (lon2, lat2));
+    if distance == 0.0 {
+        return from;
+    }
+    let a = ((1.0 - fraction) * distance).sin() / distance.sin();
+    let b = (fraction * distance).sin() / distance.sin();
+    let x = a * lat1.cos() * lon1.cos() + b * lat2.cos() * lon2.cos();
+    let y = a * lat1.cos() * lon1.sin() + b * lat2.cos() * lon2.sin();
+    let z = a * lat1.sin() + b * lat2.sin();
+    (
+        z.atan2((x * x + y * y).sqrt()).to_degrees(),
+        y.atan2(x).to_degrees(),
+    )
+}
+
+impl DatasetLeg {
+    /// Returns points of the great-circle path of the leg, at most [`STEP`] km apart
+    pub fn great_circle(&self) -> impl Iterator<Item = (f64, f64)> {
+        let from = (self.start_lat, self.start_lon);
+        let to = (self.end_lat, self.end_lon);
+        let steps = (crate::distance(from, to) / STEP).ceil().max(1.0) as usize;
+        (0..=steps).map(move |i| intermediate(from, to, i as f64 / steps as f64))
+    }
+
+    /// Returns whether the great-circle path of the leg intersects `region`
+    /// # Implementation
+    /// The path is approximated by points at most [`STEP`] km apart, so regions narrower than
+    /// that may be missed.
+    pub fn crosses(&self, region: &Region) -> bool {
+        self.great_circle().any(|point| region.contains(point))
+    }
+}
+
+/// Returns the legs of the public dataset at `roots` on `year`; years without it in the default version are read
+/// from the legacy database (see [`crate::legacy`]), and years in neither have no legs
+async fn year_legs(
+    year: i32,
+    roots: &Roots,
+    client: &dyn BlobStorageProvider,
+) -> Result<Vec<DatasetLeg>, std::io::Error> {
+    let key = format!("{}all/year={year}/data.csv", roots.aggregated(&roots.legs));
+    if let Some(data) = crate::io::maybe_get(&key, client).await? {
+        return crate::csv::deserialize::<DatasetLeg>(&data).collect();
+    }
+    if &*roots.version != DEFAULT_VERSION {
+        return Ok(vec![]);
+    }
+    let legacy = crate::legacy::read(&crate::legacy::year_blob_name(year), client).await?;
+    Ok(legacy
+        .unwrap_or_default()
+        .into_iter()
+        .map(DatasetLeg::from)
+        .collect())
+}
+
+/// Returns the legs of the public dataset at `roots` on `year` whose great-circle path intersects `region`
+/// (e.g. a [`Region::Polygons`] of a national park or a [`Region::Corridor`] along a fjord).
+/// Use [`Roots::published`] for the public dataset of the published version.
+/// # Error
+/// Errors if the dataset of the year cannot be read or is not a valid CSV
+pub async fn legs_crossing(
+    region: &Region,
+    year: i32,
+    roots: &Roots,
+    client: &dyn BlobStorageProvider,
+) -> Result<Vec<DatasetLeg>, std::io::Error> {
+    let mut legs = year_legs(year, roots, client).await?;
+    legs.retain(|leg| leg.crosses(region));
+    Ok(legs)
+}
+
+/// A position in the legs of a query, ordered by ICAO number, start and end, after which the next [`Page`] starts.
+/// It is written as `{icao_number}@{start}@{end}`, with the unix timestamps in nanoseconds, since starts and ends
+/// have fractions of seconds (e.g. `45d2ed@1672567200500000000@1672570800000000000`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub icao_number: Arc<str>,
+    pub start: time::OffsetDateTime,
+    /// the end of the leg, so that legs of an aircraft with the same start are not skipped nor repeated
+    pub end: time::OffsetDateTime,
+}
+
+impl Cursor {
+    fn of(leg: &DatasetLeg) -> Self {
+        Self {
+            icao_number: leg.icao_number.clone(),
+            start: leg.start,
+            end: leg.end,
+        }
+    }
+
+    fn key(&self) -> (&str, time::OffsetDateTime, time::OffsetDateTime) {
+        (&self.icao_number, self.start, self.end)
+    }
+}
+
+impl std::fmt::Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}@{}@{}",
+            self.icao_number,
+            self.start.unix_timestamp_nanos(),
+            self.end.unix_timestamp_nanos()
+        )
+    }
+}
+
+impl std::str::FromStr for Cursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("cursor `{s}` must be `{{icao_number}}@{{start}}@{{end}}`");
+        let datetime = |nanos: &str| {
+            let nanos = nanos.parse::<i128>().map_err(|_| error())?;
+            time::OffsetDateTime::from_unix_timestamp_nanos(nanos).map_err(|_| error())
+        };
+        let (icao_number, datetimes) = s.split_once('@').ok_or_else(error)?;
+        let (start, end) = datetimes.split_once('@').ok_or_else(error)?;
+        Ok(Self {
+            icao_number: icao_number.into(),
+            start: datetime(start)?,
+            end: datetime(end)?,
+        })
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// A query of the legs of a year of the public dataset, e.g. as parsed by an HTTP API from its query string
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegsQuery {
+    /// Only legs starting on or after this date
+    pub from: Option<time::Date>,
+    /// Only legs starting before this date
+    pub to: Option<time::Date>,
+    /// Only legs departing from or arriving at this airport
+    pub airport: Option<String>,
+    /// Only legs of this aircraft model
+    pub model: Option<String>,
+    /// Only legs after this cursor (the `next` of the previous [`Page`])
+    pub cursor: Option<Cursor>,
+    /// The maximum number of legs of the [`Page`] (at least 1)
+    pub limit: usize,
+}
+
+impl Default for LegsQuery {
+    fn default() -> Self {
+        Self {
+            from: None,
+            to: None,
+            airport: None,
+            model: None,
+            cursor: None,
+            limit: DEFAULT_LIMIT,
+        }
+    }
+}
+
+impl LegsQuery {
+    /// Whether `leg` passes the filters of the query (the cursor and limit are not filters)
+    pub fn matches(&self, leg: &DatasetLeg) -> bool {
+        let date = leg.start.date();
+        let airport = |airport: &Option<Arc<str>>| airport.as_deref() == self.airport.as_deref();
+        self.from.is_none_or(|from| date >= from)
+            && self.to.is_none_or(|to| date < to)
+            && (self.airport.is_none()
+                || airport(&leg.from_airport_icao)
+                || airport(&leg.to_airport_icao))
+            && self
+                .model
+                .as_deref()
+                .is_none_or(|model| leg.aircraft_model.as_deref() == Some(model))
+    }
+
+    /// Returns the [`Page`] of `legs` of this query
+    pub fn page(&self, legs: impl Iterator<Item = DatasetLeg>) -> Page {
+        let mut legs = legs
+            .filter(|leg| self.matches(leg))
+            .filter(|leg| {
+                self.cursor
+                    .as_ref()
+                    .is_none_or(|cursor| (&*leg.icao_number, leg.start, leg.end) > cursor.key())
+            })
+            .collect::<Vec<_>>();
+        legs.sort_unstable_by(|a, b| {
+            (&a.icao_number, a.start, a.end).cmp(&(&b.icao_number, b.start, b.end))
+        });
+        let limit = self.limit.max(1);
+        let next = (legs.len() > limit).then(|| Cursor::of(&legs[limit - 1]));
+        legs.truncate(limit);
+        Page { legs, next }
+    }
+}
+
+/// A page of legs of a [`LegsQuery`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Page {
+    pub legs: Vec<DatasetLeg>,
+    /// The cursor of the next page, `None` when this is the last page
+    pub next: Option<Cursor>,
+}
+
+/// A representation of a [`Page`] that a client can request (e.g. via the HTTP `Accept` header)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// `application/json`: the [`Page`] as an object with `legs` and `next`
+    Json,
+    /// `text/csv`: the legs of the [`Page`]; the cursor of the next page must be returned out of band (e.g. in a header)
+    Csv,
+}
+
+impl ContentType {
+    /// Returns the first [`ContentType`] of an HTTP `Accept` header that is supported, defaulting to JSON
+    /// when the header is missing or accepts anything. Returns `None` when no media type is supported.
+    /// Quality values are ignored; media types are taken in the order they are written.
+    pub fn negotiate(accept: Option<&str>) -> Option<Self> {
+        let Some(accept) = accept else {
+            return Some(Self::Json);
+        };
+        accept
+            .split(',')
+            .map(|media| media.split(';').next().unwrap_or_default().trim())
+            .find_map(|media| match media {
+                "application/json" | "application/*" | "*/*" => Some(Self::Json),
+                "text/csv" | "text/*" => Some(Self::Csv),
+                _ => None,
+            })
+    }
+
+    /// The media type (e.g. `text/csv`)
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Csv => "text/csv",
+        }
+    }
+
+    /// Serializes `page` in this content type
+    pub fn serialize(&self, page: &Page) -> Vec<u8> {
+        match self {
+            Self::Json => serde_json::to_vec(page).expect("a page is always serializable"),
+            Self::Csv => crate::csv::serialize(page.legs.iter()),
+        }
+    }
+}
+
+/// Returns the [`Page`] of `query` of the legs of the public dataset at `roots` on `year`.
+/// Use [`Roots::published`] for the public dataset of the published version.
+/// # Error
+/// Errors if the dataset of the year cannot be read or is not a valid CSV
+pub async fn query_legs(
+    query: &LegsQuery,
+    year: i32,
+    roots: &Roots,
+    client: &dyn BlobStorageProvider,
+) -> Result<Page, std::io::Error> {
+    let legs = year_legs(year, roots, client).await?;
+    Ok(query.page(legs.into_iter()))
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn leg(from: (f64, f64), to: (f64, f64)) -> DatasetLeg {
+        DatasetLeg {
+            icao_number: "45d2ed".into(),
+            start: datetime!(2023-01-01 10:00:00 UTC),
+            start_lat: from.0,
+            start_lon: from.1,
+            end: datetime!(2023-01-01 11:00:00 UTC),
+            end_lat: to.0,
+            end_lon: to.1,
+            aircraft_model: Some("GULFSTREAM 5".into()),
+            from_airport_icao: Some("EKCH".into()),
+            to_airport_icao: Some("EDDB".into()),
+        }
+    }
+
+    #[test]
+    fn crossing() {
+        // Copenhagen to Berlin
+        let leg = leg((55.6, 12.6), (52.4, 13.5));
+        // neither end is in the region, but the path is
+        let baltic = Region::from_bbox("12.5,54.0,13.5,54.5").unwrap();
+        assert!(leg.crosses(&baltic));
+        let jutland = Region::from_bbox("8.0,55.0,9.5,57.0").unwrap();
+        assert!(!leg.crosses(&jutland));
+
+        let corridor = Region::Corridor {
+            path: vec![(12.0, 54.2), (14.0, 54.2)],
+            width: 10.0,
+        };
+        assert!(leg.crosses(&corridor));
+
+        let points = leg.great_circle().collect::<Vec<_>>();
+        let close =
+            |a: &(f64, f64), b: (f64, f64)| (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9;
+        assert!(close(points.first().unwrap(), (55.6, 12.6)));
+        assert!(close(points.last().unwrap(), (52.4, 13.5)));
+    }
+
+    #[test]
+    fn query() {
+        let legs = (0..5)
+            .map(|day| {
+                let mut leg = leg((55.6, 12.6), (52.4, 13.5));
+                leg.start += time::Duration::days(day);
+                leg
+            })
+            .collect::<Vec<_>>();
+
+        let query = LegsQuery {
+            from: Some(time::macros::date!(2023 - 01 - 02)),
+            airport: Some("EDDB".to_string()),
+            limit: 2,
+            ..Default::default()
+        };
+        let page = query.page(legs.clone().into_iter());
+        assert_eq!(page.legs, legs[1..3]);
+        let next = page.next.unwrap();
+        assert_eq!(next.to_string().parse::<Cursor>().unwrap(), next);
+
+        let query = LegsQuery {
+            cursor: Some(next),
+            ..query
+        };
+        let page = query.page(legs.clone().into_iter());
+        assert_eq!(page.legs, legs[3..5]);
+        assert_eq!(page.next, None);
+
+        let query = LegsQuery {
+            model: Some("BEECH 400 Beechjet".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query.page(legs.into_iter()).legs, vec![]);
+
+        assert_eq!(
+            ContentType::negotiate(Some("text/csv;q=0.9, application/json")),
+            Some(ContentType::Csv)
+        );
+        assert_eq!(ContentType::negotiate(None), Some(ContentType::Json));
+        assert_eq!(ContentType::negotiate(Some("image/png")), None);
+    }
+
+    #[test]
+    fn cursor_precision() {
+        let mut legs = [(); 3].map(|_| leg((55.6, 12.6), (52.4, 13.5)));
+        // starts have fractions of seconds (see `M-identify-legs`)
+        legs[0].start = datetime!(2023-01-01 10:00:00.25 UTC);
+        legs[1].start = datetime!(2023-01-01 10:00:00.75 UTC);
+        // the same start as the previous leg
+        legs[2].start = legs[1].start;
+        legs[2].end = datetime!(2023-01-01 12:00:00 UTC);
+
+        let mut query = LegsQuery {
+            limit: 1,
+            ..Default::default()
+        };
+        let mut pages = vec![];
+        loop {
+            let page = query.page(legs.clone().into_iter());
+            pages.extend(page.legs);
+            let Some(next) = page.next else { break };
+            assert_eq!(next.to_string().parse::<Cursor>().unwrap(), next);
+            query.cursor = Some(next.to_string().parse().unwrap());
+        }
+        assert_eq!(pages, legs);
+    }
+
+    #[tokio::test]
+    async fn legacy() {
+        let root = std::env::temp_dir().join("test_dataset_legacy");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let data = b"icao_number,aircraft_model,start,from_lat,from_lon,from_altitude,end,to_lat,to_lon,to_altitude,duration,distance,hours_above_30000,hours_above_40000
+45d2ed,GULFSTREAM 5,2019-01-01 10:00:00,55.6,12.6,0,2019-01-01 11:00:00,52.4,13.5,0,1.0,400,0.0,0.0
+";
+        crate::io::put(
+            &crate::legacy::year_blob_name(2019),
+            data.to_vec(),
+            crate::compression::Compression::None,
+            &disk,
+        )
+        .await
+        .unwrap();
+
+        let roots = Roots::default();
+        let page = query_legs(&LegsQuery::default(), 2019, &roots, &disk)
+            .await
+            .unwrap();
+        let mut expected = leg((55.6, 12.6), (52.4, 13.5));
+        expected.start = datetime!(2019-01-01 10:00:00 UTC);
+        expected.end = datetime!(2019-01-01 11:00:00 UTC);
+        expected.from_airport_icao = None;
+        expected.to_airport_icao = None;
+        assert_eq!(page.legs, vec![expected]);
+        assert!(query_legs(&LegsQuery::default(), 2020, &roots, &disk)
+            .await
+            .unwrap()
+            .legs
+            .is_empty());
+        // other versions are not completed with the legacy database
+        assert!(
+            query_legs(&LegsQuery::default(), 2019, &Roots::new("v3"), &disk)
+                .await
+                .unwrap()
+                .legs
+                .is_empty()
+        );
+    }
+}
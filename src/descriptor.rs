@@ -0,0 +1,167 @@
+//! Contains the descriptor of the methodology (`M-descriptor`): a machine-readable record of every choice of a run
+//! that affects the numbers of the datasets of legs (thresholds, factors of emissions, models, units), written next
+//! to them as `methodology.json` so that the published datasets are self-documenting and reviewable.
+use serde::Serialize;
+
+use crate::{
+    commercial::Parameters,
+    emissions::EmissionsConfig,
+    etl::legs::{AggregateConfig, LegOut},
+    legs::LegsConfig,
+    model::{ModelOverride, ModelReclassification},
+};
+
+/// The name of the blob of the descriptor, next to `status.json`
+pub static BLOB: &str = "methodology.json";
+
+/// The settings of the computation of legs that are not part of [`AggregateConfig`]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Processing {
+    /// The thresholds identifying legs
+    pub legs: LegsConfig,
+    /// Whether barometric altitudes were corrected with the QNH of METAR observations (see `M-altitudes`)
+    pub qnh_correction: bool,
+    /// The resolution in degrees of the grid of winds aloft, when legs were enriched with them (see `M-winds`)
+    pub winds_resolution: Option<f64>,
+}
+
+/// The thresholds of [`LegsConfig`], with durations in seconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Thresholds {
+    /// km/h
+    pub min_ground_speed: f64,
+    /// seconds
+    pub max_time_gap: f64,
+    /// seconds
+    pub min_duration: f64,
+    /// km
+    pub min_distance: f64,
+    /// feet
+    pub landing_altitude_threshold: f64,
+    /// km/h
+    pub max_taxi_speed: f64,
+    /// km/h
+    pub stationary_speed: f64,
+    /// seconds
+    pub min_parked: f64,
+}
+
+impl From<LegsConfig> for Thresholds {
+    fn from(config: LegsConfig) -> Self {
+        Self {
+            min_ground_speed: config.min_ground_speed,
+            max_time_gap: config.max_time_gap.as_seconds_f64(),
+            min_duration: config.min_duration.as_seconds_f64(),
+            min_distance: config.min_distance,
+            landing_altitude_threshold: config.landing_altitude_threshold,
+            max_taxi_speed: config.max_taxi_speed,
+            stationary_speed: config.stationary_speed,
+            min_parked: config.min_parked.as_seconds_f64(),
+        }
+    }
+}
+
+/// The thresholds of the existence of a commercial alternative (see `M-commercial-alternative`), in km
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Alternative {
+    pub airport_max_distance: f64,
+    pub min_route_distance: f64,
+    pub max_medium_route_distance: f64,
+}
+
+/// The descriptor of the methodology of a run, written to [`BLOB`]
+#[derive(Serialize)]
+pub struct Descriptor<'a> {
+    /// the version of this crate
+    pub version: &'static str,
+    /// the version of the schema of the datasets (see [`crate::schema::version`])
+    pub schema_version: String,
+    pub legs: Thresholds,
+    pub qnh_correction: bool,
+    pub winds_resolution: Option<f64>,
+    /// the factors used to compute `co2_emissions`
+    pub emissions: EmissionsConfig,
+    /// the parameters of the backend computing `commercial_co2_emissions`
+    pub commercial: Parameters,
+    pub commercial_alternative: Alternative,
+    /// the models whose consumption was overridden by `src/models_overrides.csv`
+    pub model_overrides: &'a [ModelOverride],
+    /// the reclassifications of models in `src/models_changelog.csv`
+    pub model_changelog: &'a [ModelReclassification],
+    /// the calendar in which legs are assigned to years
+    pub timezone: &'static str,
+    pub distance_unit: &'static str,
+    pub mass_unit: &'static str,
+}
+
+impl<'a> Descriptor<'a> {
+    /// Returns the [`Descriptor`] of a run aggregating legs with `config`
+    pub fn new(config: &AggregateConfig<'a>) -> Self {
+        let processing = config.processing;
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            schema_version: crate::schema::version::<LegOut>(),
+            legs: processing.legs.into(),
+            qnh_correction: processing.qnh_correction,
+            winds_resolution: processing.winds_resolution,
+            emissions: *config.emissions,
+            commercial: crate::commercial::parameters(config.emissions),
+            commercial_alternative: Alternative {
+                airport_max_distance: crate::commercial::ALTERNATIVE_AIRPORT_MAX_DISTANCE,
+                min_route_distance: crate::commercial::MIN_ROUTE_DISTANCE,
+                max_medium_route_distance: crate::commercial::MAX_MEDIUM_ROUTE_DISTANCE,
+            },
+            model_overrides: config.model_overrides,
+            model_changelog: config.model_changelog,
+            timezone: config.calendar.name(),
+            distance_unit: config.units.distance_unit(),
+            mass_unit: config.units.mass_unit(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        commercial::CommercialBackend, compression::Compression, etl::legs::Roots, format::Format,
+        fs_local::LocalDisk, timezone::Calendar, units::Units,
+    };
+
+    #[test]
+    fn work() {
+        let emissions = EmissionsConfig {
+            commercial_backend: CommercialBackend::Myclimate,
+            ..Default::default()
+        };
+        let roots = Roots::default();
+        let root = std::env::temp_dir().join("test_descriptor");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = LocalDisk::new(&root);
+        let config = AggregateConfig {
+            emissions: &emissions,
+            model_overrides: &[],
+            model_changelog: &[],
+            units: Units::Metric,
+            format: Format::Csv,
+            compression: Compression::None,
+            calendar: Calendar::Utc,
+            concurrency: 1,
+            year_concurrency: 1,
+            processing: &Processing::default(),
+            roots: &roots,
+            lock: None,
+            client: &disk,
+        };
+        let descriptor = Descriptor::new(&config);
+        let value = serde_json::to_value(&descriptor).unwrap();
+
+        assert_eq!(value["legs"]["max_time_gap"], 300.0);
+        assert_eq!(value["legs"]["min_distance"], 3.0);
+        assert_eq!(value["commercial"]["backend"], "myclimate");
+        assert_eq!(value["commercial"]["detour"], 95.0);
+        assert_eq!(value["commercial"]["short_haul"]["load_factor"], 0.82);
+        assert_eq!(value["emissions"]["jet_a"]["co2_per_kg"], 3.16);
+        assert_eq!(value["timezone"], "UTC");
+    }
+}
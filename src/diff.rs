@@ -0,0 +1,182 @@
+//! Contains the implementation to compare two runs of a dataset partitioned by month and ICAO number
+//! (e.g. `leg/v2/` and `leg/v3/`), so that the effect of a change of methodology can be reviewed before
+//! it replaces the published dataset.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::fs::BlobStorageProvider;
+
+/// A row of a CSV, as a map from column name to value
+type Row = BTreeMap<String, String>;
+
+/// The difference between two runs on a month and ICAO number
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionDiff {
+    /// The month (e.g. `2023-01`)
+    pub month: String,
+    /// The ICAO number
+    pub icao_number: String,
+    /// Number of rows only in the new run
+    pub added: usize,
+    /// Number of rows only in the old run
+    pub removed: usize,
+    /// Number of rows in both runs with different values
+    pub changed: usize,
+    /// Number of rows in both runs with the same values
+    pub unchanged: usize,
+}
+
+impl PartitionDiff {
+    /// Whether the runs differ on this partition
+    pub fn is_changed(&self) -> bool {
+        self.added + self.removed + self.changed > 0
+    }
+}
+
+fn rows(data: Option<&[u8]>, key: &[&str]) -> Result<HashMap<Vec<String>, Row>, std::io::Error> {
+    let Some(data) = data else {
+        return Ok(Default::default());
+    };
+    crate::csv::deserialize::<Row>(data)
+        .map(|row| {
+            let row = row?;
+            let pk = key
+                .iter()
+                .map(|column| {
+                    row.get(*column).cloned().ok_or_else(|| {
+                        std::io::Error::other(format!("column `{column}` does not exist"))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((pk, row))
+        })
+        .collect()
+}
+
+/// Returns the number of `(added, removed, changed, unchanged)` rows between two CSVs, where rows
+/// are identified by the columns `key`. A missing CSV has no rows.
+pub fn diff_csv(
+    old: Option<&[u8]>,
+    new: Option<&[u8]>,
+    key: &[&str],
+) -> Result<(usize, usize, usize, usize), std::io::Error> {
+    let old = rows(old, key)?;
+    let new = rows(new, key)?;
+
+    let removed = old.keys().filter(|pk| !new.contains_key(*pk)).count();
+    let (mut added, mut changed, mut unchanged) = (0, 0, 0);
+    for (pk, row) in &new {
+        match old.get(pk) {
+            None => added += 1,
+            Some(old_row) if old_row != row => changed += 1,
+            Some(_) => unchanged += 1,
+        }
+    }
+    Ok((added, removed, changed, unchanged))
+}
+
+/// Returns the set of `(month, icao_number)` of the partitions under `{root}data/`
+async fn partitions(
+    root: &str,
+    client: &dyn BlobStorageProvider,
+) -> Result<BTreeSet<(String, String)>, std::io::Error> {
+    let prefix = format!("{root}data/");
+    Ok(client
+        .list(&prefix)
+        .await?
+        .into_iter()
+        .filter_map(|blob| {
+            let partition = blob.strip_prefix(&prefix)?.strip_suffix("data.csv")?;
+            let keys = crate::serde::hive_to_map(partition);
+            Some((
+                keys.get("month")?.to_string(),
+                keys.get("icao_number")?.to_string(),
+            ))
+        })
+        .collect())
+}
+
+/// Returns the [`PartitionDiff`] of every partition `data/month={month}/icao_number={icao}/data.csv`
+/// in `old_root` or `new_root`, ordered by month and ICAO number. Rows are identified by the columns `key`.
+pub async fn diff(
+    old_root: &str,
+    new_root: &str,
+    key: &[&str],
+    client: &dyn BlobStorageProvider,
+) -> Result<Vec<PartitionDiff>, std::io::Error> {
+    let mut all = partitions(old_root, client).await?;
+    all.extend(partitions(new_root, client).await?);
+    log::info!("partitions: {}", all.len());
+
+    let tasks = all.into_iter().map(|(month, icao_number)| async move {
+        let blob =
+            |root: &str| format!("{root}data/month={month}/icao_number={icao_number}/data.csv");
+        let old = client.maybe_get(&blob(old_root)).await?;
+        let new = client.maybe_get(&blob(new_root)).await?;
+        let (added, removed, changed, unchanged) = diff_csv(old.as_deref(), new.as_deref(), key)?;
+        Ok::<_, std::io::Error>(PartitionDiff {
+            month,
+            icao_number,
+            added,
+            removed,
+            changed,
+            unchanged,
+        })
+    });
+
+    futures::stream::iter(tasks)
+        .buffered(100)
+        .try_collect()
+        .await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn csv() {
+        let old = b"icao_number,start,distance\naa,1,10\naa,2,20\naa,3,30\n";
+        let new = b"icao_number,start,distance\naa,2,20\naa,3,31\naa,4,40\naa,5,50\n";
+        assert_eq!(
+            diff_csv(Some(old), Some(new), &["icao_number", "start"]).unwrap(),
+            (2, 1, 1, 1)
+        );
+        assert_eq!(diff_csv(Some(old), None, &["start"]).unwrap(), (0, 3, 0, 0));
+        assert!(diff_csv(Some(old), None, &["end"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn work() {
+        let client = &crate::fs::LocalDisk;
+        let _ = std::fs::remove_dir_all("database/test_diff");
+        let blob = |root: &str, icao: &str| {
+            format!("test_diff/{root}/data/month=2023-01/icao_number={icao}/data.csv")
+        };
+        client
+            .put(&blob("old", "aa"), b"start,distance\n1,10\n".to_vec())
+            .await
+            .unwrap();
+        client
+            .put(&blob("new", "aa"), b"start,distance\n1,11\n".to_vec())
+            .await
+            .unwrap();
+        client
+            .put(&blob("new", "bb"), b"start,distance\n1,10\n".to_vec())
+            .await
+            .unwrap();
+
+        let diff = diff("test_diff/old/", "test_diff/new/", &["start"], client)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            diff.iter()
+                .map(|d| (d.icao_number.as_str(), d.added, d.removed, d.changed))
+                .collect::<Vec<_>>(),
+            vec![("aa", 0, 0, 1), ("bb", 1, 0, 0)]
+        );
+    }
+}
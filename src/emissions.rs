@@ -1,12 +1,139 @@
+use serde::{Deserialize, Serialize};
+
+use crate::commercial::CommercialBackend;
+
+pub mod phased;
+
 static LITER_PER_GALON: f64 = 3.78541;
-static KG_PER_LITER: f64 = 0.8;
-static EMISSIONS_PER_KG: f64 = 3.16;
+
+fn default_commercial_co2_per_km() -> f64 {
+    0.2
+}
+
+/// A type of aviation fuel
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, Hash, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fuel {
+    /// Kerosene-type fuel used by jets and turboprops
+    #[default]
+    JetA,
+    /// Aviation gasoline used by piston engines
+    Avgas,
+}
+
+/// Factors to convert a volume of fuel to CO2 emissions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FuelFactors {
+    /// The density of the fuel in kg per liter
+    pub kg_per_liter: f64,
+    /// The CO2 emitted in kg per kg of fuel burnt
+    pub co2_per_kg: f64,
+}
+
+/// A cabin class of a commercial flight
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CabinClass {
+    Economy,
+    Business,
+    First,
+}
+
+/// The emissions of a passenger of each [`CabinClass`] relative to a business class passenger
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ClassWeights {
+    pub economy: f64,
+    pub first: f64,
+}
+
+impl Default for ClassWeights {
+    /// The ratios of the factors of long haul flights of the UK government (DEFRA, 2023)
+    fn default() -> Self {
+        Self {
+            economy: 0.35,
+            first: 1.38,
+        }
+    }
+}
+
+/// The [`FuelFactors`] of each [`Fuel`], and the emissions of commercial flights
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct EmissionsConfig {
+    pub jet_a: FuelFactors,
+    pub avgas: FuelFactors,
+    /// The CO2 emitted in kg per km by a business class passenger of a commercial flight
+    #[serde(default = "default_commercial_co2_per_km")]
+    pub commercial_co2_per_km: f64,
+    /// The backend computing the emissions of commercial flights (see [`crate::commercial`])
+    #[serde(default)]
+    pub commercial_backend: CommercialBackend,
+    /// The emissions of passengers of other cabin classes relative to a business class passenger
+    #[serde(default)]
+    pub commercial_class_weights: ClassWeights,
+}
+
+impl Default for EmissionsConfig {
+    fn default() -> Self {
+        Self {
+            jet_a: FuelFactors {
+                kg_per_liter: 0.8,
+                co2_per_kg: 3.16,
+            },
+            avgas: FuelFactors {
+                kg_per_liter: 0.72,
+                co2_per_kg: 3.10,
+            },
+            commercial_co2_per_km: default_commercial_co2_per_km(),
+            commercial_backend: CommercialBackend::default(),
+            commercial_class_weights: ClassWeights::default(),
+        }
+    }
+}
+
+impl EmissionsConfig {
+    /// Returns a [`EmissionsConfig`] from JSON, e.g. `{"jet_a": {"kg_per_liter": 0.8, "co2_per_kg": 3.16}, ...}`
+    pub fn from_json(data: &[u8]) -> Result<Self, String> {
+        serde_json::from_slice(data).map_err(|e| e.to_string())
+    }
+
+    /// The [`FuelFactors`] of `fuel`
+    pub fn factors(&self, fuel: Fuel) -> FuelFactors {
+        match fuel {
+            Fuel::JetA => self.jet_a,
+            Fuel::Avgas => self.avgas,
+        }
+    }
+
+    /// Returns the total CO2 emissions in kg of an aircraft with a given
+    /// consumption (in GPH) of `fuel` flying for a given amount of time.
+    pub fn leg_co2_kg(&self, fuel: Fuel, consumption: f64, duration: time::Duration) -> f64 {
+        let hours = duration.as_seconds_f64() / 60.0 / 60.0;
+        let factors = self.factors(fuel);
+        consumption * hours * LITER_PER_GALON * factors.kg_per_liter * factors.co2_per_kg
+    }
+
+    /// Returns the CO2 emissions in kg of a business class passenger of a commercial flight
+    /// flying a given great-circle distance in km (see [`crate::commercial::ClassBased`]).
+    pub fn commercial_co2_kg(&self, distance: f64) -> f64 {
+        distance * self.commercial_co2_per_km
+    }
+
+    /// Returns the CO2 emissions in kg of a passenger of `class` of a commercial flight whose business class
+    /// passenger emits `business_co2_kg`
+    pub fn commercial_class_co2_kg(&self, business_co2_kg: f64, class: CabinClass) -> f64 {
+        let weights = self.commercial_class_weights;
+        match class {
+            CabinClass::Economy => business_co2_kg * weights.economy,
+            CabinClass::Business => business_co2_kg,
+            CabinClass::First => business_co2_kg * weights.first,
+        }
+    }
+}
 
 /// Returns the total CO2 emissions in kg of a private jet with a given
 /// consumption (in GPH) of Jet-A fuel flying for a given amount of time.
 pub fn leg_co2_kg(consumption: f64, duration: time::Duration) -> f64 {
-    let hours = duration.as_seconds_f64() / 60.0 / 60.0;
-    consumption * hours * LITER_PER_GALON * KG_PER_LITER * EMISSIONS_PER_KG
+    EmissionsConfig::default().leg_co2_kg(Fuel::JetA, consumption, duration)
 }
 
 #[cfg(test)]
@@ -20,4 +147,28 @@ mod test {
             5358.929228800001
         );
     }
+
+    #[test]
+    fn config() {
+        let config = EmissionsConfig::from_json(
+            br#"{"jet_a": {"kg_per_liter": 0.8, "co2_per_kg": 3.16}, "avgas": {"kg_per_liter": 0.7, "co2_per_kg": 3.0}}"#,
+        )
+        .unwrap();
+        assert_eq!(config.jet_a, EmissionsConfig::default().jet_a);
+        assert_eq!(config.commercial_co2_kg(100.0), 20.0);
+        assert_eq!(config.commercial_backend, CommercialBackend::ClassBased);
+        assert_eq!(
+            config.commercial_class_co2_kg(100.0, CabinClass::Economy),
+            35.0
+        );
+        assert_eq!(
+            config.commercial_class_co2_kg(100.0, CabinClass::Business),
+            100.0
+        );
+        assert_eq!(
+            config.leg_co2_kg(Fuel::Avgas, 10.0, time::Duration::hours(1)),
+            10.0 * LITER_PER_GALON * 0.7 * 3.0
+        );
+        assert!(EmissionsConfig::from_json(b"{}").is_err());
+    }
 }
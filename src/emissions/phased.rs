@@ -0,0 +1,142 @@
+//! Contains the phase-aware model of the fuel burn of a leg (`M-phased-emissions`): a leg is split into its climb,
+//! cruise and descent from the altitude of its positions, and the consumption of its model is scaled by the
+//! [`PhaseFactors`] of each phase, instead of being constant over the duration of the leg.
+use serde::{Deserialize, Serialize};
+
+use crate::Position;
+
+use super::{EmissionsConfig, Fuel};
+
+/// Positions at or above this fraction of the maximum altitude of a leg are cruising
+pub static CRUISE_FRACTION: f64 = 0.9;
+
+/// The fuel flow of each phase of a leg relative to the consumption (GPH) of its model
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PhaseFactors {
+    pub climb: f64,
+    pub cruise: f64,
+    pub descent: f64,
+}
+
+impl Default for PhaseFactors {
+    /// Factors of a typical business jet, whose average over a leg of 1.5 hours is close to 1
+    fn default() -> Self {
+        Self {
+            climb: 1.5,
+            cruise: 0.9,
+            descent: 0.4,
+        }
+    }
+}
+
+/// The duration of each phase of a leg
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Phases {
+    /// From the start of the leg to the first position cruising
+    pub climb: time::Duration,
+    /// From the first to the last position cruising
+    pub cruise: time::Duration,
+    /// From the last position cruising to the end of the leg
+    pub descent: time::Duration,
+}
+
+/// Returns the [`Phases`] of a leg given its `positions`, where positions at or above [`CRUISE_FRACTION`] of
+/// its maximum altitude are cruising.
+pub fn phases(positions: &[Position]) -> Phases {
+    let max = positions
+        .iter()
+        .map(|position| position.altitude())
+        .fold(0.0, f64::max);
+    let threshold = max * CRUISE_FRACTION;
+    let cruising = |position: &&Position| position.altitude() >= threshold;
+    let (Some(first), Some(last), Some(start), Some(end)) = (
+        positions.iter().find(cruising),
+        positions.iter().rev().find(cruising),
+        positions.first(),
+        positions.last(),
+    ) else {
+        return Phases {
+            climb: time::Duration::ZERO,
+            cruise: time::Duration::ZERO,
+            descent: time::Duration::ZERO,
+        };
+    };
+    Phases {
+        climb: first.datetime() - start.datetime(),
+        cruise: last.datetime() - first.datetime(),
+        descent: end.datetime() - last.datetime(),
+    }
+}
+
+/// Returns the total CO2 emissions in kg of a leg with `positions` of an aircraft with a given consumption
+/// (in GPH) of `fuel`, with the consumption of each of its [`Phases`] scaled by `factors`
+/// (see [`EmissionsConfig::leg_co2_kg`] for the constant consumption).
+pub fn leg_co2_kg(
+    config: &EmissionsConfig,
+    fuel: Fuel,
+    consumption: f64,
+    factors: PhaseFactors,
+    positions: &[Position],
+) -> f64 {
+    let phases = phases(positions);
+    // the duration at the constant consumption that burns the same fuel
+    let duration = phases.climb * factors.climb
+        + phases.cruise * factors.cruise
+        + phases.descent * factors.descent;
+    config.leg_co2_kg(fuel, consumption, duration)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn work() {
+        let pos = |(minutes, altitude): (i64, Option<f64>)| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(minutes * 60).unwrap(),
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude,
+            altitude_source: None,
+        };
+        let positions = [
+            (0, None),
+            (10, Some(20000.0)),
+            (20, Some(40000.0)),
+            (60, Some(37000.0)),
+            (80, Some(40000.0)),
+            (90, Some(10000.0)),
+            (100, None),
+        ]
+        .map(pos);
+        assert_eq!(
+            phases(&positions),
+            Phases {
+                climb: time::Duration::minutes(20),
+                cruise: time::Duration::minutes(60),
+                descent: time::Duration::minutes(20),
+            }
+        );
+
+        let config = EmissionsConfig::default();
+        let constant = PhaseFactors {
+            climb: 1.0,
+            cruise: 1.0,
+            descent: 1.0,
+        };
+        let emissions = leg_co2_kg(&config, Fuel::JetA, 280.0, constant, &positions);
+        let expected = config.leg_co2_kg(Fuel::JetA, 280.0, time::Duration::minutes(100));
+        assert!((emissions - expected).abs() < 1e-9);
+
+        // climbing burns more than cruising
+        let phased = leg_co2_kg(&config, Fuel::JetA, 280.0, Default::default(), &positions);
+        let expected = config.leg_co2_kg(
+            Fuel::JetA,
+            280.0,
+            time::Duration::minutes(20 * 15 + 60 * 9 + 20 * 4) / 10,
+        );
+        assert!((phased - expected).abs() < 1e-9);
+
+        assert_eq!(phases(&[]).cruise, time::Duration::ZERO);
+    }
+}
@@ -0,0 +1,207 @@
+//! Contains the stages that add columns to identified legs (e.g. departure and arrival airports),
+//! so that each run can be configured with the stages it needs.
+use std::sync::Arc;
+
+use crate::{
+    aircraft::Aircraft, airports::Airports, geo::Countries, legs::Leg, owners::Owners,
+    timezone::TimeZones,
+};
+
+/// The columns added to a leg by [`Enricher`]s. Columns are `None` when no enricher of the run computes them
+/// or when they are unknown for the leg.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Enrichment {
+    /// The identifier (ICAO code when it has one) of the departure airport
+    pub from_airport_icao: Option<Arc<str>>,
+    /// The name of the departure airport
+    pub from_airport_name: Option<Arc<str>>,
+    /// The distance in km between the start of the leg and the departure airport
+    pub from_airport_distance: Option<f64>,
+    /// The identifier (ICAO code when it has one) of the arrival airport
+    pub to_airport_icao: Option<Arc<str>>,
+    /// The name of the arrival airport
+    pub to_airport_name: Option<Arc<str>>,
+    /// The distance in km between the end of the leg and the arrival airport
+    pub to_airport_distance: Option<f64>,
+    /// The country (ISO 3166-1 alpha-2) of the start of the leg
+    pub from_country: Option<Arc<str>>,
+    /// The country (ISO 3166-1 alpha-2) of the end of the leg
+    pub to_country: Option<Arc<str>>,
+    /// The start of the leg in the local time of its position
+    pub start_local: Option<time::OffsetDateTime>,
+    /// The end of the leg in the local time of its position
+    pub end_local: Option<time::OffsetDateTime>,
+    /// The owner of the aircraft
+    pub owner: Option<Arc<str>>,
+    /// The type of the owner (e.g. `corporate`)
+    pub owner_type: Option<Arc<str>>,
+    /// The operator of the aircraft
+    pub operator: Option<Arc<str>>,
+    /// The type of the operator (e.g. `charter`)
+    pub operator_type: Option<Arc<str>>,
+    /// The names of the overlays flown over, separated by `;`
+    pub overlays: Option<Arc<str>>,
+    /// The time in minutes flown inside overlays
+    pub overlay_minutes: Option<f64>,
+    /// The lowest altitude in feet flown inside overlays
+    pub overlay_min_altitude: Option<f64>,
+    /// The distance in km between the leg and the closest overlay
+    pub overlay_distance: Option<f64>,
+}
+
+/// A stage that adds columns to a leg given the leg and its aircraft (`None` when the ICAO number
+/// is not in the database of aircrafts)
+pub trait Enricher: Send + Sync {
+    /// The name used to select this enricher on a run (e.g. `airports`)
+    fn name(&self) -> &'static str;
+
+    /// Sets the columns of `enrichment` computed by this enricher
+    fn enrich(&self, leg: &Leg, aircraft: Option<&Aircraft>, enrichment: &mut Enrichment);
+}
+
+/// Adds the departure and arrival airports (see `M-leg-airports`)
+impl Enricher for Airports {
+    fn name(&self) -> &'static str {
+        "airports"
+    }
+
+    fn enrich(&self, leg: &Leg, _: Option<&Aircraft>, enrichment: &mut Enrichment) {
+        if let Some((airport, distance)) =
+            self.closest_airport(leg.from().latitude(), leg.from().longitude())
+        {
+            enrichment.from_airport_icao = Some(airport.ident.as_str().into());
+            enrichment.from_airport_name = Some(airport.name.as_str().into());
+            enrichment.from_airport_distance = Some(distance);
+        }
+        if let Some((airport, distance)) =
+            self.closest_airport(leg.to().latitude(), leg.to().longitude())
+        {
+            enrichment.to_airport_icao = Some(airport.ident.as_str().into());
+            enrichment.to_airport_name = Some(airport.name.as_str().into());
+            enrichment.to_airport_distance = Some(distance);
+        }
+    }
+}
+
+/// Adds the departure and arrival countries (see `M-leg-countries`)
+impl Enricher for Countries {
+    fn name(&self) -> &'static str {
+        "countries"
+    }
+
+    fn enrich(&self, leg: &Leg, _: Option<&Aircraft>, enrichment: &mut Enrichment) {
+        let country = |latitude, longitude| {
+            self.country_of(latitude, longitude)
+                .map(|country| country.iso_code.clone())
+        };
+        enrichment.from_country = country(leg.from().latitude(), leg.from().longitude());
+        enrichment.to_country = country(leg.to().latitude(), leg.to().longitude());
+    }
+}
+
+/// Adds the start and end of the leg in local time (see `M-local-times`)
+impl Enricher for TimeZones {
+    fn name(&self) -> &'static str {
+        "timezones"
+    }
+
+    fn enrich(&self, leg: &Leg, _: Option<&Aircraft>, enrichment: &mut Enrichment) {
+        let local = |position: &crate::Position| {
+            self.local(
+                position.datetime(),
+                position.latitude(),
+                position.longitude(),
+            )
+        };
+        enrichment.start_local = local(leg.from());
+        enrichment.end_local = local(leg.to());
+    }
+}
+
+/// Adds the owner and operator of the aircraft (see `M-owners`)
+impl Enricher for Owners {
+    fn name(&self) -> &'static str {
+        "owners"
+    }
+
+    fn enrich(&self, _: &Leg, aircraft: Option<&Aircraft>, enrichment: &mut Enrichment) {
+        let Some(owner) = aircraft.and_then(|a| self.get(&a.icao_number, &a.tail_number)) else {
+            return;
+        };
+        enrichment.owner = owner.owner.clone();
+        enrichment.owner_type = owner.owner_type.map(|x| x.as_str().into());
+        enrichment.operator = owner.operator.clone();
+        enrichment.operator_type = owner.operator_type.map(|x| x.as_str().into());
+    }
+}
+
+/// The [`Enricher`]s of a run, applied in order
+#[derive(Default)]
+pub struct Enrichers<'a> {
+    enrichers: Vec<&'a dyn Enricher>,
+}
+
+impl<'a> Enrichers<'a> {
+    /// Returns the [`Enrichers`] of `available` whose names are in `names`, in the order of `names`
+    /// # Error
+    /// Errors if a name is not the name of any of `available`
+    pub fn select(available: &[&'a dyn Enricher], names: &[String]) -> Result<Self, String> {
+        let enrichers = names
+            .iter()
+            .map(|name| {
+                available
+                    .iter()
+                    .find(|enricher| enricher.name() == name.as_str())
+                    .copied()
+                    .ok_or_else(|| {
+                        let names = available.iter().map(|e| e.name()).collect::<Vec<_>>();
+                        format!("enricher `{name}` must be one of {names:?}")
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { enrichers })
+    }
+
+    /// The names of the enrichers
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.enrichers.iter().map(|enricher| enricher.name())
+    }
+
+    /// Returns the [`Enrichment`] of `leg` by all enrichers
+    pub fn enrich(&self, leg: &Leg, aircraft: Option<&Aircraft>) -> Enrichment {
+        let mut enrichment = Enrichment::default();
+        for enricher in &self.enrichers {
+            enricher.enrich(leg, aircraft, &mut enrichment);
+        }
+        enrichment
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Constant;
+
+    impl Enricher for Constant {
+        fn name(&self) -> &'static str {
+            "constant"
+        }
+
+        fn enrich(&self, _: &Leg, _: Option<&Aircraft>, enrichment: &mut Enrichment) {
+            enrichment.from_country = Some("DK".into());
+        }
+    }
+
+    #[test]
+    fn select() {
+        let available: &[&dyn Enricher] = &[&Constant];
+        let enrichers = Enrichers::select(available, &["constant".to_string()]).unwrap();
+        assert_eq!(enrichers.names().collect::<Vec<_>>(), vec!["constant"]);
+        assert!(Enrichers::select(available, &["owners".to_string()]).is_err());
+        assert_eq!(
+            Enrichers::select(available, &[]).unwrap().names().count(),
+            0
+        );
+    }
+}
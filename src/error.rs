@@ -0,0 +1,96 @@
+//! Contains [`Error`], the error of the library, so that callers can tell failures apart (e.g. to only retry
+//! requests that were throttled).
+//!
+//! [`BlobStorageProvider`](crate::fs::BlobStorageProvider) returns [`std::io::Error`]; an [`Error`] converted to it
+//! is recovered by converting it back (`Error::from(io_error)`), so that its variant is kept through the storage.
+
+/// An error of the library
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The storage failed (e.g. a network error or a denied request)
+    #[error("storage: {0}")]
+    Storage(std::io::Error),
+    /// Data could not be decoded (e.g. invalid JSON or CSV)
+    #[error("decode: {0}")]
+    Decode(Box<dyn std::error::Error + Send + Sync>),
+    /// Required data does not exist (e.g. a blob)
+    #[error("missing data: {0}")]
+    MissingData(String),
+    /// The request was throttled and can be retried later
+    #[error("rate limited: {0}")]
+    RateLimited(String),
+    /// An input does not pass a validation (e.g. missing credentials of a storage, or a version of the datasets
+    /// with too many anomalies)
+    #[error("invalid: {0}")]
+    Invalid(String),
+}
+
+impl Error {
+    /// Whether the failed operation can succeed when retried without changes
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Self::RateLimited(_))
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        use std::io::ErrorKind;
+        if error.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            let inner = error.into_inner().expect("checked above");
+            return *inner.downcast::<Error>().expect("checked above");
+        }
+        match error.kind() {
+            ErrorKind::NotFound => Self::MissingData(error.to_string()),
+            ErrorKind::InvalidData | ErrorKind::UnexpectedEof => Self::Decode(error.into()),
+            _ => Self::Storage(error),
+        }
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(error: Error) -> Self {
+        use std::io::ErrorKind;
+        match error {
+            Error::Storage(error) => error,
+            Error::Decode(_) => std::io::Error::new(ErrorKind::InvalidData, error),
+            Error::MissingData(_) => std::io::Error::new(ErrorKind::NotFound, error),
+            Error::Invalid(_) => std::io::Error::new(ErrorKind::InvalidData, error),
+            Error::RateLimited(_) => std::io::Error::other(error),
+        }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Self::Decode(error.into())
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(error: csv::Error) -> Self {
+        Self::Decode(error.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let error: std::io::Error = Error::RateLimited("SlowDown".to_string()).into();
+        assert!(Error::from(error).is_retryable());
+
+        let error: std::io::Error = Error::MissingData("a/data.csv".to_string()).into();
+        assert_eq!(Error::from(error).to_string(), "missing data: a/data.csv");
+
+        let error = serde_json::from_slice::<u8>(b"a").unwrap_err();
+        let error: std::io::Error = Error::from(error).into();
+        assert!(matches!(Error::from(error), Error::Decode(_)));
+
+        let error = std::io::Error::other("connection reset");
+        let error = Error::from(error);
+        assert!(matches!(error, Error::Storage(_)));
+        assert!(!error.is_retryable());
+    }
+}
@@ -0,0 +1,2 @@
+//! Contains the pipelines of the ETL binaries as library functions, so that other projects can embed them.
+pub mod legs;
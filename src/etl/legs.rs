@@ -0,0 +1,2528 @@
+//! Contains the implementation of the ETL of legs (`M-identify-legs`) used by the binary `etl_legs`:
+//! [`process_icao_month`] computes the legs of an aircraft on a month and writes them to its partition
+//! ([`pk_to_blob_name`]), and [`aggregate_year`] aggregates the partitions of a year into the public datasets.
+//! Legs are also published to [`Context::events`], which downstream projects can use as their own sink.
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use futures::{StreamExt, TryStreamExt};
+use serde::Serialize;
+
+use crate::{
+    activity::{MonthActivity, YearActivity},
+    aircraft::Aircraft,
+    airframes::MergeMap,
+    airport_watch::{Movement, Watch},
+    airports::Airports,
+    altitude::Qnhs,
+    commercial::{CommercialEmissions, Trip},
+    compression::Compression,
+    descriptor::{Descriptor, Processing},
+    emissions::{
+        phased::{self, PhaseFactors},
+        CabinClass, EmissionsConfig,
+    },
+    enrich::Enrichers,
+    events::EventPublisher,
+    exclusion::Exclusions,
+    format::Format,
+    fs::BlobStorageProvider,
+    ground_times::GroundTime,
+    legs::LegsConfig,
+    lock::Lock,
+    model::{AircraftModel, ModelOverride, ModelReclassification},
+    region::Region,
+    schema::Unit,
+    timezone::Calendar,
+    units::Units,
+    weekly::Week,
+    wind::{WindGrid, Winds},
+    Error, Position, RequiredTasks, Swap, Swaps,
+};
+
+/// The version of the datasets of legs written by default
+pub static DEFAULT_VERSION: &str = "v2";
+/// The size of the chunks the datasets of legs are serialized, compressed and written in
+pub static CHUNK_SIZE: usize = 1024 * 1024;
+
+/// The roots of the datasets written by the ETL of legs of a version (see `M-versions`).
+/// Sources (positions, aircrafts, airports, etc.) are shared by all versions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Roots {
+    /// the version (e.g. `v2`)
+    pub version: Arc<str>,
+    /// the root of the datasets of legs (e.g. `leg/v2/`)
+    pub legs: String,
+    /// the root of the datasets of activity
+    pub activity: String,
+    /// the root of the datasets of ground times
+    pub ground_times: String,
+    /// the name of the subset of aircrafts whose datasets are aggregated, when any (see [`Roots::with_subset`])
+    pub subset: Option<Arc<str>>,
+}
+
+impl Roots {
+    /// Returns the roots of `version`. Activity and ground times of [`DEFAULT_VERSION`] are at their
+    /// own roots; those of other versions are written under the root of legs, so that reprocessing
+    /// does not overwrite them.
+    pub fn new(version: &str) -> Self {
+        let legs = format!("leg/{version}/");
+        let (activity, ground_times) = if version == DEFAULT_VERSION {
+            ("activity/v1/".to_string(), "ground_times/v1/".to_string())
+        } else {
+            (format!("{legs}activity/"), format!("{legs}ground_times/"))
+        };
+        Self {
+            version: version.into(),
+            legs,
+            activity,
+            ground_times,
+            subset: None,
+        }
+    }
+
+    /// Returns the roots of the published version (see `M-versions`), or of [`DEFAULT_VERSION`] when no version
+    /// was promoted
+    pub async fn published(client: &dyn BlobStorageProvider) -> Result<Self, Error> {
+        let promotion = crate::validate::published(client).await?;
+        Ok(match promotion {
+            Some(promotion) => Self::new(&promotion.version),
+            None => Self::default(),
+        })
+    }
+
+    /// Returns these roots for the aggregation of a subset of aircrafts named `subset` (e.g. `459cd3`):
+    /// the aggregated datasets (yearly datasets, status, activity and reactivations) are written under
+    /// `{root}subset={subset}/` so that they do not overwrite those of all aircrafts. Partitions are shared.
+    pub fn with_subset(mut self, subset: &str) -> Self {
+        self.subset = Some(subset.into());
+        self
+    }
+
+    /// Returns these roots for the legs touching a region named `region` (e.g. `denmark`): all datasets,
+    /// including the partitions, are written under `{legs}region={region}/`, since the partitions of a region
+    /// do not contain all legs and must not overwrite those of all legs.
+    pub fn with_region(mut self, region: &str) -> Self {
+        self.legs = format!("{}region={region}/", self.legs);
+        self.activity = format!("{}activity/", self.legs);
+        self.ground_times = format!("{}ground_times/", self.legs);
+        self
+    }
+
+    /// Returns the root of the aggregated datasets of `root` (one of the roots of `self`)
+    pub fn aggregated(&self, root: &str) -> String {
+        match &self.subset {
+            Some(subset) => format!("{root}subset={subset}/"),
+            None => root.to_string(),
+        }
+    }
+
+    /// Returns the root of the datasets of `root` aggregated by the years of `calendar` (see `M-local-months`):
+    /// those of calendars other than UTC are written under `timezone={name}/` (with `/` in the name of
+    /// the time zone replaced by `-`) so that they do not overwrite those in UTC.
+    pub fn aggregated_in(&self, root: &str, calendar: Calendar) -> String {
+        let root = self.aggregated(root);
+        match calendar {
+            Calendar::Utc => root,
+            calendar => format!("{root}timezone={}/", calendar.name().replace('/', "-")),
+        }
+    }
+}
+
+impl Default for Roots {
+    fn default() -> Self {
+        Self::new(DEFAULT_VERSION)
+    }
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone)]
+/// A leg of an aircraft, as written to the datasets of legs (see `M-identify-legs`)
+pub struct LegOut {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The tail number (`None` when the ICAO number is not in the database of aircrafts)
+    pub tail_number: Option<Arc<str>>,
+    /// The aircraft model (`None` when the ICAO number is not in the database of aircrafts)
+    pub aircraft_model: Option<Arc<str>>,
+    /// The start timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: time::OffsetDateTime,
+    /// The start latitude
+    pub start_lat: f64,
+    /// The start longitude
+    pub start_lon: f64,
+    /// The start altitude in feet
+    pub start_altitude: f64,
+    /// The end timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: time::OffsetDateTime,
+    /// The end latitude
+    pub end_lat: f64,
+    /// The end longitude
+    pub end_lon: f64,
+    /// The end altitude in feet
+    pub end_altitude: f64,
+    /// The duration of the flight in hours
+    pub duration: f64,
+    /// The total two-dimensional flown distance of the leg in km
+    pub distance: f64,
+    /// The great-circle distance of the leg in km
+    pub great_circle_distance: f64,
+    /// The ratio between `distance` and `great_circle_distance` (`None` when the leg starts and ends at the same position)
+    pub circuity: Option<f64>,
+    /// The latitude of the midpoint of the great circle of the leg (`None` when computed before it was added)
+    pub midpoint_lat: Option<f64>,
+    /// The longitude of the midpoint of the great circle of the leg (`None` when computed before it was added)
+    pub midpoint_lon: Option<f64>,
+    /// The initial bearing in degrees of the great circle of the leg (`None` when the leg starts and ends at the same position)
+    pub initial_bearing: Option<f64>,
+    /// The time above 30.000 feet
+    pub hours_above_30000: f64,
+    /// The time above 40.000 feet
+    pub hours_above_40000: f64,
+    /// CO2 emissions in kg (`None` when the model is unknown)
+    pub co2_emissions: Option<f64>,
+    /// CO2 emissions in kg of a business class passenger flying the same great-circle distance on a
+    /// commercial flight (`None` unless run with `--with-emissions`)
+    pub commercial_co2_emissions: Option<f64>,
+    /// The backend that computed `commercial_co2_emissions` (`None` unless run with `--with-emissions`)
+    pub commercial_co2_backend: Option<Arc<str>>,
+    /// The average along-track wind component in knots (negative for headwind), when winds are available
+    pub tailwind: Option<f64>,
+    /// The average true airspeed in knots, when winds are available
+    pub true_airspeed: Option<f64>,
+    /// Whether the leg was diverted after a go-around at another airport (`None` when computed before it was added)
+    pub diverted: Option<bool>,
+    /// Whether the start of the leg was observed on the ground at an airport (otherwise the start of the
+    /// coverage of the aircraft; `None` when computed before it was added)
+    pub start_on_ground: Option<bool>,
+    /// Whether the end of the leg was observed on the ground at an airport (otherwise a loss of coverage; `None`
+    /// when computed before it was added)
+    pub end_on_ground: Option<bool>,
+    /// The identifier (ICAO code when it has one) of the departure airport, when known
+    pub from_airport_icao: Option<Arc<str>>,
+    /// The name of the departure airport, when known
+    pub from_airport_name: Option<Arc<str>>,
+    /// The distance in km between the start of the leg and the departure airport, when known
+    pub from_airport_distance: Option<f64>,
+    /// The identifier (ICAO code when it has one) of the arrival airport, when known
+    pub to_airport_icao: Option<Arc<str>>,
+    /// The name of the arrival airport, when known
+    pub to_airport_name: Option<Arc<str>>,
+    /// The distance in km between the end of the leg and the arrival airport, when known
+    pub to_airport_distance: Option<f64>,
+    /// The country (ISO 3166-1 alpha-2) of the start of the leg, when known
+    pub from_country: Option<Arc<str>>,
+    /// The country (ISO 3166-1 alpha-2) of the end of the leg, when known
+    pub to_country: Option<Arc<str>>,
+    /// The start timestamp in the local time of the start of the leg (RFC 3339 with its UTC offset), when known
+    pub start_local: Option<Arc<str>>,
+    /// The end timestamp in the local time of the end of the leg (RFC 3339 with its UTC offset), when known
+    pub end_local: Option<Arc<str>>,
+    /// The owner of the aircraft, when known
+    pub owner: Option<Arc<str>>,
+    /// The type of the owner (`individual`, `corporate` or `charter`), when known
+    pub owner_type: Option<Arc<str>>,
+    /// The operator of the aircraft, when known
+    pub operator: Option<Arc<str>>,
+    /// The type of the operator (`individual`, `corporate` or `charter`), when known
+    pub operator_type: Option<Arc<str>>,
+    /// The reason why the leg is of emergency aviation (e.g. `air ambulance base`), when it matches an
+    /// exclusion zone or an excluded aircraft (see `M-exclusions`)
+    pub excluded_reason: Option<Arc<str>>,
+    /// The time in minutes taxiing on the ground before the takeoff roll (zero when not observed; `None` when
+    /// computed before it was added), see `M-taxi`
+    pub taxi_out_minutes: Option<f64>,
+    /// The time in minutes taxiing on the ground after the landing rollout (zero when not observed; `None` when
+    /// computed before it was added), see `M-taxi`
+    pub taxi_in_minutes: Option<f64>,
+    /// CO2 emissions in kg with the consumption of the model scaled by the fuel flow of the climb, cruise and
+    /// descent of the leg (`None` when the model is unknown), see `M-phased-emissions`
+    pub phased_co2_emissions: Option<f64>,
+    /// `commercial_co2_emissions` of an economy class passenger, see `M-commercial-alternative`
+    pub commercial_economy_co2_emissions: Option<f64>,
+    /// `commercial_co2_emissions` of a first class passenger, see `M-commercial-alternative`
+    pub commercial_first_co2_emissions: Option<f64>,
+    /// Whether a scheduled commercial route plausibly exists between the airports closest to the start and end of
+    /// the leg (`None` when computed before it was added), see `M-commercial-alternative`
+    pub commercial_alternative_exists: Option<bool>,
+    /// The category of the aircraft model (e.g. `helicopter`; `None` when the model is unknown), see `M-categories`
+    pub aircraft_category: Option<Arc<str>>,
+    /// The blob name of the partition of positions the leg was computed from (e.g.
+    /// `position/icao_number=459cd3/month=2023-01/data.json`), see `M-lineage`
+    pub source_partition: Option<Arc<str>>,
+    /// The index (starting at 0) of the first position of the leg in `source_partition`
+    pub source_first_position: Option<u64>,
+    /// The index of the last position of the leg in `source_partition`
+    pub source_last_position: Option<u64>,
+    /// The source of the altitudes of the leg (`barometric`, `geometric`, `qnh-corrected` or `mixed`), see
+    /// `M-altitudes` (`None` when computed before it was added)
+    pub altitude_source: Option<Arc<str>>,
+    /// The names of the overlays the leg flew over, separated by `;` (`None` when over none), see `M-overlays`
+    pub overlays: Option<Arc<str>>,
+    /// The time in minutes flown inside overlays, see `M-overlays`
+    pub overlay_minutes: Option<f64>,
+    /// The lowest altitude in feet flown inside overlays, see `M-overlays`
+    pub overlay_min_altitude: Option<f64>,
+    /// The distance in km between the leg and the closest overlay (zero when over one; `None` when farther than
+    /// 50 km), see `M-overlays`
+    pub overlay_distance: Option<f64>,
+}
+
+crate::schema!(LegOut {
+    icao_number: "The ICAO number",
+    tail_number: "The tail number (null when the ICAO number is not in the database of aircrafts)",
+    aircraft_model: "The aircraft model (null when the ICAO number is not in the database of aircrafts)",
+    start: "The start timestamp (RFC 3339, UTC)",
+    start_lat("degrees"): "The start latitude",
+    start_lon("degrees"): "The start longitude",
+    start_altitude("ft"): "The start altitude",
+    end: "The end timestamp (RFC 3339, UTC)",
+    end_lat("degrees"): "The end latitude",
+    end_lon("degrees"): "The end longitude",
+    end_altitude("ft"): "The end altitude",
+    duration("h"): "The duration of the flight",
+    distance(Unit::Distance): "The total two-dimensional flown distance of the leg",
+    great_circle_distance(Unit::Distance): "The great-circle distance of the leg",
+    circuity: "The ratio between `distance` and `great_circle_distance` (null when the leg starts and ends at the same position)",
+    midpoint_lat("degrees"): "The latitude of the midpoint of the great circle of the leg (null for legs computed before it)",
+    midpoint_lon("degrees"): "The longitude of the midpoint of the great circle of the leg (null for legs computed before it)",
+    initial_bearing("degrees"): "The initial bearing of the great circle of the leg (null when the leg starts and ends at the same position)",
+    hours_above_30000("h"): "The time above 30.000 feet",
+    hours_above_40000("h"): "The time above 40.000 feet",
+    co2_emissions(Unit::Mass): "The CO2 emissions (null when the model is unknown)",
+    commercial_co2_emissions(Unit::Mass): "The CO2 emissions of a business class passenger flying the same great-circle distance on a commercial flight",
+    commercial_co2_backend: "The backend that computed `commercial_co2_emissions`",
+    tailwind("kn"): "The average along-track wind component (negative for headwind), when winds are available",
+    true_airspeed("kn"): "The average true airspeed, when winds are available",
+    diverted: "Whether the leg was diverted after a go-around at another airport (null for legs computed before it)",
+    start_on_ground: "Whether the start of the leg was observed on the ground at an airport (null for legs computed before it)",
+    end_on_ground: "Whether the end of the leg was observed on the ground at an airport (null for legs computed before it)",
+    from_airport_icao: "The identifier (ICAO code when it has one) of the departure airport, when known",
+    from_airport_name: "The name of the departure airport, when known",
+    from_airport_distance(Unit::Distance): "The distance between the start of the leg and the departure airport, when known",
+    to_airport_icao: "The identifier (ICAO code when it has one) of the arrival airport, when known",
+    to_airport_name: "The name of the arrival airport, when known",
+    to_airport_distance(Unit::Distance): "The distance between the end of the leg and the arrival airport, when known",
+    from_country: "The country (ISO 3166-1 alpha-2) of the start of the leg, when known",
+    to_country: "The country (ISO 3166-1 alpha-2) of the end of the leg, when known",
+    start_local: "The start timestamp in the local time of the start of the leg (RFC 3339 with its UTC offset), when known",
+    end_local: "The end timestamp in the local time of the end of the leg (RFC 3339 with its UTC offset), when known",
+    owner: "The owner of the aircraft, when known",
+    owner_type: "The type of the owner (`individual`, `corporate` or `charter`), when known",
+    operator: "The operator of the aircraft, when known",
+    operator_type: "The type of the operator (`individual`, `corporate` or `charter`), when known",
+    excluded_reason: "The reason why the leg is of emergency aviation, when it is (see `M-exclusions`)",
+    taxi_out_minutes("min"): "The time taxiing on the ground before the takeoff roll (zero when not observed; null for legs computed before it)",
+    taxi_in_minutes("min"): "The time taxiing on the ground after the landing rollout (zero when not observed; null for legs computed before it)",
+    phased_co2_emissions(Unit::Mass): "The CO2 emissions with the fuel flow of the climb, cruise and descent of the leg (null when the model is unknown)",
+    commercial_economy_co2_emissions(Unit::Mass): "The CO2 emissions of an economy class passenger on the commercial alternative",
+    commercial_first_co2_emissions(Unit::Mass): "The CO2 emissions of a first class passenger on the commercial alternative",
+    commercial_alternative_exists: "Whether a scheduled commercial route plausibly exists between the airports closest to the start and end of the leg (null for legs computed before it)",
+    aircraft_category: "The category of the aircraft model (`jet`, `helicopter` or `turboprop`; null when the model is unknown)",
+    source_partition: "The blob name of the partition of positions the leg was computed from",
+    source_first_position: "The index (starting at 0) of the first position of the leg in `source_partition`",
+    source_last_position: "The index of the last position of the leg in `source_partition`",
+    altitude_source: "The source of the altitudes of the leg (`barometric`, `geometric`, `qnh-corrected` or `mixed`)",
+    overlays: "The names of the overlays the leg flew over, separated by `;` (null when over none)",
+    overlay_minutes("min"): "The time flown inside overlays",
+    overlay_min_altitude("ft"): "The lowest altitude flown inside overlays (null when over none)",
+    overlay_distance(Unit::Distance): "The distance between the leg and the closest overlay (zero when over one; null when farther than 50 km)",
+});
+
+/// A leg of the slim datasets of legs (`M-slim`): the columns of [`LegOut`] needed by bandwidth-constrained
+/// consumers (e.g. the website), without positions, altitudes or enrichments
+#[derive(Debug, Clone, PartialEq, Serialize, serde::Deserialize)]
+pub struct SlimLeg {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The start timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: time::OffsetDateTime,
+    /// The end timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: time::OffsetDateTime,
+    /// The identifier of the departure airport
+    pub from_airport_icao: Option<Arc<str>>,
+    /// The identifier of the arrival airport
+    pub to_airport_icao: Option<Arc<str>>,
+    /// The total two-dimensional flown distance
+    pub distance: f64,
+    /// The CO2 emissions
+    pub co2_emissions: Option<f64>,
+}
+
+crate::schema!(SlimLeg {
+    icao_number: "The ICAO number",
+    start: "The start timestamp (RFC 3339, UTC)",
+    end: "The end timestamp (RFC 3339, UTC)",
+    from_airport_icao: "The identifier of the departure airport (null when unknown)",
+    to_airport_icao: "The identifier of the arrival airport (null when unknown)",
+    distance(Unit::Distance): "The total two-dimensional flown distance of the leg",
+    co2_emissions(Unit::Mass): "The CO2 emissions (null when the model is unknown)",
+});
+
+impl From<&LegOut> for SlimLeg {
+    fn from(leg: &LegOut) -> Self {
+        Self {
+            icao_number: leg.icao_number.clone(),
+            start: leg.start,
+            end: leg.end,
+            from_airport_icao: leg.from_airport_icao.clone(),
+            to_airport_icao: leg.to_airport_icao.clone(),
+            distance: leg.distance,
+            co2_emissions: leg.co2_emissions,
+        }
+    }
+}
+
+/// Number of points of the altitude profile of a leg
+static PROFILE_POINTS: usize = 32;
+
+/// The altitude profile of a leg, for thumbnails
+#[derive(serde::Serialize)]
+struct LegProfile {
+    /// The ICAO number
+    icao_number: Arc<str>,
+    /// The start timestamp of the leg
+    #[serde(with = "time::serde::rfc3339")]
+    start: time::OffsetDateTime,
+    /// The altitude in hundreds of feet at evenly spaced times from the start to the end of the leg, separated by `;`
+    profile: String,
+}
+
+impl LegProfile {
+    fn new(icao_number: Arc<str>, leg: &crate::legs::Leg) -> Self {
+        let profile = leg
+            .altitude_profile(PROFILE_POINTS)
+            .into_iter()
+            .map(|altitude| ((altitude / 100.0).round() as i64).to_string())
+            .collect::<Vec<_>>()
+            .join(";");
+        Self {
+            icao_number,
+            start: leg.from().datetime(),
+            profile,
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl crate::parquet::Record for LegOut {
+    fn columns() -> Vec<crate::parquet::Column> {
+        use crate::parquet::{Column, Kind};
+        vec![
+            Column::new("icao_number", Kind::Dictionary, false),
+            Column::new("tail_number", Kind::Dictionary, true),
+            Column::new("aircraft_model", Kind::Dictionary, true),
+            Column::new("start", Kind::Timestamp, false),
+            Column::new("start_lat", Kind::Float, false),
+            Column::new("start_lon", Kind::Float, false),
+            Column::new("start_altitude", Kind::Float, false),
+            Column::new("end", Kind::Timestamp, false),
+            Column::new("end_lat", Kind::Float, false),
+            Column::new("end_lon", Kind::Float, false),
+            Column::new("end_altitude", Kind::Float, false),
+            Column::new("duration", Kind::Float, false),
+            Column::new("distance", Kind::Float, false),
+            Column::new("great_circle_distance", Kind::Float, false),
+            Column::new("circuity", Kind::Float, true),
+            Column::new("midpoint_lat", Kind::Float, true),
+            Column::new("midpoint_lon", Kind::Float, true),
+            Column::new("initial_bearing", Kind::Float, true),
+            Column::new("hours_above_30000", Kind::Float, false),
+            Column::new("hours_above_40000", Kind::Float, false),
+            Column::new("co2_emissions", Kind::Float, true),
+            Column::new("commercial_co2_emissions", Kind::Float, true),
+            Column::new("commercial_co2_backend", Kind::Dictionary, true),
+            Column::new("tailwind", Kind::Float, true),
+            Column::new("true_airspeed", Kind::Float, true),
+            Column::new("diverted", Kind::Boolean, true),
+            Column::new("start_on_ground", Kind::Boolean, true),
+            Column::new("end_on_ground", Kind::Boolean, true),
+            Column::new("from_airport_icao", Kind::Dictionary, true),
+            Column::new("from_airport_name", Kind::Dictionary, true),
+            Column::new("from_airport_distance", Kind::Float, true),
+            Column::new("to_airport_icao", Kind::Dictionary, true),
+            Column::new("to_airport_name", Kind::Dictionary, true),
+            Column::new("to_airport_distance", Kind::Float, true),
+            Column::new("from_country", Kind::Dictionary, true),
+            Column::new("to_country", Kind::Dictionary, true),
+            Column::new("start_local", Kind::Dictionary, true),
+            Column::new("end_local", Kind::Dictionary, true),
+            Column::new("owner", Kind::Dictionary, true),
+            Column::new("owner_type", Kind::Dictionary, true),
+            Column::new("operator", Kind::Dictionary, true),
+            Column::new("operator_type", Kind::Dictionary, true),
+            Column::new("excluded_reason", Kind::Dictionary, true),
+            Column::new("taxi_out_minutes", Kind::Float, true),
+            Column::new("taxi_in_minutes", Kind::Float, true),
+            Column::new("phased_co2_emissions", Kind::Float, true),
+            Column::new("commercial_economy_co2_emissions", Kind::Float, true),
+            Column::new("commercial_first_co2_emissions", Kind::Float, true),
+            Column::new("commercial_alternative_exists", Kind::Boolean, true),
+            Column::new("aircraft_category", Kind::Dictionary, true),
+            Column::new("source_partition", Kind::Text, true),
+            Column::new("source_first_position", Kind::Integer, true),
+            Column::new("source_last_position", Kind::Integer, true),
+            Column::new("altitude_source", Kind::Dictionary, true),
+            Column::new("overlays", Kind::Dictionary, true),
+            Column::new("overlay_minutes", Kind::Float, true),
+            Column::new("overlay_min_altitude", Kind::Float, true),
+            Column::new("overlay_distance", Kind::Float, true),
+        ]
+    }
+
+    fn values(&self) -> Vec<crate::parquet::Value<'_>> {
+        use crate::parquet::Value;
+        vec![
+            Value::Text(Some(&self.icao_number)),
+            Value::Text(self.tail_number.as_deref()),
+            Value::Text(self.aircraft_model.as_deref()),
+            Value::Timestamp(Some(self.start)),
+            Value::Float(Some(self.start_lat)),
+            Value::Float(Some(self.start_lon)),
+            Value::Float(Some(self.start_altitude)),
+            Value::Timestamp(Some(self.end)),
+            Value::Float(Some(self.end_lat)),
+            Value::Float(Some(self.end_lon)),
+            Value::Float(Some(self.end_altitude)),
+            Value::Float(Some(self.duration)),
+            Value::Float(Some(self.distance)),
+            Value::Float(Some(self.great_circle_distance)),
+            Value::Float(self.circuity),
+            Value::Float(self.midpoint_lat),
+            Value::Float(self.midpoint_lon),
+            Value::Float(self.initial_bearing),
+            Value::Float(Some(self.hours_above_30000)),
+            Value::Float(Some(self.hours_above_40000)),
+            Value::Float(self.co2_emissions),
+            Value::Float(self.commercial_co2_emissions),
+            Value::Text(self.commercial_co2_backend.as_deref()),
+            Value::Float(self.tailwind),
+            Value::Float(self.true_airspeed),
+            Value::Boolean(self.diverted),
+            Value::Boolean(self.start_on_ground),
+            Value::Boolean(self.end_on_ground),
+            Value::Text(self.from_airport_icao.as_deref()),
+            Value::Text(self.from_airport_name.as_deref()),
+            Value::Float(self.from_airport_distance),
+            Value::Text(self.to_airport_icao.as_deref()),
+            Value::Text(self.to_airport_name.as_deref()),
+            Value::Float(self.to_airport_distance),
+            Value::Text(self.from_country.as_deref()),
+            Value::Text(self.to_country.as_deref()),
+            Value::Text(self.start_local.as_deref()),
+            Value::Text(self.end_local.as_deref()),
+            Value::Text(self.owner.as_deref()),
+            Value::Text(self.owner_type.as_deref()),
+            Value::Text(self.operator.as_deref()),
+            Value::Text(self.operator_type.as_deref()),
+            Value::Text(self.excluded_reason.as_deref()),
+            Value::Float(self.taxi_out_minutes),
+            Value::Float(self.taxi_in_minutes),
+            Value::Float(self.phased_co2_emissions),
+            Value::Float(self.commercial_economy_co2_emissions),
+            Value::Float(self.commercial_first_co2_emissions),
+            Value::Boolean(self.commercial_alternative_exists),
+            Value::Text(self.aircraft_category.as_deref()),
+            Value::Text(self.source_partition.as_deref()),
+            Value::Integer(self.source_first_position.map(|x| x as i64)),
+            Value::Integer(self.source_last_position.map(|x| x as i64)),
+            Value::Text(self.altitude_source.as_deref()),
+            Value::Text(self.overlays.as_deref()),
+            Value::Float(self.overlay_minutes),
+            Value::Float(self.overlay_min_altitude),
+            Value::Float(self.overlay_distance),
+        ]
+    }
+
+    fn from_fields(fields: &mut crate::parquet::Fields) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            icao_number: fields.next_field()?,
+            tail_number: fields.next_field()?,
+            aircraft_model: fields.next_field()?,
+            start: fields.next_field()?,
+            start_lat: fields.next_field()?,
+            start_lon: fields.next_field()?,
+            start_altitude: fields.next_field()?,
+            end: fields.next_field()?,
+            end_lat: fields.next_field()?,
+            end_lon: fields.next_field()?,
+            end_altitude: fields.next_field()?,
+            duration: fields.next_field()?,
+            distance: fields.next_field()?,
+            great_circle_distance: fields.next_field()?,
+            circuity: fields.next_field()?,
+            midpoint_lat: fields.next_field()?,
+            midpoint_lon: fields.next_field()?,
+            initial_bearing: fields.next_field()?,
+            hours_above_30000: fields.next_field()?,
+            hours_above_40000: fields.next_field()?,
+            co2_emissions: fields.next_field()?,
+            commercial_co2_emissions: fields.next_field()?,
+            commercial_co2_backend: fields.next_field()?,
+            tailwind: fields.next_field()?,
+            true_airspeed: fields.next_field()?,
+            diverted: fields.next_field()?,
+            start_on_ground: fields.next_field()?,
+            end_on_ground: fields.next_field()?,
+            from_airport_icao: fields.next_field()?,
+            from_airport_name: fields.next_field()?,
+            from_airport_distance: fields.next_field()?,
+            to_airport_icao: fields.next_field()?,
+            to_airport_name: fields.next_field()?,
+            to_airport_distance: fields.next_field()?,
+            from_country: fields.next_field()?,
+            to_country: fields.next_field()?,
+            start_local: fields.next_field()?,
+            end_local: fields.next_field()?,
+            owner: fields.next_field()?,
+            owner_type: fields.next_field()?,
+            operator: fields.next_field()?,
+            operator_type: fields.next_field()?,
+            excluded_reason: fields.next_field()?,
+            taxi_out_minutes: fields.next_field()?,
+            taxi_in_minutes: fields.next_field()?,
+            phased_co2_emissions: fields.next_field()?,
+            commercial_economy_co2_emissions: fields.next_field()?,
+            commercial_first_co2_emissions: fields.next_field()?,
+            commercial_alternative_exists: fields.next_field()?,
+            aircraft_category: fields.next_field()?,
+            source_partition: fields.next_field()?,
+            source_first_position: fields.next_field()?,
+            source_last_position: fields.next_field()?,
+            altitude_source: fields.next_field()?,
+            overlays: fields.next_field()?,
+            overlay_minutes: fields.next_field()?,
+            overlay_min_altitude: fields.next_field()?,
+            overlay_distance: fields.next_field()?,
+        })
+    }
+}
+
+/// Serializes `legs` in `format`
+pub fn serialize_legs(
+    legs: impl Iterator<Item = LegOut>,
+    format: Format,
+) -> Result<Vec<u8>, std::io::Error> {
+    match format {
+        Format::Csv => Ok(crate::csv::serialize(legs)),
+        #[cfg(feature = "parquet")]
+        Format::Parquet => crate::parquet::serialize(legs),
+        #[allow(unreachable_patterns)]
+        Format::Parquet => Err(std::io::Error::other(
+            "this build does not support `parquet`; compile with feature `parquet`",
+        )),
+    }
+}
+
+/// Serializes `legs` in `format` in chunks of about [`CHUNK_SIZE`], so that the datasets of legs are written
+/// (see [`crate::io::put_stream`]) without serializing all legs at once (`parquet` is serialized at once)
+fn serialize_legs_chunks<'a, L: Borrow<LegOut> + Serialize + Send + 'a>(
+    legs: impl Iterator<Item = L> + Send + 'a,
+    format: Format,
+) -> Box<dyn Iterator<Item = Result<Vec<u8>, std::io::Error>> + Send + 'a> {
+    match format {
+        Format::Csv => Box::new(crate::csv::serialize_chunks(legs, CHUNK_SIZE).map(Ok)),
+        format => {
+            let legs = legs.map(|leg| leg.borrow().clone());
+            Box::new(std::iter::once(serialize_legs(legs, format)))
+        }
+    }
+}
+
+/// Deserializes legs written by [`serialize_legs`] in `format`
+pub fn deserialize_legs(data: &[u8], format: Format) -> Result<Vec<LegOut>, std::io::Error> {
+    match format {
+        Format::Csv => crate::csv::deserialize(data).collect(),
+        #[cfg(feature = "parquet")]
+        Format::Parquet => crate::parquet::deserialize(data),
+        #[allow(unreachable_patterns)]
+        Format::Parquet => Err(std::io::Error::other(
+            "this build does not support `parquet`; compile with feature `parquet`",
+        )),
+    }
+}
+
+impl LegOut {
+    /// Converts distances and emissions from km and kg to `units`
+    pub fn with_units(mut self, units: Units) -> Self {
+        self.distance = units.distance(self.distance);
+        self.great_circle_distance = units.distance(self.great_circle_distance);
+        self.from_airport_distance = self.from_airport_distance.map(|km| units.distance(km));
+        self.to_airport_distance = self.to_airport_distance.map(|km| units.distance(km));
+        self.overlay_distance = self.overlay_distance.map(|km| units.distance(km));
+        self.co2_emissions = self.co2_emissions.map(|kg| units.mass(kg));
+        self.commercial_co2_emissions = self.commercial_co2_emissions.map(|kg| units.mass(kg));
+        self.phased_co2_emissions = self.phased_co2_emissions.map(|kg| units.mass(kg));
+        self.commercial_economy_co2_emissions = self
+            .commercial_economy_co2_emissions
+            .map(|kg| units.mass(kg));
+        self.commercial_first_co2_emissions =
+            self.commercial_first_co2_emissions.map(|kg| units.mass(kg));
+        self
+    }
+}
+
+/// The status of the dataset of legs of a year, written to `status.json`
+#[derive(serde::Serialize)]
+pub struct Metadata<'a> {
+    pub icao_months_to_process: usize,
+    pub icao_months_processed: usize,
+    pub url: String,
+    /// the factors used to compute `co2_emissions`
+    pub emissions: EmissionsConfig,
+    /// the models whose consumption was overridden by `src/models_overrides.csv`
+    pub model_overrides: &'a [ModelOverride],
+    /// the reclassifications of models in `src/models_changelog.csv`
+    pub model_changelog: &'a [ModelReclassification],
+    /// the calendar in which legs are assigned to years: `UTC`, an IANA time zone or `local` (see `M-local-months`)
+    pub timezone: &'static str,
+    /// the unit of `distance`, `great_circle_distance` and distances to airports
+    pub distance_unit: &'static str,
+    /// the unit of `co2_emissions` and `commercial_co2_emissions`
+    pub mass_unit: &'static str,
+    /// the version of the schema of the dataset (see [`crate::schema::version`])
+    pub schema_version: String,
+    /// when the dataset of the year was last written
+    #[serde(with = "time::serde::rfc3339")]
+    pub last_updated: time::OffsetDateTime,
+}
+
+async fn write_json(
+    client: &dyn BlobStorageProvider,
+    d: impl Serialize,
+    key: &str,
+) -> Result<(), Error> {
+    let mut bytes: Vec<u8> = Vec::new();
+    serde_json::to_writer(&mut bytes, &d)?;
+
+    Ok(client.put(key, bytes).await?)
+}
+
+async fn write_csv(
+    items: impl Iterator<Item = impl Serialize>,
+    key: &str,
+    client: &dyn BlobStorageProvider,
+) -> Result<(), std::io::Error> {
+    let data_csv = crate::csv::serialize(items);
+    client.put(key, data_csv).await?;
+    Ok(())
+}
+
+fn transform<'a>(
+    icao_number: &'a Arc<str>,
+    aircraft: Option<&'a Aircraft>,
+    model: Option<&'a AircraftModel>,
+    swaps: &'a [Swap],
+    positions: impl Iterator<Item = Position> + 'a,
+    context: &'a Context<'a>,
+    winds: Option<&'a WindGrid>,
+) -> impl Iterator<Item = (LegOut, Option<LegProfile>)> + 'a {
+    let Context {
+        region,
+        exclusions,
+        drop_excluded,
+        airports,
+        enrichers,
+        emissions,
+        model_phases,
+        commercial,
+        profiles,
+        legs,
+        ..
+    } = *context;
+    crate::legs::legs_with_config(positions, |p| airports.elevation(p.pos()), legs)
+        .filter(move |leg| {
+            region
+                .map(|region| region.touches(leg.positions()))
+                .unwrap_or(true)
+        })
+        .filter_map(move |leg| {
+            let excluded_reason = exclusions.and_then(|exclusions| {
+                exclusions.reason(icao_number, leg.from().pos(), leg.to().pos())
+            });
+            if drop_excluded && excluded_reason.is_some() {
+                return None;
+            }
+            // the aircraft in effect at the start of the leg (see `M-aircraft-swaps`)
+            let (aircraft, model) = swaps
+                .iter()
+                .rev()
+                .find(|swap| leg.from().datetime().date() >= swap.date)
+                .map(|swap| (Some(swap.aircraft.as_ref()), Some(swap.model.as_ref())))
+                .unwrap_or((aircraft, model));
+            let wind = winds.and_then(|winds| crate::wind::leg_wind(&leg, winds));
+            let profile = profiles.then(|| LegProfile::new(icao_number.clone(), &leg));
+            let enrichment = enrichers.enrich(&leg, aircraft);
+            let (midpoint_lat, midpoint_lon) = leg.midpoint();
+            let commercial_co2_emissions = commercial.and_then(|commercial| {
+                commercial.co2_kg(&Trip {
+                    distance: leg.great_circle_distance(),
+                    from_airport: enrichment.from_airport_icao.as_deref(),
+                    to_airport: enrichment.to_airport_icao.as_deref(),
+                })
+            });
+            let class_co2_emissions = |class| {
+                commercial_co2_emissions.map(|kg| emissions.commercial_class_co2_kg(kg, class))
+            };
+            let leg = LegOut {
+                icao_number: icao_number.clone(),
+                tail_number: aircraft.map(|a| a.tail_number.clone().into()),
+                aircraft_model: aircraft.map(|a| a.model.clone().into()),
+                start: leg.from().datetime(),
+                start_lat: leg.from().latitude(),
+                start_lon: leg.from().longitude(),
+                start_altitude: leg.from().altitude(),
+                end: leg.to().datetime(),
+                end_lat: leg.to().latitude(),
+                end_lon: leg.to().longitude(),
+                end_altitude: leg.to().altitude(),
+                duration: leg.duration().as_seconds_f64() / 60.0 / 60.0,
+                distance: leg.distance(),
+                great_circle_distance: leg.great_circle_distance(),
+                circuity: leg.circuity(),
+                midpoint_lat: Some(midpoint_lat),
+                midpoint_lon: Some(midpoint_lon),
+                initial_bearing: leg.initial_bearing(),
+                hours_above_30000: leg
+                    .positions()
+                    .windows(2)
+                    .filter(|w| w[0].altitude() > 30000.0 && w[1].altitude() > 30000.0)
+                    .map(|w| {
+                        (w[1].datetime() - w[0].datetime()).whole_seconds() as f64 / 60.0 / 60.0
+                    })
+                    .sum::<f64>(),
+                hours_above_40000: leg
+                    .positions()
+                    .windows(2)
+                    .filter(|w| w[0].altitude() > 40000.0 && w[1].altitude() > 40000.0)
+                    .map(|w| {
+                        (w[1].datetime() - w[0].datetime()).whole_seconds() as f64 / 60.0 / 60.0
+                    })
+                    .sum::<f64>(),
+                co2_emissions: model.map(|model| {
+                    emissions.leg_co2_kg(model.fuel, model.gph.into(), leg.duration())
+                }),
+                commercial_co2_emissions,
+                commercial_co2_backend: commercial.map(|commercial| commercial.name().into()),
+                tailwind: wind.map(|wind| wind.tailwind),
+                true_airspeed: wind.map(|wind| wind.true_airspeed),
+                diverted: Some(airports.diverted(&leg)),
+                start_on_ground: Some(airports.start_on_ground(&leg)),
+                end_on_ground: Some(airports.end_on_ground(&leg)),
+                from_airport_icao: enrichment.from_airport_icao,
+                from_airport_name: enrichment.from_airport_name,
+                from_airport_distance: enrichment.from_airport_distance,
+                to_airport_icao: enrichment.to_airport_icao,
+                to_airport_name: enrichment.to_airport_name,
+                to_airport_distance: enrichment.to_airport_distance,
+                from_country: enrichment.from_country,
+                to_country: enrichment.to_country,
+                start_local: enrichment.start_local.map(crate::timezone::to_rfc3339),
+                end_local: enrichment.end_local.map(crate::timezone::to_rfc3339),
+                owner: enrichment.owner,
+                owner_type: enrichment.owner_type,
+                operator: enrichment.operator,
+                operator_type: enrichment.operator_type,
+                excluded_reason: excluded_reason.cloned(),
+                taxi_out_minutes: Some(leg.taxi_out().as_seconds_f64() / 60.0),
+                taxi_in_minutes: Some(leg.taxi_in().as_seconds_f64() / 60.0),
+                phased_co2_emissions: model.map(|model| {
+                    let factors = model_phases.get(&model.model).copied().unwrap_or_default();
+                    let (fuel, consumption) = (model.fuel, model.gph.into());
+                    phased::leg_co2_kg(emissions, fuel, consumption, factors, leg.positions())
+                }),
+                commercial_economy_co2_emissions: class_co2_emissions(CabinClass::Economy),
+                commercial_first_co2_emissions: class_co2_emissions(CabinClass::First),
+                commercial_alternative_exists: Some(crate::commercial::alternative_exists(
+                    airports,
+                    leg.from().pos(),
+                    leg.to().pos(),
+                )),
+                aircraft_category: model.map(|model| model.category.name().into()),
+                // set by `lineage`
+                source_partition: None,
+                source_first_position: None,
+                source_last_position: None,
+                altitude_source: crate::altitude::leg_source(leg.positions()).map(Into::into),
+                overlays: enrichment.overlays,
+                overlay_minutes: enrichment.overlay_minutes,
+                overlay_min_altitude: enrichment.overlay_min_altitude,
+                overlay_distance: enrichment.overlay_distance,
+            };
+            Some((leg, profile))
+        })
+}
+
+/// The lineage of the legs computed from a partition of positions (see `M-lineage`).
+/// It holds the datetimes of the positions read since the first position of the last leg, so that the indexes
+/// of the positions of the next legs are found while positions are read.
+struct Lineage {
+    partition: Arc<str>,
+    /// the index of the first of `datetimes` in the partition
+    offset: usize,
+    datetimes: VecDeque<time::OffsetDateTime>,
+}
+
+impl Lineage {
+    fn new(partition: Arc<str>) -> Self {
+        Self {
+            partition,
+            offset: 0,
+            datetimes: VecDeque::new(),
+        }
+    }
+
+    /// Records the datetime of the next position of the partition
+    fn push(&mut self, datetime: time::OffsetDateTime) {
+        self.datetimes.push_back(datetime);
+    }
+
+    /// Sets the [`LegOut::source_partition`] of `leg` and the indexes of its first and last positions in it.
+    /// `leg` is the next leg computed from the positions recorded so far; legs are ordered by their start.
+    fn set(&mut self, leg: &mut LegOut) {
+        let first = self
+            .datetimes
+            .iter()
+            .position(|datetime| *datetime == leg.start);
+        let last = first.and_then(|first| {
+            self.datetimes
+                .range(first..)
+                .position(|datetime| *datetime == leg.end)
+                .map(|index| first + index)
+        });
+        leg.source_partition = Some(self.partition.clone());
+        leg.source_first_position = first.map(|index| (self.offset + index) as u64);
+        leg.source_last_position = last.map(|index| (self.offset + index) as u64);
+        // a leg may start at the last position of the previous leg
+        if let Some(first) = first {
+            self.datetimes.drain(..first);
+            self.offset += first;
+        }
+    }
+}
+
+async fn write<L: Borrow<LegOut> + Serialize + Send>(
+    roots: &Roots,
+    icao: &Arc<str>,
+    month: time::Date,
+    legs: impl Iterator<Item = Result<L, Error>> + Send,
+    format: Format,
+    compression: Compression,
+    client: &dyn BlobStorageProvider,
+) -> Result<(), Error> {
+    let key = pk_to_blob_name(roots, icao, month, format, Compression::None);
+
+    // the legs are serialized as they are computed; an error ends them and fails the write
+    let error = Mutex::new(None);
+    let legs = legs.map_while(|leg| leg.map_err(|e| *error.lock().unwrap() = Some(e)).ok());
+    let chunks = serialize_legs_chunks(legs, format).chain(std::iter::from_fn(|| {
+        error.lock().unwrap().take().map(|e: Error| Err(e.into()))
+    }));
+    crate::io::put_stream(&key, chunks, compression, client).await?;
+    log::info!("Written {} {}", icao, month);
+    Ok(())
+}
+
+async fn read_u8(
+    roots: &Roots,
+    icao: &Arc<str>,
+    month: time::Date,
+    format: Format,
+    compression: Compression,
+    client: &dyn BlobStorageProvider,
+) -> Result<Option<Vec<u8>>, std::io::Error> {
+    log::info!("Read icao={icao} month={month}");
+    let key = pk_to_blob_name(roots, icao, month, format, compression);
+    match client.maybe_get(&key).await? {
+        Some(data) => crate::compression::decompress(data).map(Some),
+        // partitions written with another compression
+        None => {
+            let key = pk_to_blob_name(roots, icao, month, format, Compression::None);
+            crate::io::maybe_get(&key, client).await
+        }
+    }
+}
+
+/// Returns the blob name of the partition of legs of `icao` on `month` in `format` and `compression` under `roots`
+/// (e.g. `leg/v2/data/month=2023-01/icao_number=459cd3/data.csv` or `.../data.csv.zst`)
+pub fn pk_to_blob_name(
+    roots: &Roots,
+    icao: &str,
+    month: time::Date,
+    format: Format,
+    compression: Compression,
+) -> String {
+    let month = crate::serde::month_to_part(month);
+    let extension = format.extension();
+    let compression = compression.extension();
+    format!(
+        "{}data/month={month}/icao_number={icao}/data.{extension}{compression}",
+        roots.legs
+    )
+}
+
+fn profile_pk_to_blob_name(roots: &Roots, icao: &str, month: time::Date) -> String {
+    let month = crate::serde::month_to_part(month);
+    format!(
+        "{}profile/month={month}/icao_number={icao}/data.csv",
+        roots.legs
+    )
+}
+
+/// Returns the blob name of the [`Manifest`] of the partition of legs of `icao` on `month` under `roots`
+/// (e.g. `leg/v2/manifest/month=2023-01/icao_number=459cd3/manifest.json`)
+pub fn manifest_pk_to_blob_name(roots: &Roots, icao: &str, month: time::Date) -> String {
+    let month = crate::serde::month_to_part(month);
+    format!(
+        "{}manifest/month={month}/icao_number={icao}/manifest.json",
+        roots.legs
+    )
+}
+
+fn activity_pk_to_blob_name(roots: &Roots, icao: &str, month: time::Date) -> String {
+    let month = crate::serde::month_to_part(month);
+    format!(
+        "{}data/month={month}/icao_number={icao}/data.csv",
+        roots.activity
+    )
+}
+
+/// State shared by all tasks of a run
+pub struct Context<'a> {
+    pub client: &'a dyn BlobStorageProvider,
+    /// where the outputs are written to
+    pub roots: &'a Roots,
+    /// where every computed leg is published to, when any
+    pub events: Option<&'a dyn EventPublisher>,
+    /// only legs touching it are written, when any
+    pub region: Option<&'a Region>,
+    /// the exclusion zones and aircrafts whose legs are tagged with `excluded_reason`, when any
+    pub exclusions: Option<&'a Exclusions>,
+    /// whether legs matching `exclusions` are not written instead of tagged
+    pub drop_excluded: bool,
+    /// the changes of aircrafts within months, whose legs are of the aircraft in effect at their start
+    pub swaps: Option<&'a Swaps>,
+    pub airports: &'a Airports,
+    /// the stages adding columns to legs
+    pub enrichers: &'a Enrichers<'a>,
+    pub winds: Option<&'a Winds>,
+    /// the QNH of METAR observations correcting barometric altitudes, when any (see `M-altitudes`)
+    pub qnhs: Option<&'a Qnhs>,
+    pub emissions: &'a EmissionsConfig,
+    /// the fuel flow of the phases of legs of each model; models without them use the default
+    pub model_phases: &'a HashMap<String, PhaseFactors>,
+    /// the backend computing the emissions of the same legs on commercial flights, when computed
+    pub commercial: Option<&'a dyn CommercialEmissions>,
+    /// whether to write the altitude profile of every leg
+    pub profiles: bool,
+    /// the file format of legs
+    pub format: Format,
+    /// the compression of the partitions of legs
+    pub compression: Compression,
+    /// the thresholds to identify legs
+    pub legs: LegsConfig,
+    /// whether to deserialize positions on rayon's thread pool (requires feature `parallel`)
+    pub parallel_decode: bool,
+}
+
+/// The manifest of a partition of legs, written by [`process_icao_month`] to [`manifest_pk_to_blob_name`]:
+/// how long each of its stages took and how much it read (see `M-manifests`)
+#[derive(Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    pub icao_number: Arc<str>,
+    /// The month (e.g. `2023-01`)
+    pub month: String,
+    /// The size in bytes of the positions read
+    pub source_bytes: usize,
+    /// The number of legs written
+    pub legs: usize,
+    /// The number of legs written without departure or arrival airport (see `M-leg-airports`)
+    pub legs_without_airports: usize,
+    /// The time in seconds to read the positions (and winds)
+    pub extract: f64,
+    /// The time in seconds to deserialize the positions and compute, serialize and write the legs (legs are
+    /// written as they are computed) and activity
+    pub transform: f64,
+    /// The time in seconds to write the profiles and activity and to publish the legs
+    pub load: f64,
+}
+
+impl Manifest {
+    /// The total time in seconds of the partition
+    pub fn total(&self) -> f64 {
+        self.extract + self.transform + self.load
+    }
+}
+
+/// The summary of the [`Manifest`]s of a run: the total time of each stage and the slowest partitions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimingReport {
+    /// The number of slowest partitions kept
+    limit: usize,
+    /// The number of partitions
+    pub partitions: usize,
+    /// The total size in bytes of the positions read
+    pub source_bytes: usize,
+    /// The total time in seconds of each stage
+    pub extract: f64,
+    pub transform: f64,
+    pub load: f64,
+    slowest: Vec<Manifest>,
+}
+
+impl TimingReport {
+    /// Returns a new [`TimingReport`] keeping the `limit` slowest partitions
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            ..Default::default()
+        }
+    }
+
+    /// Adds `manifest` to the report
+    pub fn push(&mut self, manifest: Manifest) {
+        self.partitions += 1;
+        self.source_bytes += manifest.source_bytes;
+        self.extract += manifest.extract;
+        self.transform += manifest.transform;
+        self.load += manifest.load;
+        self.slowest.push(manifest);
+        // amortize the sorting over `limit` pushes
+        if self.slowest.len() >= 2 * self.limit.max(1) {
+            self.truncate();
+        }
+    }
+
+    fn truncate(&mut self) {
+        self.slowest.sort_by(|a, b| b.total().total_cmp(&a.total()));
+        self.slowest.truncate(self.limit);
+    }
+
+    /// Returns the slowest partitions, slowest first
+    pub fn slowest(mut self) -> Vec<Manifest> {
+        self.truncate();
+        self.slowest
+    }
+}
+
+/// A month of an aircraft that failed, written to the errors of a run
+#[derive(Serialize, serde::Deserialize, Debug, Clone, PartialEq)]
+pub struct TaskError {
+    pub icao_number: Arc<str>,
+    /// The month (e.g. `2023-01`)
+    pub month: String,
+    /// The error
+    pub error: String,
+}
+
+impl TaskError {
+    /// Returns the [`TaskError`] of `icao_number` on `month` failing with `error`
+    pub fn new(icao_number: Arc<str>, month: time::Date, error: impl std::fmt::Display) -> Self {
+        Self {
+            icao_number,
+            month: crate::serde::month_to_part(month),
+            error: error.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for TaskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}: {}", self.icao_number, self.month, self.error)
+    }
+}
+
+/// The soft warnings of a run of the ETL of legs, which fail the run in strict mode (see `M-strict`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Warnings {
+    /// The months of ICAO numbers with positions but not in the database of aircrafts (see `M-backfill`)
+    pub unmatched: usize,
+    /// The months of aircrafts that failed (e.g. positions that could not be deserialized) and their error
+    pub failed: Vec<TaskError>,
+    /// The months of aircrafts that timed out (or were rate limited) on every attempt
+    pub timed_out: usize,
+    /// The legs without departure or arrival airport, when airports are computed
+    pub missing_airports: Option<usize>,
+}
+
+impl Warnings {
+    /// The maximum number of failures listed in [`Warnings::diagnostics`]
+    const MAX_FAILURES: usize = 10;
+
+    /// Adds the warnings of the partition of `manifest`
+    pub fn push(&mut self, manifest: &Manifest) {
+        if let Some(missing) = self.missing_airports.as_mut() {
+            *missing += manifest.legs_without_airports;
+        }
+    }
+
+    /// Returns one line per kind of warning, empty when there are no warnings
+    pub fn diagnostics(&self) -> Vec<String> {
+        let mut diagnostics = vec![];
+        if self.unmatched > 0 {
+            diagnostics.push(format!(
+                "{} months of ICAO numbers with positions but not in the database of aircrafts",
+                self.unmatched
+            ));
+        }
+        if !self.failed.is_empty() {
+            let examples = self.failed.iter().take(Self::MAX_FAILURES);
+            diagnostics.push(format!(
+                "{} months of aircrafts failed: {}",
+                self.failed.len(),
+                examples
+                    .map(|x| x.to_string())
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ));
+        }
+        if self.timed_out > 0 {
+            diagnostics.push(format!(
+                "{} months of aircrafts timed out or were rate limited on every attempt",
+                self.timed_out
+            ));
+        }
+        if let Some(missing @ 1..) = self.missing_airports {
+            diagnostics.push(format!(
+                "{missing} legs without departure or arrival airport"
+            ));
+        }
+        diagnostics
+    }
+}
+
+/// Computes the legs of `icao_number` on `month` and writes them to [`pk_to_blob_name`], together with
+/// their profiles (when [`Context::profiles`]), the activity of the month and its [`Manifest`], and publishes them
+/// to [`Context::events`].
+/// `aircraft` and `model` are `None` when the ICAO number has positions but is not in the database of aircrafts.
+/// # Error
+/// [`Error::MissingData`] when the positions do not exist, [`Error::Decode`] when they cannot be deserialized,
+/// and [`Error::RateLimited`] when the storage throttled a request (the month can be processed again later).
+pub async fn process_icao_month(
+    icao_number: &Arc<str>,
+    aircraft: Option<&Aircraft>,
+    model: Option<&AircraftModel>,
+    month: time::Date,
+    context: &Context<'_>,
+) -> Result<Manifest, Error> {
+    let Context {
+        client,
+        events,
+        winds,
+        qnhs,
+        ..
+    } = *context;
+    // extract
+    let start = std::time::Instant::now();
+    let winds = match winds {
+        Some(winds) => winds.month(month, client).await?,
+        None => None,
+    };
+    let qnhs = match qnhs {
+        Some(qnhs) => qnhs.month(month, client).await?,
+        None => None,
+    };
+    let data = crate::icao_to_trace::get_month_positions_json(icao_number, month, client).await?;
+    let source_bytes = data.len();
+    let extract = start.elapsed();
+    let partition = crate::trace_month::pk_to_blob_name(icao_number, month);
+    let error = Mutex::new(None);
+    let lineage = Mutex::new(Lineage::new(partition.into()));
+    let mut observed = HashSet::new();
+    let positions = crate::icao_to_trace::decode_positions(&data, context.parallel_decode)
+        .map_while(|position| position.map_err(|e| *error.lock().unwrap() = Some(e)).ok())
+        .inspect(|position| {
+            observed.insert(position.datetime().date());
+            lineage.lock().unwrap().push(position.datetime());
+        })
+        .map(|position| match &qnhs {
+            Some(qnhs) => qnhs.correct(position),
+            None => position,
+        });
+    // transform and load (positions are lazily deserialized while legs are computed and written)
+    let start = std::time::Instant::now();
+    let mut spans = vec![];
+    let mut legs_without_airports = 0;
+    let mut profiles = vec![];
+    // legs are only kept to be published once written
+    let publish = events.is_some();
+    let mut published = vec![];
+    let swaps = context
+        .swaps
+        .and_then(|swaps| swaps.get(&(icao_number.clone(), month)))
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+    for swap in swaps {
+        log::info!(
+            "{icao_number},{month}: aircraft {} from {}",
+            swap.aircraft.tail_number,
+            swap.date
+        );
+    }
+    let legs = transform(
+        icao_number,
+        aircraft,
+        model,
+        swaps,
+        positions,
+        context,
+        winds.as_deref(),
+    )
+    .map(|(mut leg, profile)| {
+        lineage.lock().unwrap().set(&mut leg);
+        spans.push((leg.start, leg.end));
+        if leg.from_airport_icao.is_none() || leg.to_airport_icao.is_none() {
+            legs_without_airports += 1;
+        }
+        profiles.extend(profile);
+        if publish {
+            published.push(leg.clone());
+        }
+        Ok(leg)
+    })
+    .chain(std::iter::from_fn(|| {
+        error.lock().unwrap().take().map(|e| Err(e.into()))
+    }));
+    write(
+        context.roots,
+        icao_number,
+        month,
+        legs,
+        context.format,
+        context.compression,
+        client,
+    )
+    .await?;
+    let legs_count = spans.len();
+    let activity = crate::activity::month_activity(
+        icao_number.clone(),
+        month,
+        observed.into_iter(),
+        spans.into_iter(),
+    );
+    let transform = start.elapsed();
+    // load
+    let start = std::time::Instant::now();
+    if context.profiles {
+        let key = profile_pk_to_blob_name(context.roots, icao_number, month);
+        write_csv(profiles.into_iter(), &key, client).await?;
+    }
+    let key = activity_pk_to_blob_name(context.roots, icao_number, month);
+    write_csv(std::iter::once(activity), &key, client).await?;
+    // notify
+    if let Some(events) = events {
+        for leg in &published {
+            crate::events::publish_json(events, icao_number, leg).await?;
+        }
+    }
+    let manifest = Manifest {
+        icao_number: icao_number.clone(),
+        month: crate::serde::month_to_part(month),
+        source_bytes,
+        legs: legs_count,
+        legs_without_airports,
+        extract: extract.as_secs_f64(),
+        transform: transform.as_secs_f64(),
+        load: start.elapsed().as_secs_f64(),
+    };
+    let key = manifest_pk_to_blob_name(context.roots, icao_number, month);
+    let data = serde_json::to_vec(&manifest).map_err(std::io::Error::other)?;
+    client.put(&key, data).await?;
+    Ok(manifest)
+}
+
+fn group_by_year(
+    required: impl Iterator<Item = (Arc<str>, time::Date)>,
+) -> HashMap<i32, HashSet<(Arc<str>, time::Date)>> {
+    required.fold(HashMap::<i32, HashSet<_>>::new(), |mut acc, v| {
+        acc.entry(v.1.year())
+            .and_modify(|entries| {
+                entries.insert(v.clone());
+            })
+            .or_insert(HashSet::from([v]));
+        acc
+    })
+}
+
+/// Assigns each leg to the canonical ICAO number of its airframe and drops legs of the same airframe
+/// reported under another ICAO number that overlap in time (the same flight reported under two ICAO numbers).
+/// Overlapping legs of the same ICAO number are kept, since they are not duplicates of a merge.
+/// Legs are ordered by ICAO number, start and end; legs of the same airframe with the same start and end are
+/// ordered by their original ICAO number, so that the result does not depend on the order of `legs`.
+fn merge_airframes(legs: impl Iterator<Item = LegOut>, merges: &MergeMap) -> Vec<LegOut> {
+    let mut legs = legs
+        .map(|mut leg| {
+            let original = leg.icao_number.clone();
+            leg.icao_number = crate::airframes::airframe(merges, &original).clone();
+            (original, leg)
+        })
+        .collect::<Vec<_>>();
+    legs.sort_unstable_by(|(a_original, a), (b_original, b)| {
+        (&a.icao_number, a.start, a.end, a_original).cmp(&(
+            &b.icao_number,
+            b.start,
+            b.end,
+            b_original,
+        ))
+    });
+    legs.dedup_by(|(original, leg), (previous_original, previous)| {
+        leg.icao_number == previous.icao_number
+            && original != previous_original
+            && leg.start < previous.end
+    });
+    legs.into_iter().map(|(_, leg)| leg).collect()
+}
+
+/// Returns the [`GroundTime`]s of all aircrafts from `legs` ordered by ICAO number and start
+fn ground_times(legs: &[LegOut]) -> Vec<GroundTime> {
+    legs.chunk_by(|a, b| a.icao_number == b.icao_number)
+        .flat_map(|legs| {
+            crate::ground_times::ground_times(
+                legs[0].icao_number.clone(),
+                legs.iter().map(|leg| {
+                    (
+                        (leg.start, leg.from_airport_icao.clone()),
+                        (leg.end, leg.to_airport_icao.clone()),
+                    )
+                }),
+            )
+        })
+        .collect()
+}
+
+/// Groups `legs` by the countries they start or end in.
+/// Legs starting and ending in the same country are in that country once; legs without known countries are dropped.
+fn group_by_country(legs: &[LegOut]) -> HashMap<Arc<str>, Vec<&LegOut>> {
+    legs.iter()
+        .fold(HashMap::<_, Vec<_>>::new(), |mut acc, leg| {
+            let mut countries = [leg.from_country.clone(), leg.to_country.clone()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+            countries.dedup();
+            for country in countries {
+                acc.entry(country).or_default().push(leg);
+            }
+            acc
+        })
+}
+
+/// The options of the aggregation of the partitions of legs into the yearly datasets
+pub struct AggregateConfig<'a> {
+    /// the factors used to compute `co2_emissions`, written to the status
+    pub emissions: &'a EmissionsConfig,
+    /// the models whose consumption was overridden, written to the status
+    pub model_overrides: &'a [ModelOverride],
+    /// the reclassifications of models, written to the status
+    pub model_changelog: &'a [ModelReclassification],
+    /// the units of the yearly datasets
+    pub units: Units,
+    /// the file format of legs
+    pub format: Format,
+    /// the compression of the partitions and the yearly datasets of legs
+    pub compression: Compression,
+    /// the calendar in which legs are assigned to years
+    pub calendar: Calendar,
+    /// the maximum number of partitions read concurrently (by each year)
+    pub concurrency: usize,
+    /// the maximum number of years aggregated concurrently
+    pub year_concurrency: usize,
+    /// the settings of the computation of legs, written to the descriptor of the methodology (see `M-descriptor`)
+    pub processing: &'a Processing,
+    /// where the partitions are read from and the yearly datasets written to
+    pub roots: &'a Roots,
+    /// the lock of the run, checked before the datasets of each year are written, so that a run that lost it
+    /// (e.g. it was stolen) does not overwrite those of the run holding it
+    pub lock: Option<&'a Lock>,
+    pub client: &'a dyn BlobStorageProvider,
+}
+
+/// Returns the prefixes of the datasets of all legs and of legs by country, and the blob name of the status,
+/// in `units` and `calendar`. The public dataset is in metric units; other units are written next to it
+fn aggregate_blob_names(
+    roots: &Roots,
+    units: Units,
+    calendar: Calendar,
+) -> (String, String, String) {
+    let root = &roots.aggregated_in(&roots.legs, calendar);
+    match units {
+        Units::Metric => (
+            format!("{root}all/"),
+            format!("{root}by_country/"),
+            format!("{root}status.json"),
+        ),
+        units => {
+            let units = format!("units={}-{}/", units.distance_unit(), units.mass_unit());
+            let all = format!("{root}all/{units}");
+            let status = format!("{all}status.json");
+            (all, format!("{root}by_country/{units}"), status)
+        }
+    }
+}
+
+/// Returns the prefix of the slim datasets of legs (see [`SlimLeg`]) in `units` and `calendar`
+fn slim_blob_name(roots: &Roots, units: Units, calendar: Calendar) -> String {
+    let root = &roots.aggregated_in(&roots.legs, calendar);
+    match units {
+        Units::Metric => format!("{root}slim/"),
+        units => format!(
+            "{root}slim/units={}-{}/",
+            units.distance_unit(),
+            units.mass_unit()
+        ),
+    }
+}
+
+/// Aggregates the partitions `completed` of `year` into the datasets of all legs, of legs by country and of
+/// ground times of the year, with the ICAO numbers of the same airframe merged according to `merges`.
+/// Returns the [`Metadata`] of the year.
+///
+/// The legs of the year are held in memory once, since legs of the same airframe are merged and ordered across
+/// partitions; the partitions are decoded as they are read, the datasets by country borrow from those legs, and
+/// all datasets are serialized and written in chunks of [`CHUNK_SIZE`] (`parquet` is serialized at once).
+pub async fn aggregate_year<'a>(
+    year: i32,
+    completed: &HashSet<(Arc<str>, time::Date)>,
+    merges: &MergeMap,
+    config: &AggregateConfig<'a>,
+) -> Result<Metadata<'a>, Error> {
+    let AggregateConfig {
+        emissions,
+        model_overrides,
+        model_changelog,
+        units,
+        format,
+        compression,
+        calendar,
+        concurrency,
+        roots,
+        lock,
+        client,
+        ..
+    } = *config;
+    let (all, by_country, _) = aggregate_blob_names(roots, units, calendar);
+
+    // each partition is decoded once read, so that only the legs of the year are held in memory
+    let tasks = completed.iter().map(|(icao_number, date)| async move {
+        let Some(content) = read_u8(roots, icao_number, *date, format, compression, client).await?
+        else {
+            return Ok(vec![]); // drop those that do not exist
+        };
+        let legs = deserialize_legs(&content, format)?;
+        Ok::<_, Error>(
+            legs.into_iter()
+                .filter(|leg| {
+                    // partitions of adjacent years contain legs of this year in calendars other than UTC
+                    calendar == Calendar::Utc
+                        || calendar.date(leg.start, leg.start_local.as_deref()).year() == year
+                })
+                .collect::<Vec<_>>(),
+        )
+    });
+
+    log::info!("Gettings all legs for year={year}");
+    let legs = futures::stream::iter(tasks)
+        .buffered(concurrency)
+        .try_fold(Vec::new(), |mut acc, legs| async move {
+            acc.extend(legs);
+            Ok(acc)
+        })
+        .await?;
+    let legs = merge_airframes(legs.into_iter(), merges)
+        .into_iter()
+        .map(|leg| leg.with_units(units))
+        .collect::<Vec<_>>();
+
+    if let Some(lock) = lock {
+        lock.check(client).await?;
+    }
+    log::info!("Writing all legs for year={year}");
+    let key = format!("{all}year={year}/data.{}", format.extension());
+    let chunks = serialize_legs_chunks(legs.iter(), format);
+    let legs_key = crate::io::put_stream(&key, chunks, compression, client).await?;
+    log::info!("Written {legs_key}");
+
+    log::info!("Writing slim legs for year={year}");
+    let slim_key = format!(
+        "{}year={year}/data.csv",
+        slim_blob_name(roots, units, calendar)
+    );
+    let chunks = crate::csv::serialize_chunks(legs.iter().map(SlimLeg::from), CHUNK_SIZE).map(Ok);
+    let written = crate::io::put_stream(&slim_key, chunks, compression, client).await?;
+    log::info!("Written {written}");
+
+    log::info!("Writing ground times for year={year}");
+    let ground_times_key = format!(
+        "{}all/year={year}/data.csv",
+        roots.aggregated_in(&roots.ground_times, calendar)
+    );
+    write_csv(ground_times(&legs).iter(), &ground_times_key, client).await?;
+    log::info!("Written {ground_times_key}");
+
+    log::info!("Writing legs by country for year={year}");
+    for (country, legs) in group_by_country(&legs) {
+        let key = format!(
+            "{by_country}country={country}/year={year}/data.{}",
+            format.extension()
+        );
+        let chunks = serialize_legs_chunks(legs.into_iter(), format);
+        crate::io::put_stream(&key, chunks, compression, client).await?;
+    }
+    Ok(Metadata {
+        icao_months_to_process: completed.len(),
+        icao_months_processed: completed.len(),
+        url: format!("https://private-jets.fra1.digitaloceanspaces.com/{legs_key}"),
+        emissions: *emissions,
+        model_overrides,
+        model_changelog,
+        timezone: calendar.name(),
+        distance_unit: units.distance_unit(),
+        mass_unit: units.mass_unit(),
+        schema_version: crate::schema::version::<LegOut>(),
+        last_updated: time::OffsetDateTime::now_utc(),
+    })
+}
+
+/// Aggregates the partitions `required` of each year with [`aggregate_year`], [`AggregateConfig::year_concurrency`]
+/// years at a time, and writes the [`Metadata`] of each year to `status.json` once the year is aggregated.
+/// # Error
+/// A year that fails does not stop the aggregation of the other years; the error of the first failed year
+/// is returned once all years are aggregated.
+pub async fn aggregate(
+    required: impl Iterator<Item = (Arc<str>, time::Date)>,
+    config: &AggregateConfig<'_>,
+) -> Result<(), Error> {
+    let client = config.client;
+    let roots = config.roots;
+    let (all, by_country, status) = aggregate_blob_names(roots, config.units, config.calendar);
+
+    // so that loaders do not need to guess the types and units of the columns (see `M-schemas`)
+    let data = format!("{}data/", roots.legs);
+    crate::schema::write::<LegOut>(&data, Units::Metric, client).await?;
+    crate::schema::write::<LegOut>(&all, config.units, client).await?;
+    crate::schema::write::<LegOut>(&by_country, config.units, client).await?;
+    let slim = slim_blob_name(roots, config.units, config.calendar);
+    crate::schema::write::<SlimLeg>(&slim, config.units, client).await?;
+    let ground_times = format!(
+        "{}all/",
+        roots.aggregated_in(&roots.ground_times, config.calendar)
+    );
+    crate::schema::write::<GroundTime>(&ground_times, config.units, client).await?;
+    let methodology = format!(
+        "{}{}",
+        status.trim_end_matches("status.json"),
+        crate::descriptor::BLOB
+    );
+    write_json(client, Descriptor::new(config), &methodology).await?;
+    log::info!("Written {methodology}");
+
+    let merges = crate::airframes::read(client).await?;
+    log::info!(
+        "ICAO numbers merged into another airframe: {}",
+        merges.len()
+    );
+
+    // group by year
+    let mut required_by_year = group_by_year(required);
+    if config.calendar != Calendar::Utc {
+        // the first and last hours of a year in UTC may be of the adjacent year in other calendars
+        let adjacent = required_by_year
+            .values()
+            .flatten()
+            .filter_map(|(icao_number, month)| {
+                let year = match month.month() {
+                    time::Month::January => month.year() - 1,
+                    time::Month::December => month.year() + 1,
+                    _ => return None,
+                };
+                Some((year, (icao_number.clone(), *month)))
+            })
+            .collect::<Vec<_>>();
+        for (year, key) in adjacent {
+            if let Some(completed) = required_by_year.get_mut(&year) {
+                completed.insert(key);
+            }
+        }
+    }
+
+    // years not aggregated in this run keep their previous status
+    let mut metadata = match client.maybe_get(&status).await? {
+        Some(data) => serde_json::from_slice::<HashMap<String, serde_json::Value>>(&data)?,
+        None => Default::default(),
+    };
+
+    // run tasks by year
+    let mut required_by_year = required_by_year.into_iter().collect::<Vec<_>>();
+    required_by_year.sort_unstable_by_key(|(year, _)| *year);
+    let merges = &merges;
+    let tasks = required_by_year.iter().map(|(year, completed)| async move {
+        (
+            *year,
+            aggregate_year(*year, completed, merges, config).await,
+        )
+    });
+    let mut years = futures::stream::iter(tasks).buffer_unordered(config.year_concurrency.max(1));
+    let mut failed = vec![];
+    while let Some((year, result)) = years.next().await {
+        let year_metadata = match result {
+            Ok(year_metadata) => year_metadata,
+            Err(e) => {
+                log::error!("year={year} failed to aggregate: {e}");
+                failed.push((year, e));
+                continue;
+            }
+        };
+        metadata.insert(year.to_string(), serde_json::to_value(year_metadata)?);
+
+        // so that the status is up to date while the remaining years are aggregated
+        write_json(client, &metadata, &status).await?;
+        log::info!("status written for year={year}");
+    }
+    failed.sort_unstable_by_key(|(year, _)| *year);
+    match failed.into_iter().next() {
+        Some((_, e)) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Aggregates the monthly activity of each aircraft into its yearly activity.
+/// Returns the yearly activities of each aircraft.
+pub async fn aggregate_activity(
+    required: impl Iterator<Item = (Arc<str>, time::Date)>,
+    roots: &Roots,
+    concurrency: usize,
+    client: &dyn BlobStorageProvider,
+) -> Result<HashMap<Arc<str>, Vec<YearActivity>>, Error> {
+    let root = format!("{}all/", roots.aggregated(&roots.activity));
+    crate::schema::write::<YearActivity>(&root, Units::Metric, client).await?;
+    let mut by_icao = HashMap::<Arc<str>, Vec<YearActivity>>::new();
+    for (year, completed) in group_by_year(required) {
+        let tasks = completed.iter().map(|(icao_number, date)| async move {
+            client
+                .maybe_get(&activity_pk_to_blob_name(roots, icao_number, *date))
+                .await
+        });
+
+        log::info!("Gettings all activity for year={year}");
+        let months_by_icao = futures::stream::iter(tasks)
+            .buffered(concurrency)
+            .try_collect::<Vec<_>>()
+            .await?
+            .into_iter()
+            .flatten() // drop those that do not exist
+            .map(|content| {
+                crate::csv::deserialize::<MonthActivity>(&content).collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .fold(HashMap::<Arc<str>, Vec<_>>::new(), |mut acc, month| {
+                acc.entry(month.icao_number.clone())
+                    .or_default()
+                    .push(month);
+                acc
+            });
+
+        let mut activity = months_by_icao
+            .into_iter()
+            .map(|(icao_number, months)| {
+                crate::activity::year_activity(icao_number, year, months.into_iter())
+            })
+            .collect::<Vec<_>>();
+        activity.sort_unstable_by(|a, b| a.icao_number.cmp(&b.icao_number));
+
+        let key = format!("{root}year={year}/data.csv");
+        write_csv(activity.iter(), &key, client).await?;
+        log::info!("Written {key}");
+        for activity in activity {
+            by_icao
+                .entry(activity.icao_number.clone())
+                .or_default()
+                .push(activity);
+        }
+    }
+    Ok(by_icao)
+}
+
+/// Aggregates the legs of the weeks of the months of `required` into `{legs}weekly/data.csv` (see `M-weekly`),
+/// replacing the rows of those weeks and keeping the rows of other weeks.
+/// # Implementation
+/// The legs of the weeks are read from the partitions of the aircrafts of `required` on every month with a day of
+/// those weeks, so that weeks across two months are complete.
+pub async fn aggregate_weeks(
+    required: impl Iterator<Item = (Arc<str>, time::Date)>,
+    config: &AggregateConfig<'_>,
+) -> Result<(), Error> {
+    let AggregateConfig {
+        units,
+        concurrency,
+        roots,
+        client,
+        ..
+    } = *config;
+    let (icao_numbers, months): (HashSet<_>, HashSet<_>) = required.unzip();
+    let weeks = months
+        .iter()
+        .flat_map(|month| crate::weekly::weeks_of_month(*month))
+        .collect::<HashSet<_>>();
+    let months = weeks
+        .iter()
+        .flat_map(|week| {
+            let start = crate::weekly::week_start(*week);
+            let end = start + time::Duration::days(6);
+            crate::months_between(start, end)
+        })
+        .collect::<HashSet<_>>();
+    log::info!("Aggregating {} weeks", weeks.len());
+
+    let tasks = icao_numbers.iter().flat_map(|icao_number| {
+        months.iter().map(move |month| {
+            let to = crate::trace_month::first_of_next_month(month).previous_day();
+            let to = to.expect("month to have a previous day");
+            crate::query::legs_of(roots.clone(), icao_number, *month, to, client).try_collect()
+        })
+    });
+    let legs = futures::stream::iter(tasks)
+        .buffer_unordered(concurrency)
+        .try_collect::<Vec<Vec<LegOut>>>()
+        .await?;
+    let now = time::OffsetDateTime::now_utc();
+    let mut rows = crate::weekly::weeks(&weeks, legs.iter().flatten(), units, now);
+
+    let root = format!("{}weekly/", roots.aggregated(&roots.legs));
+    let key = format!("{root}data.csv");
+    let names = rows.iter().map(|x| x.week.clone()).collect::<HashSet<_>>();
+    let existing = match crate::io::get_csv::<Week>(&key, client).await {
+        Ok(existing) => existing,
+        Err(Error::MissingData(_)) => vec![],
+        Err(e) => return Err(e),
+    };
+    rows.extend(existing.into_iter().filter(|x| !names.contains(&x.week)));
+    rows.sort_unstable_by(|a, b| a.week.cmp(&b.week));
+    write_csv(rows.iter(), &key, client).await?;
+    crate::schema::write::<Week>(&root, units, client).await?;
+    log::info!("Written {key}");
+    Ok(())
+}
+
+/// Writes the [`Movement`]s of the legs of `required` at the airports of `watches` to
+/// `{legs}airport_watch/month={month}/data.csv` (see `M-airport-watch`), replacing the rows of the aircrafts of
+/// `required` on each month and keeping the rows of other aircrafts.
+pub async fn aggregate_airport_watch(
+    required: impl Iterator<Item = (Arc<str>, time::Date)>,
+    watches: &[Watch],
+    airports: &Airports,
+    config: &AggregateConfig<'_>,
+) -> Result<(), Error> {
+    let AggregateConfig {
+        concurrency,
+        roots,
+        client,
+        ..
+    } = *config;
+    let mut by_month = HashMap::<time::Date, HashSet<Arc<str>>>::new();
+    for (icao_number, month) in required {
+        by_month.entry(month).or_default().insert(icao_number);
+    }
+    log::info!(
+        "Watching {} airports on {} months",
+        watches.len(),
+        by_month.len()
+    );
+
+    let root = format!("{}airport_watch/", roots.aggregated(&roots.legs));
+    for (month, icao_numbers) in by_month {
+        let to = crate::trace_month::first_of_next_month(&month).previous_day();
+        let to = to.expect("month to have a previous day");
+        let tasks = icao_numbers.iter().map(|icao_number| {
+            crate::query::legs_of(roots.clone(), icao_number, month, to, client).try_collect()
+        });
+        let legs = futures::stream::iter(tasks)
+            .buffer_unordered(concurrency)
+            .try_collect::<Vec<Vec<LegOut>>>()
+            .await?;
+        let mut rows = crate::airport_watch::movements(watches, airports, legs.iter().flatten())?;
+
+        let key = format!(
+            "{root}month={}/data.csv",
+            crate::serde::month_to_part(month)
+        );
+        let existing = match crate::io::get_csv::<Movement>(&key, client).await {
+            Ok(existing) => existing,
+            Err(Error::MissingData(_)) => vec![],
+            Err(e) => return Err(e),
+        };
+        rows.extend(
+            existing
+                .into_iter()
+                .filter(|x| !icao_numbers.contains(&x.icao_number)),
+        );
+        rows.sort_by_key(|x| x.datetime);
+        write_csv(rows.iter(), &key, client).await?;
+        log::info!("Written {key}");
+    }
+    crate::schema::write::<Movement>(&root, Units::Metric, client).await?;
+    Ok(())
+}
+
+/// Writes the [`Reactivation`]s of all aircrafts to `reactivations.csv` and publishes to `notify` those that
+/// were not in it before (so that subscribers receive each reactivation once).
+pub async fn reactivations(
+    activity: HashMap<Arc<str>, Vec<YearActivity>>,
+    min_idle_months: i32,
+    notify: Option<&dyn EventPublisher>,
+    roots: &Roots,
+    client: &dyn BlobStorageProvider,
+) -> Result<(), Error> {
+    let mut reactivations = activity
+        .into_iter()
+        .flat_map(|(icao_number, years)| {
+            crate::activity::reactivations(icao_number, years.into_iter(), min_idle_months)
+        })
+        .collect::<Vec<_>>();
+    reactivations
+        .sort_unstable_by(|a, b| (&a.resumed, &a.icao_number).cmp(&(&b.resumed, &b.icao_number)));
+    log::info!("reactivations: {}", reactivations.len());
+
+    let key = format!("{}reactivations.csv", roots.aggregated(&roots.activity));
+    let previous = match crate::io::maybe_get(&key, client).await? {
+        Some(data) => crate::csv::deserialize::<crate::activity::Reactivation>(&data)
+            .map(|x| x.map(|x| (x.icao_number, x.resumed)))
+            .collect::<Result<HashSet<_>, _>>()?,
+        None => Default::default(),
+    };
+    write_csv(reactivations.iter(), &key, client).await?;
+    log::info!("Written {key}");
+
+    if let Some(notify) = notify {
+        let new = reactivations
+            .iter()
+            .filter(|x| !previous.contains(&(x.icao_number.clone(), x.resumed.clone())));
+        let mut published = 0;
+        for reactivation in new {
+            crate::events::publish_json(notify, &reactivation.icao_number, reactivation).await?;
+            published += 1;
+        }
+        notify.flush().await?;
+        log::info!("published {published} new reactivations");
+    }
+    Ok(())
+}
+
+/// An `(icao_number, month)` with positions but without an [`Aircraft`] in the database of aircrafts
+#[derive(serde::Serialize)]
+struct Unmatched<'a> {
+    icao_number: &'a str,
+    month: String,
+}
+
+/// Returns the `(icao_number, month)` of `years` with positions that are not in `required`
+/// (e.g. because the ICAO number was removed from the database of aircrafts),
+/// and writes them to `unmatched_icaos.csv`.
+/// The positions are listed from their index, rebuilt from a full listing when `refresh_index` is true
+/// (see [`crate::icao_to_trace::indexed_client`]).
+pub async fn unmatched(
+    required: &RequiredTasks,
+    years: &HashSet<i32>,
+    refresh_index: bool,
+    roots: &Roots,
+    client: &(dyn BlobStorageProvider + Sync),
+) -> Result<Vec<(Arc<str>, time::Date)>, Error> {
+    let positions = crate::icao_to_trace::indexed_client(client, refresh_index).await?;
+    let mut unmatched = crate::icao_to_trace::list_months_positions(&positions)
+        .await?
+        .into_iter()
+        .filter(|(_, month)| years.contains(&month.year()))
+        .filter(|key| !required.contains_key(key))
+        .collect::<Vec<_>>();
+    unmatched.sort_unstable_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+
+    let key = format!("{}unmatched_icaos.csv", roots.legs);
+    let rows = unmatched.iter().map(|(icao_number, month)| Unmatched {
+        icao_number,
+        month: crate::serde::month_to_part(*month),
+    });
+    write_csv(rows, &key, client).await?;
+    log::info!("Written {key}");
+    Ok(unmatched)
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+
+    #[test]
+    fn blob_names() {
+        let roots = Roots::default();
+        assert_eq!(
+            pk_to_blob_name(
+                &roots,
+                "459cd3",
+                date!(2023 - 01 - 01),
+                Format::Csv,
+                Compression::None
+            ),
+            "leg/v2/data/month=2023-01/icao_number=459cd3/data.csv"
+        );
+        let (all, by_country, status) = aggregate_blob_names(&roots, Units::Metric, Calendar::Utc);
+        assert_eq!(
+            (all.as_str(), by_country.as_str(), status.as_str()),
+            ("leg/v2/all/", "leg/v2/by_country/", "leg/v2/status.json")
+        );
+        let new_york = "America/New_York".parse().unwrap();
+        let (all, _, status) = aggregate_blob_names(&roots, Units::Metric, new_york);
+        assert_eq!(
+            (all.as_str(), status.as_str()),
+            (
+                "leg/v2/timezone=America-New_York/all/",
+                "leg/v2/timezone=America-New_York/status.json"
+            )
+        );
+        assert_eq!(
+            activity_pk_to_blob_name(&roots, "459cd3", date!(2023 - 01 - 01)),
+            "activity/v1/data/month=2023-01/icao_number=459cd3/data.csv"
+        );
+
+        assert_eq!(
+            pk_to_blob_name(
+                &roots,
+                "459cd3",
+                date!(2023 - 01 - 01),
+                Format::Csv,
+                Compression::Zstd
+            ),
+            "leg/v2/data/month=2023-01/icao_number=459cd3/data.csv.zst"
+        );
+
+        let roots = Roots::new("v3");
+        assert_eq!(
+            pk_to_blob_name(
+                &roots,
+                "459cd3",
+                date!(2023 - 01 - 01),
+                Format::Csv,
+                Compression::None
+            ),
+            "leg/v3/data/month=2023-01/icao_number=459cd3/data.csv"
+        );
+        assert_eq!(
+            activity_pk_to_blob_name(&roots, "459cd3", date!(2023 - 01 - 01)),
+            "leg/v3/activity/data/month=2023-01/icao_number=459cd3/data.csv"
+        );
+        assert_eq!(roots.ground_times, "leg/v3/ground_times/");
+        assert_eq!(
+            manifest_pk_to_blob_name(&roots, "459cd3", date!(2023 - 01 - 01)),
+            "leg/v3/manifest/month=2023-01/icao_number=459cd3/manifest.json"
+        );
+
+        // partitions are shared with the subset; aggregates are not
+        let roots = Roots::default().with_subset("459cd3");
+        assert_eq!(
+            pk_to_blob_name(
+                &roots,
+                "459cd3",
+                date!(2023 - 01 - 01),
+                Format::Csv,
+                Compression::None
+            ),
+            "leg/v2/data/month=2023-01/icao_number=459cd3/data.csv"
+        );
+        let (all, _, status) = aggregate_blob_names(&roots, Units::Metric, Calendar::Utc);
+        assert_eq!(
+            (all.as_str(), status.as_str()),
+            (
+                "leg/v2/subset=459cd3/all/",
+                "leg/v2/subset=459cd3/status.json"
+            )
+        );
+        assert_eq!(
+            roots.aggregated(&roots.activity),
+            "activity/v1/subset=459cd3/"
+        );
+        // partitions of a region are not shared
+        let roots = Roots::default().with_region("denmark");
+        assert_eq!(
+            pk_to_blob_name(
+                &roots,
+                "459cd3",
+                date!(2023 - 01 - 01),
+                Format::Csv,
+                Compression::None
+            ),
+            "leg/v2/region=denmark/data/month=2023-01/icao_number=459cd3/data.csv"
+        );
+        let (all, _, status) = aggregate_blob_names(&roots, Units::Metric, Calendar::Utc);
+        assert_eq!(
+            (all.as_str(), status.as_str()),
+            (
+                "leg/v2/region=denmark/all/",
+                "leg/v2/region=denmark/status.json"
+            )
+        );
+        assert_eq!(roots.activity, "leg/v2/region=denmark/activity/");
+    }
+
+    #[test]
+    fn timing_report() {
+        let manifest = |icao_number: &str, transform: f64| Manifest {
+            icao_number: icao_number.into(),
+            month: "2023-01".to_string(),
+            source_bytes: 100,
+            legs: 1,
+            legs_without_airports: 0,
+            extract: 1.0,
+            transform,
+            load: 1.0,
+        };
+        let mut report = TimingReport::new(2);
+        for (i, transform) in [3.0, 1.0, 5.0, 2.0, 4.0].into_iter().enumerate() {
+            report.push(manifest(&i.to_string(), transform));
+        }
+        assert_eq!(report.partitions, 5);
+        assert_eq!(report.source_bytes, 500);
+        assert_eq!(report.transform, 15.0);
+        assert_eq!(report.load, 5.0);
+        assert_eq!(
+            report.slowest(),
+            vec![manifest("2", 5.0), manifest("4", 4.0)]
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let mut warnings = Warnings::default();
+        assert!(warnings.diagnostics().is_empty());
+
+        let manifest = Manifest {
+            icao_number: "459cd3".into(),
+            month: "2023-01".to_string(),
+            source_bytes: 100,
+            legs: 3,
+            legs_without_airports: 2,
+            extract: 1.0,
+            transform: 1.0,
+            load: 1.0,
+        };
+        // airports are not computed
+        warnings.push(&manifest);
+        assert!(warnings.diagnostics().is_empty());
+
+        warnings.missing_airports = Some(0);
+        warnings.push(&manifest);
+        let month = date!(2023 - 01 - 01);
+        warnings.failed = (0..12)
+            .map(|i| TaskError::new("459cd3".into(), month, format!("failure {i}")))
+            .collect();
+        let diagnostics = warnings.diagnostics();
+        assert_eq!(diagnostics.len(), 2);
+        assert!(
+            diagnostics[0].starts_with("12 months of aircrafts failed: 459cd3 2023-01: failure 0;")
+        );
+        assert!(diagnostics[0].ends_with("failure 9"));
+        assert_eq!(
+            diagnostics[1],
+            "2 legs without departure or arrival airport"
+        );
+    }
+
+    fn leg(start: &str) -> LegOut {
+        serde_json::from_value(serde_json::json!({
+            "icao_number": "459cd3",
+            "start": start,
+            "start_lat": 55.6,
+            "start_lon": 12.6,
+            "start_altitude": 0.0,
+            "end": start,
+            "end_lat": 49.0,
+            "end_lon": 2.5,
+            "end_altitude": 0.0,
+            "duration": 1.5,
+            "distance": 1000.0,
+            "great_circle_distance": 1000.0,
+            "midpoint_lat": 52.3,
+            "midpoint_lon": 7.5,
+            "hours_above_30000": 1.0,
+            "hours_above_40000": 0.0,
+            "diverted": false,
+            "start_on_ground": true,
+            "end_on_ground": true,
+            "taxi_out_minutes": 0.0,
+            "taxi_in_minutes": 0.0,
+            "commercial_alternative_exists": false,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn schema() {
+        let leg = leg("2023-01-01T10:00:00Z");
+        let data = crate::csv::serialize(std::iter::once(leg.with_units(Units::Aviation)));
+        let data = String::from_utf8(data).unwrap();
+        let header = data.lines().next().unwrap().split(',').collect::<Vec<_>>();
+
+        let fields = <LegOut as crate::schema::Schema>::fields();
+        let names = fields.iter().map(|field| field.name).collect::<Vec<_>>();
+        assert_eq!(names, header);
+
+        let schema = crate::schema::to_json::<LegOut>(Units::Aviation);
+        let schema = serde_json::from_slice::<serde_json::Value>(&schema).unwrap();
+        assert_eq!(schema["columns"][12]["name"], "distance");
+        assert_eq!(schema["columns"][12]["unit"], "nm");
+        assert_eq!(schema["columns"][3]["type"], "datetime");
+        assert_eq!(schema["columns"][14]["nullable"], true);
+    }
+
+    #[test]
+    fn old_partitions() {
+        // the header of partitions written before columns were added
+        let data = b"icao_number,tail_number,aircraft_model,start,start_lat,start_lon,start_altitude,end,end_lat,end_lon,end_altitude,duration,distance,great_circle_distance,hours_above_30000,hours_above_40000,co2_emissions
+459cd3,OY-GFS,GULFSTREAM 5,2023-01-01T10:00:00Z,55.6,12.6,0.0,2023-01-01T11:30:00Z,49.0,2.5,0.0,1.5,1100.0,1005.0,1.0,0.0,5000.0
+";
+        let legs = crate::csv::deserialize::<LegOut>(data)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(legs.len(), 1);
+        let leg = &legs[0];
+        assert_eq!(leg.co2_emissions, Some(5000.0));
+        assert_eq!((leg.midpoint_lat, leg.circuity), (None, None));
+        assert_eq!(
+            (leg.diverted, leg.start_on_ground, leg.end_on_ground),
+            (None, None, None)
+        );
+        assert_eq!((leg.taxi_out_minutes, leg.taxi_in_minutes), (None, None));
+        assert_eq!(leg.commercial_alternative_exists, None);
+        assert_eq!(leg.overlays, None);
+    }
+
+    #[test]
+    fn lineage() {
+        let at = |minutes| {
+            time::macros::datetime!(2023-01-01 10:00 UTC) + time::Duration::minutes(minutes)
+        };
+        let mut legs = [leg("2023-01-01T10:01:00Z"), leg("2023-01-01T10:05:00Z")];
+        legs[0].end = at(5);
+        legs[1].end = at(9);
+
+        let mut lineage = Lineage::new("position/a".into());
+        // a leg is computed once positions after its end are read
+        (0..7).for_each(|minutes| lineage.push(at(minutes)));
+        lineage.set(&mut legs[0]);
+        (7..10).for_each(|minutes| lineage.push(at(minutes)));
+        lineage.set(&mut legs[1]);
+        // positions before the start of the last leg are not held
+        assert_eq!((lineage.offset, lineage.datetimes.len()), (5, 5));
+        let ranges = legs
+            .iter()
+            .map(|leg| (leg.source_first_position, leg.source_last_position))
+            .collect::<Vec<_>>();
+        // the second leg starts at the last position of the first
+        assert_eq!(ranges, vec![(Some(1), Some(5)), (Some(5), Some(9))]);
+        assert_eq!(legs[1].source_partition.as_deref(), Some("position/a"));
+    }
+
+    #[test]
+    fn merge_airframes() {
+        let mut legs = [
+            leg("2023-01-02T10:00:00Z"),
+            leg("2023-01-01T10:00:00Z"),
+            leg("2023-01-01T10:00:00Z"),
+            leg("2023-01-01T09:00:00Z"),
+        ];
+        for leg in legs.iter_mut() {
+            leg.end = leg.start + time::Duration::minutes(30);
+        }
+        legs[1].icao_number = "45d2ed".into();
+        legs[1].tail_number = Some("OY-B".into());
+        legs[2].tail_number = Some("OY-A".into());
+        legs[3].icao_number = "45d2ed".into();
+        let merges = MergeMap::from([("45d2ed".into(), "459cd3".into())]);
+
+        let expected = super::merge_airframes(legs.clone().into_iter(), &merges);
+        // the same flight reported under both ICAO numbers is kept once, from the lowest ICAO number
+        assert_eq!(expected.len(), 3);
+        assert_eq!(expected[1].tail_number.as_deref(), Some("OY-A"));
+        legs.reverse();
+
+        // overlapping legs of an ICAO number that was never merged are kept
+        let mut other = [leg("2023-01-01T10:00:00Z"), leg("2023-01-01T10:10:00Z")];
+        for leg in other.iter_mut() {
+            leg.icao_number = "4ca7b5".into();
+            leg.end = leg.start + time::Duration::minutes(30);
+        }
+        let result = super::merge_airframes(other.into_iter(), &merges);
+        assert_eq!(result.len(), 2);
+
+        let result = super::merge_airframes(legs.into_iter(), &merges);
+        assert_eq!(
+            serde_json::to_value(result).unwrap(),
+            serde_json::to_value(expected).unwrap()
+        );
+    }
+
+    #[derive(Default)]
+    struct Published(std::sync::Mutex<Vec<String>>);
+
+    #[async_trait::async_trait]
+    impl EventPublisher for Published {
+        async fn publish(&self, key: &str, _: Vec<u8>) -> Result<(), std::io::Error> {
+            self.0.lock().unwrap().push(key.to_string());
+            Ok(())
+        }
+
+        async fn flush(&self) -> Result<(), std::io::Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn reactivations() {
+        let root = std::env::temp_dir().join("test_reactivations");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let roots = Roots::default();
+        let key = format!("{}reactivations.csv", roots.aggregated(&roots.activity));
+        let previous = crate::activity::Reactivation {
+            icao_number: "aa".into(),
+            last_flight: "2022-01-01".to_string(),
+            resumed: "2023-01-01".to_string(),
+            idle_months: 11,
+        };
+        disk.put(&key, crate::csv::serialize(std::iter::once(previous)))
+            .await
+            .unwrap();
+
+        // flew on the first day of `month`
+        let flew = |icao_number: &str, month: &str| {
+            let year = month[..4].parse().unwrap();
+            let month = MonthActivity {
+                icao_number: icao_number.into(),
+                month: month.to_string(),
+                days: format!("F{}", "I".repeat(27)),
+            };
+            crate::activity::year_activity(icao_number.into(), year, std::iter::once(month))
+        };
+        let activity = HashMap::from([
+            (
+                "aa".into(),
+                vec![flew("aa", "2022-01"), flew("aa", "2023-01")],
+            ),
+            (
+                "bb".into(),
+                vec![flew("bb", "2022-01"), flew("bb", "2023-03")],
+            ),
+        ]);
+        let notify = Published::default();
+        super::reactivations(activity, 6, Some(&notify), &roots, &disk)
+            .await
+            .unwrap();
+        // the reactivation of `aa` was already published by a previous run
+        assert_eq!(*notify.0.lock().unwrap(), vec!["bb"]);
+        let written = crate::io::get_csv::<crate::activity::Reactivation>(&key, &disk)
+            .await
+            .unwrap();
+        assert_eq!(written.len(), 2);
+    }
+
+    static EMISSIONS: std::sync::LazyLock<EmissionsConfig> =
+        std::sync::LazyLock::new(EmissionsConfig::default);
+    static PROCESSING: std::sync::LazyLock<Processing> =
+        std::sync::LazyLock::new(Processing::default);
+
+    /// The [`AggregateConfig`] of the tests, of the datasets at `roots` in `disk`
+    fn config<'a>(roots: &'a Roots, disk: &'a crate::fs_local::LocalDisk) -> AggregateConfig<'a> {
+        AggregateConfig {
+            emissions: &EMISSIONS,
+            model_overrides: &[],
+            model_changelog: &[],
+            units: Units::Metric,
+            format: Format::Csv,
+            compression: Compression::None,
+            calendar: Calendar::Utc,
+            concurrency: 2,
+            year_concurrency: 2,
+            processing: &PROCESSING,
+            roots,
+            lock: None,
+            client: disk,
+        }
+    }
+
+    #[tokio::test]
+    async fn aggregate_years() {
+        let root = std::env::temp_dir().join("test_aggregate_years");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let roots = Roots::default();
+        let month = |year| time::Date::from_calendar_date(year, time::Month::January, 1).unwrap();
+        let key = |year| {
+            pk_to_blob_name(
+                &roots,
+                "459cd3",
+                month(year),
+                Format::Csv,
+                Compression::None,
+            )
+        };
+        let legs = ["2023-01-02T10:00:00Z", "2024-01-02T10:00:00Z"].map(leg);
+        for year in [2023, 2024] {
+            let legs = legs.iter().filter(|leg| leg.start.year() == year);
+            disk.put(&key(year), crate::csv::serialize(legs))
+                .await
+                .unwrap();
+        }
+        // a partition that cannot be deserialized
+        disk.put(&key(2022), b"icao_number\n459cd3\n".to_vec())
+            .await
+            .unwrap();
+
+        let config = config(&roots, &disk);
+        let required = [2022, 2023, 2024].map(|year| ("459cd3".into(), month(year)));
+        let result = aggregate(required.into_iter(), &config).await;
+        assert!(matches!(result, Err(Error::Decode(_))));
+
+        // the other years are aggregated
+        let status = disk.maybe_get("leg/v2/status.json").await.unwrap().unwrap();
+        let status = serde_json::from_slice::<HashMap<String, serde_json::Value>>(&status).unwrap();
+        let mut years = status.keys().cloned().collect::<Vec<_>>();
+        years.sort();
+        assert_eq!(years, vec!["2023", "2024"]);
+        assert_eq!(
+            status["2023"]["schema_version"],
+            crate::schema::version::<LegOut>()
+        );
+        assert_eq!(
+            status["2023"]["url"],
+            "https://private-jets.fra1.digitaloceanspaces.com/leg/v2/all/year=2023/data.csv"
+        );
+        // the descriptor of the methodology is written next to the status
+        let methodology = disk.maybe_get("leg/v2/methodology.json").await.unwrap();
+        let methodology =
+            serde_json::from_slice::<serde_json::Value>(&methodology.unwrap()).unwrap();
+        assert_eq!(methodology["legs"]["min_duration"], 300.0);
+        for year in [2023, 2024] {
+            let key = format!("leg/v2/all/year={year}/data.csv");
+            let data = disk.maybe_get(&key).await.unwrap().unwrap();
+            assert_eq!(deserialize_legs(&data, Format::Csv).unwrap().len(), 1);
+            let key = format!("leg/v2/slim/year={year}/data.csv");
+            let slim = crate::io::get_csv::<SlimLeg>(&key, &disk).await.unwrap();
+            assert_eq!(slim, vec![SlimLeg::from(&legs[(year - 2023) as usize])]);
+            assert!(data.len() > 5 * disk.maybe_get(&key).await.unwrap().unwrap().len());
+        }
+
+        // a run that lost its lock does not write the datasets of a year
+        let ttl = time::Duration::minutes(10);
+        let lock = Lock::acquire("leg/v2/lock.json", "a".to_string(), ttl, false, &disk)
+            .await
+            .unwrap();
+        Lock::acquire("leg/v2/lock.json", "b".to_string(), ttl, true, &disk)
+            .await
+            .unwrap();
+        let config = AggregateConfig {
+            lock: Some(&lock),
+            ..config
+        };
+        let key = "leg/v2/all/year=2023/data.csv";
+        disk.delete(key).await.unwrap();
+        let required = [("459cd3".into(), month(2023))];
+        assert!(aggregate(required.into_iter(), &config).await.is_err());
+        assert_eq!(disk.maybe_get(key).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn aggregate_weeks() {
+        let root = std::env::temp_dir().join("test_aggregate_weeks");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let roots = Roots::default();
+        let (january, february) = (date!(2024 - 01 - 01), date!(2024 - 02 - 01));
+        let legs = [
+            "2024-01-31T10:00:00Z",
+            "2024-02-02T10:00:00Z",
+            "2024-02-20T10:00:00Z",
+        ]
+        .map(leg);
+        for month in [january, february] {
+            let key = pk_to_blob_name(&roots, "459cd3", month, Format::Csv, Compression::None);
+            let legs = legs.iter().filter(|leg| leg.start.month() == month.month());
+            disk.put(&key, crate::csv::serialize(legs)).await.unwrap();
+        }
+
+        let config = config(&roots, &disk);
+        let read = || async {
+            crate::io::get_csv::<Week>("leg/v2/weekly/data.csv", &disk)
+                .await
+                .unwrap()
+        };
+        for month in [february, january] {
+            let required = std::iter::once(("459cd3".into(), month));
+            super::aggregate_weeks(required, &config).await.unwrap();
+        }
+        let weeks = read().await;
+        // the weeks of january replace those aggregated with february
+        assert_eq!(weeks.len(), 9);
+        assert_eq!(weeks[0].week, "2024-W01");
+        // the week across january and february is complete
+        assert_eq!(weeks[4].week, "2024-W05");
+        assert_eq!(weeks[4].legs, 2);
+        assert_eq!(weeks[7].legs, 1);
+    }
+
+    #[tokio::test]
+    async fn aggregate_airport_watch() {
+        let root = std::env::temp_dir().join("test_aggregate_airport_watch");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let roots = Roots::default();
+        let month = date!(2024 - 01 - 01);
+        let key = pk_to_blob_name(&roots, "459cd3", month, Format::Csv, Compression::None);
+        let legs = [leg("2024-01-10T10:00:00Z")];
+        disk.put(&key, crate::csv::serialize(legs.iter()))
+            .await
+            .unwrap();
+        let airports = Airports::new(vec![crate::airports::Airport {
+            ident: "EKCH".to_string(),
+            airport_type: "large_airport".to_string(),
+            name: "Copenhagen Kastrup Airport".to_string(),
+            latitude_deg: 55.6179,
+            longitude_deg: 12.656,
+            elevation_ft: Some(17.0),
+            iso_country: "DK".to_string(),
+            municipality: None,
+            scheduled_service: "yes".to_string(),
+            iata_code: Some("CPH".to_string()),
+        }]);
+        let watches = ["EKCH:5".parse::<Watch>().unwrap()];
+        // a movement of another aircraft, from a previous run
+        let key = "leg/v2/airport_watch/month=2024-01/data.csv";
+        let mut other = crate::airport_watch::movements(&watches, &airports, legs.iter()).unwrap();
+        other[0].icao_number = "45d2ed".into();
+        disk.put(key, crate::csv::serialize(other.iter()))
+            .await
+            .unwrap();
+
+        let config = config(&roots, &disk);
+        for _ in 0..2 {
+            let required = std::iter::once(("459cd3".into(), month));
+            super::aggregate_airport_watch(required, &watches, &airports, &config)
+                .await
+                .unwrap();
+        }
+        let movements = crate::io::get_csv::<Movement>(key, &disk).await.unwrap();
+        // the movements of the run replace those of previous runs
+        assert_eq!(movements.len(), 2);
+        assert_eq!(movements[0].movement.as_ref(), "departure");
+        assert!(disk
+            .maybe_get("leg/v2/airport_watch/schema.json")
+            .await
+            .unwrap()
+            .is_some());
+    }
+}
@@ -0,0 +1,76 @@
+use std::error::Error;
+
+use async_trait::async_trait;
+use serde::Serialize;
+
+/// An object that can be used to publish events to a message broker.
+#[async_trait]
+pub trait EventPublisher {
+    /// Publishes `payload` under `key` (e.g. the icao number the event refers to)
+    async fn publish(&self, key: &str, payload: Vec<u8>) -> Result<(), std::io::Error>;
+    /// Waits until all published events were delivered to the broker
+    async fn flush(&self) -> Result<(), std::io::Error>;
+}
+
+/// Publishes `event` serialized as JSON under `key`.
+pub async fn publish_json(
+    publisher: &dyn EventPublisher,
+    key: &str,
+    event: impl Serialize,
+) -> Result<(), std::io::Error> {
+    let mut bytes: Vec<u8> = Vec::new();
+    serde_json::to_writer(&mut bytes, &event)?;
+    publisher.publish(key, bytes).await
+}
+
+/// Splits a url of the form `scheme://address/name` into `(scheme, address, name)`
+fn parse_url(url: &str) -> Option<(&str, &str, &str)> {
+    let (scheme, rest) = url.split_once("://")?;
+    let (address, name) = rest.split_once('/')?;
+    (!address.is_empty() && !name.is_empty()).then_some((scheme, address, name))
+}
+
+/// Initializes an [`EventPublisher`] from a url, either
+/// * `nats://host:port/subject` (requires feature `nats`), or
+/// * `kafka://host:port/topic` (requires feature `kafka`), or
+/// * `http(s)://host/path` (a webhook)
+pub async fn client(url: &str) -> Result<Box<dyn EventPublisher>, Box<dyn Error>> {
+    let Some((scheme, _address, _name)) = parse_url(url) else {
+        return Err(format!("{url} is not of the form scheme://address/name").into());
+    };
+    match scheme {
+        "http" | "https" => Ok(Box::new(crate::events_webhook::client(url.to_string()))),
+        #[cfg(feature = "nats")]
+        "nats" => Ok(Box::new(
+            crate::events_nats::client(_address, _name.to_string()).await?,
+        )),
+        #[cfg(feature = "kafka")]
+        "kafka" => Ok(Box::new(
+            crate::events_kafka::client(_address, _name.to_string()).await?,
+        )),
+        #[allow(unreachable_patterns)]
+        "nats" | "kafka" => Err(format!("this build does not support `{scheme}` events").into()),
+        _ => Err(format!("unknown event publisher `{scheme}`").into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert_eq!(
+            parse_url("nats://localhost:4222/legs"),
+            Some(("nats", "localhost:4222", "legs"))
+        );
+        assert_eq!(parse_url("kafka://localhost:9092"), None);
+        assert_eq!(parse_url("localhost:9092/legs"), None);
+    }
+
+    #[tokio::test]
+    async fn unknown_scheme() {
+        assert!(client("amqp://localhost:5672/legs").await.is_err());
+        assert!(client("https://localhost/hooks/legs").await.is_ok());
+    }
+}
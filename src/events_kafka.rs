@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+
+use async_trait::async_trait;
+use rskafka::{
+    chrono::Utc,
+    client::{
+        partition::{Compression, PartitionClient, UnknownTopicHandling},
+        ClientBuilder,
+    },
+    record::Record,
+};
+
+use crate::events::EventPublisher;
+
+/// An [`EventPublisher`] for [Kafka](https://kafka.apache.org).
+/// Events are produced to partition 0 of the topic, keyed by the event's key.
+pub struct KafkaPublisher {
+    pub client: PartitionClient,
+}
+
+/// Initialize a [`KafkaPublisher`] connected to the bootstrap broker `address` (e.g. `localhost:9092`)
+pub async fn client(address: &str, topic: String) -> Result<KafkaPublisher, std::io::Error> {
+    let client = ClientBuilder::new(vec![address.to_string()])
+        .build()
+        .await
+        .map_err(std::io::Error::other)?
+        .partition_client(topic, 0, UnknownTopicHandling::Retry)
+        .await
+        .map_err(std::io::Error::other)?;
+    Ok(KafkaPublisher { client })
+}
+
+#[async_trait]
+impl EventPublisher for KafkaPublisher {
+    async fn publish(&self, key: &str, payload: Vec<u8>) -> Result<(), std::io::Error> {
+        let record = Record {
+            key: Some(key.as_bytes().to_vec()),
+            value: Some(payload),
+            headers: BTreeMap::new(),
+            timestamp: Utc::now(),
+        };
+        self.client
+            .produce(vec![record], Compression::NoCompression)
+            .await
+            .map(|_| ())
+            .map_err(std::io::Error::other)
+    }
+
+    async fn flush(&self) -> Result<(), std::io::Error> {
+        // records are acknowledged by the broker on `publish`
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use async_trait::async_trait;
+
+use crate::events::EventPublisher;
+
+/// An [`EventPublisher`] for [NATS](https://nats.io).
+/// Events are published to the subject `{subject}.{key}`, so that consumers
+/// can subscribe to all events (`{subject}.*`) or to a specific key.
+pub struct NatsPublisher {
+    pub client: async_nats::Client,
+    pub subject: String,
+}
+
+/// Initialize a [`NatsPublisher`] connected to `address` (e.g. `localhost:4222`)
+pub async fn client(address: &str, subject: String) -> Result<NatsPublisher, std::io::Error> {
+    let client = async_nats::connect(address)
+        .await
+        .map_err(std::io::Error::other)?;
+    Ok(NatsPublisher { client, subject })
+}
+
+#[async_trait]
+impl EventPublisher for NatsPublisher {
+    async fn publish(&self, key: &str, payload: Vec<u8>) -> Result<(), std::io::Error> {
+        self.client
+            .publish(format!("{}.{key}", self.subject), payload.into())
+            .await
+            .map_err(std::io::Error::other)
+    }
+
+    async fn flush(&self) -> Result<(), std::io::Error> {
+        self.client.flush().await.map_err(std::io::Error::other)
+    }
+}
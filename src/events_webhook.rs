@@ -0,0 +1,39 @@
+use async_trait::async_trait;
+
+use crate::events::EventPublisher;
+
+/// An [`EventPublisher`] for HTTP webhooks.
+/// Events are `POST`ed to `url` as JSON, with the key in the header `X-Event-Key`.
+pub struct WebhookPublisher {
+    pub client: reqwest::Client,
+    pub url: String,
+}
+
+/// Initialize a [`WebhookPublisher`] posting to `url` (e.g. `https://example.com/hooks/legs`)
+pub fn client(url: String) -> WebhookPublisher {
+    WebhookPublisher {
+        client: reqwest::Client::new(),
+        url,
+    }
+}
+
+#[async_trait]
+impl EventPublisher for WebhookPublisher {
+    async fn publish(&self, key: &str, payload: Vec<u8>) -> Result<(), std::io::Error> {
+        self.client
+            .post(&self.url)
+            .header("Content-Type", "application/json")
+            .header("X-Event-Key", key)
+            .body(payload)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(std::io::Error::other)
+            .map(|_| ())
+    }
+
+    async fn flush(&self) -> Result<(), std::io::Error> {
+        // every event is delivered when published
+        Ok(())
+    }
+}
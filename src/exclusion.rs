@@ -0,0 +1,131 @@
+//! Contains the implementation of exclusion zones (`M-exclusions`): areas (e.g. bases of air ambulances) and
+//! aircrafts (e.g. of organ transport operators) of emergency aviation, whose legs are tagged or excluded from
+//! the datasets of legs.
+use std::{collections::HashMap, sync::Arc};
+
+use serde_json::Value;
+
+use crate::region::Region;
+
+/// A set of exclusion zones and excluded aircrafts, each with the reason of its exclusion
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Exclusions {
+    /// the reason, the polygons and their bounding box of each zone
+    zones: Vec<(Arc<str>, Region, Region)>,
+    /// the reason of each excluded ICAO number
+    aircrafts: HashMap<Arc<str>, Arc<str>>,
+}
+
+impl Exclusions {
+    /// Returns [`Exclusions`] from a GeoJSON `FeatureCollection` whose features have the property `reason`.
+    /// A feature with a (multi)polygon is an exclusion zone; a feature with the property `icao_numbers`
+    /// (a list of ICAO numbers) excludes those aircrafts anywhere.
+    pub fn from_geojson(data: &[u8]) -> Result<Self, String> {
+        let value = serde_json::from_slice::<Value>(data).map_err(|e| e.to_string())?;
+        let features = value
+            .get("features")
+            .and_then(|x| x.as_array())
+            .ok_or_else(|| "GeoJSON must be a FeatureCollection".to_string())?;
+
+        let mut exclusions = Self::default();
+        for feature in features {
+            let properties = feature.get("properties");
+            let reason: Arc<str> = properties
+                .and_then(|x| x.get("reason"))
+                .and_then(|x| x.as_str())
+                .ok_or_else(|| format!("feature without `reason`: {feature}"))?
+                .into();
+
+            if let Some(geometry) = feature.get("geometry").filter(|x| !x.is_null()) {
+                let polygons = crate::region::parse_geojson(geometry)?;
+                let bbox = crate::geo::bounding_box(&polygons);
+                exclusions
+                    .zones
+                    .push((reason.clone(), Region::Polygons(polygons), bbox));
+            }
+            if let Some(icao_numbers) = properties.and_then(|x| x.get("icao_numbers")) {
+                let icao_numbers = icao_numbers
+                    .as_array()
+                    .ok_or_else(|| format!("`icao_numbers` must be a list: {feature}"))?;
+                for icao_number in icao_numbers {
+                    let icao_number = icao_number
+                        .as_str()
+                        .ok_or_else(|| format!("ICAO numbers must be strings: {feature}"))?;
+                    exclusions
+                        .aircrafts
+                        .insert(icao_number.to_ascii_lowercase().into(), reason.clone());
+                }
+            }
+        }
+        Ok(exclusions)
+    }
+
+    /// Returns the reason of the exclusion of a leg of `icao_number` from `from` to `to` (`(latitude, longitude)`),
+    /// or `None` when it is not excluded.
+    /// # Implementation
+    /// A leg is excluded when its aircraft is excluded or when it starts or ends in an exclusion zone;
+    /// the aircraft takes precedence, followed by the zones in the order of the file.
+    pub fn reason(&self, icao_number: &str, from: (f64, f64), to: (f64, f64)) -> Option<&Arc<str>> {
+        self.aircrafts.get(icao_number).or_else(|| {
+            self.zones
+                .iter()
+                .find(|(_, zone, bbox)| {
+                    [from, to]
+                        .into_iter()
+                        .any(|point| bbox.contains(point) && zone.contains(point))
+                })
+                .map(|(reason, _, _)| reason)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn work() {
+        let data = br#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {"reason": "air ambulance base"},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[12.0, 55.0], [13.0, 55.0], [13.0, 56.0], [12.0, 56.0], [12.0, 55.0]]]
+                }
+            }, {
+                "type": "Feature",
+                "properties": {"reason": "organ transport", "icao_numbers": ["45D2ED"]},
+                "geometry": null
+            }]
+        }"#;
+        let exclusions = Exclusions::from_geojson(data).unwrap();
+
+        let copenhagen = (55.6, 12.6);
+        let paris = (49.0, 2.5);
+        let london = (51.5, -0.1);
+        let reason = |icao_number, from, to| {
+            exclusions
+                .reason(icao_number, from, to)
+                .map(|x| x.to_string())
+        };
+        assert_eq!(
+            reason("459cd3", paris, copenhagen).as_deref(),
+            Some("air ambulance base")
+        );
+        assert_eq!(reason("459cd3", paris, london), None);
+        // the aircraft takes precedence
+        assert_eq!(
+            reason("45d2ed", paris, copenhagen).as_deref(),
+            Some("organ transport")
+        );
+
+        assert!(Exclusions::from_geojson(
+            br#"{"type": "FeatureCollection", "features": [{
+            "type": "Feature", "properties": {}, "geometry": null
+        }]}"#
+        )
+        .is_err());
+    }
+}
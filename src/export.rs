@@ -0,0 +1,228 @@
+//! Contains the export of datasets to another storage (`M-export`), e.g. to produce a mirror or an archive of the full
+//! history of legs (such as a deposit in Zenodo). Exports are resumable: blobs copied by a previous (stopped) export
+//! are not copied again.
+use std::{collections::BTreeMap, sync::Mutex};
+
+use futures::{StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::fs::BlobStorageProvider;
+
+/// The blob of the destination with the [`ExportState`] of the export
+pub static STATE: &str = "export/state.json";
+/// The blob of the destination with the manifest of the exported blobs, written once the export completes
+pub static MANIFEST: &str = "export/manifest.csv";
+
+/// Number of copied blobs after which the [`ExportState`] is written
+static WRITE_EVERY: usize = 1000;
+
+/// A blob copied by an export
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exported {
+    /// The name of the blob, the same on the source and the destination
+    pub blob_name: String,
+    /// The size of the blob in bytes
+    pub bytes: usize,
+    /// The SHA-256 of the blob, in hexadecimal
+    pub sha256: String,
+    /// The entity tag of the blob on the source when it was copied (see [`BlobStorageProvider::etag`])
+    pub etag: Option<String>,
+}
+
+crate::schema!(Exported {
+    blob_name: "The name of the blob",
+    bytes("bytes"): "The size of the blob",
+    sha256: "The SHA-256 of the blob, in hexadecimal",
+    etag: "The entity tag of the blob on the source when it was copied",
+});
+
+/// The state of an export, written to [`STATE`] on the destination
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExportState {
+    /// The copied blobs, by name
+    pub exported: BTreeMap<String, Exported>,
+    /// Whether all blobs were copied
+    pub completed: bool,
+}
+
+/// The outcome of [`export`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Report {
+    /// The number of blobs copied
+    pub copied: usize,
+    /// The number of blobs skipped because a previous export copied them and they did not change since
+    pub skipped: usize,
+    /// The number of bytes copied
+    pub bytes: usize,
+}
+
+fn sha256(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+async fn write_state(
+    state: &ExportState,
+    destination: &dyn BlobStorageProvider,
+) -> Result<(), std::io::Error> {
+    destination.put(STATE, serde_json::to_vec(state)?).await
+}
+
+/// Copies the blobs under `prefixes` (e.g. `leg/v2/`) of `source` to the same blob names on `destination`,
+/// `concurrency` blobs at a time, and writes the manifest of all exported blobs to [`MANIFEST`].
+/// # Implementation
+/// The state of the export is written to [`STATE`] on the destination every 1000 copied blobs and at the end, so that
+/// an export that is stopped resumes from it: blobs it copied are skipped unless their entity tag on the source changed
+/// (blobs of sources without entity tags are skipped by name).
+/// # Error
+/// Errors if a blob cannot be read or written; the state up to the failure is written before returning the error
+pub async fn export(
+    prefixes: &[String],
+    source: &dyn BlobStorageProvider,
+    destination: &dyn BlobStorageProvider,
+    concurrency: usize,
+) -> Result<Report, std::io::Error> {
+    let state = match destination.maybe_get(STATE).await? {
+        Some(data) => serde_json::from_slice::<ExportState>(&data)?,
+        None => ExportState::default(),
+    };
+    log::info!("{STATE}: {} blobs exported before", state.exported.len());
+    let state = Mutex::new(ExportState {
+        completed: false,
+        ..state
+    });
+
+    let mut keys = vec![];
+    for prefix in prefixes {
+        let mut prefix_keys = source.list(prefix).await?;
+        log::info!("{prefix}: {} blobs", prefix_keys.len());
+        keys.append(&mut prefix_keys);
+    }
+    keys.sort_unstable();
+    keys.dedup();
+    // the state of previous exports is not part of the exported data
+    keys.retain(|key| key != STATE && key != MANIFEST);
+
+    let state = &state;
+    let tasks = keys.into_iter().map(|key| async move {
+        let etag = source.etag(&key).await?;
+        let previous = state.lock().unwrap().exported.get(&key).cloned();
+        if previous.is_some_and(|previous| previous.etag == etag) {
+            return Ok(None);
+        }
+        let Some(data) = source.maybe_get(&key).await? else {
+            // deleted since it was listed
+            return Ok(None);
+        };
+        let exported = Exported {
+            blob_name: key.clone(),
+            bytes: data.len(),
+            sha256: sha256(&data),
+            etag,
+        };
+        destination.put(&key, data).await?;
+        Ok::<_, std::io::Error>(Some(exported))
+    });
+
+    let mut report = Report::default();
+    let copied = futures::stream::iter(tasks)
+        .buffer_unordered(concurrency)
+        .try_for_each(|exported| {
+            let write = match exported {
+                Some(exported) => {
+                    report.copied += 1;
+                    report.bytes += exported.bytes;
+                    let mut state = state.lock().unwrap();
+                    state.exported.insert(exported.blob_name.clone(), exported);
+                    (report.copied % WRITE_EVERY == 0).then(|| state.clone())
+                }
+                None => {
+                    report.skipped += 1;
+                    None
+                }
+            };
+            async move {
+                if let Some(state) = write {
+                    write_state(&state, destination).await?;
+                    log::info!("exported {} blobs", state.exported.len());
+                }
+                Ok(())
+            }
+        })
+        .await;
+    if let Err(e) = copied {
+        let state = state.lock().unwrap().clone();
+        write_state(&state, destination).await?;
+        return Err(e);
+    }
+
+    let mut state = state.lock().unwrap().clone();
+    state.completed = true;
+    let manifest = crate::csv::serialize(state.exported.values());
+    destination.put(MANIFEST, manifest).await?;
+    write_state(&state, destination).await?;
+    log::info!(
+        "exported {} blobs ({} bytes); {} unchanged",
+        report.copied,
+        report.bytes,
+        report.skipped
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_local::LocalDisk;
+
+    #[tokio::test]
+    async fn work() {
+        let root = std::env::temp_dir().join("test_export");
+        let _ = std::fs::remove_dir_all(&root);
+        let source = LocalDisk::new(root.join("source"));
+        let destination = LocalDisk::new(root.join("destination"));
+        source.put("leg/v2/all/a.csv", b"a".to_vec()).await.unwrap();
+        source.put("leg/v2/all/b.csv", b"b".to_vec()).await.unwrap();
+        source.put("aircraft/c.csv", b"c".to_vec()).await.unwrap();
+        let prefixes = ["leg/v2/".to_string()];
+
+        let report = export(&prefixes, &source, &destination, 2).await.unwrap();
+        assert_eq!((report.copied, report.skipped, report.bytes), (2, 0, 2));
+        assert_eq!(
+            destination.maybe_get("leg/v2/all/a.csv").await.unwrap(),
+            Some(b"a".to_vec())
+        );
+        assert_eq!(destination.maybe_get("aircraft/c.csv").await.unwrap(), None);
+        let manifest = destination.maybe_get(MANIFEST).await.unwrap().unwrap();
+        let manifest = crate::csv::deserialize::<Exported>(&manifest)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(
+            manifest[0].sha256,
+            "ca978112ca1bbdcafac231b39a23dc4da786eff8147c4e72b9807785afee48bb"
+        );
+
+        // a resumed export only copies the blobs that changed
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        source
+            .put("leg/v2/all/b.csv", b"bb".to_vec())
+            .await
+            .unwrap();
+        let report = export(&prefixes, &source, &destination, 2).await.unwrap();
+        assert_eq!((report.copied, report.skipped), (1, 1));
+        assert_eq!(
+            destination.maybe_get("leg/v2/all/b.csv").await.unwrap(),
+            Some(b"bb".to_vec())
+        );
+        let state = destination.maybe_get(STATE).await.unwrap().unwrap();
+        assert!(
+            serde_json::from_slice::<ExportState>(&state)
+                .unwrap()
+                .completed
+        );
+    }
+}
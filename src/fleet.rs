@@ -0,0 +1,223 @@
+//! Contains the implementation of the fleet dataset: when each aircraft was first and last observed and its age.
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{aircraft::Aircrafts, fs::BlobStorageProvider};
+
+static ROOT: &str = "fleet/v1/";
+static DATABASE: &str = "fleet/v1/data.csv";
+
+/// An aircraft of the fleet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FleetAircraft {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The tail number, from the most recent snapshot of aircrafts containing the ICAO number
+    pub tail_number: Option<String>,
+    /// The model, from the most recent snapshot of aircrafts containing the ICAO number
+    pub model: Option<String>,
+    /// The country of registration (see `M-country-of-registration`)
+    pub registration_country: Option<Arc<str>>,
+    /// The year the aircraft was manufactured
+    pub manufacture_year: Option<u16>,
+    /// The first month with positions of the aircraft (e.g. `2019-01`)
+    pub first_month: String,
+    /// The last month with positions of the aircraft (e.g. `2023-12`)
+    pub last_month: String,
+    /// The age in years of the aircraft on its last month
+    pub age: Option<i32>,
+    /// The URL of the record of the aircraft in its registry, when the registry has stable URLs per aircraft
+    pub registry_url: Option<String>,
+    /// The URL of photos of the aircraft
+    pub photos_url: Option<String>,
+    /// The URL of the history of ADS-B positions of the aircraft
+    pub tracking_url: Option<String>,
+}
+
+crate::schema!(FleetAircraft {
+    icao_number: "The ICAO number",
+    tail_number: "The tail number, from the most recent snapshot of aircrafts containing the ICAO number",
+    model: "The model, from the most recent snapshot of aircrafts containing the ICAO number",
+    registration_country: "The country of registration (see `M-country-of-registration`)",
+    manufacture_year: "The year the aircraft was manufactured",
+    first_month: "The first month with positions of the aircraft (e.g. 2019-01)",
+    last_month: "The last month with positions of the aircraft (e.g. 2023-12)",
+    age("years"): "The age of the aircraft on its last month",
+    registry_url: "The URL of the record of the aircraft in its registry",
+    photos_url: "The URL of photos of the aircraft",
+    tracking_url: "The URL of the history of ADS-B positions of the aircraft",
+});
+
+/// Returns the URL of the record of `tail_number` in its registry, when known.
+/// Only the FAA (`N` tail numbers) has stable URLs per aircraft.
+fn registry_url(tail_number: &str) -> Option<String> {
+    tail_number
+        .starts_with('N')
+        .then(|| format!("https://registry.faa.gov/AircraftInquiry/Search/NNumberResult?nNumberTxt={tail_number}"))
+}
+
+/// Sets the links of external references of `aircraft` (see `M-fleet-links`)
+fn add_links(aircraft: &mut FleetAircraft) {
+    if let Some(tail_number) = aircraft.tail_number.as_deref() {
+        aircraft.registry_url = registry_url(tail_number);
+        aircraft.photos_url = Some(format!(
+            "https://www.jetphotos.com/registration/{tail_number}"
+        ));
+    }
+    aircraft.tracking_url = Some(format!(
+        "https://globe.adsbexchange.com/?icao={}",
+        aircraft.icao_number
+    ));
+}
+
+/// Returns the [`FleetAircraft`]s from the set of `(icao_number, month)` with positions and the snapshots of aircrafts,
+/// ordered by ICAO number. Links to external references are only added when `links`.
+pub fn fleet(
+    months: impl Iterator<Item = (Arc<str>, Date)>,
+    aircrafts: &HashMap<Date, Aircrafts>,
+    links: bool,
+) -> Vec<FleetAircraft> {
+    let first_last = crate::airframes::activity(months);
+
+    let mut snapshots = aircrafts.iter().collect::<Vec<_>>();
+    // most recent first
+    snapshots.sort_unstable_by(|a, b| b.0.cmp(a.0));
+
+    let mut fleet = first_last
+        .into_iter()
+        .map(|(icao_number, (first, last))| {
+            let aircraft = snapshots
+                .iter()
+                .find_map(|(_, aircrafts)| aircrafts.get(&icao_number));
+            let manufacture_year = aircraft.and_then(|a| a.manufacture_year);
+            let mut aircraft = FleetAircraft {
+                registration_country: crate::country::registration_country(
+                    &icao_number,
+                    aircraft.map(|a| a.tail_number.as_str()),
+                ),
+                tail_number: aircraft.map(|a| a.tail_number.clone()),
+                model: aircraft.map(|a| a.model.clone()),
+                manufacture_year,
+                first_month: crate::serde::month_to_part(first),
+                last_month: crate::serde::month_to_part(last),
+                age: manufacture_year.map(|year| last.year() - year as i32),
+                icao_number,
+                registry_url: None,
+                photos_url: None,
+                tracking_url: None,
+            };
+            if links {
+                add_links(&mut aircraft);
+            }
+            aircraft
+        })
+        .collect::<Vec<_>>();
+    fleet.sort_unstable_by(|a, b| a.icao_number.cmp(&b.icao_number));
+    fleet
+}
+
+/// Computes the [`FleetAircraft`]s from the database of positions and aircrafts and writes them to `client`.
+/// Links to external references are only added when `links`.
+pub async fn etl_fleet(
+    client: &dyn BlobStorageProvider,
+    links: bool,
+) -> Result<(), Box<dyn Error>> {
+    let months = crate::icao_to_trace::list_months_positions(client).await?;
+    log::info!("months with positions: {}", months.len());
+    let aircrafts = crate::aircraft::read_all(client).await?;
+
+    let fleet = fleet(months.into_iter(), &aircrafts, links);
+    client
+        .put(DATABASE, crate::csv::serialize(fleet.into_iter()))
+        .await?;
+    log::info!("Written {DATABASE}");
+    crate::schema::write::<FleetAircraft>(ROOT, crate::units::Units::Metric, client).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+    use crate::aircraft::Aircraft;
+
+    #[test]
+    fn work() {
+        let aircraft = |tail_number: &str| Aircraft {
+            icao_number: "aa".into(),
+            tail_number: tail_number.to_string(),
+            type_designator: "F2TH".to_string(),
+            model: "FALCON 2000".to_string(),
+            country: None,
+            manufacture_year: Some(2007),
+        };
+        let aircrafts = HashMap::from([
+            (
+                date!(2023 - 01 - 01),
+                HashMap::from([("aa".into(), aircraft("OY-OLD"))]),
+            ),
+            (
+                date!(2024 - 01 - 01),
+                HashMap::from([("aa".into(), aircraft("OY-NEW"))]),
+            ),
+        ]);
+        let months = [
+            ("aa".into(), date!(2021 - 03 - 01)),
+            ("aa".into(), date!(2019 - 02 - 01)),
+            ("bb".into(), date!(2020 - 01 - 01)),
+        ];
+
+        let fleet = fleet(months.clone().into_iter(), &aircrafts, false);
+
+        assert_eq!(
+            fleet,
+            vec![
+                FleetAircraft {
+                    icao_number: "aa".into(),
+                    tail_number: Some("OY-NEW".to_string()),
+                    model: Some("FALCON 2000".to_string()),
+                    registration_country: None,
+                    manufacture_year: Some(2007),
+                    first_month: "2019-02".to_string(),
+                    last_month: "2021-03".to_string(),
+                    age: Some(14),
+                    registry_url: None,
+                    photos_url: None,
+                    tracking_url: None,
+                },
+                FleetAircraft {
+                    icao_number: "bb".into(),
+                    tail_number: None,
+                    model: None,
+                    registration_country: None,
+                    manufacture_year: None,
+                    first_month: "2020-01".to_string(),
+                    last_month: "2020-01".to_string(),
+                    age: None,
+                    registry_url: None,
+                    photos_url: None,
+                    tracking_url: None,
+                },
+            ]
+        );
+
+        let fleet = super::fleet(months.into_iter(), &aircrafts, true);
+        assert_eq!(
+            fleet[0].photos_url.as_deref(),
+            Some("https://www.jetphotos.com/registration/OY-NEW")
+        );
+        assert_eq!(fleet[0].registry_url, None);
+        assert_eq!(fleet[1].photos_url, None);
+        assert_eq!(
+            fleet[1].tracking_url.as_deref(),
+            Some("https://globe.adsbexchange.com/?icao=bb")
+        );
+        assert_eq!(
+            registry_url("N123AB").as_deref(),
+            Some("https://registry.faa.gov/AircraftInquiry/Search/NNumberResult?nNumberTxt=N123AB")
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! Contains the file formats in which datasets can be written.
+
+/// A file format of a dataset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    /// Comma-separated values (see [`crate::csv`])
+    #[default]
+    Csv,
+    /// Apache Parquet (see `flights::parquet`; requires feature `parquet`)
+    Parquet,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "parquet" => Ok(Self::Parquet),
+            other => Err(format!("format `{other}` must be `csv` or `parquet`")),
+        }
+    }
+}
+
+impl Format {
+    /// The extension of files of this format (e.g. `csv`)
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+}
@@ -1,77 +1,223 @@
-use std::path::{Path, PathBuf};
-
 use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
 
 static ROOT: &'static str = "database/";
 
 /// An object that can be used to get and put blobs.
 #[async_trait]
-pub trait BlobStorageProvider {
+pub trait BlobStorageProvider: Sync {
     async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error>;
     async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error>;
     async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error>;
     async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error>;
 
+    /// Writes the concatenation of `chunks` to `blob_name`, so that large blobs can be written without
+    /// holding them in memory. By default, the chunks are collected and written with [`Self::put`].
+    /// When a chunk is an error, the blob is not written and the error is returned.
+    async fn put_stream(
+        &self,
+        blob_name: &str,
+        chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    ) -> Result<(), std::io::Error> {
+        let contents = chunks.try_concat().await?;
+        self.put(blob_name, contents).await
+    }
+
+    /// Returns the entity tag of `blob_name`, which changes whenever its contents change, without reading it.
+    /// It is `None` when the blob does not exist or when the storage has no entity tags (the default).
+    async fn etag(&self, _blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        Ok(None)
+    }
+
     fn can_put(&self) -> bool;
 }
 
-fn visit_dirs<P: AsRef<Path>>(
-    dir: P,
-    cb: &mut dyn FnMut(&std::fs::DirEntry),
-) -> std::io::Result<()> {
-    if dir.as_ref().is_dir() {
-        for entry in std::fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_dir() {
-                visit_dirs(&path, cb)?;
-            } else {
-                cb(&entry);
+#[async_trait]
+impl<T: BlobStorageProvider + Sync + ?Sized> BlobStorageProvider for &T {
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        (**self).maybe_get(blob_name).await
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        (**self).put(blob_name, contents).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        (**self).list(prefix).await
+    }
+
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        (**self).delete(blob_name).await
+    }
+
+    async fn put_stream(
+        &self,
+        blob_name: &str,
+        chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    ) -> Result<(), std::io::Error> {
+        (**self).put_stream(blob_name, chunks).await
+    }
+
+    async fn etag(&self, blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        (**self).etag(blob_name).await
+    }
+
+    fn can_put(&self) -> bool {
+        (**self).can_put()
+    }
+}
+
+/// The storage of the datasets of a binary
+#[cfg(feature = "build-binary")]
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// The remote storage (requires `--access-key` and `--secret-access-key`, except to read it)
+    Remote,
+    /// A directory of the local disk (see `--root`)
+    Local,
+    /// A container of Azure Blob Storage (requires `--azure-account`, `--azure-container` and
+    /// `--azure-sas-token` or `--azure-account-key`)
+    Azure,
+}
+
+/// The arguments of the binaries that select the storage of the datasets (see [`client_from_args`])
+#[cfg(feature = "build-binary")]
+#[derive(clap::Args, Debug, Clone)]
+pub struct StorageArgs {
+    /// Where the datasets are read from and written to
+    #[arg(long, value_enum, default_value_t = Backend::Remote)]
+    pub backend: Backend,
+    /// The directory of the `local` backend
+    #[arg(long, default_value = "database/")]
+    pub root: std::path::PathBuf,
+    /// The token to the remote storage (required by the `remote` backend)
+    #[arg(long)]
+    pub access_key: Option<String>,
+    /// The token to the remote storage (required by the `remote` backend)
+    #[arg(long)]
+    pub secret_access_key: Option<String>,
+    /// The storage account of the `azure` backend
+    #[arg(long)]
+    pub azure_account: Option<String>,
+    /// The container of the `azure` backend
+    #[arg(long)]
+    pub azure_container: Option<String>,
+    /// The SAS token of the container of the `azure` backend
+    #[arg(long)]
+    pub azure_sas_token: Option<String>,
+    /// The key of the storage account of the `azure` backend (used when there is no SAS token)
+    #[arg(long)]
+    pub azure_account_key: Option<String>,
+}
+
+/// Returns the client of the storage selected by `args`, and the metric of its retried requests when it
+/// retries them according to `retry`
+/// # Error
+/// [`crate::Error::Invalid`] when the credentials of the backend are missing, unless `anonymous` and the backend
+/// is `remote`, which is then read anonymously (and cannot be written to)
+#[cfg(feature = "build-binary")]
+pub async fn client_from_args_with_retry(
+    args: &StorageArgs,
+    retry: crate::fs_s3::RetryPolicy,
+    anonymous: bool,
+) -> Result<
+    (
+        std::sync::Arc<dyn BlobStorageProvider + Send + Sync>,
+        Option<crate::fs_s3::RetryMetric>,
+    ),
+    crate::Error,
+> {
+    use std::sync::Arc;
+
+    Ok(match args.backend {
+        Backend::Remote => match (args.access_key.clone(), args.secret_access_key.clone()) {
+            (Some(access_key), Some(secret_access_key)) => {
+                let client =
+                    crate::fs_s3::client_with_retry(access_key, secret_access_key, retry).await;
+                let retries = client.retries();
+                (Arc::new(client), Some(retries))
+            }
+            (None, None) if anonymous => (Arc::new(crate::fs_s3::anonymous_client().await), None),
+            _ => {
+                return Err(crate::Error::Invalid(
+                    "the remote backend requires access_key and secret_access_key".to_string(),
+                ))
             }
+        },
+        Backend::Local => (Arc::new(crate::fs_local::LocalDisk::new(&args.root)), None),
+        Backend::Azure => {
+            let credential = crate::fs_azure::Credential::new(
+                args.azure_sas_token.clone(),
+                args.azure_account_key.clone(),
+            );
+            let (Some(account), Some(container), Some(credential)) = (
+                args.azure_account.clone(),
+                args.azure_container.clone(),
+                credential,
+            ) else {
+                return Err(crate::Error::Invalid("the azure backend requires azure_account, azure_container and azure_sas_token or azure_account_key".to_string()));
+            };
+            let client = crate::fs_azure::client_with_retry(account, container, credential, retry);
+            let retries = client.retries();
+            (Arc::new(client), Some(retries))
         }
-    }
-    Ok(())
+    })
+}
+
+/// Returns the client of the storage selected by `args`
+/// # Error
+/// [`crate::Error::Invalid`] when the credentials of the backend are missing
+#[cfg(feature = "build-binary")]
+pub async fn client_from_args(
+    args: &StorageArgs,
+) -> Result<std::sync::Arc<dyn BlobStorageProvider + Send + Sync>, crate::Error> {
+    client_from_args_with_retry(args, Default::default(), false)
+        .await
+        .map(|(client, _)| client)
 }
 
-/// A [`BlobStorageProvider`] for local disk
+/// A [`BlobStorageProvider`] for the local directory `database/`
+/// (see [`crate::fs_local::LocalDisk`] for other directories)
 pub struct LocalDisk;
 
+impl LocalDisk {
+    fn disk(&self) -> crate::fs_local::LocalDisk {
+        crate::fs_local::LocalDisk::new(ROOT)
+    }
+}
+
 #[async_trait]
 impl BlobStorageProvider for LocalDisk {
     #[must_use]
     async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
-        let path = PathBuf::from(ROOT).join(Path::new(blob_name));
-        if path.try_exists()? {
-            Ok(Some(std::fs::read(path)?))
-        } else {
-            Ok(None)
-        }
+        self.disk().maybe_get(blob_name).await
     }
 
     #[must_use]
     async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
-        let path = PathBuf::from(ROOT).join(Path::new(blob_name));
-        let mut dir = path.clone();
-        dir.pop();
-        std::fs::create_dir_all(dir)?;
-        std::fs::write(path, &contents)?;
-        Ok(())
+        self.disk().put(blob_name, contents).await
     }
 
     #[must_use]
     async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
-        let path = PathBuf::from(ROOT).join(Path::new(prefix));
-        let mut paths = vec![];
-        visit_dirs(path, &mut |dir| {
-            let path = dir.path().to_str().unwrap().to_string();
-            paths.push(path[ROOT.len()..].to_string())
-        })?;
-        Ok(paths)
+        self.disk().list(prefix).await
     }
 
     #[must_use]
-    async fn delete(&self, _prefix: &str) -> Result<(), std::io::Error> {
-        todo!()
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        self.disk().delete(blob_name).await
+    }
+
+    async fn put_stream(
+        &self,
+        blob_name: &str,
+        chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    ) -> Result<(), std::io::Error> {
+        self.disk().put_stream(blob_name, chunks).await
+    }
+
+    async fn etag(&self, blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        self.disk().etag(blob_name).await
     }
 
     fn can_put(&self) -> bool {
@@ -168,3 +314,107 @@ pub(crate) async fn cached_call<F: futures::Future<Output = Result<Vec<u8>, std:
     };
     Ok(data)
 }
+
+/// Returns `chunks` regrouped into parts of at least `size` bytes (except the last), as used by
+/// multipart uploads of [`BlobStorageProvider::put_stream`]. An empty stream has no parts.
+pub(crate) fn parts(
+    chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    size: usize,
+) -> BoxStream<'_, Result<Vec<u8>, std::io::Error>> {
+    futures::stream::try_unfold(Some(chunks), move |chunks| async move {
+        let Some(mut chunks) = chunks else {
+            return Ok(None);
+        };
+        let mut part = Vec::with_capacity(size);
+        while part.len() < size {
+            let Some(chunk) = chunks.try_next().await? else {
+                return Ok((!part.is_empty()).then_some((part, None)));
+            };
+            part.extend(chunk);
+        }
+        Ok(Some((part, Some(chunks))))
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[cfg(feature = "build-binary")]
+    #[tokio::test]
+    async fn client_from_args() {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct Cli {
+            #[command(flatten)]
+            storage: StorageArgs,
+        }
+        let args = |args: &[&str]| Cli::parse_from(["bin"].iter().chain(args)).storage;
+
+        let root = std::env::temp_dir().join("test_client_from_args");
+        let local = args(&["--backend", "local", "--root", root.to_str().unwrap()]);
+        let client = super::client_from_args(&local).await.unwrap();
+        client.put("a.csv", b"a".to_vec()).await.unwrap();
+        assert!(root.join("a.csv").exists());
+
+        let remote = args(&["--access-key", "a"]);
+        let error = super::client_from_args(&remote).await.err().unwrap();
+        assert!(matches!(error, crate::Error::Invalid(_)));
+        let azure = args(&["--backend", "azure", "--azure-account", "a"]);
+        let error = super::client_from_args(&azure).await.err().unwrap();
+        assert!(matches!(error, crate::Error::Invalid(_)));
+    }
+
+    #[tokio::test]
+    async fn parts() {
+        let chunks = |chunks: Vec<&'static [u8]>| {
+            futures::stream::iter(chunks.into_iter().map(|x| Ok(x.to_vec()))).boxed()
+        };
+        let parts = |chunks| super::parts(chunks, 3).try_collect::<Vec<_>>();
+
+        assert_eq!(
+            parts(chunks(vec![b"ab", b"c", b"de", b"fgh", b"i"]))
+                .await
+                .unwrap(),
+            vec![b"abc".to_vec(), b"defgh".to_vec(), b"i".to_vec()]
+        );
+        assert_eq!(
+            parts(chunks(vec![b"abc"])).await.unwrap(),
+            vec![b"abc".to_vec()]
+        );
+        assert!(parts(chunks(vec![])).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn put_stream() {
+        let chunks = futures::stream::iter(vec![Ok(b"a".to_vec()), Ok(b"bc".to_vec())]).boxed();
+        LocalDisk
+            .put_stream("test_fs/put_stream.csv", chunks)
+            .await
+            .unwrap();
+        assert_eq!(
+            LocalDisk.maybe_get("test_fs/put_stream.csv").await.unwrap(),
+            Some(b"abc".to_vec())
+        );
+
+        // a failed chunk does not leave a partial blob
+        let chunks = futures::stream::iter(vec![
+            Ok(b"a".to_vec()),
+            Err(std::io::Error::other("decode")),
+        ])
+        .boxed();
+        assert!(LocalDisk
+            .put_stream("test_fs/put_stream_error.csv", chunks)
+            .await
+            .is_err());
+        assert_eq!(
+            LocalDisk
+                .maybe_get("test_fs/put_stream_error.csv")
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -0,0 +1,509 @@
+//! Contains the implementation of [`BlobStorageProvider`] on a container of Azure Blob Storage,
+//! used to mirror the datasets of the remote storage.
+use std::{collections::BTreeMap, io::Error};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use futures::{stream::BoxStream, TryStreamExt};
+use hmac::{Hmac, Mac};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    Method, Response, StatusCode, Url,
+};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use sha2::Sha256;
+
+use crate::{
+    fs::BlobStorageProvider,
+    fs_s3::{RetryMetric, RetryPolicy},
+};
+
+/// The version of the REST API of Azure Blob Storage
+static VERSION: &str = "2021-08-06";
+
+/// The size of the blocks of blobs written in blocks
+static BLOCK_SIZE: usize = 8 * 1024 * 1024;
+
+/// How requests to a container are authorized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credential {
+    /// A shared access signature (SAS) token, i.e. the query string of a SAS URL
+    SasToken(String),
+    /// The (base64-encoded) key of the storage account, used to sign every request
+    AccountKey(String),
+}
+
+impl Credential {
+    /// Returns a [`Credential`] of `sas_token` or, when `None`, of `account_key`
+    pub fn new(sas_token: Option<String>, account_key: Option<String>) -> Option<Self> {
+        sas_token
+            .map(Self::SasToken)
+            .or(account_key.map(Self::AccountKey))
+    }
+}
+
+pub struct ContainerClient {
+    client: ClientWithMiddleware,
+    pub account: String,
+    pub container: String,
+    credential: Option<Credential>,
+    retries: RetryMetric,
+}
+
+#[async_trait::async_trait]
+impl Middleware for RetryMetric {
+    async fn handle(
+        &self,
+        request: reqwest::Request,
+        extensions: &mut http::Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        self.record_attempt();
+        next.run(request, extensions).await
+    }
+}
+
+impl ContainerClient {
+    /// Returns the [`RetryMetric`] of this client, which remains valid after the client is moved
+    pub fn retries(&self) -> RetryMetric {
+        self.retries.clone()
+    }
+
+    /// Returns the url of `blob_name`, or of the container when `None`
+    fn url(&self, blob_name: Option<&str>) -> Url {
+        let mut url = Url::parse(&format!("https://{}.blob.core.windows.net/", self.account))
+            .expect("the account to be a valid host name");
+        {
+            let mut segments = url.path_segments_mut().expect("https urls to have a path");
+            segments.pop_if_empty().push(&self.container);
+            if let Some(blob_name) = blob_name {
+                segments.extend(blob_name.split('/'));
+            }
+        }
+        if let Some(Credential::SasToken(token)) = &self.credential {
+            url.set_query(Some(token.trim_start_matches('?')));
+        }
+        url
+    }
+
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        mut headers: HeaderMap,
+        body: Option<Vec<u8>>,
+    ) -> Result<Response, Error> {
+        let date = time::OffsetDateTime::now_utc()
+            .format(time::macros::format_description!(
+                "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+            ))
+            .map_err(Error::other)?;
+        headers.insert(
+            "x-ms-date",
+            HeaderValue::from_str(&date).map_err(Error::other)?,
+        );
+        headers.insert("x-ms-version", HeaderValue::from_static(VERSION));
+        if let Some(Credential::AccountKey(key)) = &self.credential {
+            let content_length = body.as_ref().map_or(0, |x| x.len());
+            let authorization =
+                shared_key(&self.account, key, &method, &url, &headers, content_length)?;
+            headers.insert(
+                AUTHORIZATION,
+                HeaderValue::from_str(&authorization).map_err(Error::other)?,
+            );
+        }
+
+        self.retries.record_request();
+        let request = self.client.request(method, url).headers(headers);
+        let request = match body {
+            Some(body) => request.body(body),
+            None => request,
+        };
+        request.send().await.map_err(Error::other)
+    }
+}
+
+/// Returns the `Authorization` header of a request signed with the `key` of `account`, as described in
+/// https://learn.microsoft.com/en-us/rest/api/storageservices/authorize-with-shared-key
+fn shared_key(
+    account: &str,
+    key: &str,
+    method: &Method,
+    url: &Url,
+    headers: &HeaderMap,
+    content_length: usize,
+) -> Result<String, Error> {
+    let content_type = headers
+        .get(CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or("");
+    // an empty body is signed with an empty content length
+    let content_length = if content_length > 0 {
+        content_length.to_string()
+    } else {
+        String::new()
+    };
+
+    let mut canonicalized_headers = headers
+        .iter()
+        .filter(|(name, _)| name.as_str().starts_with("x-ms-"))
+        .map(|(name, value)| {
+            let value = value.to_str().unwrap_or("").trim();
+            format!("{}:{value}\n", name.as_str())
+        })
+        .collect::<Vec<_>>();
+    canonicalized_headers.sort_unstable();
+
+    let mut parameters = BTreeMap::<String, Vec<String>>::new();
+    for (name, value) in url.query_pairs() {
+        parameters
+            .entry(name.to_lowercase())
+            .or_default()
+            .push(value.into_owned());
+    }
+    let mut canonicalized_resource = format!("/{account}{}", url.path());
+    for (name, mut values) in parameters {
+        values.sort_unstable();
+        canonicalized_resource.push_str(&format!("\n{name}:{}", values.join(",")));
+    }
+
+    // the headers not used by this client (e.g. Content-MD5 or Range) are signed empty
+    let string_to_sign = format!(
+        "{method}\n\n\n{content_length}\n\n{content_type}\n\n\n\n\n\n\n{}{canonicalized_resource}",
+        canonicalized_headers.concat()
+    );
+
+    let key = STANDARD.decode(key).map_err(Error::other)?;
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).map_err(Error::other)?;
+    mac.update(string_to_sign.as_bytes());
+    let signature = STANDARD.encode(mac.finalize().into_bytes());
+    Ok(format!("SharedKey {account}:{signature}"))
+}
+
+/// Returns `response` when it is successful, and an error with its status and body otherwise,
+/// [`crate::Error::RateLimited`] when it was throttled by the storage (after the retries)
+async fn error_for_status(response: Response) -> Result<Response, Error> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let error = format!("{status}: {body}");
+    if matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    ) {
+        Err(crate::Error::RateLimited(error).into())
+    } else {
+        Err(Error::other(error))
+    }
+}
+
+fn content_type(blob_name: &str) -> &'static str {
+    if blob_name.ends_with(".json") {
+        "application/json"
+    } else {
+        "text/csv"
+    }
+}
+
+/// Returns the identifier of the block `number`; identifiers of the blocks of a blob have the same length
+fn block_id(number: usize) -> String {
+    STANDARD.encode(format!("{number:06}"))
+}
+
+/// Returns the body of `Put Block List` committing the blocks `0..blocks`
+fn block_list(blocks: usize) -> String {
+    let latest = (0..blocks)
+        .map(|number| format!("<Latest>{}</Latest>", block_id(number)))
+        .collect::<String>();
+    format!(r#"<?xml version="1.0" encoding="utf-8"?><BlockList>{latest}</BlockList>"#)
+}
+
+/// Returns the contents of the elements `tag` of `xml`
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    xml.split(open.as_str())
+        .skip(1)
+        .filter_map(|x| x.split_once(close.as_str()).map(|(x, _)| x))
+        .collect()
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Returns the names of the blobs of a page of `List Blobs` and the marker of the next page, if any
+fn parse_list(xml: &str) -> (Vec<String>, Option<String>) {
+    let names = elements(xml, "Name").into_iter().map(unescape).collect();
+    let marker = elements(xml, "NextMarker")
+        .into_iter()
+        .find(|x| !x.is_empty())
+        .map(unescape);
+    (names, marker)
+}
+
+/// Initialize a [`ContainerClient`] with `credential` (anonymous when `None`)
+fn new_client(
+    account: String,
+    container: String,
+    credential: Option<Credential>,
+    retry: RetryPolicy,
+) -> ContainerClient {
+    let retry_policy = ExponentialBackoff::builder()
+        .retry_bounds(retry.initial_backoff, retry.max_backoff)
+        .build_with_max_retries(retry.max_retries);
+    let retries = RetryMetric::default();
+    // the metric is after the retries so that it records every attempt
+    let client = ClientBuilder::new(reqwest::Client::new())
+        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+        .with(retries.clone())
+        .build();
+
+    ContainerClient {
+        client,
+        account,
+        container,
+        credential,
+        retries,
+    }
+}
+
+/// Initialize a [`ContainerClient`] of `container` of the storage `account` authorized with `credential`
+pub fn client(account: String, container: String, credential: Credential) -> ContainerClient {
+    client_with_retry(account, container, credential, Default::default())
+}
+
+/// Initialize a [`ContainerClient`] of `container` of the storage `account` authorized with `credential`
+/// that retries transient errors according to `retry`
+pub fn client_with_retry(
+    account: String,
+    container: String,
+    credential: Credential,
+    retry: RetryPolicy,
+) -> ContainerClient {
+    new_client(account, container, Some(credential), retry)
+}
+
+/// Initialize an anonymous [`ContainerClient`] of a container with public read access
+pub fn anonymous_client(account: String, container: String) -> ContainerClient {
+    new_client(account, container, None, Default::default())
+}
+
+#[async_trait::async_trait]
+impl BlobStorageProvider for ContainerClient {
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let url = self.url(Some(blob_name));
+        let response = self.send(Method::GET, url, HeaderMap::new(), None).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = error_for_status(response).await?;
+        Ok(Some(response.bytes().await.map_err(Error::other)?.to_vec()))
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ms-blob-type", HeaderValue::from_static("BlockBlob"));
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(content_type(blob_name)),
+        );
+
+        let url = self.url(Some(blob_name));
+        let response = self.send(Method::PUT, url, headers, Some(contents)).await?;
+        error_for_status(response).await.map(|_| ())
+    }
+
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        let url = self.url(Some(blob_name));
+        let response = self
+            .send(Method::DELETE, url, HeaderMap::new(), None)
+            .await?;
+        // deleting a blob that does not exist succeeds, as in the remote storage
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        error_for_status(response).await.map(|_| ())
+    }
+
+    async fn etag(&self, blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        let url = self.url(Some(blob_name));
+        let response = self.send(Method::HEAD, url, HeaderMap::new(), None).await?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = error_for_status(response).await?;
+        let etag = response.headers().get(reqwest::header::ETAG);
+        Ok(etag.and_then(|x| x.to_str().ok()).map(|x| x.to_string()))
+    }
+
+    /// Writes `chunks` in blocks of [`BLOCK_SIZE`] committed at the end,
+    /// or with a single request when they are smaller than a block
+    async fn put_stream(
+        &self,
+        blob_name: &str,
+        chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    ) -> Result<(), std::io::Error> {
+        let mut blocks = crate::fs::parts(chunks, BLOCK_SIZE);
+        let first = blocks.try_next().await?.unwrap_or_default();
+        if first.len() < BLOCK_SIZE {
+            return self.put(blob_name, first).await;
+        }
+
+        // blocks that are not committed are discarded by the storage
+        let mut block = Some(first);
+        let mut number = 0;
+        while let Some(contents) = block {
+            let mut url = self.url(Some(blob_name));
+            url.query_pairs_mut()
+                .append_pair("comp", "block")
+                .append_pair("blockid", &block_id(number));
+            let response = self
+                .send(Method::PUT, url, HeaderMap::new(), Some(contents))
+                .await?;
+            error_for_status(response).await?;
+            number += 1;
+            block = blocks.try_next().await?;
+        }
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-ms-blob-content-type",
+            HeaderValue::from_static(content_type(blob_name)),
+        );
+        let mut url = self.url(Some(blob_name));
+        url.query_pairs_mut().append_pair("comp", "blocklist");
+        let body = block_list(number).into_bytes();
+        let response = self.send(Method::PUT, url, headers, Some(body)).await?;
+        error_for_status(response).await.map(|_| ())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        let mut blobs = vec![];
+        let mut marker = None::<String>;
+        loop {
+            let mut url = self.url(None);
+            url.query_pairs_mut()
+                .append_pair("restype", "container")
+                .append_pair("comp", "list")
+                .append_pair("prefix", prefix);
+            if let Some(marker) = &marker {
+                url.query_pairs_mut().append_pair("marker", marker);
+            }
+            let response = self.send(Method::GET, url, HeaderMap::new(), None).await?;
+            let response = error_for_status(response).await?;
+            let page = response.text().await.map_err(Error::other)?;
+
+            let (names, next) = parse_list(&page);
+            blobs.extend(names);
+            match next {
+                Some(next) => marker = Some(next),
+                None => break,
+            }
+        }
+        Ok(blobs)
+    }
+
+    fn can_put(&self) -> bool {
+        self.credential.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn throttled() {
+        let response = |status: u16| {
+            let response = http::Response::builder()
+                .status(status)
+                .body("ServerBusy")
+                .unwrap();
+            Response::from(response)
+        };
+        for status in [429, 503] {
+            let error = error_for_status(response(status)).await.unwrap_err();
+            assert!(crate::Error::from(error).is_retryable());
+        }
+        let error = error_for_status(response(403)).await.unwrap_err();
+        assert!(!crate::Error::from(error).is_retryable());
+        assert!(error_for_status(response(200)).await.is_ok());
+    }
+
+    #[test]
+    fn url() {
+        let client = anonymous_client("account".to_string(), "private-jets".to_string());
+        assert_eq!(
+            client.url(Some("leg/v2/all/year=2023/data.csv")).as_str(),
+            "https://account.blob.core.windows.net/private-jets/leg/v2/all/year=2023/data.csv"
+        );
+        assert!(!client.can_put());
+
+        let client = super::client(
+            "account".to_string(),
+            "private-jets".to_string(),
+            Credential::SasToken("?sv=2021-08-06&sig=a%2Bb".to_string()),
+        );
+        assert_eq!(
+            client.url(None).as_str(),
+            "https://account.blob.core.windows.net/private-jets?sv=2021-08-06&sig=a%2Bb"
+        );
+        assert!(client.can_put());
+    }
+
+    #[test]
+    fn list() {
+        let page = r#"<?xml version="1.0" encoding="utf-8"?>
+<EnumerationResults ServiceEndpoint="https://account.blob.core.windows.net/" ContainerName="private-jets">
+<Prefix>leg/</Prefix><Blobs><Blob><Name>leg/a.csv</Name><Properties /></Blob><Blob><Name>leg/b&amp;c.csv</Name></Blob></Blobs>
+<NextMarker>2!abc</NextMarker></EnumerationResults>"#;
+        assert_eq!(
+            parse_list(page),
+            (
+                vec!["leg/a.csv".to_string(), "leg/b&c.csv".to_string()],
+                Some("2!abc".to_string())
+            )
+        );
+
+        let page = "<EnumerationResults><Blobs /><NextMarker /></EnumerationResults>";
+        assert_eq!(parse_list(page), (vec![], None));
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(block_id(0), "MDAwMDAw");
+        assert_eq!(
+            block_list(2),
+            r#"<?xml version="1.0" encoding="utf-8"?><BlockList><Latest>MDAwMDAw</Latest><Latest>MDAwMDAx</Latest></BlockList>"#
+        );
+    }
+
+    #[test]
+    fn sign() {
+        let url = Url::parse(
+            "https://account.blob.core.windows.net/private-jets?restype=container&comp=list&prefix=leg%2F",
+        )
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ms-version", HeaderValue::from_static(VERSION));
+        headers.insert(
+            "x-ms-date",
+            HeaderValue::from_static("Mon, 01 Jan 2024 00:00:00 GMT"),
+        );
+        let key = STANDARD.encode("key");
+
+        let authorization = shared_key("account", &key, &Method::GET, &url, &headers, 0).unwrap();
+        assert!(authorization.starts_with("SharedKey account:"));
+        // the signature depends on the request
+        let other = shared_key("account", &key, &Method::PUT, &url, &headers, 0).unwrap();
+        assert_ne!(authorization, other);
+
+        assert!(shared_key("account", "not base64", &Method::GET, &url, &headers, 0).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+//! Contains a [`BlobStorageProvider`] that keeps a copy of the blobs it reads under a prefix (e.g. the positions of
+//! `position/`) on a directory of the local disk, so that repeated runs (e.g. computing legs again with other
+//! thresholds) do not download them again from the remote storage.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use tokio::sync::Semaphore;
+
+use crate::fs::BlobStorageProvider;
+
+/// Returns `etag` as a file name, i.e. with characters other than alphanumerics and `-` replaced by `_`
+fn file_name(etag: &str) -> String {
+    etag.trim_matches('"')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Lru {
+    /// the size in bytes and last use of each cached file
+    files: HashMap<PathBuf, (u64, u64)>,
+    /// the total size in bytes of the cached files
+    size: u64,
+    /// incremented on every use of a file
+    clock: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, path: &Path, size: u64) {
+        self.clock += 1;
+        if let Some((previous, _)) = self.files.insert(path.to_path_buf(), (size, self.clock)) {
+            self.size -= previous;
+        }
+        self.size += size;
+    }
+
+    fn remove(&mut self, path: &Path) {
+        if let Some((size, _)) = self.files.remove(path) {
+            self.size -= size;
+        }
+    }
+
+    /// Removes the least recently used files until the total size is at most `max_size`;
+    /// returns the removed files
+    fn evict(&mut self, max_size: u64) -> Vec<PathBuf> {
+        let mut files = self
+            .files
+            .iter()
+            .map(|(path, (_, used))| (*used, path.clone()))
+            .collect::<Vec<_>>();
+        files.sort_unstable();
+        let mut evicted = vec![];
+        for (_, path) in files {
+            if self.size <= max_size {
+                break;
+            }
+            self.remove(&path);
+            evicted.push(path);
+        }
+        evicted
+    }
+}
+
+/// A [`BlobStorageProvider`] that caches the blobs read under `prefix` in a directory of the local disk.
+/// # Implementation
+/// A blob is cached at `{dir}/{blob_name}/{etag}`, with the entity tag of the blob in the underlying storage
+/// (see [`BlobStorageProvider::etag`]); a blob whose entity tag changed since it was cached is read again.
+/// Blobs of storages without entity tags are not cached.
+///
+/// The cache is capped at `max_size` bytes by removing the least recently used blobs. Reads of blobs not in the
+/// cache are limited to `max_downloads` at a time, so that a run with many concurrent tasks waits for the
+/// downloads in flight instead of starting more of them; reads from the cache are not limited.
+pub struct CachedClient<C: BlobStorageProvider> {
+    client: C,
+    dir: PathBuf,
+    prefix: String,
+    max_size: u64,
+    downloads: Semaphore,
+    lru: Mutex<Lru>,
+}
+
+impl<C: BlobStorageProvider> CachedClient<C> {
+    /// Returns a new [`CachedClient`] of the blobs of `client` under `prefix`, cached in `dir`.
+    /// Blobs already in `dir` (e.g. of a previous run) are used from least to most recently modified.
+    pub fn new(
+        client: C,
+        dir: impl Into<PathBuf>,
+        prefix: &str,
+        max_size: u64,
+        max_downloads: usize,
+    ) -> Result<Self, std::io::Error> {
+        let dir = dir.into();
+        let mut files = vec![];
+        visit_files(&dir, &mut files)?;
+        files.sort_unstable_by_key(|(_, _, modified)| *modified);
+        let mut lru = Lru::default();
+        for (path, size, _) in files {
+            lru.touch(&path, size);
+        }
+        log::info!(
+            "{} - {} cached blobs ({} bytes)",
+            dir.display(),
+            lru.files.len(),
+            lru.size
+        );
+        Ok(Self {
+            client,
+            dir,
+            prefix: prefix.to_string(),
+            max_size,
+            downloads: Semaphore::new(max_downloads.max(1)),
+            lru: Mutex::new(lru),
+        })
+    }
+
+    /// The total size in bytes of the cached blobs
+    pub fn size(&self) -> u64 {
+        self.lru.lock().unwrap().size
+    }
+
+    fn blob_dir(&self, blob_name: &str) -> PathBuf {
+        self.dir.join(Path::new(blob_name))
+    }
+
+    /// Removes the cached copies of `blob_name`
+    fn invalidate(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        if !blob_name.starts_with(&self.prefix) {
+            return Ok(());
+        }
+        let dir = self.blob_dir(blob_name);
+        let mut lru = self.lru.lock().unwrap();
+        let mut files = vec![];
+        visit_files(&dir, &mut files)?;
+        for (path, _, _) in files {
+            std::fs::remove_file(&path)?;
+            lru.remove(&path);
+        }
+        Ok(())
+    }
+
+    /// Writes `data` of `blob_name` with `etag` to the cache, replacing other copies of the blob and
+    /// removing the least recently used blobs above `max_size`
+    fn insert(&self, blob_name: &str, etag: &str, data: &[u8]) -> Result<(), std::io::Error> {
+        let size = data.len() as u64;
+        if size > self.max_size {
+            return Ok(());
+        }
+        self.invalidate(blob_name)?;
+        let path = self.blob_dir(blob_name).join(file_name(etag));
+        std::fs::create_dir_all(self.blob_dir(blob_name))?;
+        std::fs::write(&path, data)?;
+        let evicted = {
+            let mut lru = self.lru.lock().unwrap();
+            lru.touch(&path, size);
+            lru.evict(self.max_size)
+        };
+        for path in evicted {
+            log::info!("{} - evicted", path.display());
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends the path, size and time of last modification of every file under `dir` to `files`
+fn visit_files(
+    dir: &Path,
+    files: &mut Vec<(PathBuf, u64, SystemTime)>,
+) -> Result<(), std::io::Error> {
+    if !dir.is_dir() {
+        return Ok(());
+    }
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            visit_files(&path, files)?;
+        } else {
+            let metadata = std::fs::metadata(&path)?;
+            files.push((path, metadata.len(), metadata.modified()?));
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl<C: BlobStorageProvider + Sync + Send> BlobStorageProvider for CachedClient<C> {
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        if !blob_name.starts_with(&self.prefix) {
+            return self.client.maybe_get(blob_name).await;
+        }
+        let Some(etag) = self.client.etag(blob_name).await? else {
+            return self.client.maybe_get(blob_name).await;
+        };
+        let path = self.blob_dir(blob_name).join(file_name(&etag));
+        if let Ok(data) = std::fs::read(&path) {
+            log::info!("{blob_name} - cache hit");
+            self.lru.lock().unwrap().touch(&path, data.len() as u64);
+            // so that the order of use is kept across runs
+            std::fs::File::options()
+                .write(true)
+                .open(&path)?
+                .set_modified(SystemTime::now())?;
+            return Ok(Some(data));
+        }
+
+        let _permit = self
+            .downloads
+            .acquire()
+            .await
+            .map_err(std::io::Error::other)?;
+        log::info!("{blob_name} - cache miss");
+        let data = self.client.maybe_get(blob_name).await?;
+        if let Some(data) = &data {
+            // the entity tag is read before the blob, so that a blob changed in between is read again
+            self.insert(blob_name, &etag, data)?;
+        }
+        Ok(data)
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        self.invalidate(blob_name)?;
+        self.client.put(blob_name, contents).await
+    }
+
+    async fn put_stream(
+        &self,
+        blob_name: &str,
+        chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    ) -> Result<(), std::io::Error> {
+        self.invalidate(blob_name)?;
+        self.client.put_stream(blob_name, chunks).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        self.client.list(prefix).await
+    }
+
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        self.invalidate(blob_name)?;
+        self.client.delete(blob_name).await
+    }
+
+    async fn etag(&self, blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        self.client.etag(blob_name).await
+    }
+
+    fn can_put(&self) -> bool {
+        self.client.can_put()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs_local::LocalDisk;
+
+    #[tokio::test]
+    async fn work() {
+        let root = std::env::temp_dir().join("test_fs_cache");
+        let _ = std::fs::remove_dir_all(&root);
+        let remote = LocalDisk::new(root.join("remote"));
+        let cache = root.join("cache");
+        remote.put("position/a.json", vec![1; 10]).await.unwrap();
+        remote.put("position/b.json", vec![2; 10]).await.unwrap();
+        remote.put("leg/c.json", vec![3; 10]).await.unwrap();
+
+        let client = CachedClient::new(&remote, &cache, "position/", 25, 2).unwrap();
+        for blob_name in ["position/a.json", "position/b.json", "leg/c.json"] {
+            client.maybe_get(blob_name).await.unwrap().unwrap();
+        }
+        // blobs of other prefixes are not cached
+        assert_eq!(client.size(), 20);
+
+        // a blob changed in the storage is read again
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        remote.put("position/a.json", vec![4; 5]).await.unwrap();
+        assert_eq!(
+            client.maybe_get("position/a.json").await.unwrap(),
+            Some(vec![4; 5])
+        );
+        assert_eq!(client.size(), 15);
+
+        // the least recently used blob (b) is evicted above the maximum size
+        remote.put("position/d.json", vec![5; 15]).await.unwrap();
+        client.maybe_get("position/d.json").await.unwrap();
+        assert_eq!(client.size(), 20);
+
+        // the cache is kept across clients
+        let client = CachedClient::new(&remote, &cache, "position/", 25, 2).unwrap();
+        assert_eq!(client.size(), 20);
+        let mut files = vec![];
+        visit_files(&cache.join("position/b.json"), &mut files).unwrap();
+        assert!(files.is_empty());
+        assert_eq!(client.maybe_get("position/x.json").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Contains a [`BlobStorageProvider`] that answers listings of a prefix from an index blob instead of listing the
+//! underlying storage, which takes minutes over hundreds of thousands of blobs.
+use std::{collections::BTreeSet, sync::Mutex};
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+
+use crate::fs::BlobStorageProvider;
+
+static INDEX_ROOT: &str = "index/";
+
+/// Number of new blobs after which the index is written
+static WRITE_EVERY: usize = 1000;
+
+fn index_blob_name(prefix: &str) -> String {
+    format!("{INDEX_ROOT}{prefix}keys.txt")
+}
+
+#[derive(Default)]
+struct Index {
+    keys: BTreeSet<String>,
+    /// number of keys added since the index was last written
+    pending: usize,
+}
+
+/// A [`BlobStorageProvider`] that keeps an index of all blobs under `prefix`.
+/// # Implementation
+/// The index is a blob with one key per line. It is updated on every [`BlobStorageProvider::put`]
+/// to `prefix` and written every 1000 new keys and on [`IndexedClient::flush`].
+/// Keys written after the last write of the index are missing from it if the process stops before it; they
+/// are only recovered by rebuilding the index from a full listing, i.e. with `refresh` (`--refresh-index` of
+/// `etl_positions` and `etl_legs`) or when no index exists (see [`IndexedClient::new`]).
+pub struct IndexedClient<C: BlobStorageProvider> {
+    client: C,
+    prefix: String,
+    index: Mutex<Index>,
+}
+
+impl<C: BlobStorageProvider> IndexedClient<C> {
+    /// Returns a new [`IndexedClient`] of `prefix`, reading the index from `client`.
+    /// When `refresh` is true or no index exists, the index is rebuilt from a full listing of `prefix`.
+    pub async fn new(client: C, prefix: &str, refresh: bool) -> Result<Self, std::io::Error> {
+        let index_blob = index_blob_name(prefix);
+        let existing = if refresh {
+            None
+        } else {
+            client.maybe_get(&index_blob).await?
+        };
+        let keys = match existing {
+            Some(data) => {
+                let data = String::from_utf8(data).map_err(std::io::Error::other)?;
+                data.lines().map(|x| x.to_string()).collect()
+            }
+            None => {
+                log::info!("{index_blob} - rebuilding from listing {prefix}");
+                let keys = client.list(prefix).await?.into_iter().collect();
+                if client.can_put() {
+                    client.put(&index_blob, serialize(&keys)).await?;
+                }
+                keys
+            }
+        };
+        log::info!("{index_blob} - {} keys", keys.len());
+
+        Ok(Self {
+            client,
+            prefix: prefix.to_string(),
+            index: Mutex::new(Index { keys, pending: 0 }),
+        })
+    }
+
+    /// Writes the index to the underlying storage
+    pub async fn flush(&self) -> Result<(), std::io::Error> {
+        let data = {
+            let mut index = self.index.lock().unwrap();
+            index.pending = 0;
+            serialize(&index.keys)
+        };
+        self.client.put(&index_blob_name(&self.prefix), data).await
+    }
+
+    /// Adds the written `blob_name` to the index, writing the index every [`WRITE_EVERY`] new keys
+    async fn insert(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        if !blob_name.starts_with(&self.prefix) {
+            return Ok(());
+        }
+        let write = {
+            let mut index = self.index.lock().unwrap();
+            if index.keys.insert(blob_name.to_string()) {
+                index.pending += 1;
+            }
+            index.pending >= WRITE_EVERY
+        };
+        if write {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+}
+
+fn serialize(keys: &BTreeSet<String>) -> Vec<u8> {
+    keys.iter()
+        .flat_map(|key| key.as_bytes().iter().chain(b"\n"))
+        .copied()
+        .collect()
+}
+
+#[async_trait]
+impl<C: BlobStorageProvider + Sync + Send> BlobStorageProvider for IndexedClient<C> {
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        self.client.maybe_get(blob_name).await
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        self.client.put(blob_name, contents).await?;
+        self.insert(blob_name).await
+    }
+
+    async fn put_stream(
+        &self,
+        blob_name: &str,
+        chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    ) -> Result<(), std::io::Error> {
+        self.client.put_stream(blob_name, chunks).await?;
+        self.insert(blob_name).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        if !prefix.starts_with(&self.prefix) {
+            return self.client.list(prefix).await;
+        }
+        let index = self.index.lock().unwrap();
+        Ok(index
+            .keys
+            .range(prefix.to_string()..)
+            .take_while(|key| key.starts_with(prefix))
+            .cloned()
+            .collect())
+    }
+
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        self.client.delete(blob_name).await?;
+        self.index.lock().unwrap().keys.remove(blob_name);
+        Ok(())
+    }
+
+    async fn etag(&self, blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        self.client.etag(blob_name).await
+    }
+
+    fn can_put(&self) -> bool {
+        self.client.can_put()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fs::LocalDisk;
+
+    #[tokio::test]
+    async fn work() {
+        let prefix = "test_fs_index/";
+        let _ = std::fs::remove_dir_all("database/test_fs_index");
+        LocalDisk
+            .put(&format!("{prefix}a/data.csv"), vec![])
+            .await
+            .unwrap();
+
+        // rebuilt from listing
+        let client = IndexedClient::new(LocalDisk, prefix, true).await.unwrap();
+        assert_eq!(
+            client.list(prefix).await.unwrap(),
+            vec![format!("{prefix}a/data.csv")]
+        );
+
+        client
+            .put(&format!("{prefix}b/data.csv"), vec![])
+            .await
+            .unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(
+            client.list(&format!("{prefix}b/")).await.unwrap(),
+            vec![format!("{prefix}b/data.csv")]
+        );
+
+        // read from the index
+        let client = IndexedClient::new(LocalDisk, prefix, false).await.unwrap();
+        assert_eq!(client.list(prefix).await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,157 @@
+//! Contains the implementation of [`BlobStorageProvider`] on a directory of the local disk,
+//! so that the pipeline can run end-to-end without remote storage.
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use async_trait::async_trait;
+use futures::{stream::BoxStream, TryStreamExt};
+
+use crate::fs::BlobStorageProvider;
+
+fn visit_dirs<P: AsRef<Path>>(
+    dir: P,
+    cb: &mut dyn FnMut(&std::fs::DirEntry),
+) -> std::io::Result<()> {
+    if dir.as_ref().is_dir() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.is_dir() {
+                visit_dirs(&path, cb)?;
+            } else {
+                cb(&entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A [`BlobStorageProvider`] that maps blob names to paths under a root directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalDisk {
+    root: PathBuf,
+}
+
+impl LocalDisk {
+    /// Returns a new [`LocalDisk`] storing blobs under `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, blob_name: &str) -> PathBuf {
+        self.root.join(Path::new(blob_name))
+    }
+
+    /// Returns the path of `blob_name`, creating its directory
+    fn create_dir(&self, blob_name: &str) -> Result<PathBuf, std::io::Error> {
+        let path = self.path(blob_name);
+        let mut dir = path.clone();
+        dir.pop();
+        std::fs::create_dir_all(dir)?;
+        Ok(path)
+    }
+}
+
+#[async_trait]
+impl BlobStorageProvider for LocalDisk {
+    #[must_use]
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let path = self.path(blob_name);
+        if path.try_exists()? {
+            Ok(Some(std::fs::read(path)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[must_use]
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        let path = self.create_dir(blob_name)?;
+        std::fs::write(path, &contents)?;
+        Ok(())
+    }
+
+    async fn put_stream(
+        &self,
+        blob_name: &str,
+        mut chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    ) -> Result<(), std::io::Error> {
+        let path = self.create_dir(blob_name)?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        let written = async {
+            while let Some(chunk) = chunks.try_next().await? {
+                file.write_all(&chunk)?;
+            }
+            file.flush()
+        }
+        .await;
+        if written.is_err() {
+            // so that a partial blob is not mistaken for a written one
+            let _ = std::fs::remove_file(path);
+        }
+        written
+    }
+
+    #[must_use]
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        let mut paths = vec![];
+        visit_dirs(self.path(prefix), &mut |entry| {
+            let path = entry.path();
+            let path = path.strip_prefix(&self.root).unwrap_or(&path);
+            paths.push(path.to_str().unwrap().to_string())
+        })?;
+        Ok(paths)
+    }
+
+    #[must_use]
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        match std::fs::remove_file(self.path(blob_name)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            other => other,
+        }
+    }
+
+    /// The entity tag of a file is its length and time of last modification
+    async fn etag(&self, blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        let metadata = match std::fs::metadata(self.path(blob_name)) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            other => other?,
+        };
+        let modified = metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_err(std::io::Error::other)?;
+        Ok(Some(format!("{}-{}", metadata.len(), modified.as_nanos())))
+    }
+
+    fn can_put(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn work() {
+        let root = std::env::temp_dir().join("test_fs_local");
+        let _ = std::fs::remove_dir_all(&root);
+        let client = LocalDisk::new(&root);
+
+        assert_eq!(client.maybe_get("a/data.csv").await.unwrap(), None);
+        client.put("a/data.csv", b"a".to_vec()).await.unwrap();
+        assert_eq!(
+            client.maybe_get("a/data.csv").await.unwrap(),
+            Some(b"a".to_vec())
+        );
+        assert!(root.join("a/data.csv").exists());
+        assert_eq!(client.list("a/").await.unwrap(), vec!["a/data.csv"]);
+
+        client.delete("a/data.csv").await.unwrap();
+        assert_eq!(client.maybe_get("a/data.csv").await.unwrap(), None);
+        client.delete("a/data.csv").await.unwrap();
+    }
+}
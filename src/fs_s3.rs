@@ -1,16 +1,147 @@
-use std::io::Error;
+use std::{
+    io::Error,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
+use aws_config::retry::RetryConfig;
 use aws_credential_types::provider::ProvideCredentials;
 use aws_sdk_s3::{
-    config::Credentials, error::SdkError, operation::get_object::GetObjectError,
-    primitives::ByteStream, types::ObjectCannedAcl,
+    config::http::HttpResponse,
+    config::{
+        interceptors::{
+            BeforeSerializationInterceptorContextRef, BeforeTransmitInterceptorContextRef,
+        },
+        ConfigBag, Credentials, Intercept, RuntimeComponents,
+    },
+    error::{BoxError, ProvideErrorMetadata, SdkError},
+    operation::{get_object::GetObjectError, head_object::HeadObjectError},
+    primitives::ByteStream,
+    types::{CompletedMultipartUpload, CompletedPart, ObjectCannedAcl},
 };
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
 
 use crate::fs::BlobStorageProvider;
+
+/// The size of the parts of multipart uploads (at least 5 MiB, the minimum of S3)
+static PART_SIZE: usize = 8 * 1024 * 1024;
+
 pub struct ContainerClient {
     pub client: aws_sdk_s3::Client,
     pub bucket: String,
     can_put: bool,
+    retries: RetryMetric,
+}
+
+/// Policy to retry requests that failed with a transient error (e.g. a 503 or a connection reset),
+/// with exponential backoff and jitter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The maximum number of retries of a request
+    pub max_retries: u32,
+    /// The backoff before the first retry; it doubles on every retry
+    pub initial_backoff: Duration,
+    /// The maximum backoff between retries
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn config(&self) -> RetryConfig {
+        RetryConfig::standard()
+            .with_max_attempts(self.max_retries + 1)
+            .with_initial_backoff(self.initial_backoff)
+            .with_max_backoff(self.max_backoff)
+    }
+}
+
+/// Number of requests and attempts of a [`ContainerClient`] (or [`crate::fs_azure::ContainerClient`]);
+/// the attempts beyond the first of each request are retries
+#[derive(Debug, Clone, Default)]
+pub struct RetryMetric {
+    requests: Arc<AtomicUsize>,
+    attempts: Arc<AtomicUsize>,
+}
+
+impl RetryMetric {
+    pub(crate) fn record_request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_attempt(&self) {
+        self.attempts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of retried attempts so far
+    pub fn retried(&self) -> usize {
+        self.attempts
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.requests.load(Ordering::Relaxed))
+    }
+}
+
+impl Intercept for RetryMetric {
+    fn name(&self) -> &'static str {
+        "RetryMetric"
+    }
+
+    fn read_before_execution(
+        &self,
+        _context: &BeforeSerializationInterceptorContextRef<'_>,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        self.record_request();
+        Ok(())
+    }
+
+    fn read_before_attempt(
+        &self,
+        _context: &BeforeTransmitInterceptorContextRef<'_>,
+        _runtime_components: &RuntimeComponents,
+        _cfg: &mut ConfigBag,
+    ) -> Result<(), BoxError> {
+        self.record_attempt();
+        Ok(())
+    }
+}
+
+impl ContainerClient {
+    /// Returns the [`RetryMetric`] of this client, which remains valid after the client is moved
+    pub fn retries(&self) -> RetryMetric {
+        self.retries.clone()
+    }
+}
+
+/// Returns the error of a failed request, [`crate::Error::RateLimited`] when it was throttled by the storage
+fn request_error<E>(error: SdkError<E, HttpResponse>) -> Error
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    let status = error
+        .raw_response()
+        .map(|response| response.status().as_u16());
+    let throttled = matches!(status, Some(429 | 503))
+        || matches!(
+            error.code(),
+            Some("SlowDown" | "Throttling" | "TooManyRequests")
+        );
+    if throttled {
+        crate::Error::RateLimited(error.to_string()).into()
+    } else {
+        Error::other(error)
+    }
 }
 
 async fn get(client: &ContainerClient, blob_name: &str) -> Result<Option<Vec<u8>>, Error> {
@@ -28,10 +159,10 @@ async fn get(client: &ContainerClient, blob_name: &str) -> Result<Option<Vec<u8>
                 if matches!(e.err(), GetObjectError::NoSuchKey(_)) {
                     return Ok(None);
                 } else {
-                    return Err(Error::other(err));
+                    return Err(request_error(err));
                 }
             }
-            _ => return Err(Error::other(err)),
+            _ => return Err(request_error(err)),
         },
         Ok(x) => x,
     };
@@ -44,12 +175,17 @@ async fn get(client: &ContainerClient, blob_name: &str) -> Result<Option<Vec<u8>
         .map_err(Error::other)
 }
 
+fn content_type(blob_name: &str) -> &'static str {
+    if blob_name.ends_with(".json") {
+        "application/json"
+    } else {
+        "text/csv"
+    }
+}
+
 async fn put(client: &ContainerClient, blob_name: &str, content: Vec<u8>) -> Result<(), Error> {
     let stream = ByteStream::from(content);
-    let content_type = blob_name
-        .ends_with(".json")
-        .then_some("application/json")
-        .unwrap_or("text/csv");
+    let content_type = content_type(blob_name);
 
     client
         .client
@@ -61,10 +197,104 @@ async fn put(client: &ContainerClient, blob_name: &str, content: Vec<u8>) -> Res
         .content_type(content_type)
         .send()
         .await
-        .map_err(Error::other)
+        .map_err(request_error)
         .map(|_| ())
 }
 
+/// Writes `chunks` to `blob_name` with a multipart upload of parts of [`PART_SIZE`],
+/// or with a single request when they are smaller than a part
+async fn put_stream(
+    client: &ContainerClient,
+    blob_name: &str,
+    chunks: BoxStream<'_, Result<Vec<u8>, Error>>,
+) -> Result<(), Error> {
+    let mut parts = crate::fs::parts(chunks, PART_SIZE);
+    let first = parts.try_next().await?.unwrap_or_default();
+    if first.len() < PART_SIZE {
+        return put(client, blob_name, first).await;
+    }
+
+    let upload = client
+        .client
+        .create_multipart_upload()
+        .bucket(&client.bucket)
+        .key(blob_name)
+        .acl(ObjectCannedAcl::PublicRead)
+        .content_type(content_type(blob_name))
+        .send()
+        .await
+        .map_err(request_error)?;
+    let upload_id = upload
+        .upload_id()
+        .ok_or_else(|| Error::other("multipart upload without an id"))?;
+
+    let parts = futures::stream::once(async { Ok(first) }).chain(parts);
+    match upload_parts(client, blob_name, upload_id, parts).await {
+        Ok(parts) => client
+            .client
+            .complete_multipart_upload()
+            .bucket(&client.bucket)
+            .key(blob_name)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(request_error)
+            .map(|_| ()),
+        Err(e) => {
+            // so that the uploaded parts are not kept (and billed) by the storage
+            if let Err(abort) = client
+                .client
+                .abort_multipart_upload()
+                .bucket(&client.bucket)
+                .key(blob_name)
+                .upload_id(upload_id)
+                .send()
+                .await
+            {
+                log::error!("{blob_name} - failed to abort multipart upload: {abort}");
+            }
+            Err(e)
+        }
+    }
+}
+
+async fn upload_parts(
+    client: &ContainerClient,
+    blob_name: &str,
+    upload_id: &str,
+    parts: impl futures::Stream<Item = Result<Vec<u8>, Error>>,
+) -> Result<Vec<CompletedPart>, Error> {
+    let mut parts = std::pin::pin!(parts);
+    let mut completed = vec![];
+    while let Some(part) = parts.try_next().await? {
+        // part numbers start at 1
+        let part_number = completed.len() as i32 + 1;
+        let response = client
+            .client
+            .upload_part()
+            .bucket(&client.bucket)
+            .key(blob_name)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .body(ByteStream::from(part))
+            .send()
+            .await
+            .map_err(request_error)?;
+        completed.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(response.e_tag().map(|x| x.to_string()))
+                .build(),
+        );
+    }
+    Ok(completed)
+}
+
 async fn delete(client: &ContainerClient, blob_name: &str) -> Result<(), Error> {
     client
         .client
@@ -73,7 +303,7 @@ async fn delete(client: &ContainerClient, blob_name: &str) -> Result<(), Error>
         .key(blob_name)
         .send()
         .await
-        .map_err(Error::other)
+        .map_err(request_error)
         .map(|_| ())
 }
 
@@ -104,66 +334,80 @@ impl ProvideCredentials for Provider {
     }
 }
 
-/// Initialize a [`ContainerClient`] access key and secret access key
-pub async fn client(access_key: String, secret_access_key: String) -> ContainerClient {
-    let provider = Provider {
-        access_key,
-        secret_access_key,
-    };
-
-    let config = aws_config::ConfigLoader::default()
+/// Initialize a [`ContainerClient`] with `provider` (anonymous when `None`)
+async fn new_client(provider: Option<Provider>, retry: RetryPolicy) -> ContainerClient {
+    let loader = aws_config::ConfigLoader::default()
         .behavior_version(aws_config::BehaviorVersion::latest())
         .region("fra1")
-        .endpoint_url("https://fra1.digitaloceanspaces.com")
-        .credentials_provider(provider)
-        .load()
-        .await;
-    let client = aws_sdk_s3::Client::new(&config);
+        .endpoint_url("https://fra1.digitaloceanspaces.com");
+    let can_put = provider.is_some();
+    let loader = match provider {
+        Some(provider) => loader.credentials_provider(provider),
+        None => loader.no_credentials(),
+    };
+    let config = loader.retry_config(retry.config()).load().await;
+
+    let retries = RetryMetric::default();
+    let config = aws_sdk_s3::config::Builder::from(&config)
+        .interceptor(retries.clone())
+        .build();
+    let client = aws_sdk_s3::Client::from_conf(config);
 
     ContainerClient {
         client,
         bucket: "private-jets".to_string(),
-        can_put: true,
+        can_put,
+        retries,
     }
 }
 
+/// Initialize a [`ContainerClient`] access key and secret access key
+pub async fn client(access_key: String, secret_access_key: String) -> ContainerClient {
+    client_with_retry(access_key, secret_access_key, Default::default()).await
+}
+
+/// Initialize a [`ContainerClient`] access key and secret access key that retries transient errors
+/// according to `retry`
+pub async fn client_with_retry(
+    access_key: String,
+    secret_access_key: String,
+    retry: RetryPolicy,
+) -> ContainerClient {
+    let provider = Provider {
+        access_key,
+        secret_access_key,
+    };
+    new_client(Some(provider), retry).await
+}
+
 /// Initialize an anonymous [`ContainerClient`]
 pub async fn anonymous_client() -> ContainerClient {
-    let config = aws_config::ConfigLoader::default()
-        .behavior_version(aws_config::BehaviorVersion::latest())
-        .region("fra1")
-        .endpoint_url("https://fra1.digitaloceanspaces.com")
-        .no_credentials()
-        .load()
-        .await;
-    let client = aws_sdk_s3::Client::new(&config);
-
-    ContainerClient {
-        client,
-        bucket: "private-jets".to_string(),
-        can_put: false,
-    }
+    new_client(None, Default::default()).await
 }
 
 #[async_trait::async_trait]
 impl BlobStorageProvider for ContainerClient {
     #[must_use]
     async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
-        get(&self, blob_name).await.map_err(std::io::Error::other)
+        get(self, blob_name).await
     }
 
     #[must_use]
     async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
-        put(&self, blob_name, contents)
-            .await
-            .map_err(std::io::Error::other)
+        put(self, blob_name, contents).await
     }
 
     #[must_use]
     async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
-        delete(&self, blob_name)
-            .await
-            .map_err(std::io::Error::other)
+        delete(self, blob_name).await
+    }
+
+    async fn put_stream(
+        &self,
+        blob_name: &str,
+        chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    ) -> Result<(), std::io::Error> {
+        put_stream(self, blob_name, chunks).await
     }
 
     #[must_use]
@@ -177,7 +421,7 @@ impl BlobStorageProvider for ContainerClient {
             .send()
             .try_collect()
             .await
-            .map_err(std::io::Error::other)?
+            .map_err(request_error)?
             .into_iter()
             .map(|response| {
                 response
@@ -190,6 +434,25 @@ impl BlobStorageProvider for ContainerClient {
             .collect())
     }
 
+    async fn etag(&self, blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(blob_name)
+            .send()
+            .await;
+        match head {
+            Ok(head) => Ok(head.e_tag().map(|x| x.to_string())),
+            Err(SdkError::ServiceError(ref e))
+                if matches!(e.err(), HeadObjectError::NotFound(_)) =>
+            {
+                Ok(None)
+            }
+            Err(err) => Err(request_error(err)),
+        }
+    }
+
     fn can_put(&self) -> bool {
         self.can_put
     }
@@ -0,0 +1,293 @@
+//! Contains geodesic utilities on `(latitude, longitude)` points in degrees (distances, bearings and destinations),
+//! and the implementation to reverse-geocode positions to countries and continents using the
+//! polygons of [Natural Earth](https://www.naturalearthdata.com/downloads/50m-cultural-vectors/)'s admin 0 countries.
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::{
+    fs::{self, BlobStorageProvider},
+    region::Region,
+};
+
+static DATABASE: &str = "country/naturalearth/data.geojson";
+
+/// Mean radius of the earth in km
+pub static EARTH_RADIUS: f64 = 6371.0;
+/// The number of km in a foot
+static KM_PER_FEET: f64 = 0.0003048;
+
+/// Polygons whose first ring is their exterior and the others their holes, of `(longitude, latitude)` in degrees
+type Polygons = Vec<Vec<Vec<(f64, f64)>>>;
+
+/// Returns the great-circle distance in km between two `(latitude, longitude)` in degrees using the
+/// haversine formula on a sphere of radius [`EARTH_RADIUS`]
+pub fn haversine(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let from = geoutils::Location::new(from.0, from.1);
+    let to = geoutils::Location::new(to.0, to.1);
+    from.haversine_distance_to(&to).meters() / 1000.0
+}
+
+/// Returns the distance in km between two `(latitude, longitude)` in degrees on the WGS-84 ellipsoid using
+/// Vincenty's inverse formula, or `None` when it does not converge (e.g. nearly antipodal points)
+pub fn vincenty(from: (f64, f64), to: (f64, f64)) -> Option<f64> {
+    let from = geoutils::Location::new(from.0, from.1);
+    let to = geoutils::Location::new(to.0, to.1);
+    from.distance_to(&to)
+        .ok()
+        .map(|distance| distance.meters() / 1000.0)
+}
+
+/// Returns the initial bearing in degrees `[0, 360)` (clockwise from north) of the great circle from `from` to `to`,
+/// both `(latitude, longitude)` in degrees. It is `None` when both are the same point
+pub fn initial_bearing(from: (f64, f64), to: (f64, f64)) -> Option<f64> {
+    let radians =
+        |(latitude, longitude): (f64, f64)| (longitude.to_radians(), latitude.to_radians());
+    let (distance, bearing) = crate::region::angular_distance_bearing(radians(from), radians(to));
+    (distance > 0.0).then(|| bearing.to_degrees().rem_euclid(360.0))
+}
+
+/// Returns the `(latitude, longitude)` in degrees reached from `from` after `distance` km along the great circle
+/// with initial `bearing` in degrees
+pub fn destination(from: (f64, f64), bearing: f64, distance: f64) -> (f64, f64) {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let bearing = bearing.to_radians();
+    let angular = distance / EARTH_RADIUS;
+    let lat2 = (lat1.sin() * angular.cos() + lat1.cos() * angular.sin() * bearing.cos()).asin();
+    let lon2 = lon1
+        + (bearing.sin() * angular.sin() * lat1.cos())
+            .atan2(angular.cos() - lat1.sin() * lat2.sin());
+    (
+        lat2.to_degrees(),
+        (lon2.to_degrees() + 540.0).rem_euclid(360.0) - 180.0,
+    )
+}
+
+/// Returns the straight-line distance in km between two `(latitude, longitude, altitude)`, in degrees and feet,
+/// combining their [`haversine`] distance with the difference of their altitudes
+pub fn distance_3d(from: (f64, f64, f64), to: (f64, f64, f64)) -> f64 {
+    let horizontal = haversine((from.0, from.1), (to.0, to.1));
+    let vertical = (to.2 - from.2) * KM_PER_FEET;
+    horizontal.hypot(vertical)
+}
+
+fn url() -> &'static str {
+    "https://raw.githubusercontent.com/nvkelso/natural-earth-vector/master/geojson/ne_50m_admin_0_countries.geojson"
+}
+
+/// A country
+#[derive(Debug, Clone, PartialEq)]
+pub struct Country {
+    /// The country in ISO 3166-1 alpha-2 (e.g. `DK`)
+    pub iso_code: Arc<str>,
+    /// The name of the country (e.g. `Denmark`)
+    pub name: Arc<str>,
+    /// The continent of the country (e.g. `Europe`)
+    pub continent: Arc<str>,
+}
+
+/// A set of [`Country`]s and their borders
+#[derive(Debug, Clone, PartialEq)]
+pub struct Countries {
+    /// each country, its borders and their bounding box
+    countries: Vec<(Country, Region, Region)>,
+}
+
+/// Returns the bounding box of the exterior rings of `polygons`
+pub(crate) fn bounding_box(polygons: &[Vec<Vec<(f64, f64)>>]) -> Region {
+    let points = polygons.iter().filter_map(|rings| rings.first()).flatten();
+    let (min_lon, min_lat, max_lon, max_lat) = points.fold(
+        (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+        |(min_lon, min_lat, max_lon, max_lat), (lon, lat)| {
+            (
+                min_lon.min(*lon),
+                min_lat.min(*lat),
+                max_lon.max(*lon),
+                max_lat.max(*lat),
+            )
+        },
+    );
+    Region::BoundingBox {
+        min_lon,
+        min_lat,
+        max_lon,
+        max_lat,
+    }
+}
+
+/// Returns the [`Country`] and its polygons from a GeoJSON `Feature` of Natural Earth,
+/// or `None` when the feature has no ISO 3166-1 alpha-2 code (e.g. disputed areas)
+fn parse_feature(feature: &Value) -> Result<Option<(Country, Polygons)>, String> {
+    let properties = feature
+        .get("properties")
+        .ok_or_else(|| format!("feature without properties: {feature}"))?;
+    let property = |name: &str| properties.get(name).and_then(|x| x.as_str());
+    // `ISO_A2` is `-99` for some countries (e.g. France); `ISO_A2_EH` fixes them
+    let Some(iso_code) = [property("ISO_A2_EH"), property("ISO_A2")]
+        .into_iter()
+        .flatten()
+        .find(|code| *code != "-99")
+    else {
+        return Ok(None);
+    };
+    let country = Country {
+        iso_code: iso_code.into(),
+        name: property("NAME").unwrap_or(iso_code).into(),
+        continent: property("CONTINENT").unwrap_or_default().into(),
+    };
+    Ok(Some((country, crate::region::parse_geojson(feature)?)))
+}
+
+impl Countries {
+    /// Returns [`Countries`] from a GeoJSON `FeatureCollection` of Natural Earth's admin 0 countries
+    pub fn from_geojson(data: &[u8]) -> Result<Self, String> {
+        let value = serde_json::from_slice::<Value>(data).map_err(|e| e.to_string())?;
+        let countries = value
+            .get("features")
+            .and_then(|x| x.as_array())
+            .ok_or_else(|| "GeoJSON must be a FeatureCollection".to_string())?
+            .iter()
+            .map(parse_feature)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .map(|(country, polygons)| {
+                let bbox = bounding_box(&polygons);
+                (country, Region::Polygons(polygons), bbox)
+            })
+            .collect();
+        Ok(Self { countries })
+    }
+
+    /// Returns the [`Country`] containing `(latitude, longitude)`, or `None` when it is not in any
+    /// country (e.g. at sea or on a coast not covered by the 1:50m borders).
+    pub fn country_of(&self, latitude: f64, longitude: f64) -> Option<&Country> {
+        self.countries
+            .iter()
+            .find(|(_, borders, bbox)| {
+                bbox.contains((latitude, longitude)) && borders.contains((latitude, longitude))
+            })
+            .map(|(country, _, _)| country)
+    }
+}
+
+async fn extract() -> Result<Vec<u8>, std::io::Error> {
+    Ok(reqwest::get(url())
+        .await
+        .map_err(std::io::Error::other)?
+        .bytes()
+        .await
+        .map_err(std::io::Error::other)?
+        .to_vec())
+}
+
+/// Returns [`Countries`] from [Natural Earth](https://www.naturalearthdata.com/).
+/// # Implementation
+/// The dataset is cached in `client` (or on local disk when `client` cannot be written to)
+/// the first time it is used.
+pub async fn countries(client: &dyn BlobStorageProvider) -> Result<Countries, std::io::Error> {
+    let data =
+        fs::cached_call(DATABASE, extract(), client, fs::CacheAction::ReadFetchWrite).await?;
+    Countries::from_geojson(&data).map_err(std::io::Error::other)
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// points away from the poles, where bearings are ill-defined
+    fn point() -> impl Strategy<Value = (f64, f64)> {
+        (-80.0..80.0f64, -180.0..180.0f64)
+    }
+
+    #[test]
+    fn geodesic() {
+        let copenhagen = (55.618, 12.656);
+        let paris = (49.010, 2.548);
+        assert!((haversine(copenhagen, paris) - 1005.0).abs() < 5.0);
+        assert!((vincenty(copenhagen, paris).unwrap() - 1008.0).abs() < 5.0);
+        assert!((initial_bearing(copenhagen, paris).unwrap() - 227.2).abs() < 0.1);
+        assert_eq!(initial_bearing(paris, paris), None);
+        let (lat, lon) = destination((0.0, 0.0), 90.0, EARTH_RADIUS * std::f64::consts::PI / 2.0);
+        assert!(lat.abs() < 1e-9 && (lon - 90.0).abs() < 1e-9);
+        assert!((distance_3d((0.0, 0.0, 0.0), (0.0, 0.0, 10000.0)) - 3.048).abs() < 1e-9);
+    }
+
+    proptest! {
+        #[test]
+        fn haversine_is_a_distance(from in point(), to in point()) {
+            let distance = haversine(from, to);
+            prop_assert!(distance >= 0.0);
+            prop_assert!(distance <= EARTH_RADIUS * std::f64::consts::PI + 1.0);
+            prop_assert!((distance - haversine(to, from)).abs() < 1e-6);
+        }
+
+        #[test]
+        fn vincenty_close_to_haversine(from in point(), to in point()) {
+            // the sphere differs from the ellipsoid by at most ~0.5%
+            if let Some(vincenty) = vincenty(from, to) {
+                prop_assert!((vincenty - haversine(from, to)).abs() <= 0.006 * vincenty + 0.01);
+            }
+        }
+
+        #[test]
+        fn destination_inverts_bearing(from in point(), bearing in 0.0..360.0f64, distance in 1.0..5000.0f64) {
+            let to = destination(from, bearing, distance);
+            prop_assert!((haversine(from, to) - distance).abs() < 0.01);
+            let initial = initial_bearing(from, to).unwrap();
+            let difference = (initial - bearing).rem_euclid(360.0);
+            prop_assert!(difference.min(360.0 - difference) < 1e-6);
+        }
+
+        #[test]
+        fn distance_3d_above_haversine(from in point(), to in point(), altitudes in (0.0..45000.0f64, 0.0..45000.0f64)) {
+            let horizontal = haversine(from, to);
+            prop_assert!(distance_3d((from.0, from.1, altitudes.0), (to.0, to.1, altitudes.1)) >= horizontal);
+            prop_assert_eq!(distance_3d((from.0, from.1, altitudes.0), (to.0, to.1, altitudes.0)), horizontal);
+        }
+    }
+
+    #[test]
+    fn country_of() {
+        let data = br#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {"ISO_A2": "-99", "ISO_A2_EH": "DK", "NAME": "Denmark", "CONTINENT": "Europe"},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[8.0, 54.5], [13.0, 54.5], [13.0, 57.8], [8.0, 57.8], [8.0, 54.5]]]
+                }
+            }, {
+                "type": "Feature",
+                "properties": {"ISO_A2": "DE", "NAME": "Germany", "CONTINENT": "Europe"},
+                "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [[[[6.0, 47.3], [15.0, 47.3], [15.0, 54.5], [6.0, 54.5], [6.0, 47.3]]]]
+                }
+            }, {
+                "type": "Feature",
+                "properties": {"ISO_A2": "-99", "NAME": "Disputed", "CONTINENT": "Europe"},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0], [0.0, 0.0]]]
+                }
+            }]
+        }"#;
+        let countries = Countries::from_geojson(data).unwrap();
+
+        let denmark = countries.country_of(55.68, 12.57).unwrap();
+        assert_eq!(denmark.iso_code.as_ref(), "DK");
+        assert_eq!(denmark.continent.as_ref(), "Europe");
+        assert_eq!(
+            countries.country_of(52.52, 13.40).unwrap().name.as_ref(),
+            "Germany"
+        );
+        assert!(countries.country_of(0.5, 0.5).is_none());
+        assert!(countries.country_of(60.0, 10.0).is_none());
+
+        assert!(Countries::from_geojson(br#"{"type": "Polygon"}"#).is_err());
+    }
+}
@@ -0,0 +1,211 @@
+//! Contains the implementation of the export of the public dataset of legs (`leg/v2/all/year={year}/data.csv`)
+//! to [GeoJSON](https://datatracker.ietf.org/doc/html/rfc7946) (`M-geojson`), for mapping frontends.
+use std::{error::Error, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fs::BlobStorageProvider;
+
+static DATABASE_ROOT: &str = "leg/v2/";
+
+/// Maximum distance in km between consecutive points of the great-circle path of a leg
+static STEP: f64 = 100.0;
+
+/// A leg of the public dataset of legs, restricted to the columns exported to GeoJSON.
+/// All columns but the coordinates are the properties of its [`Feature`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeoLeg {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The tail number, when known
+    #[serde(default)]
+    pub tail_number: Option<Arc<str>>,
+    /// The aircraft model, when known
+    #[serde(default)]
+    pub aircraft_model: Option<Arc<str>>,
+    /// The start timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: time::OffsetDateTime,
+    /// The start latitude
+    #[serde(skip_serializing)]
+    pub start_lat: f64,
+    /// The start longitude
+    #[serde(skip_serializing)]
+    pub start_lon: f64,
+    /// The end timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: time::OffsetDateTime,
+    /// The end latitude
+    #[serde(skip_serializing)]
+    pub end_lat: f64,
+    /// The end longitude
+    #[serde(skip_serializing)]
+    pub end_lon: f64,
+    /// The duration of the leg in hours
+    pub duration: f64,
+    /// The total flown distance of the leg in km
+    pub distance: f64,
+    /// The great-circle distance of the leg in km
+    pub great_circle_distance: f64,
+    /// The CO2 emissions in kg, when known
+    #[serde(default)]
+    pub co2_emissions: Option<f64>,
+    /// The identifier of the departure airport, when known
+    #[serde(default)]
+    pub from_airport_icao: Option<Arc<str>>,
+    /// The identifier of the arrival airport, when known
+    #[serde(default)]
+    pub to_airport_icao: Option<Arc<str>>,
+    /// The country (ISO 3166-1 alpha-2) of the start of the leg, when known
+    #[serde(default)]
+    pub from_country: Option<Arc<str>>,
+    /// The country (ISO 3166-1 alpha-2) of the end of the leg, when known
+    #[serde(default)]
+    pub to_country: Option<Arc<str>>,
+}
+
+/// A GeoJSON `LineString`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type")]
+pub enum Geometry {
+    LineString {
+        /// The `[longitude, latitude]` of each point
+        coordinates: Vec<[f64; 2]>,
+    },
+}
+
+/// A GeoJSON `Feature` of a leg
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename = "Feature")]
+pub struct Feature {
+    pub geometry: Geometry,
+    pub properties: GeoLeg,
+}
+
+/// A GeoJSON `FeatureCollection` of legs
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename = "FeatureCollection")]
+pub struct FeatureCollection {
+    pub features: Vec<Feature>,
+}
+
+/// Returns the `[longitude, latitude]` of the great-circle path of `leg`, at most [`STEP`] km apart.
+/// Longitudes are continuous (e.g. `179` is followed by `181` instead of `-179`), so that legs crossing the
+/// antimeridian are drawn over it instead of around the world.
+fn line_string(leg: &GeoLeg) -> Vec<[f64; 2]> {
+    let from = (leg.start_lat, leg.start_lon);
+    let to = (leg.end_lat, leg.end_lon);
+    let steps = (crate::distance(from, to) / STEP).ceil().max(1.0) as usize;
+    let mut previous = None::<f64>;
+    (0..=steps)
+        .map(|i| {
+            let (lat, mut lon) = crate::dataset::intermediate(from, to, i as f64 / steps as f64);
+            if let Some(previous) = previous {
+                lon += 360.0 * ((previous - lon) / 360.0).round();
+            }
+            previous = Some(lon);
+            [lon, lat]
+        })
+        .collect()
+}
+
+/// Returns the [`FeatureCollection`] of `legs`, with one `LineString` along the great circle of each leg
+pub fn feature_collection(legs: impl Iterator<Item = GeoLeg>) -> FeatureCollection {
+    FeatureCollection {
+        features: legs
+            .map(|leg| Feature {
+                geometry: Geometry::LineString {
+                    coordinates: line_string(&leg),
+                },
+                properties: leg,
+            })
+            .collect(),
+    }
+}
+
+/// Returns the blob name of the GeoJSON of the legs of `year`, or of the legs of `icao_number` on `year`
+pub fn blob_name(year: i32, icao_number: Option<&str>) -> String {
+    match icao_number {
+        Some(icao_number) => {
+            format!("{DATABASE_ROOT}geojson/icao_number={icao_number}/year={year}/data.geojson")
+        }
+        None => format!("{DATABASE_ROOT}geojson/year={year}/data.geojson"),
+    }
+}
+
+/// Converts the legs of `year` (only of `icao_number` when set) of the public dataset of legs to a
+/// [`FeatureCollection`] and writes it to [`blob_name`].
+/// Returns the number of exported legs.
+pub async fn export_geojson(
+    year: i32,
+    icao_number: Option<&str>,
+    client: &dyn BlobStorageProvider,
+) -> Result<usize, Box<dyn Error>> {
+    let key = format!("{DATABASE_ROOT}all/year={year}/data.csv");
+    let Some(data) = crate::io::maybe_get(&key, client).await? else {
+        return Err(format!("{key} does not exist").into());
+    };
+    let legs = crate::csv::deserialize::<GeoLeg>(&data)
+        .filter(|leg| {
+            leg.as_ref().map_or(true, |leg| {
+                icao_number.is_none_or(|icao_number| &*leg.icao_number == icao_number)
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let count = legs.len();
+
+    let collection = feature_collection(legs.into_iter());
+    let key = blob_name(year, icao_number);
+    let data = serde_json::to_vec(&collection).map_err(std::io::Error::other)?;
+    client.put(&key, data).await?;
+    log::info!("Written {key} ({count} legs)");
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn work() {
+        let data = b"icao_number,tail_number,aircraft_model,start,start_lat,start_lon,end,end_lat,end_lon,duration,distance,great_circle_distance,co2_emissions,from_airport_icao,to_airport_icao,from_country,to_country
+459cd3,OY-GFS,FALCON 2000,2023-01-01T10:00:00Z,55.6,12.6,2023-01-01T12:00:00Z,49.0,2.5,2.0,1050.0,1000.0,5000.0,EKCH,LFPB,DK,FR
+459cd3,OY-GFS,FALCON 2000,2023-01-02T10:00:00Z,60.0,179.0,2023-01-02T11:00:00Z,60.0,-179.0,1.0,120.0,111.0,,,,,
+";
+        let legs = crate::csv::deserialize::<GeoLeg>(data)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let collection = feature_collection(legs.into_iter());
+
+        let Geometry::LineString { coordinates } = &collection.features[0].geometry;
+        // ~1020 km, at most 100 km apart
+        assert_eq!(coordinates.len(), 12);
+        let close =
+            |a: [f64; 2], b: [f64; 2]| (a[0] - b[0]).abs() < 1e-9 && (a[1] - b[1]).abs() < 1e-9;
+        assert!(close(coordinates[0], [12.6, 55.6]));
+        assert!(close(coordinates[11], [2.5, 49.0]));
+
+        // over the antimeridian
+        let Geometry::LineString { coordinates } = &collection.features[1].geometry;
+        assert_eq!(coordinates.len(), 3);
+        assert!((coordinates[2][0] - 181.0).abs() < 1e-9);
+
+        let value = serde_json::to_value(&collection).unwrap();
+        assert_eq!(value["type"], "FeatureCollection");
+        assert_eq!(value["features"][0]["type"], "Feature");
+        assert_eq!(value["features"][0]["geometry"]["type"], "LineString");
+        let properties = &value["features"][0]["properties"];
+        assert_eq!(properties["tail_number"], "OY-GFS");
+        assert_eq!(properties["co2_emissions"], 5000.0);
+        assert!(properties.get("start_lat").is_none());
+        assert_eq!(
+            value["features"][1]["properties"]["from_airport_icao"],
+            serde_json::Value::Null
+        );
+
+        assert_eq!(
+            blob_name(2023, Some("459cd3")),
+            "leg/v2/geojson/icao_number=459cd3/year=2023/data.geojson"
+        );
+    }
+}
@@ -0,0 +1,104 @@
+//! Contains the implementation of the time aircrafts spend on the ground at an airport between
+//! consecutive legs (turnarounds), used e.g. to identify home bases and empty legs.
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// The time an aircraft spent on the ground at an airport between two consecutive legs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroundTime {
+    /// The identifier of the airport (its ICAO code when it has one)
+    pub airport: Arc<str>,
+    /// The ICAO number of the aircraft
+    pub icao_number: Arc<str>,
+    /// The end of the leg arriving at the airport
+    #[serde(with = "time::serde::rfc3339")]
+    pub arrival: OffsetDateTime,
+    /// The start of the next leg, departing from the airport
+    #[serde(with = "time::serde::rfc3339")]
+    pub departure: OffsetDateTime,
+    /// The hours between `arrival` and `departure`
+    pub hours_on_ground: f64,
+}
+
+crate::schema!(GroundTime {
+    airport: "The identifier of the airport (its ICAO code when it has one)",
+    icao_number: "The ICAO number of the aircraft",
+    arrival: "The end of the leg arriving at the airport (RFC 3339, UTC)",
+    departure: "The start of the next leg, departing from the airport (RFC 3339, UTC)",
+    hours_on_ground("h"): "The time between `arrival` and `departure`",
+});
+
+/// Returns the [`GroundTime`]s of `icao_number` given its legs as `(departure, arrival)`, each a
+/// datetime and an airport (`None` when unknown), ordered by departure.
+/// Consecutive legs are a [`GroundTime`] only when the first arrives at the airport the second departs from;
+/// otherwise legs were likely missed in between and the time on the ground is unknown.
+pub fn ground_times(
+    icao_number: Arc<str>,
+    legs: impl Iterator<
+        Item = (
+            (OffsetDateTime, Option<Arc<str>>),
+            (OffsetDateTime, Option<Arc<str>>),
+        ),
+    >,
+) -> Vec<GroundTime> {
+    legs.collect::<Vec<_>>()
+        .windows(2)
+        .filter_map(|w| {
+            let (_, (arrival, to)) = &w[0];
+            let ((departure, from), _) = &w[1];
+            let airport = to.as_ref().filter(|to| Some(*to) == from.as_ref())?;
+            Some(GroundTime {
+                airport: airport.clone(),
+                icao_number: icao_number.clone(),
+                arrival: *arrival,
+                departure: *departure,
+                hours_on_ground: (*departure - *arrival).as_seconds_f64() / 60.0 / 60.0,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn work() {
+        let airport = |ident: &str| Some(Arc::<str>::from(ident));
+        let legs = vec![
+            (
+                (datetime!(2023-01-01 10:00 UTC), airport("EKCH")),
+                (datetime!(2023-01-01 11:00 UTC), airport("EKBI")),
+            ),
+            (
+                (datetime!(2023-01-01 14:30 UTC), airport("EKBI")),
+                (datetime!(2023-01-01 15:30 UTC), airport("EKCH")),
+            ),
+            // a leg was missed in between
+            (
+                (datetime!(2023-01-03 10:00 UTC), airport("EKYT")),
+                (datetime!(2023-01-03 11:00 UTC), None),
+            ),
+            (
+                (datetime!(2023-01-04 10:00 UTC), None),
+                (datetime!(2023-01-04 11:00 UTC), airport("EKCH")),
+            ),
+        ];
+
+        let result = ground_times("45d2ed".into(), legs.into_iter());
+        assert_eq!(
+            result,
+            vec![GroundTime {
+                airport: "EKBI".into(),
+                icao_number: "45d2ed".into(),
+                arrival: datetime!(2023-01-01 11:00 UTC),
+                departure: datetime!(2023-01-01 14:30 UTC),
+                hours_on_ground: 3.5,
+            }]
+        );
+    }
+}
@@ -7,6 +7,7 @@ use time::Date;
 use time::OffsetDateTime;
 
 use super::Position;
+use crate::altitude::{AltitudeSource, Preference};
 use crate::fs;
 
 fn last_2(icao: &str) -> &str {
@@ -154,6 +155,7 @@ fn compute_trace(data: &[u8]) -> Result<(f64, Vec<serde_json::Value>), std::io::
 /// * `1` is latitude (f64)
 /// * `2` is longitude (f64)
 /// * `3` is either Baro. Altitude in feet (f32) or "ground" (String)
+/// * `10` is, when present, Geom. Altitude in feet (f32)
 /// # Implementation
 /// Because these are historical values, this function caches them the first time it is used
 /// by the two arguments
@@ -165,7 +167,10 @@ async fn trace_cached(
     compute_trace(&globe_history_cached(icao, date, client).await?)
 }
 
-fn compute_positions(start_trace: (f64, Vec<serde_json::Value>)) -> impl Iterator<Item = Position> {
+fn compute_positions(
+    start_trace: (f64, Vec<serde_json::Value>),
+    altitude: Preference,
+) -> impl Iterator<Item = Position> {
     use time::ext::NumericalDuration;
 
     let (start, trace) = start_trace;
@@ -176,45 +181,56 @@ fn compute_positions(start_trace: (f64, Vec<serde_json::Value>)) -> impl Iterato
         let datetime = start + delta;
         let latitude = entry[1].as_f64().unwrap();
         let longitude = entry[2].as_f64().unwrap();
-        entry[3]
-            .as_str()
-            .and_then(|x| {
-                (x == "ground").then_some(Position {
-                    datetime,
-                    latitude,
-                    longitude,
-                    altitude: None,
-                })
-            })
-            .or_else(|| {
-                entry[3].as_f64().and_then(|altitude| {
-                    Some(Position {
-                        datetime,
-                        latitude,
-                        longitude,
-                        altitude: Some(altitude),
-                    })
-                })
-            })
+        if entry[3].as_str() == Some("ground") {
+            return Some(Position {
+                datetime,
+                latitude,
+                longitude,
+                altitude: None,
+                altitude_source: None,
+            });
+        }
+        // `10` is the geometric altitude in feet, when reported
+        let geometric = entry.get(10).and_then(|x| x.as_f64());
+        let (altitude, source) = crate::altitude::select(entry[3].as_f64(), geometric, altitude)?;
+        Some(Position {
+            datetime,
+            latitude,
+            longitude,
+            altitude: Some(altitude),
+            // barometric altitudes are not written, see `M-altitudes`
+            altitude_source: (source != AltitudeSource::Barometric).then_some(source),
+        })
     })
 }
 
 /// Returns an iterator of [`Position`] over the trace of `icao` on day `date` according
-/// to the [methodology `M-daily-adsb`](../methodology.md).
+/// to the [methodology `M-daily-adsb`](../methodology.md), with barometric altitudes (see [`positions_with`]).
 pub async fn positions(
     icao_number: &str,
     date: time::Date,
     client: &dyn fs::BlobStorageProvider,
+) -> Result<impl Iterator<Item = Position>, std::io::Error> {
+    positions_with(icao_number, date, Preference::Barometric, client).await
+}
+
+/// Same as [`positions`], with the altitude of `altitude` (see `M-altitudes`).
+pub async fn positions_with(
+    icao_number: &str,
+    date: time::Date,
+    altitude: Preference,
+    client: &dyn fs::BlobStorageProvider,
 ) -> Result<impl Iterator<Item = Position>, std::io::Error> {
     trace_cached(icao_number, &date, client)
         .await
-        .map(compute_positions)
+        .map(|trace| compute_positions(trace, altitude))
 }
 
 pub(crate) fn cached_aircraft_positions<'a>(
     icao_number: &'a str,
     from: Date,
     to: Date,
+    altitude: Preference,
     client: &'a dyn fs::BlobStorageProvider,
 ) -> impl Iterator<
     Item = impl futures::future::Future<Output = Result<Vec<Position>, std::io::Error>> + 'a,
@@ -226,13 +242,94 @@ pub(crate) fn cached_aircraft_positions<'a>(
     }
     .map(move |date| async move {
         Result::<_, std::io::Error>::Ok(
-            positions(icao_number, date, client)
+            positions_with(icao_number, date, altitude, client)
                 .await?
                 .collect::<Vec<_>>(),
         )
     })
 }
 
+/// The result of probing a source of positions with a known ICAO number and date
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct Health {
+    /// The name of the source
+    pub source: &'static str,
+    /// The time it took to retrieve the trace, in seconds
+    pub latency: f64,
+    /// The number of positions of the trace
+    pub positions: usize,
+    /// The schema of the trace, as the sorted set of its keys and of the lengths of its entries
+    /// (e.g. `keys=icao,timestamp,trace;entry_lengths=14`)
+    pub schema: String,
+    /// Why the trace could not be parsed, if it could not
+    pub error: Option<String>,
+}
+
+/// Returns the schema and number of positions of a trace, or an error if it cannot be parsed into positions
+fn check_trace(data: &[u8]) -> Result<(String, usize), String> {
+    let value = serde_json::from_slice::<serde_json::Value>(data).map_err(|e| e.to_string())?;
+    let object = value.as_object().ok_or("trace is not a JSON object")?;
+    let mut keys = object.keys().map(|x| x.as_str()).collect::<Vec<_>>();
+    keys.sort_unstable();
+
+    object
+        .get("timestamp")
+        .and_then(|x| x.as_f64())
+        .ok_or("`timestamp` is not a number")?;
+    let trace = object
+        .get("trace")
+        .and_then(|x| x.as_array())
+        .ok_or("`trace` is not an array")?;
+
+    let mut lengths = std::collections::BTreeSet::new();
+    for entry in trace {
+        let entry = entry.as_array().ok_or("entry is not an array")?;
+        lengths.insert(entry.len());
+        let numbers = entry.iter().take(3).all(|x| x.is_f64() || x.is_i64());
+        let altitude = entry
+            .get(3)
+            .is_some_and(|x| x.is_number() || x.as_str() == Some("ground"));
+        if entry.len() < 4 || !numbers || !altitude {
+            return Err(format!(
+                "entry {entry:?} is not [time, latitude, longitude, altitude, ...]"
+            ));
+        }
+    }
+    let lengths = lengths
+        .into_iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    Ok((
+        format!("keys={};entry_lengths={lengths}", keys.join(",")),
+        trace.len(),
+    ))
+}
+
+/// Probes [ADS-B exchange](https://globe.adsbexchange.com) for the trace of `icao_number` on `date`
+/// (bypassing any cache) and returns its [`Health`].
+pub async fn healthcheck(icao_number: &str, date: time::Date) -> Health {
+    let start = std::time::Instant::now();
+    let data = globe_history(icao_number, &date).await;
+    let latency = start.elapsed().as_secs_f64();
+
+    let (schema, positions, error) = match data
+        .map_err(|e| e.to_string())
+        .and_then(|data| check_trace(&data))
+    {
+        Ok((schema, positions)) if positions > 0 => (schema, positions, None),
+        Ok((schema, _)) => (schema, 0, Some("trace has no positions".to_string())),
+        Err(e) => (String::new(), 0, Some(e)),
+    };
+    Health {
+        source: "adsbexchange",
+        latency,
+        positions,
+        schema,
+        error,
+    }
+}
+
 pub use crate::trace_month::*;
 
 #[cfg(test)]
@@ -247,7 +344,7 @@ mod test {
         let data = globe_history("45860d", &date!(2019 - 01 - 04))
             .await
             .unwrap();
-        let first = compute_positions(compute_trace(&data).unwrap())
+        let first = compute_positions(compute_trace(&data).unwrap(), Preference::Barometric)
             .next()
             .unwrap();
         assert_eq!(first.datetime.hour(), 6);
@@ -255,6 +352,42 @@ mod test {
         assert_eq!(first.grounded(), true);
     }
 
+    #[test]
+    fn altitudes() {
+        let data = br#"{"timestamp": 1.0, "trace": [[0, 55.0, 12.0, "ground"], [10, 55.1, 12.1, 1000, 1, 1, 0, 0, null, "adsb_icao", 1100], [20, 55.2, 12.2, null, 1, 1, 0, 0, null, "adsb_icao", 2100]]}"#;
+        let trace = || compute_trace(data).unwrap();
+
+        let positions = compute_positions(trace(), Preference::Barometric).collect::<Vec<_>>();
+        let altitudes = positions
+            .iter()
+            .map(|p| (p.altitude, p.altitude_source()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            altitudes,
+            vec![
+                (None, None),
+                (Some(1000.0), Some(AltitudeSource::Barometric)),
+                (Some(2100.0), Some(AltitudeSource::Geometric)),
+            ]
+        );
+        // barometric altitudes are the default and are not written
+        assert_eq!(positions[1].altitude_source, None);
+
+        let positions = compute_positions(trace(), Preference::Geometric).collect::<Vec<_>>();
+        assert_eq!(positions[1].altitude, Some(1100.0));
+    }
+
+    #[test]
+    fn schema() {
+        let data = br#"{"icao": "aa", "timestamp": 1.0, "trace": [[0.0, 55.0, 12.0, "ground"], [10, 55.1, 12.1, 1000, 1]]}"#;
+        assert_eq!(
+            check_trace(data).unwrap(),
+            ("keys=icao,timestamp,trace;entry_lengths=4,5".to_string(), 2)
+        );
+        assert!(check_trace(br#"{"timestamp": 1.0, "trace": [[0.0, 55.0]]}"#).is_err());
+        assert!(check_trace(b"{}").is_err());
+    }
+
     #[tokio::test]
     async fn edge_cases() {
         // https://globe.adsbexchange.com/globe_history/2022/10/21/traces/23/trace_full_a7e823.json
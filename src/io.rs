@@ -1,12 +1,141 @@
 use serde::de::DeserializeOwned;
 
-use crate::fs::BlobStorageProvider;
+use crate::{compression::Compression, fs::BlobStorageProvider, Error};
 
+/// Returns the decompressed content of `key` or, when it does not exist, of `key` compressed with any
+/// [`Compression`] (e.g. `data.csv.zst`), so that compressed and uncompressed datasets are read the same way.
+/// Returns `None` when none exists.
+/// Blobs written by [`put`] and [`put_stream`] have no sibling of another compression, so which one is read
+/// does not depend on the order of [`Compression::ALL`].
+pub async fn maybe_get(
+    key: &str,
+    client: &dyn BlobStorageProvider,
+) -> Result<Option<Vec<u8>>, std::io::Error> {
+    for compression in Compression::ALL {
+        let key = format!("{key}{}", compression.extension());
+        if let Some(data) = client.maybe_get(&key).await? {
+            return crate::compression::decompress(data).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Deletes the blobs of `key` compressed with a [`Compression`] other than `compression` (e.g. a `data.csv`
+/// written before the dataset was compressed), so that they do not shadow the blob of `compression` in
+/// [`maybe_get`]
+async fn delete_siblings(
+    key: &str,
+    compression: Compression,
+    client: &dyn BlobStorageProvider,
+) -> Result<(), std::io::Error> {
+    let siblings = Compression::ALL
+        .into_iter()
+        .filter(|other| *other != compression)
+        .map(|other| format!("{key}{}", other.extension()))
+        .collect::<Vec<_>>();
+    let directory = key.rsplit_once('/').map_or("", |(directory, _)| directory);
+    for blob_name in client.list(directory).await? {
+        if siblings.contains(&blob_name) {
+            client.delete(&blob_name).await?;
+            log::info!(
+                "Deleted {blob_name}, superseded by {key}{}",
+                compression.extension()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Writes `data` compressed with `compression` to `key` followed by the extension of `compression`, and deletes
+/// the blobs of `key` of other compressions.
+/// Returns the written blob name.
+pub async fn put(
+    key: &str,
+    data: Vec<u8>,
+    compression: Compression,
+    client: &dyn BlobStorageProvider,
+) -> Result<String, std::io::Error> {
+    let written = format!("{key}{}", compression.extension());
+    client.put(&written, compression.compress(data)?).await?;
+    delete_siblings(key, compression, client).await?;
+    Ok(written)
+}
+
+/// Writes `chunks` compressed with `compression` to `key` followed by the extension of `compression`
+/// (see [`BlobStorageProvider::put_stream`]), so that the blob is never held in memory at once, and deletes
+/// the blobs of `key` of other compressions.
+/// Returns the written blob name.
+/// # Error
+/// The first error of `chunks`, in which case the blob is not written (see [`BlobStorageProvider::put_stream`])
+pub async fn put_stream(
+    key: &str,
+    chunks: impl Iterator<Item = Result<Vec<u8>, std::io::Error>> + Send,
+    compression: Compression,
+    client: &dyn BlobStorageProvider,
+) -> Result<String, std::io::Error> {
+    use futures::StreamExt;
+
+    let written = format!("{key}{}", compression.extension());
+    let chunks = futures::stream::iter(compression.compress_chunks(chunks)).boxed();
+    client.put_stream(&written, chunks).await?;
+    delete_siblings(key, compression, client).await?;
+    Ok(written)
+}
+
+/// Returns the rows of the CSV at `key`, compressed or not (see [`maybe_get`])
+/// # Error
+/// [`Error::MissingData`] when `key` does not exist, and [`Error::Decode`] when it is not a valid CSV of `D`
 pub async fn get_csv<D: DeserializeOwned>(
     key: &str,
     client: &dyn BlobStorageProvider,
-) -> Result<Vec<D>, std::io::Error> {
-    let content = client.maybe_get(key).await?.expect("File to be present");
+) -> Result<Vec<D>, Error> {
+    let content = maybe_get(key, client)
+        .await?
+        .ok_or_else(|| Error::MissingData(format!("{key} does not exist")))?;
+
+    Ok(super::csv::deserialize::<D>(&content).collect::<Result<_, _>>()?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn siblings() {
+        let root = std::env::temp_dir().join("test_io_siblings");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let key = "leg/v2/all/year=2023/data.csv";
+        // a stale blob written before the dataset was compressed
+        disk.put(key, b"stale".to_vec()).await.unwrap();
+        disk.put("leg/v2/all/year=2023/data.json", b"{}".to_vec())
+            .await
+            .unwrap();
+
+        let chunks = std::iter::once(Ok(b"fresh".to_vec()));
+        let written = put_stream(key, chunks, Compression::Zstd, &disk)
+            .await
+            .unwrap();
+        assert_eq!(written, "leg/v2/all/year=2023/data.csv.zst");
+        assert_eq!(disk.maybe_get(key).await.unwrap(), None);
+        assert_eq!(
+            maybe_get(key, &disk).await.unwrap(),
+            Some(b"fresh".to_vec())
+        );
+        // other blobs are kept
+        assert!(disk
+            .maybe_get("leg/v2/all/year=2023/data.json")
+            .await
+            .unwrap()
+            .is_some());
 
-    super::csv::deserialize::<D>(&content).collect()
+        put(key, b"uncompressed".to_vec(), Compression::None, &disk)
+            .await
+            .unwrap();
+        assert_eq!(disk.maybe_get(&written).await.unwrap(), None);
+        assert_eq!(
+            maybe_get(key, &disk).await.unwrap(),
+            Some(b"uncompressed".to_vec())
+        );
+    }
 }
@@ -0,0 +1,204 @@
+//! Contains the reading of the legacy database of legs (`leg/v1/`, see `M-legacy`), whose rows are mapped into
+//! [`LegOut`], so that consumers of [`crate::query`] and [`crate::dataset`] get the full history of legs while months
+//! and years not yet migrated to the current version are only in `v1`.
+use std::sync::Arc;
+
+use serde::Deserialize;
+use time::{format_description::FormatItem, macros::format_description, PrimitiveDateTime};
+
+use crate::{etl::legs::LegOut, fs::BlobStorageProvider};
+
+static DATABASE_ROOT: &str = "leg/v1/";
+
+/// The timestamps of `v1` written without a UTC offset (in UTC)
+static TIMESTAMP: &[FormatItem<'static>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+
+/// Returns the blob name of the partition of legs of `icao` on `month` of `v1`
+pub fn pk_to_blob_name(icao: &str, month: time::Date) -> String {
+    let month = crate::serde::month_to_part(month);
+    format!("{DATABASE_ROOT}data/icao_number={icao}/month={month}/data.csv")
+}
+
+/// Returns the blob name of the yearly dataset of legs of `year` of `v1`
+pub fn year_blob_name(year: i32) -> String {
+    format!("{DATABASE_ROOT}all/year={year}/data.csv")
+}
+
+/// A leg of `v1`: its ends are `from_*` and `to_*`, its timestamps are strings and it has no enrichments.
+/// Columns named as in the current version (e.g. `start_lat`) are also accepted.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LegV1 {
+    pub icao_number: Arc<str>,
+    #[serde(default)]
+    pub tail_number: Option<Arc<str>>,
+    #[serde(default)]
+    pub aircraft_model: Option<Arc<str>>,
+    /// RFC 3339 or `YYYY-MM-DD HH:MM:SS` in UTC
+    pub start: String,
+    #[serde(alias = "start_lat")]
+    pub from_lat: f64,
+    #[serde(alias = "start_lon")]
+    pub from_lon: f64,
+    #[serde(alias = "start_altitude")]
+    pub from_altitude: f64,
+    /// RFC 3339 or `YYYY-MM-DD HH:MM:SS` in UTC
+    pub end: String,
+    #[serde(alias = "end_lat")]
+    pub to_lat: f64,
+    #[serde(alias = "end_lon")]
+    pub to_lon: f64,
+    #[serde(alias = "end_altitude")]
+    pub to_altitude: f64,
+    /// hours
+    pub duration: f64,
+    /// km
+    pub distance: f64,
+    /// km, computed from the ends when missing
+    #[serde(default)]
+    pub great_circle_distance: Option<f64>,
+    pub hours_above_30000: f64,
+    pub hours_above_40000: f64,
+    /// kg
+    #[serde(default)]
+    pub co2_emissions: Option<f64>,
+}
+
+/// Returns the timestamp of `v1` `value` (RFC 3339 or `YYYY-MM-DD HH:MM:SS` in UTC)
+fn parse_timestamp(value: &str) -> Result<time::OffsetDateTime, std::io::Error> {
+    let value = value.trim();
+    time::OffsetDateTime::parse(value, &time::format_description::well_known::Rfc3339)
+        .or_else(|_| PrimitiveDateTime::parse(value, TIMESTAMP).map(|x| x.assume_utc()))
+        .map_err(|e| {
+            let message = format!("invalid timestamp `{value}` of leg/v1: {e}");
+            std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+        })
+}
+
+impl TryFrom<LegV1> for LegOut {
+    type Error = std::io::Error;
+
+    /// Maps a [`LegV1`] into a [`LegOut`]: columns derived from its ends (e.g. `midpoint_lat`) are computed,
+    /// columns that `v1` did not record are empty, zero or `false`
+    fn try_from(leg: LegV1) -> Result<Self, Self::Error> {
+        let from = (leg.from_lat, leg.from_lon);
+        let to = (leg.to_lat, leg.to_lon);
+        let great_circle_distance = leg
+            .great_circle_distance
+            .unwrap_or_else(|| crate::distance(from, to));
+        let (midpoint_lat, midpoint_lon) = crate::dataset::intermediate(from, to, 0.5);
+        Ok(Self {
+            icao_number: leg.icao_number,
+            tail_number: leg.tail_number,
+            aircraft_model: leg.aircraft_model,
+            start: parse_timestamp(&leg.start)?,
+            start_lat: leg.from_lat,
+            start_lon: leg.from_lon,
+            start_altitude: leg.from_altitude,
+            end: parse_timestamp(&leg.end)?,
+            end_lat: leg.to_lat,
+            end_lon: leg.to_lon,
+            end_altitude: leg.to_altitude,
+            duration: leg.duration,
+            distance: leg.distance,
+            great_circle_distance,
+            circuity: (great_circle_distance > 0.0).then(|| leg.distance / great_circle_distance),
+            midpoint_lat: Some(midpoint_lat),
+            midpoint_lon: Some(midpoint_lon),
+            initial_bearing: crate::geo::initial_bearing(from, to),
+            hours_above_30000: leg.hours_above_30000,
+            hours_above_40000: leg.hours_above_40000,
+            co2_emissions: leg.co2_emissions,
+            commercial_co2_emissions: None,
+            commercial_co2_backend: None,
+            tailwind: None,
+            true_airspeed: None,
+            diverted: None,
+            start_on_ground: None,
+            end_on_ground: None,
+            from_airport_icao: None,
+            from_airport_name: None,
+            from_airport_distance: None,
+            to_airport_icao: None,
+            to_airport_name: None,
+            to_airport_distance: None,
+            from_country: None,
+            to_country: None,
+            start_local: None,
+            end_local: None,
+            owner: None,
+            owner_type: None,
+            operator: None,
+            operator_type: None,
+            excluded_reason: None,
+            taxi_out_minutes: None,
+            taxi_in_minutes: None,
+            phased_co2_emissions: None,
+            commercial_economy_co2_emissions: None,
+            commercial_first_co2_emissions: None,
+            commercial_alternative_exists: None,
+            aircraft_category: None,
+            source_partition: None,
+            source_first_position: None,
+            source_last_position: None,
+            altitude_source: None,
+            overlays: None,
+            overlay_minutes: None,
+            overlay_min_altitude: None,
+            overlay_distance: None,
+        })
+    }
+}
+
+/// Deserializes a CSV of legs of `v1` into [`LegOut`]s
+pub fn deserialize(data: &[u8]) -> Result<Vec<LegOut>, std::io::Error> {
+    crate::csv::deserialize::<LegV1>(data)
+        .map(|leg| leg.and_then(LegOut::try_from))
+        .collect()
+}
+
+/// Returns the legs of `v1` in blob `key`, or `None` when it does not exist
+pub async fn read(
+    key: &str,
+    client: &dyn BlobStorageProvider,
+) -> Result<Option<Vec<LegOut>>, std::io::Error> {
+    crate::io::maybe_get(key, client)
+        .await?
+        .map(|data| deserialize(&data))
+        .transpose()
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn work() {
+        let data = b"icao_number,tail_number,aircraft_model,start,from_lat,from_lon,from_altitude,end,to_lat,to_lon,to_altitude,duration,distance,hours_above_30000,hours_above_40000,co2_emissions
+459cd3,OY-GFS,GULFSTREAM 5,2019-01-02 10:00:00,55.6,12.6,0,2019-01-02T11:30:00Z,49.0,2.5,0,1.5,1100,1.0,0.0,5000
+459cd3,,,2019-01-03 10:00:00,55.6,12.6,0,2019-01-03 11:00:00,55.6,12.6,0,1.0,300,0.0,0.0,
+";
+        let legs = deserialize(data).unwrap();
+        assert_eq!(legs.len(), 2);
+        let leg = &legs[0];
+        assert_eq!(leg.start, datetime!(2019-01-02 10:00:00 UTC));
+        assert_eq!(leg.end, datetime!(2019-01-02 11:30:00 UTC));
+        assert_eq!(leg.tail_number.as_deref(), Some("OY-GFS"));
+        assert_eq!((leg.end_lat, leg.end_lon), (49.0, 2.5));
+        assert!((leg.great_circle_distance - 1005.0).abs() < 5.0);
+        assert!(leg.midpoint_lat.is_some_and(|lat| lat < 55.6 && lat > 49.0));
+        assert_eq!(leg.co2_emissions, Some(5000.0));
+        // a sightseeing flight
+        assert_eq!(legs[1].circuity, None);
+        assert_eq!(legs[1].tail_number, None);
+
+        assert!(deserialize(
+            b"icao_number,start,from_lat,from_lon,from_altitude,end,to_lat,to_lon,to_altitude,duration,distance,hours_above_30000,hours_above_40000
+459cd3,yesterday,55.6,12.6,0,2019-01-03 11:00:00,55.6,12.6,0,1.0,300,0.0,0.0
+"
+        )
+        .is_err());
+    }
+}
@@ -5,11 +5,25 @@ use crate::Position;
 #[derive(Debug, Clone, PartialEq)]
 pub struct Leg {
     /// Sequence of positions defining the leg. Ends may start Flying, when the first/last observed
-    /// position was flying. Otherwise, first and last are Grounded.
+    /// position was flying. Otherwise, first and last are Grounded: the start of the takeoff roll and
+    /// the end of the landing rollout (see `M-taxi`).
     positions: Vec<Position>,
+    /// Time taxiing on the ground before the takeoff roll
+    taxi_out: time::Duration,
+    /// Time taxiing on the ground after the landing rollout
+    taxi_in: time::Duration,
 }
 
 impl Leg {
+    #[cfg(test)]
+    fn new(positions: Vec<Position>) -> Self {
+        Self {
+            positions,
+            taxi_out: time::Duration::ZERO,
+            taxi_in: time::Duration::ZERO,
+        }
+    }
+
     /// Positions of the leg
     pub fn positions(&self) -> &[Position] {
         &self.positions
@@ -25,11 +39,39 @@ impl Leg {
         self.positions.windows(2).map(|w| w[0].distace(&w[1])).sum()
     }
 
+    /// The ratio between the flown [`Leg::distance`] and the [`Leg::great_circle_distance`] (1 for a straight leg).
+    /// It is `None` when the leg starts and ends at the same position (e.g. a sightseeing flight)
+    pub fn circuity(&self) -> Option<f64> {
+        let great_circle_distance = self.great_circle_distance();
+        (great_circle_distance > 0.0).then(|| self.distance() / great_circle_distance)
+    }
+
+    /// The `(latitude, longitude)` of the midpoint of the great circle from the start to the end of the leg
+    pub fn midpoint(&self) -> (f64, f64) {
+        crate::dataset::intermediate(self.from().pos(), self.to().pos(), 0.5)
+    }
+
+    /// The initial bearing in degrees `[0, 360)` (clockwise from north) of the great circle from the start
+    /// to the end of the leg. It is `None` when the leg starts and ends at the same position
+    pub fn initial_bearing(&self) -> Option<f64> {
+        crate::geo::initial_bearing(self.from().pos(), self.to().pos())
+    }
+
     /// Leg duration
     pub fn duration(&self) -> time::Duration {
         self.to().datetime() - self.from().datetime()
     }
 
+    /// The time taxiing on the ground before the takeoff roll (zero when not observed), see `M-taxi`
+    pub fn taxi_out(&self) -> time::Duration {
+        self.taxi_out
+    }
+
+    /// The time taxiing on the ground after the landing rollout (zero when not observed), see `M-taxi`
+    pub fn taxi_in(&self) -> time::Duration {
+        self.taxi_in
+    }
+
     pub fn from(&self) -> &Position {
         self.positions.first().unwrap()
     }
@@ -37,16 +79,96 @@ impl Leg {
     pub fn to(&self) -> &Position {
         self.positions.last().unwrap()
     }
+
+    /// Returns the altitude in feet at `points` evenly spaced times from the start to the end of the leg,
+    /// linearly interpolated between positions.
+    pub fn altitude_profile(&self, points: usize) -> Vec<f64> {
+        let start = self.from().datetime();
+        let duration = self.duration().as_seconds_f64();
+        let mut positions = self.positions.windows(2).peekable();
+        (0..points)
+            .map(|i| {
+                let offset = if points > 1 {
+                    duration * i as f64 / (points - 1) as f64
+                } else {
+                    0.0
+                };
+                let t = start + time::Duration::seconds_f64(offset);
+                // advance to the pair of positions around `t`
+                while positions.next_if(|w| w[1].datetime() < t).is_some() {}
+                let Some(w) = positions.peek() else {
+                    return self.to().altitude();
+                };
+                let span = (w[1].datetime() - w[0].datetime()).as_seconds_f64();
+                if span <= 0.0 {
+                    return w[1].altitude();
+                }
+                let fraction = ((t - w[0].datetime()).as_seconds_f64() / span).clamp(0.0, 1.0);
+                w[0].altitude() + (w[1].altitude() - w[0].altitude()) * fraction
+            })
+            .collect()
+    }
+}
+
+/// Thresholds of the heuristics to identify legs, see [methodology `M-identify-legs`](../methodology.md).
+/// The defaults are tuned for jets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LegsConfig {
+    /// Minimum average ground speed of a leg in km/h; slower legs are ignored as noise
+    pub min_ground_speed: f64,
+    /// Maximum time between two positions close to the ground for the aircraft to be considered flying between them
+    pub max_time_gap: time::Duration,
+    /// Minimum duration of a leg; shorter legs are ignored as noise
+    pub min_duration: time::Duration,
+    /// Minimum great-circle distance of a leg in km; shorter legs are ignored as noise
+    pub min_distance: f64,
+    /// Height above the ground in feet below which a position is close to the ground (see `max_time_gap`)
+    pub landing_altitude_threshold: f64,
+    /// Ground speed in km/h above which an aircraft on the ground is on its takeoff roll or landing rollout
+    /// instead of taxiing
+    pub max_taxi_speed: f64,
+    /// Ground speed in km/h below which an aircraft on the ground is stationary
+    pub stationary_speed: f64,
+    /// Minimum time stationary on the ground for an aircraft to be parked, which ends a taxi-in and
+    /// starts a taxi-out
+    pub min_parked: time::Duration,
 }
 
-fn grounded_heuristic(previous_position: &Position, position: &Position) -> bool {
+impl Default for LegsConfig {
+    fn default() -> Self {
+        Self {
+            min_ground_speed: 0.0,
+            max_time_gap: time::Duration::minutes(5),
+            min_duration: time::Duration::minutes(5),
+            min_distance: 3.0,
+            landing_altitude_threshold: 10000.0,
+            max_taxi_speed: 60.0,
+            stationary_speed: 5.0,
+            min_parked: time::Duration::minutes(10),
+        }
+    }
+}
+
+/// Returns the height above ground in feet of `position` given the elevation of the ground
+fn height(position: &Position, elevation: &impl Fn(&Position) -> f64) -> f64 {
+    position.altitude() - elevation(position)
+}
+
+fn grounded_heuristic(
+    previous_position: &Position,
+    position: &Position,
+    elevation: &impl Fn(&Position) -> f64,
+    config: &LegsConfig,
+) -> bool {
     let is_flying = previous_position.flying() || position.flying();
     if !is_flying {
         return false;
     }
+    let threshold = config.landing_altitude_threshold;
     let lost_close_to_ground = position.datetime() - previous_position.datetime()
-        > time::Duration::minutes(5)
-        && (position.altitude() < 10000.0 || previous_position.altitude() < 10000.0);
+        > config.max_time_gap
+        && (height(position, elevation) < threshold
+            || height(previous_position, elevation) < threshold);
 
     // lost signal for more than 10h => assume it landed somewhere
     let lost_somewhere =
@@ -56,75 +178,270 @@ fn grounded_heuristic(previous_position: &Position, position: &Position) -> bool
 }
 
 /// Implementation of the definition of landed in [M-identify-legs](../methodology.md).
-fn landed(previous_position: &Position, position: &Position) -> bool {
+fn landed(
+    previous_position: &Position,
+    position: &Position,
+    elevation: &impl Fn(&Position) -> f64,
+    config: &LegsConfig,
+) -> bool {
     (previous_position.flying() && position.grounded())
-        || grounded_heuristic(previous_position, position)
+        || grounded_heuristic(previous_position, position, elevation, config)
 }
 
-fn is_grounded(previous_position: &Position, position: &Position) -> bool {
+fn is_grounded(
+    previous_position: &Position,
+    position: &Position,
+    elevation: &impl Fn(&Position) -> f64,
+    config: &LegsConfig,
+) -> bool {
     (previous_position.grounded() && position.grounded())
-        || grounded_heuristic(previous_position, position)
+        || grounded_heuristic(previous_position, position, elevation, config)
+}
+
+/// The ground speed in km/h from `a` to `b` (zero when they have the same timestamp)
+fn ground_speed(a: &Position, b: &Position) -> f64 {
+    let hours = (b.datetime() - a.datetime()).as_seconds_f64() / 60.0 / 60.0;
+    if hours > 0.0 {
+        a.distace(b) / hours
+    } else {
+        0.0
+    }
+}
+
+/// Returns the index of the start of the takeoff roll in `ground`, the positions on the ground before
+/// a takeoff: the first of the last positions moving faster than [`LegsConfig::max_taxi_speed`].
+fn takeoff_roll(ground: &[Position], config: &LegsConfig) -> usize {
+    let mut start = ground.len().saturating_sub(1);
+    while start > 0 && ground_speed(&ground[start - 1], &ground[start]) > config.max_taxi_speed {
+        start -= 1;
+    }
+    start
+}
+
+/// Returns `leg` ended at the end of its landing rollout and with its taxi-in, given `ground`, the positions
+/// on the ground since its landing (the first being the last position of `leg`).
+fn with_taxi_in(mut leg: Leg, ground: &[Position], config: &LegsConfig) -> Leg {
+    if ground.is_empty() {
+        return leg;
+    }
+    let mut end = 0;
+    while end + 1 < ground.len()
+        && ground_speed(&ground[end], &ground[end + 1]) > config.max_taxi_speed
+    {
+        end += 1;
+    }
+    leg.positions.extend_from_slice(&ground[1..=end]);
+    // until the last movement before it parked
+    let taxi_end = (end + 1..ground.len())
+        .rev()
+        .find(|i| ground_speed(&ground[i - 1], &ground[*i]) > config.stationary_speed)
+        .unwrap_or(end);
+    leg.taxi_in = ground[taxi_end].datetime() - ground[end].datetime();
+    leg
 }
 
 /// Iterator returning [`Leg`] computed according to the [methodology `M-identify-legs`](../methodology.md).
-pub struct Legs<I: Iterator<Item = Position>> {
+pub struct Legs<I: Iterator<Item = Position>, E: Fn(&Position) -> f64> {
     positions: I,
     previous_position: Position,
     sequence: Vec<Position>,
+    /// the taxi-out of the leg in `sequence`
+    taxi_out: time::Duration,
+    /// the positions on the ground since the aircraft landed or was last parked
+    ground: Vec<Position>,
+    /// since when the aircraft is stationary on the ground
+    stationary_since: Option<time::OffsetDateTime>,
+    /// the last leg, whose landing rollout and taxi-in are still being observed
+    landed: Option<Leg>,
+    /// the elevation of the ground in feet at a position
+    elevation: E,
+    config: LegsConfig,
 }
 
-impl<I: Iterator<Item = Position>> Legs<I> {
-    fn new(mut positions: I) -> Self {
+impl<I: Iterator<Item = Position>> Legs<I, fn(&Position) -> f64> {
+    #[cfg(test)]
+    fn new(positions: I) -> Self {
+        Legs::new_with_elevation(positions, |_| 0.0, Default::default())
+    }
+}
+
+impl<I: Iterator<Item = Position>, E: Fn(&Position) -> f64> Legs<I, E> {
+    fn new_with_elevation(mut positions: I, elevation: E, config: LegsConfig) -> Self {
         let previous_position = positions.next().unwrap_or(Position {
             datetime: time::OffsetDateTime::from_unix_timestamp(0).unwrap(),
             latitude: 0.0,
             longitude: 0.0,
             altitude: None,
+            altitude_source: None,
         });
         Self {
             positions,
             sequence: vec![],
+            taxi_out: time::Duration::ZERO,
+            ground: previous_position
+                .grounded()
+                .then(|| previous_position.clone())
+                .into_iter()
+                .collect(),
+            stationary_since: None,
+            landed: None,
             previous_position,
+            elevation,
+            config,
         }
     }
+
+    /// Returns the leg that landed, with its taxi-in observed in the positions on the ground since then,
+    /// and restarts the positions on the ground from `position`
+    fn park(&mut self, position: &Position) -> Option<Leg> {
+        let leg = self
+            .landed
+            .take()
+            .map(|leg| with_taxi_in(leg, &self.ground, &self.config));
+        self.ground = position
+            .grounded()
+            .then(|| position.clone())
+            .into_iter()
+            .collect();
+        self.stationary_since = None;
+        leg
+    }
+
+    /// Processes `position` after `self.previous_position`, returning a leg when it is complete
+    fn step(&mut self, position: &Position) -> Option<Leg> {
+        let previous_position = &self.previous_position.clone();
+        let landed = landed(previous_position, position, &self.elevation, &self.config);
+        if !is_grounded(previous_position, position, &self.elevation, &self.config) {
+            // it is flying -> add it to the sequence
+            let mut leg = None;
+            if self.sequence.is_empty() {
+                if previous_position.grounded() {
+                    // it took off: the sequence starts at the takeoff roll
+                    let start = takeoff_roll(&self.ground, &self.config);
+                    let taxi_start = (0..start)
+                        .find(|i| {
+                            ground_speed(&self.ground[*i], &self.ground[i + 1])
+                                > self.config.stationary_speed
+                        })
+                        .unwrap_or(start);
+                    // without parking since the last landing, the movement on the ground is its taxi-in
+                    self.taxi_out = match self.landed {
+                        Some(_) => time::Duration::ZERO,
+                        None => self.ground[start].datetime() - self.ground[taxi_start].datetime(),
+                    };
+                    self.sequence.extend_from_slice(&self.ground[start..]);
+                    self.ground.truncate(start + 1);
+                } else {
+                    self.taxi_out = time::Duration::ZERO;
+                    self.sequence.push(previous_position.clone());
+                }
+                leg = self.park(position);
+            }
+            self.sequence.push(position.clone());
+            if landed {
+                // it landed: its rollout and taxi-in are observed in the next positions
+                self.landed = Some(Leg {
+                    positions: std::mem::take(&mut self.sequence),
+                    taxi_out: self.taxi_out,
+                    taxi_in: time::Duration::ZERO,
+                });
+                self.ground = vec![position.clone()];
+            }
+            return leg;
+        }
+
+        if landed {
+            // it landed out of coverage (see `grounded_heuristic`)
+            let leg = self.park(position);
+            if self.sequence.is_empty() {
+                return leg;
+            }
+            return Some(Leg {
+                positions: std::mem::take(&mut self.sequence),
+                taxi_out: self.taxi_out,
+                taxi_in: time::Duration::ZERO,
+            });
+        }
+
+        // it is on the ground
+        if position.datetime() - previous_position.datetime() > self.config.max_time_gap {
+            // lost signal on the ground => assume it parked
+            return self.park(position);
+        }
+        if ground_speed(previous_position, position) < self.config.stationary_speed {
+            let since = *self
+                .stationary_since
+                .get_or_insert(previous_position.datetime());
+            if position.datetime() - since >= self.config.min_parked {
+                self.ground.push(position.clone());
+                let leg = self.park(position);
+                self.stationary_since = Some(since);
+                return leg;
+            }
+        } else {
+            self.stationary_since = None;
+        }
+        self.ground.push(position.clone());
+        None
+    }
 }
 
-impl<I: Iterator<Item = Position>> Iterator for Legs<I> {
+impl<I: Iterator<Item = Position>, E: Fn(&Position) -> f64> Iterator for Legs<I, E> {
     type Item = Leg;
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(position) = self.positions.next() {
-            if !is_grounded(&self.previous_position, &position) {
-                // it is flying -> add it to the sequence
-                if self.sequence.is_empty() {
-                    self.sequence.push(self.previous_position.clone());
-                }
-                self.sequence.push(position.clone());
-            }
-            if landed(&self.previous_position, &position) {
-                if !self.sequence.is_empty() {
-                    self.previous_position = position;
-                    return Some(Leg {
-                        positions: std::mem::take(&mut self.sequence),
-                    });
-                };
-            }
+            let leg = self.step(&position);
             self.previous_position = position;
+            if leg.is_some() {
+                return leg;
+            }
         }
-        (!self.sequence.is_empty()).then_some(Leg {
+        if let Some(leg) = self.landed.take() {
+            return Some(with_taxi_in(leg, &self.ground, &self.config));
+        }
+        (!self.sequence.is_empty()).then(|| Leg {
             positions: std::mem::take(&mut self.sequence),
+            taxi_out: self.taxi_out,
+            taxi_in: time::Duration::ZERO,
         })
     }
 }
 
 /// Returns a set of [`Leg`]s from a sequence of [`Position`]s according
 /// to the [methodology `M-identify-legs`](../methodology.md).
+/// The altitude of positions is assumed to be relative to the sea level.
 pub fn legs(positions: impl Iterator<Item = Position>) -> impl Iterator<Item = Leg> {
-    Legs::new(positions)
+    legs_with_elevation(positions, |_| 0.0)
+}
+
+/// Returns a set of [`Leg`]s from a sequence of [`Position`]s according
+/// to the [methodology `M-identify-legs`](../methodology.md), where `elevation` returns the
+/// elevation of the ground (in feet) at a position, e.g. [`crate::airports::Airports::elevation`].
+pub fn legs_with_elevation(
+    positions: impl Iterator<Item = Position>,
+    elevation: impl Fn(&Position) -> f64,
+) -> impl Iterator<Item = Leg> {
+    legs_with_config(positions, elevation, Default::default())
+}
+
+/// Same as [`legs_with_elevation`], with the thresholds of the heuristics in `config`
+/// instead of their defaults.
+pub fn legs_with_config(
+    positions: impl Iterator<Item = Position>,
+    elevation: impl Fn(&Position) -> f64,
+    config: LegsConfig,
+) -> impl Iterator<Item = Leg> {
+    Legs::new_with_elevation(positions, elevation, config)
         // ignore legs that are too fast, as they are likely noise
-        .filter(|leg| leg.duration() > time::Duration::minutes(5))
+        .filter(move |leg| leg.duration() > config.min_duration)
         // ignore legs that are too short, as they are likely noise
-        .filter(|leg| leg.great_circle_distance() > 3.0)
+        .filter(move |leg| leg.great_circle_distance() > config.min_distance)
+        // ignore legs that are too slow, as they are likely noise
+        .filter(move |leg| {
+            let hours = leg.duration().as_seconds_f64() / 60.0 / 60.0;
+            leg.distance() >= config.min_ground_speed * hours
+        })
 }
 
 #[cfg(test)]
@@ -133,7 +450,74 @@ mod test {
 
     #[test]
     fn positions() {
-        assert_eq!(Leg { positions: vec![] }.positions(), &[]);
+        assert_eq!(Leg::new(vec![]).positions(), &[]);
+    }
+
+    #[test]
+    fn altitude_profile() {
+        let pos = |(t, altitude): (i64, Option<f64>)| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude,
+            altitude_source: None,
+        };
+        let leg = Leg::new(
+            [
+                (0, None),
+                (100, Some(10000.0)),
+                (300, Some(10000.0)),
+                (400, None),
+            ]
+            .into_iter()
+            .map(pos)
+            .collect(),
+        );
+        assert_eq!(
+            leg.altitude_profile(5),
+            vec![0.0, 10000.0, 10000.0, 10000.0, 0.0]
+        );
+        assert_eq!(leg.altitude_profile(9)[1], 5000.0);
+        assert_eq!(leg.altitude_profile(1), vec![0.0]);
+    }
+
+    #[test]
+    fn circuity() {
+        let pos = |(latitude, longitude): (f64, f64)| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(0).unwrap(),
+            latitude,
+            longitude,
+            altitude: None,
+            altitude_source: None,
+        };
+        let leg = |positions: Vec<(f64, f64)>| Leg::new(positions.into_iter().map(pos).collect());
+        assert_eq!(leg(vec![(0.0, 0.0), (0.0, 1.0)]).circuity(), Some(1.0));
+        let detour = leg(vec![(0.0, 0.0), (1.0, 0.5), (0.0, 1.0)]).circuity();
+        assert!(detour.unwrap() > 2.0);
+        assert_eq!(
+            leg(vec![(0.0, 0.0), (1.0, 0.0), (0.0, 0.0)]).circuity(),
+            None
+        );
+    }
+
+    #[test]
+    fn midpoint_and_bearing() {
+        let pos = |(latitude, longitude): (f64, f64)| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(0).unwrap(),
+            latitude,
+            longitude,
+            altitude: None,
+            altitude_source: None,
+        };
+        let leg = |positions: Vec<(f64, f64)>| Leg::new(positions.into_iter().map(pos).collect());
+        let east = leg(vec![(0.0, 0.0), (1.0, 1.0), (0.0, 10.0)]);
+        let (latitude, longitude) = east.midpoint();
+        assert!(latitude.abs() < 1e-9 && (longitude - 5.0).abs() < 1e-9);
+        assert!((east.initial_bearing().unwrap() - 90.0).abs() < 1e-9);
+
+        let west = leg(vec![(0.0, 0.0), (0.0, -10.0)]);
+        assert!((west.initial_bearing().unwrap() - 270.0).abs() < 1e-9);
+        assert_eq!(leg(vec![(1.0, 1.0), (1.0, 1.0)]).initial_bearing(), None);
     }
 
     #[test]
@@ -147,6 +531,7 @@ mod test {
             latitude: 0.0,
             longitude: 0.0,
             altitude,
+            altitude_source: None,
         };
 
         let legs = Legs::new(positions.into_iter().map(pos)).collect::<Vec<_>>();
@@ -155,9 +540,7 @@ mod test {
             legs,
             expected
                 .into_iter()
-                .map(|positions| Leg {
-                    positions: positions.into_iter().map(pos).collect()
-                })
+                .map(|positions| Leg::new(positions.into_iter().map(pos).collect()))
                 .collect::<Vec<_>>()
         );
     }
@@ -220,6 +603,87 @@ mod test {
         );
     }
 
+    #[test]
+    fn high_elevation_and_5m_is_new_leg() {
+        // > 10k feet above sea level, but < 10k feet above the ground
+        let alt = 12000f64;
+        let pos = |(t, altitude): (i64, Option<f64>)| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude: 0.0,
+            longitude: 0.0,
+            altitude,
+            altitude_source: None,
+        };
+        let positions = vec![
+            (0, None),
+            (10, Some(alt)),
+            (10 + 5 * 60 + 1, Some(alt)), // >5m -> new leg
+            (10 + 5 * 60 + 2, Some(alt)),
+        ];
+
+        let legs = Legs::new(positions.clone().into_iter().map(pos));
+        assert_eq!(legs.count(), 1);
+
+        let legs = Legs::new_with_elevation(
+            positions.clone().into_iter().map(pos),
+            |_| 7820.0,
+            Default::default(),
+        );
+        assert_eq!(legs.count(), 2);
+
+        // a lower threshold of landing
+        let config = LegsConfig {
+            landing_altitude_threshold: 4000.0,
+            ..Default::default()
+        };
+        let legs = Legs::new_with_elevation(positions.into_iter().map(pos), |_| 7820.0, config);
+        assert_eq!(legs.count(), 1);
+    }
+
+    #[test]
+    fn config() {
+        let pos = |(t, lon, altitude): (i64, f64, Option<f64>)| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(t).unwrap(),
+            latitude: 0.0,
+            longitude: lon,
+            altitude,
+            altitude_source: None,
+        };
+        // ~55 km in 1 hour, a position every 4 minutes
+        let positions = (0..=15)
+            .map(|i| {
+                let altitude = (i > 0 && i < 15).then_some(2000.0);
+                (i * 240, 0.5 * i as f64 / 15.0, altitude)
+            })
+            .collect::<Vec<_>>();
+        let count = |config: LegsConfig| {
+            legs_with_config(positions.clone().into_iter().map(pos), |_| 0.0, config).count()
+        };
+
+        assert_eq!(count(Default::default()), 1);
+        let slow = LegsConfig {
+            min_ground_speed: 100.0,
+            ..Default::default()
+        };
+        assert_eq!(count(slow), 0);
+        let long = LegsConfig {
+            min_duration: time::Duration::hours(2),
+            ..Default::default()
+        };
+        assert_eq!(count(long), 0);
+        let far = LegsConfig {
+            min_distance: 100.0,
+            ..Default::default()
+        };
+        assert_eq!(count(far), 0);
+        // gaps of 4 minutes close to the ground
+        let gaps = LegsConfig {
+            max_time_gap: time::Duration::minutes(3),
+            ..Default::default()
+        };
+        assert_eq!(count(gaps), 0);
+    }
+
     #[test]
     fn high_and_10h_is_new_leg() {
         // > 10k feet
@@ -243,4 +707,61 @@ mod test {
             ],
         );
     }
+
+    #[test]
+    fn taxi() {
+        // ~1 km/min (60 km/h) is 0.009 degrees of longitude per minute at the equator
+        let pos = |(minutes, lon, altitude): (i64, f64, Option<f64>)| Position {
+            datetime: time::OffsetDateTime::from_unix_timestamp(minutes * 60).unwrap(),
+            latitude: 0.0,
+            longitude: lon,
+            altitude,
+            altitude_source: None,
+        };
+        let positions = vec![
+            // parked
+            (0, 0.0, None),
+            (15, 0.0, None),
+            // taxi-out of 10 minutes at ~13 km/h
+            (20, 0.01, None),
+            (25, 0.02, None),
+            // takeoff roll at ~130 km/h
+            (26, 0.04, None),
+            (27, 0.06, Some(1000.0)),
+            (30, 0.3, Some(12000.0)),
+            (60, 3.0, Some(30000.0)),
+            (87, 5.7, Some(12000.0)),
+            (90, 6.0, Some(1000.0)),
+            // landing rollout
+            (91, 6.02, None),
+            (92, 6.04, None),
+            // taxi-in of 4 minutes
+            (94, 6.045, None),
+            (96, 6.05, None),
+            // parked
+            (120, 6.05, None),
+        ];
+        let legs = Legs::new(positions.into_iter().map(pos)).collect::<Vec<_>>();
+        assert_eq!(legs.len(), 1);
+        let leg = &legs[0];
+        assert_eq!(leg.from(), &pos((25, 0.02, None)));
+        assert_eq!(leg.to(), &pos((92, 6.04, None)));
+        assert_eq!(leg.taxi_out(), time::Duration::minutes(10));
+        assert_eq!(leg.taxi_in(), time::Duration::minutes(4));
+
+        // a leg starting flying has no taxi-out
+        let leg = Legs::new(
+            [
+                (0, 0.0, Some(30000.0)),
+                (30, 3.0, Some(1000.0)),
+                (31, 3.02, None),
+            ]
+            .into_iter()
+            .map(pos),
+        )
+        .next()
+        .unwrap();
+        assert_eq!(leg.taxi_out(), time::Duration::ZERO);
+        assert_eq!(leg.taxi_in(), time::Duration::ZERO);
+    }
 }
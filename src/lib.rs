@@ -1,19 +1,88 @@
-#[forbid(unsafe_code)]
+// unsafe code is only allowed in `mmap` (memory-mapped files); `forbid` cannot be relaxed by a module
+#![cfg_attr(not(feature = "mmap"), forbid(unsafe_code))]
+#![cfg_attr(feature = "mmap", deny(unsafe_code))]
+pub mod access_log;
+pub mod activity;
 pub mod aircraft;
+pub mod airframes;
+pub mod airport_watch;
+pub mod airports;
+pub mod altitude;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+pub mod backfill;
+pub mod bootstrap;
+pub mod checkpoint;
+pub mod commercial;
+pub mod compression;
 pub(crate) mod country;
 pub mod csv;
+pub mod dataset;
+pub mod descriptor;
+pub mod diff;
 pub mod emissions;
+pub mod enrich;
+mod error;
+pub mod etl;
+pub mod events;
+#[cfg(feature = "kafka")]
+pub mod events_kafka;
+#[cfg(feature = "nats")]
+pub mod events_nats;
+pub mod events_webhook;
+pub mod exclusion;
+pub mod export;
+pub mod fleet;
+pub mod format;
 pub mod fs;
+pub mod fs_azure;
+pub mod fs_cache;
+pub mod fs_index;
+pub mod fs_local;
 pub mod fs_s3;
+pub mod geo;
+pub mod geojson;
+pub mod ground_times;
 pub mod icao_to_trace;
 pub mod io;
+pub mod legacy;
 pub mod legs;
+pub mod lock;
+pub mod merge_positions;
+#[cfg(feature = "mmap")]
+#[allow(unsafe_code)]
+pub mod mmap;
 pub mod model;
+pub mod overlays;
+pub mod owners;
+#[cfg(feature = "parquet")]
+pub mod parquet;
 mod private_jets_in_time;
+pub mod query;
+pub mod region;
+pub mod registry;
+pub mod replay;
+pub mod retired;
+pub mod runs;
+pub mod sample;
+pub mod schema;
 pub mod serde;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod stats;
+pub mod timezone;
 mod trace_month;
-
-pub use private_jets_in_time::{private_jets_in_month, RequiredTasks};
+pub mod units;
+pub mod validate;
+pub mod weekly;
+pub mod wind;
+
+pub use error::Error;
+pub use merge_positions::{merge_positions, PositionsSource};
+pub use private_jets_in_time::{
+    months_between, months_of_years, private_jets_in_month, private_jets_in_month_of,
+    private_jets_in_month_with_models, RequiredTasks, Swap, Swaps,
+};
 
 /// A position of an aircraft
 #[derive(Debug, Clone, PartialEq, ::serde::Serialize, ::serde::Deserialize)]
@@ -26,8 +95,20 @@ pub struct Position {
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
     altitude: Option<f64>,
+    /// The source of `altitude` (see `M-altitudes`); None means barometric (or on the ground)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    altitude_source: Option<altitude::AltitudeSource>,
 }
 
+crate::schema!(Position {
+    datetime: "When the aircraft was at the position",
+    latitude: "The latitude of the position",
+    longitude: "The longitude of the position",
+    altitude("ft"): "The altitude of the position (null when on the ground)",
+    altitude_source: "The source of the altitude (`geometric` or `qnh-corrected`; null when barometric or on the ground)",
+});
+
 impl Position {
     pub fn flying(&self) -> bool {
         self.altitude.is_some()
@@ -53,6 +134,14 @@ impl Position {
         self.altitude.unwrap_or(0.0)
     }
 
+    /// The source of the altitude, `None` when on the ground
+    pub fn altitude_source(&self) -> Option<altitude::AltitudeSource> {
+        self.flying().then(|| {
+            self.altitude_source
+                .unwrap_or(altitude::AltitudeSource::Barometric)
+        })
+    }
+
     pub fn datetime(&self) -> time::OffsetDateTime {
         self.datetime
     }
@@ -63,11 +152,9 @@ impl Position {
     }
 }
 
-/// Returns the distance between two geo-points in km
+/// Returns the distance between two geo-points in km (see [`geo::haversine`])
 fn distance(from: (f64, f64), to: (f64, f64)) -> f64 {
-    let from = geoutils::Location::new(from.0, from.1);
-    let to = geoutils::Location::new(to.0, to.1);
-    from.haversine_distance_to(&to).meters() / 1000.0
+    geo::haversine(from, to)
 }
 
 /// An iterator between two [`time::Date`]s in increments
@@ -0,0 +1,196 @@
+//! Contains the implementation of a lock (lease) on a blob, so that two runs of a pipeline do not write the
+//! same datasets concurrently.
+//!
+//! The lock is held by writing a [`Lease`] to a blob, and kept by writing its heartbeat periodically.
+//! A lease whose heartbeat is older than a time-to-live is stale (e.g. its run was killed) and can be taken over.
+//! Since the storage has no atomic conditional writes, a lease is read back after being written to detect
+//! another run acquiring it at the same time.
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::fs::BlobStorageProvider;
+
+/// The holder of a lock
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Lease {
+    /// An identifier of the holder (e.g. the run)
+    pub holder: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub acquired: OffsetDateTime,
+    /// When the holder last confirmed it holds the lock
+    #[serde(with = "time::serde::rfc3339")]
+    pub heartbeat: OffsetDateTime,
+}
+
+impl Lease {
+    /// Whether the holder has not confirmed holding the lock for longer than `ttl`
+    pub fn is_stale(&self, now: OffsetDateTime, ttl: time::Duration) -> bool {
+        now - self.heartbeat > ttl
+    }
+}
+
+async fn read(
+    key: &str,
+    client: &(dyn BlobStorageProvider + Sync),
+) -> Result<Option<Lease>, std::io::Error> {
+    client
+        .maybe_get(key)
+        .await?
+        .map(|data| serde_json::from_slice(&data).map_err(std::io::Error::other))
+        .transpose()
+}
+
+async fn write(
+    key: &str,
+    lease: &Lease,
+    client: &(dyn BlobStorageProvider + Sync),
+) -> Result<(), std::io::Error> {
+    let data = serde_json::to_vec(lease).map_err(std::io::Error::other)?;
+    client.put(key, data).await
+}
+
+/// A lock held on a blob
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lock {
+    key: String,
+    holder: String,
+}
+
+impl Lock {
+    /// Acquires the lock at `key` for `holder`.
+    /// A lock held by another holder is only taken over when it is stale (older than `ttl`) or when `steal`.
+    /// # Error
+    /// Errors if the lock is held by another holder, or it cannot be read or written
+    pub async fn acquire(
+        key: &str,
+        holder: String,
+        ttl: time::Duration,
+        steal: bool,
+        client: &(dyn BlobStorageProvider + Sync),
+    ) -> Result<Self, std::io::Error> {
+        let now = OffsetDateTime::now_utc();
+        if let Some(lease) = read(key, client).await? {
+            if lease.is_stale(now, ttl) {
+                log::warn!("taking over stale lock {key} of {}", lease.holder);
+            } else if steal {
+                log::warn!("stealing lock {key} of {}", lease.holder);
+            } else {
+                return Err(std::io::Error::other(format!(
+                    "{key} is held by {} since {} (last heartbeat at {})",
+                    lease.holder, lease.acquired, lease.heartbeat
+                )));
+            }
+        }
+        let lease = Lease {
+            holder: holder.clone(),
+            acquired: now,
+            heartbeat: now,
+        };
+        write(key, &lease, client).await?;
+
+        let lock = Self {
+            key: key.to_string(),
+            holder,
+        };
+        lock.verify(client).await?;
+        Ok(lock)
+    }
+
+    /// Errors if the lock is no longer held by this holder (e.g. it was stolen)
+    async fn verify(
+        &self,
+        client: &(dyn BlobStorageProvider + Sync),
+    ) -> Result<Lease, std::io::Error> {
+        match read(&self.key, client).await? {
+            Some(lease) if lease.holder == self.holder => Ok(lease),
+            Some(lease) => Err(std::io::Error::other(format!(
+                "{} was taken by {}",
+                self.key, lease.holder
+            ))),
+            None => Err(std::io::Error::other(format!(
+                "{} was released by another run",
+                self.key
+            ))),
+        }
+    }
+
+    /// Checks that this holder still holds the lock, e.g. before writing datasets that another run may write
+    /// # Error
+    /// Errors if the lock is no longer held by this holder, or it cannot be read
+    pub async fn check(
+        &self,
+        client: &(dyn BlobStorageProvider + Sync),
+    ) -> Result<(), std::io::Error> {
+        self.verify(client).await.map(|_| ())
+    }
+
+    /// Confirms that this holder still holds the lock
+    /// # Error
+    /// Errors if the lock is no longer held by this holder, or it cannot be read or written
+    pub async fn heartbeat(
+        &self,
+        client: &(dyn BlobStorageProvider + Sync),
+    ) -> Result<(), std::io::Error> {
+        let mut lease = self.verify(client).await?;
+        lease.heartbeat = OffsetDateTime::now_utc();
+        write(&self.key, &lease, client).await
+    }
+
+    /// Releases the lock, unless it is no longer held by this holder
+    /// # Error
+    /// Errors if the lock cannot be read or deleted
+    pub async fn release(
+        self,
+        client: &(dyn BlobStorageProvider + Sync),
+    ) -> Result<(), std::io::Error> {
+        match self.verify(client).await {
+            Ok(_) => client.delete(&self.key).await,
+            Err(e) => {
+                log::warn!("{e}");
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn work() {
+        let root = std::env::temp_dir().join("test_lock");
+        let _ = std::fs::remove_dir_all(&root);
+        let client = crate::fs_local::LocalDisk::new(&root);
+        let ttl = time::Duration::minutes(10);
+
+        let lock = Lock::acquire("lock.json", "a".to_string(), ttl, false, &client)
+            .await
+            .unwrap();
+        assert!(
+            Lock::acquire("lock.json", "b".to_string(), ttl, false, &client)
+                .await
+                .is_err()
+        );
+        lock.heartbeat(&client).await.unwrap();
+
+        // a stale lock is taken over (every lock is stale with a negative time-to-live)
+        let stale = time::Duration::seconds(-1);
+        let stolen = Lock::acquire("lock.json", "b".to_string(), stale, false, &client)
+            .await
+            .unwrap();
+        assert!(lock.heartbeat(&client).await.is_err());
+        assert!(lock.check(&client).await.is_err());
+        stolen.check(&client).await.unwrap();
+        // releasing a lock no longer held keeps the lock of the new holder
+        lock.release(&client).await.unwrap();
+        assert!(client.maybe_get("lock.json").await.unwrap().is_some());
+
+        let lock = Lock::acquire("lock.json", "c".to_string(), ttl, true, &client)
+            .await
+            .unwrap();
+        assert!(stolen.heartbeat(&client).await.is_err());
+        lock.release(&client).await.unwrap();
+        assert!(client.maybe_get("lock.json").await.unwrap().is_none());
+    }
+}
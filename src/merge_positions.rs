@@ -0,0 +1,447 @@
+//! Contains the implementation of the merge of the positions of an aircraft on a month from more than one
+//! source of ADS-B positions (`M-merge-positions`), to fill the holes in the coverage of each source, and of the
+//! selection of a single source with failover (`M-source-selection`).
+use std::{collections::BTreeMap, sync::Arc};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{altitude::Preference, fs::BlobStorageProvider, Position};
+
+/// Positions of different sources closer in time than this (in seconds) are the same position
+pub static DUPLICATE_SECONDS: f64 = 1.0;
+
+/// Positions of different sources implying a speed above this (in km/h) between them are in conflict
+/// (see [`crate::validate::MACH_1`])
+pub static CONFLICT_SPEED: f64 = crate::validate::MACH_1;
+
+/// A source of the positions of aircrafts
+#[async_trait]
+pub trait PositionsSource: Sync {
+    /// The name of the source, for logging
+    fn name(&self) -> &str;
+    /// Returns the positions of `icao_number` on the month starting at `month`, ordered by timestamp
+    async fn month_positions(
+        &self,
+        icao_number: &str,
+        month: Date,
+    ) -> Result<Vec<Position>, std::io::Error>;
+}
+
+/// The positions from `https://globe.adsbexchange.com`, cached in `client` (see `M-daily-adsb`)
+pub struct Adsbexchange<'a> {
+    pub client: &'a dyn BlobStorageProvider,
+    /// The altitude of the positions fetched (see `M-altitudes`)
+    pub altitude: Preference,
+}
+
+#[async_trait]
+impl PositionsSource for Adsbexchange<'_> {
+    fn name(&self) -> &str {
+        "adsbexchange"
+    }
+
+    async fn month_positions(
+        &self,
+        icao_number: &str,
+        month: Date,
+    ) -> Result<Vec<Position>, std::io::Error> {
+        crate::icao_to_trace::month_positions_with(icao_number, month, self.altitude, self.client)
+            .await
+    }
+}
+
+/// A database of positions in the layout of `M-daily-adsb` (`position/icao_number={icao}/month={month}/data.json`),
+/// e.g. of historical dumps of [adsb.lol](https://adsb.lol) converted to it.
+/// Months without positions in the database have no positions.
+pub struct Stored<'a> {
+    pub name: String,
+    pub client: &'a dyn BlobStorageProvider,
+}
+
+#[async_trait]
+impl PositionsSource for Stored<'_> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn month_positions(
+        &self,
+        icao_number: &str,
+        month: Date,
+    ) -> Result<Vec<Position>, std::io::Error> {
+        let blob_name = crate::trace_month::pk_to_blob_name(icao_number, month);
+        let Some(data) = self.client.maybe_get(&blob_name).await? else {
+            return Ok(vec![]);
+        };
+        Ok(serde_json::from_slice(&data)?)
+    }
+}
+
+/// Returns whether `a` and `b`, of different sources, are either the same position or in conflict
+fn overlaps(a: &Position, b: &Position) -> bool {
+    let seconds = (b.datetime() - a.datetime()).as_seconds_f64().abs();
+    seconds < DUPLICATE_SECONDS || a.distace(b) / (seconds / 60.0 / 60.0) > CONFLICT_SPEED
+}
+
+/// Returns the positions of `sources`, ordered by timestamp, with `sources` in order of priority (first is highest).
+/// # Implementation
+/// Positions are merged by timestamp. A position overlapping (see [`DUPLICATE_SECONDS`] and [`CONFLICT_SPEED`])
+/// the previous position, of another source, is the same position or in conflict with it, and only the position of
+/// the source with the highest priority is kept.
+pub fn merge(sources: Vec<Vec<Position>>) -> Vec<Position> {
+    let mut positions = sources
+        .into_iter()
+        .enumerate()
+        .flat_map(|(priority, positions)| positions.into_iter().map(move |p| (priority, p)))
+        .collect::<Vec<_>>();
+    positions.sort_by(|a, b| a.1.datetime().cmp(&b.1.datetime()).then(a.0.cmp(&b.0)));
+
+    let mut merged = Vec::<(usize, Position)>::with_capacity(positions.len());
+    for (priority, position) in positions {
+        match merged.last_mut() {
+            Some((last_priority, last))
+                if *last_priority != priority && overlaps(last, &position) =>
+            {
+                if priority < *last_priority {
+                    *last_priority = priority;
+                    *last = position;
+                }
+            }
+            _ => merged.push((priority, position)),
+        }
+    }
+    merged.into_iter().map(|(_, position)| position).collect()
+}
+
+/// Returns the positions of `icao_number` on the month starting at `month` of all `sources`, in order of priority
+/// (first is highest), merged with [`merge`] according to `M-merge-positions`.
+pub async fn merge_positions(
+    sources: &[&dyn PositionsSource],
+    icao_number: &str,
+    month: Date,
+) -> Result<Vec<Position>, std::io::Error> {
+    let positions = futures::future::try_join_all(
+        sources
+            .iter()
+            .map(|source| source.month_positions(icao_number, month)),
+    )
+    .await?;
+    for (source, positions) in sources.iter().zip(positions.iter()) {
+        log::info!(
+            "{icao_number},{month}: {} positions from {}",
+            positions.len(),
+            source.name()
+        );
+    }
+    Ok(merge(positions))
+}
+
+/// The record of the source that served the positions of an aircraft on a month (see `M-source-selection`)
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Served {
+    /// The name of the source (see [`PositionsSource::name`])
+    pub source: String,
+    /// The number of positions served
+    pub positions: usize,
+    /// The time it took the source to serve them, in milliseconds
+    pub latency_ms: u64,
+    /// The sources tried before it, and why they did not serve the positions
+    pub failed: Vec<(String, String)>,
+}
+
+/// The database of the sources that served each partition of positions
+static SERVED: &str = "position_source/";
+
+/// Returns the blob name of the [`Served`] of the positions of `icao_number` on `month`
+pub fn served_pk_to_blob_name(icao_number: &str, month: Date) -> String {
+    let month = crate::serde::month_to_part(month);
+    format!("{SERVED}icao_number={icao_number}/month={month}/source.json")
+}
+
+/// Blob name of the [`Coverage`] of all runs
+pub fn coverage_blob_name() -> String {
+    format!("{SERVED}coverage.json")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+struct Stats {
+    months: usize,
+    positions: usize,
+    latency_ms: u64,
+}
+
+impl Stats {
+    fn add(&mut self, served: &Served) {
+        self.months += 1;
+        self.positions += served.positions;
+        self.latency_ms += served.latency_ms;
+    }
+
+    /// The number of positions served per month, and the mean latency
+    fn score(&self) -> (f64, u64) {
+        match self.months {
+            0 => (0.0, u64::MAX),
+            months => (
+                self.positions as f64 / months as f64,
+                self.latency_ms / months as u64,
+            ),
+        }
+    }
+}
+
+/// The historical coverage of each source, of each aircraft and overall, used to select the source of the positions
+/// of an aircraft on a month (see `M-source-selection`)
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Coverage {
+    /// source -> icao_number -> stats
+    aircrafts: BTreeMap<String, BTreeMap<Arc<str>, Stats>>,
+    /// source -> stats
+    sources: BTreeMap<String, Stats>,
+}
+
+impl Coverage {
+    /// Adds `served` of the positions of `icao_number`
+    pub fn add(&mut self, icao_number: &str, served: &Served) {
+        self.aircrafts
+            .entry(served.source.clone())
+            .or_default()
+            .entry(icao_number.into())
+            .or_default()
+            .add(served);
+        self.sources
+            .entry(served.source.clone())
+            .or_default()
+            .add(served);
+    }
+
+    /// Returns `sources` ordered by their coverage of `icao_number`, best first.
+    /// # Implementation
+    /// Sources are ordered by the number of positions per month they served of `icao_number` or, when no source
+    /// served it before, of all aircrafts, and then by their mean latency. Sources on a tie keep their order.
+    pub fn order<'a>(
+        &self,
+        sources: &[&'a dyn PositionsSource],
+        icao_number: &str,
+    ) -> Vec<&'a dyn PositionsSource> {
+        let of_aircraft = |source: &dyn PositionsSource| {
+            self.aircrafts
+                .get(source.name())
+                .and_then(|aircrafts| aircrafts.get(icao_number))
+                .copied()
+        };
+        let known = sources.iter().any(|source| of_aircraft(*source).is_some());
+        let score = |source: &dyn PositionsSource| {
+            let stats = if known {
+                of_aircraft(source)
+            } else {
+                self.sources.get(source.name()).copied()
+            };
+            stats.unwrap_or_default().score()
+        };
+        let mut sources = sources.to_vec();
+        sources.sort_by(|a, b| {
+            let (a, b) = (score(*a), score(*b));
+            b.0.total_cmp(&a.0).then(a.1.cmp(&b.1))
+        });
+        sources
+    }
+
+    /// Returns the [`Coverage`] in `client`, empty when it does not exist
+    pub async fn read(client: &dyn BlobStorageProvider) -> Result<Self, std::io::Error> {
+        let Some(data) = client.maybe_get(&coverage_blob_name()).await? else {
+            return Ok(Self::default());
+        };
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Writes the [`Coverage`] to `client`
+    pub async fn write(&self, client: &dyn BlobStorageProvider) -> Result<(), std::io::Error> {
+        client
+            .put(&coverage_blob_name(), serde_json::to_vec(self)?)
+            .await
+    }
+}
+
+/// Returns the positions of `icao_number` on the month starting at `month` from the first of `sources` that serves
+/// them (e.g. ordered by [`Coverage::order`]), and the [`Served`] record of the source that served them.
+/// # Implementation
+/// A source that errors or has no positions fails over to the next source. When no source has positions, the
+/// (empty) positions of the first source that did not error are returned.
+/// # Errors
+/// The error of the last source when all sources error
+pub async fn failover_positions(
+    sources: &[&dyn PositionsSource],
+    icao_number: &str,
+    month: Date,
+) -> Result<(Vec<Position>, Served), std::io::Error> {
+    let mut failed = vec![];
+    let mut empty = None;
+    let mut error = None;
+    for source in sources {
+        let start = std::time::Instant::now();
+        let result = source.month_positions(icao_number, month).await;
+        let served = |positions: &Vec<Position>, failed| Served {
+            source: source.name().to_string(),
+            positions: positions.len(),
+            latency_ms: start.elapsed().as_millis() as u64,
+            failed,
+        };
+        match result {
+            Ok(positions) if !positions.is_empty() => {
+                let served = served(&positions, failed);
+                return Ok((positions, served));
+            }
+            Ok(positions) => {
+                log::warn!("{icao_number},{month}: no positions from {}", source.name());
+                failed.push((source.name().to_string(), "no positions".to_string()));
+                empty = empty.or_else(|| Some((served(&positions, vec![]), positions)));
+            }
+            Err(e) => {
+                log::warn!("{icao_number},{month}: {} failed: {e}", source.name());
+                failed.push((source.name().to_string(), e.to_string()));
+                error = Some(e);
+            }
+        }
+    }
+    match (empty, error) {
+        (Some((mut served, positions)), _) => {
+            failed.retain(|(name, _)| *name != served.source);
+            served.failed = failed;
+            Ok((positions, served))
+        }
+        (None, Some(e)) => Err(e),
+        (None, None) => Err(std::io::Error::other("there are no sources of positions")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn position(datetime: time::OffsetDateTime, longitude: f64) -> Position {
+        Position {
+            datetime,
+            latitude: 65.0,
+            longitude,
+            altitude: Some(35000.0),
+            altitude_source: None,
+        }
+    }
+
+    #[test]
+    fn work() {
+        let primary = vec![
+            position(datetime!(2023-01-01 10:00:00 UTC), -40.0),
+            position(datetime!(2023-01-01 10:01:00 UTC), -39.9),
+            // hole over Greenland
+            position(datetime!(2023-01-01 12:00:00 UTC), -20.0),
+        ];
+        let secondary = vec![
+            // duplicate
+            position(datetime!(2023-01-01 10:00:00.5 UTC), -40.0),
+            // fills the hole
+            position(datetime!(2023-01-01 11:00:00 UTC), -30.0),
+            // in conflict (1 second and ~50 km apart)
+            position(datetime!(2023-01-01 12:00:01 UTC), -19.0),
+        ];
+
+        let merged = merge(vec![primary.clone(), secondary.clone()]);
+        assert_eq!(
+            merged,
+            vec![
+                primary[0].clone(),
+                primary[1].clone(),
+                secondary[1].clone(),
+                primary[2].clone(),
+            ]
+        );
+
+        // the priority resolves conflicts
+        let merged = merge(vec![secondary.clone(), primary.clone()]);
+        assert_eq!(
+            merged,
+            vec![
+                secondary[0].clone(),
+                primary[1].clone(),
+                secondary[1].clone(),
+                secondary[2].clone(),
+            ]
+        );
+
+        // a single source is unchanged
+        assert_eq!(merge(vec![primary.clone()]), primary);
+    }
+
+    struct Fixed {
+        name: &'static str,
+        positions: Option<Vec<Position>>,
+    }
+
+    #[async_trait]
+    impl PositionsSource for Fixed {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn month_positions(&self, _: &str, _: Date) -> Result<Vec<Position>, std::io::Error> {
+            self.positions
+                .clone()
+                .ok_or_else(|| std::io::Error::other("unavailable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn failover() {
+        let month = time::macros::date!(2023 - 01 - 01);
+        let positions = vec![position(datetime!(2023-01-01 10:00:00 UTC), -40.0)];
+        let down = Fixed {
+            name: "a",
+            positions: None,
+        };
+        let empty = Fixed {
+            name: "b",
+            positions: Some(vec![]),
+        };
+        let up = Fixed {
+            name: "c",
+            positions: Some(positions.clone()),
+        };
+        let sources: [&dyn PositionsSource; 3] = [&down, &empty, &up];
+
+        let (result, served) = failover_positions(&sources, "459cd3", month).await.unwrap();
+        assert_eq!(result, positions);
+        assert_eq!(served.source, "c");
+        let failed = served
+            .failed
+            .iter()
+            .map(|x| x.0.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(failed, vec!["a", "b"]);
+
+        // the source with the best coverage of the aircraft is tried first
+        let mut coverage = Coverage::default();
+        coverage.add("459cd3", &served);
+        let names = |icao_number| {
+            coverage
+                .order(&sources, icao_number)
+                .iter()
+                .map(|x| x.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("459cd3"), vec!["c", "a", "b"]);
+        // and of all aircrafts for aircrafts without history
+        assert_eq!(names("45d2ed"), vec!["c", "a", "b"]);
+
+        // the empty positions of a source when no source has positions
+        let (result, served) = failover_positions(&[&down, &empty], "459cd3", month)
+            .await
+            .unwrap();
+        assert!(result.is_empty());
+        assert_eq!(served.source, "b");
+        assert!(failover_positions(&[&down], "459cd3", month).await.is_err());
+    }
+}
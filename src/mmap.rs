@@ -0,0 +1,328 @@
+//! Contains a reader of a local copy of the public dataset of legs (e.g. synced from the remote storage)
+//! that memory-maps its files and parses legs borrowing from the mapped bytes, for repeated local analysis
+//! without copying nor allocating each row as [`crate::csv`] does.
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use memmap2::Mmap;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::dataset::DatasetLeg;
+
+fn invalid(message: impl Into<String>) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message.into())
+}
+
+/// An iterator over the records of a CSV, whose fields borrow from it unless they contain escaped quotes
+struct Records<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Records<'a> {
+    /// Returns the next field, and whether it is the last of its record
+    fn field(&mut self) -> Result<(Cow<'a, str>, bool), std::io::Error> {
+        let data = self.data;
+        let (field, rest) = if data.first() == Some(&b'"') {
+            let mut i = 1;
+            let mut escaped = false;
+            loop {
+                match (data.get(i), data.get(i + 1)) {
+                    (None, _) => return Err(invalid("unterminated quoted field")),
+                    (Some(b'"'), Some(b'"')) => {
+                        escaped = true;
+                        i += 2;
+                    }
+                    (Some(b'"'), _) => break,
+                    _ => i += 1,
+                }
+            }
+            let field = std::str::from_utf8(&data[1..i]).map_err(invalid_utf8)?;
+            let field = match escaped {
+                true => Cow::Owned(field.replace("\"\"", "\"")),
+                false => Cow::Borrowed(field),
+            };
+            (field, &data[i + 1..])
+        } else {
+            let end = data
+                .iter()
+                .position(|b| matches!(b, b',' | b'\n' | b'\r'))
+                .unwrap_or(data.len());
+            let field = std::str::from_utf8(&data[..end]).map_err(invalid_utf8)?;
+            (Cow::Borrowed(field), &data[end..])
+        };
+        let (last, rest) = match rest {
+            [b',', rest @ ..] => (false, rest),
+            [b'\r', b'\n', rest @ ..] | [b'\n', rest @ ..] | [b'\r', rest @ ..] => (true, rest),
+            [] => (true, rest),
+            _ => return Err(invalid("a quoted field must be followed by a separator")),
+        };
+        self.data = rest;
+        Ok((field, last))
+    }
+}
+
+fn invalid_utf8(e: std::str::Utf8Error) -> std::io::Error {
+    invalid(e.to_string())
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Vec<Cow<'a, str>>, std::io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        let mut fields = vec![];
+        loop {
+            match self.field() {
+                Ok((field, last)) => {
+                    fields.push(field);
+                    if last {
+                        return Some(Ok(fields));
+                    }
+                }
+                Err(e) => {
+                    // stop on the first error
+                    self.data = &[];
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+/// A leg of the public dataset borrowing its text from a [`Partition`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct LegRef<'a> {
+    pub icao_number: Cow<'a, str>,
+    pub start: OffsetDateTime,
+    pub start_lat: f64,
+    pub start_lon: f64,
+    pub end: OffsetDateTime,
+    pub end_lat: f64,
+    pub end_lon: f64,
+    pub aircraft_model: Option<Cow<'a, str>>,
+    pub from_airport_icao: Option<Cow<'a, str>>,
+    pub to_airport_icao: Option<Cow<'a, str>>,
+}
+
+impl From<&LegRef<'_>> for DatasetLeg {
+    fn from(leg: &LegRef<'_>) -> Self {
+        Self {
+            icao_number: leg.icao_number.as_ref().into(),
+            start: leg.start,
+            start_lat: leg.start_lat,
+            start_lon: leg.start_lon,
+            end: leg.end,
+            end_lat: leg.end_lat,
+            end_lon: leg.end_lon,
+            aircraft_model: leg.aircraft_model.as_deref().map(Into::into),
+            from_airport_icao: leg.from_airport_icao.as_deref().map(Into::into),
+            to_airport_icao: leg.to_airport_icao.as_deref().map(Into::into),
+        }
+    }
+}
+
+/// The indices of the columns of [`LegRef`] in a file
+struct Columns {
+    required: [usize; 7],
+    optional: [Option<usize>; 3],
+}
+
+static REQUIRED: [&str; 7] = [
+    "icao_number",
+    "start",
+    "start_lat",
+    "start_lon",
+    "end",
+    "end_lat",
+    "end_lon",
+];
+static OPTIONAL: [&str; 3] = ["aircraft_model", "from_airport_icao", "to_airport_icao"];
+
+impl Columns {
+    fn new(header: &[Cow<str>]) -> Result<Self, std::io::Error> {
+        let index = |name: &str| header.iter().position(|column| column == name);
+        let mut required = [0; 7];
+        for (i, name) in REQUIRED.iter().enumerate() {
+            required[i] = index(name).ok_or_else(|| invalid(format!("missing column {name}")))?;
+        }
+        Ok(Self {
+            required,
+            optional: OPTIONAL.map(index),
+        })
+    }
+
+    fn parse<'a>(&self, mut record: Vec<Cow<'a, str>>) -> Result<LegRef<'a>, std::io::Error> {
+        let text = |record: &mut Vec<Cow<'a, str>>, i: usize| {
+            record
+                .get_mut(i)
+                .map(std::mem::take)
+                .ok_or_else(|| invalid("record with fewer fields than the header"))
+        };
+        let float = |record: &[Cow<str>], i: usize| {
+            record
+                .get(i)
+                .and_then(|x| x.parse::<f64>().ok())
+                .ok_or_else(|| invalid(format!("column {i} must be a number")))
+        };
+        let datetime = |record: &[Cow<str>], i: usize| {
+            record
+                .get(i)
+                .and_then(|x| OffsetDateTime::parse(x, &Rfc3339).ok())
+                .ok_or_else(|| invalid(format!("column {i} must be a RFC 3339 datetime")))
+        };
+        let [icao_number, start, start_lat, start_lon, end, end_lat, end_lon] = self.required;
+        let mut optional = |i: Option<usize>| -> Result<Option<Cow<'a, str>>, std::io::Error> {
+            Ok(match i {
+                Some(i) => Some(text(&mut record, i)?).filter(|x| !x.is_empty()),
+                None => None,
+            })
+        };
+        let [aircraft_model, from_airport_icao, to_airport_icao] = self.optional;
+        let aircraft_model = optional(aircraft_model)?;
+        let from_airport_icao = optional(from_airport_icao)?;
+        let to_airport_icao = optional(to_airport_icao)?;
+        Ok(LegRef {
+            start: datetime(&record, start)?,
+            start_lat: float(&record, start_lat)?,
+            start_lon: float(&record, start_lon)?,
+            end: datetime(&record, end)?,
+            end_lat: float(&record, end_lat)?,
+            end_lon: float(&record, end_lon)?,
+            icao_number: text(&mut record, icao_number)?,
+            aircraft_model,
+            from_airport_icao,
+            to_airport_icao,
+        })
+    }
+}
+
+/// An iterator over the [`LegRef`]s of a CSV of legs of the public dataset
+pub struct Legs<'a> {
+    records: Records<'a>,
+    /// the columns, once the header was read
+    columns: Option<Columns>,
+}
+
+impl<'a> Iterator for Legs<'a> {
+    type Item = Result<LegRef<'a>, std::io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.columns.is_none() {
+            let header = self.records.next()?;
+            match header.and_then(|header| Columns::new(&header)) {
+                Ok(columns) => self.columns = Some(columns),
+                Err(e) => {
+                    self.records.data = &[];
+                    return Some(Err(e));
+                }
+            }
+        }
+        let columns = self.columns.as_ref()?;
+        let record = self.records.next()?;
+        Some(record.and_then(|record| columns.parse(record)))
+    }
+}
+
+/// Returns the [`LegRef`]s of `data`, a CSV of legs of the public dataset
+/// # Error
+/// Each item errors if its record is not a valid leg
+pub fn legs(data: &[u8]) -> Legs<'_> {
+    Legs {
+        records: Records { data },
+        columns: None,
+    }
+}
+
+/// A memory-mapped file of the public dataset of legs
+pub struct Partition {
+    mmap: Mmap,
+}
+
+impl Partition {
+    /// Memory-maps the file at `path`
+    /// # Safety
+    /// The file must not be modified, truncated nor replaced in place (e.g. by syncing the dataset again, or by
+    /// [`crate::io::put`] or [`crate::compression::recompress`] on a [`crate::fs_local::LocalDisk`]) while the
+    /// returned [`Partition`] or any leg borrowed from it is alive; otherwise the behavior is undefined.
+    /// # Error
+    /// Errors if the file cannot be opened or mapped
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the caller guarantees that the file is not modified while it is mapped
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(Self { mmap })
+    }
+
+    /// Returns the legs of this partition, borrowing from it
+    pub fn legs(&self) -> Legs<'_> {
+        legs(&self.mmap)
+    }
+}
+
+/// A local copy of the public dataset of legs, at `{root}/leg/v2/all/year={year}/data.csv`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalDataset {
+    root: PathBuf,
+}
+
+impl LocalDataset {
+    /// Returns a new [`LocalDataset`] under the directory `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Memory-maps the partition of `year`
+    /// # Safety
+    /// The same as [`Partition::open`]: the partition must not be modified while it is mapped.
+    /// # Error
+    /// Errors if the partition does not exist or cannot be mapped
+    pub unsafe fn year(&self, year: i32) -> Result<Partition, std::io::Error> {
+        let path = self
+            .root
+            .join(format!("leg/v2/all/year={year}"))
+            .join("data.csv");
+        // SAFETY: the caller guarantees that the partition is not modified while it is mapped
+        unsafe { Partition::open(path) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn work() {
+        let data = br#"icao_number,tail_number,aircraft_model,start,start_lat,start_lon,end,end_lat,end_lon,from_airport_icao,from_airport_name,to_airport_icao
+45d2ed,OY-GFS,"BEECH 400 ""Beechjet""",2023-01-01T10:00:00Z,55.6,12.6,2023-01-01T11:00:00Z,52.4,13.5,EKCH,"Copenhagen, Kastrup",EDDB
+45d2ed,OY-GFS,,2023-01-02T10:00:00Z,52.4,13.5,2023-01-02T11:00:00Z,55.6,12.6,,,
+"#;
+        let legs = legs(data).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(legs.len(), 2);
+        assert!(matches!(legs[0].icao_number, Cow::Borrowed("45d2ed")));
+        assert_eq!(
+            legs[0].aircraft_model.as_deref(),
+            Some(r#"BEECH 400 "Beechjet""#)
+        );
+        assert_eq!(legs[0].to_airport_icao.as_deref(), Some("EDDB"));
+        assert_eq!(legs[1].aircraft_model, None);
+        assert_eq!(DatasetLeg::from(&legs[1]).end_lat, 55.6);
+
+        let root = std::env::temp_dir().join("test_mmap");
+        let dir = root.join("leg/v2/all/year=2023");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("data.csv"), data).unwrap();
+        // SAFETY: the file is not modified by this test while it is mapped
+        let partition = unsafe { LocalDataset::new(&root).year(2023) }.unwrap();
+        assert_eq!(partition.legs().count(), 2);
+        assert!(unsafe { LocalDataset::new(&root).year(2019) }.is_err());
+
+        assert!(super::legs(b"icao_number\n45d2ed\n")
+            .next()
+            .unwrap()
+            .is_err());
+    }
+}
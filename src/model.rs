@@ -2,9 +2,52 @@ use std::{collections::HashMap, error::Error, sync::Arc};
 
 use serde::{Deserialize, Serialize};
 
+use crate::emissions::{phased::PhaseFactors, Fuel};
+
 /// A map of the aircraft model (e.g. `BEECH 400 Beechjet`) to an [`AircraftModel`].
 pub type AircraftModels = HashMap<String, Arc<AircraftModel>>;
 
+/// The category of an aircraft model, each with its own database of models
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, Default, Hash, PartialEq, Eq, PartialOrd, Ord,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    /// private jets, in `src/models.csv`
+    #[default]
+    Jet,
+    /// helicopters, in `src/models_helicopters.csv`
+    Helicopter,
+    /// turboprops, in `src/models_turboprops.csv`
+    Turboprop,
+}
+
+impl std::str::FromStr for Category {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jets" => Ok(Self::Jet),
+            "helicopters" => Ok(Self::Helicopter),
+            "turboprops" => Ok(Self::Turboprop),
+            other => Err(format!(
+                "category `{other}` must be `jets`, `helicopters` or `turboprops`"
+            )),
+        }
+    }
+}
+
+impl Category {
+    /// The name of the category (e.g. `jet`)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Jet => "jet",
+            Self::Helicopter => "helicopter",
+            Self::Turboprop => "turboprop",
+        }
+    }
+}
+
 /// In-memory representation of an aircraft model
 #[derive(Serialize, Deserialize, Debug, Clone, Hash, PartialEq, Eq)]
 pub struct AircraftModel {
@@ -12,22 +55,267 @@ pub struct AircraftModel {
     pub model: String,
     /// the consumption in gallons per hour
     pub gph: u32,
+    /// the fuel used (defaults to Jet-A)
+    #[serde(default)]
+    pub fuel: Fuel,
+    /// the category of the model, set by the database it is loaded from
+    #[serde(default)]
+    pub category: Category,
     /// the source that identifies it as a private jet
     pub source: String,
     /// the date of when the source was retrieved
     pub date: String,
 }
 
+/// A correction of the consumption of a model contributed by the community, in `src/models_overrides.csv`.
+/// It replaces the (averaged) entry of the model in `src/models.csv`, or adds the model when it is not there.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModelOverride {
+    /// the model (e.g. `BEECH 400 Beechjet`)
+    pub model: String,
+    /// the consumption in gallons per hour
+    pub gph: u32,
+    /// the fuel used (defaults to Jet-A)
+    #[serde(default)]
+    pub fuel: Fuel,
+    /// the source of the correction
+    pub source: String,
+    /// the date of when the source was retrieved
+    pub date: String,
+    /// who contributed the correction (e.g. a GitHub handle)
+    pub contributor: String,
+    /// why the entry in `src/models.csv` is replaced
+    pub reason: String,
+}
+
+/// The passenger capacity of a model contributed by the community, in `src/models_seats.csv`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModelSeats {
+    /// the model (e.g. `BEECH 400 Beechjet`)
+    pub model: String,
+    /// the maximum number of passenger seats
+    pub seats: u32,
+    /// the source of the capacity
+    pub source: String,
+    /// the date of when the source was retrieved
+    pub date: String,
+}
+
+/// The fuel flow of each phase of legs of a model contributed by the community, in `src/models_phases.csv`
+/// (see [`PhaseFactors`])
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ModelPhases {
+    /// the model (e.g. `BEECH 400 Beechjet`)
+    pub model: String,
+    /// the fuel flow while climbing relative to `gph`
+    pub climb: f64,
+    /// the fuel flow while cruising relative to `gph`
+    pub cruise: f64,
+    /// the fuel flow while descending relative to `gph`
+    pub descent: f64,
+    /// the source of the fuel flows
+    pub source: String,
+    /// the date of when the source was retrieved
+    pub date: String,
+}
+
+/// A change of the category of a model (e.g. from `private jet` to `air-taxi turboprop`), in `src/models_changelog.csv`.
+/// Models reclassified out of `private jet` are removed from `src/models.csv`; their entry here keeps the figures
+/// computed before the change explainable.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ModelReclassification {
+    /// the model (e.g. `BEECH 200 Super King Air`)
+    pub model: String,
+    /// the date of the change (e.g. `2024-06-01`)
+    pub date: String,
+    /// the category before the change (e.g. `private jet`)
+    pub from: String,
+    /// the category after the change (e.g. `air-taxi turboprop`)
+    pub to: String,
+    /// why the model was reclassified
+    pub reason: String,
+    /// the source supporting the change
+    pub source: String,
+}
+
+/// Returns all [`ModelReclassification`]s in `src/models_changelog.csv`
+/// # Error
+/// Errors if the file cannot be read or is not a valid CSV
+pub fn load_model_changelog() -> Result<Vec<ModelReclassification>, Box<dyn Error>> {
+    parse_model_changelog(&std::fs::read("src/models_changelog.csv")?)
+}
+
+/// Returns all [`ModelReclassification`]s in `data`, a CSV in the format of `src/models_changelog.csv`
+/// # Error
+/// Errors if `data` is not a valid CSV
+pub fn parse_model_changelog(data: &[u8]) -> Result<Vec<ModelReclassification>, Box<dyn Error>> {
+    Ok(super::csv::deserialize(data).collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Returns the most recent [`ModelReclassification`] of `model` in `changelog` on or before `date`
+/// (`YYYY-MM-DD`), or `None` when it was never reclassified until then
+pub fn last_reclassification<'a>(
+    changelog: &'a [ModelReclassification],
+    model: &str,
+    date: &str,
+) -> Option<&'a ModelReclassification> {
+    changelog
+        .iter()
+        .filter(|x| x.model == model && x.date.as_str() <= date)
+        .max_by(|a, b| a.date.cmp(&b.date))
+}
+
+/// Returns the number of seats of each model in `src/models_seats.csv`
+/// # Error
+/// Errors if the file cannot be read or is not a valid CSV
+pub fn load_model_seats() -> Result<HashMap<String, u32>, Box<dyn Error>> {
+    parse_model_seats(&std::fs::read("src/models_seats.csv")?)
+}
+
+/// Returns the number of seats of each model in `data`, a CSV in the format of `src/models_seats.csv`.
+/// Models with more than one row have the seats of their last row
+/// # Error
+/// Errors if `data` is not a valid CSV
+pub fn parse_model_seats(data: &[u8]) -> Result<HashMap<String, u32>, Box<dyn Error>> {
+    Ok(super::csv::deserialize::<ModelSeats>(data)
+        .map(|x| x.map(|x| (x.model, x.seats)))
+        .collect::<Result<HashMap<_, _>, _>>()?)
+}
+
+/// Returns the [`PhaseFactors`] of each model in `src/models_phases.csv`; models without them
+/// use [`PhaseFactors::default`]
+/// # Error
+/// Errors if the file cannot be read or is not a valid CSV
+pub fn load_model_phases() -> Result<HashMap<String, PhaseFactors>, Box<dyn Error>> {
+    parse_model_phases(&std::fs::read("src/models_phases.csv")?)
+}
+
+/// Returns the [`PhaseFactors`] of each model in `data`, a CSV in the format of `src/models_phases.csv`.
+/// Models with more than one row have the factors of their last row
+/// # Error
+/// Errors if `data` is not a valid CSV
+pub fn parse_model_phases(data: &[u8]) -> Result<HashMap<String, PhaseFactors>, Box<dyn Error>> {
+    Ok(super::csv::deserialize::<ModelPhases>(data)
+        .map(|x| {
+            x.map(|x| {
+                let factors = PhaseFactors {
+                    climb: x.climb,
+                    cruise: x.cruise,
+                    descent: x.descent,
+                };
+                (x.model, factors)
+            })
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?)
+}
+
+/// Returns all [`ModelOverride`]s in `src/models_overrides.csv`
+/// # Error
+/// Errors if the file cannot be read or is not a valid CSV
+pub fn load_model_overrides() -> Result<Vec<ModelOverride>, Box<dyn Error>> {
+    parse_model_overrides(&std::fs::read("src/models_overrides.csv")?)
+}
+
+/// Returns all [`ModelOverride`]s in `data`, a CSV in the format of `src/models_overrides.csv`
+/// # Error
+/// Errors if `data` is not a valid CSV
+pub fn parse_model_overrides(data: &[u8]) -> Result<Vec<ModelOverride>, Box<dyn Error>> {
+    Ok(super::csv::deserialize(data).collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Replaces (or adds, as [`Category::Jet`]) the models of `overrides` in `models`
+pub fn apply_overrides(models: &mut AircraftModels, overrides: &[ModelOverride]) {
+    for o in overrides {
+        let model = AircraftModel {
+            model: o.model.clone(),
+            gph: o.gph,
+            fuel: o.fuel,
+            category: models
+                .get(&o.model)
+                .map(|model| model.category)
+                .unwrap_or_default(),
+            source: o.source.clone(),
+            date: o.date.clone(),
+        };
+        models.insert(o.model.clone(), Arc::new(model));
+    }
+}
+
 /// Returns the set of all [`AircraftModel`] in `src/models.csv`,
 /// corresponding to aircraft types whose primary use is to be a private jet
 /// according to the [methodology `M-models-for-private-use`](../methodology.md).
-/// The gph of each model is the average over all sources as per [methodology `M-average-consumption`](../methodology.md).
+/// The gph of each model is the average over all sources as per [methodology `M-average-consumption`](../methodology.md),
+/// unless overridden in `src/models_overrides.csv` (see [`ModelOverride`]).
 /// # Error
-/// Errors if the file cannot be read
+/// Errors if the files cannot be read
 pub fn load_private_jet_models() -> Result<AircraftModels, Box<dyn Error>> {
-    let data = super::csv::load("src/models.csv", |a: AircraftModel| (a.clone(), a))?;
+    private_jet_models(&std::fs::read("src/models.csv")?, &load_model_overrides()?)
+}
+
+/// Returns the set of all [`AircraftModel`] of helicopters in `src/models_helicopters.csv`
+/// (e.g. of police and air ambulances), averaged as in [`load_private_jet_models`].
+/// # Error
+/// Errors if the file cannot be read or is not a valid CSV
+pub fn load_helicopter_models() -> Result<AircraftModels, Box<dyn Error>> {
+    models_of(
+        &std::fs::read("src/models_helicopters.csv")?,
+        Category::Helicopter,
+    )
+}
+
+/// Returns the set of all [`AircraftModel`] of turboprops in `src/models_turboprops.csv`,
+/// averaged as in [`load_private_jet_models`].
+/// # Error
+/// Errors if the file cannot be read or is not a valid CSV
+pub fn load_turboprop_models() -> Result<AircraftModels, Box<dyn Error>> {
+    models_of(
+        &std::fs::read("src/models_turboprops.csv")?,
+        Category::Turboprop,
+    )
+}
+
+/// Returns the set of all [`AircraftModel`] of `categories`. Models in more than one database
+/// are of the last of `categories`.
+/// # Error
+/// Errors if the files cannot be read
+pub fn load_models(categories: &[Category]) -> Result<AircraftModels, Box<dyn Error>> {
+    let mut models = AircraftModels::new();
+    for category in categories {
+        models.extend(match category {
+            Category::Jet => load_private_jet_models()?,
+            Category::Helicopter => load_helicopter_models()?,
+            Category::Turboprop => load_turboprop_models()?,
+        });
+    }
+    Ok(models)
+}
+
+/// Returns the set of all [`AircraftModel`] in `models`, a CSV in the format of `src/models.csv`,
+/// averaged and overridden by `overrides` as in [`load_private_jet_models`].
+/// # Error
+/// Errors if `models` is not a valid CSV
+pub fn private_jet_models(
+    models: &[u8],
+    overrides: &[ModelOverride],
+) -> Result<AircraftModels, Box<dyn Error>> {
+    let mut data = models_of(models, Category::Jet)?;
+    apply_overrides(&mut data, overrides);
+    Ok(data)
+}
+
+/// Returns the set of all [`AircraftModel`] in `models`, a CSV in the format of `src/models.csv`, of `category`.
+/// The gph of models with more than one row is their average.
+fn models_of(models: &[u8], category: Category) -> Result<AircraftModels, Box<dyn Error>> {
+    let data = super::csv::deserialize::<AircraftModel>(models)
+        .map(|a| {
+            a.map(|mut a| {
+                a.category = category;
+                (a.clone(), a)
+            })
+        })
+        .collect::<Result<HashMap<_, _>, _>>()?;
 
-    let data = data
+    Ok(data
         .into_iter()
         .fold(
             HashMap::<String, (AircraftModel, u32)>::default(),
@@ -51,9 +339,7 @@ pub fn load_private_jet_models() -> Result<AircraftModels, Box<dyn Error>> {
             all.gph /= count;
             (model, Arc::new(all))
         })
-        .collect();
-
-    Ok(data)
+        .collect())
 }
 
 #[cfg(test)]
@@ -73,4 +359,73 @@ mod test {
         let data_csv = crate::csv::serialize(models.into_iter());
         std::fs::write("models.csv", data_csv).unwrap();
     }
+
+    #[test]
+    fn overrides() {
+        let data = b"model,gph,fuel,source,date,contributor,reason
+GULFSTREAM 5,470,jet-a,https://example.com/g5,2024-05-01,someone,newer brochure
+A NEW MODEL,100,avgas,https://example.com/new,2024-05-01,someone,missing model
+";
+        let overrides = crate::csv::deserialize::<ModelOverride>(data)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let mut models = load_private_jet_models().unwrap();
+        let count = models.len();
+        apply_overrides(&mut models, &overrides);
+
+        assert_eq!(models.len(), count + 1);
+        let g5 = models.get("GULFSTREAM 5").unwrap();
+        assert_eq!(g5.gph, 470);
+        assert_eq!(g5.source, "https://example.com/g5");
+        assert_eq!(models.get("A NEW MODEL").unwrap().fuel, Fuel::Avgas);
+    }
+
+    #[test]
+    fn changelog() {
+        assert!(load_model_changelog().is_ok());
+
+        let data = b"model,date,from,to,reason,source
+BEECH 200 Super King Air,2023-06-01,private jet,air-taxi turboprop,mostly operated by air taxis,https://example.com/a
+BEECH 200 Super King Air,2024-06-01,air-taxi turboprop,private jet,operators changed,https://example.com/b
+";
+        let changelog = parse_model_changelog(data).unwrap();
+        let last = |date| {
+            last_reclassification(&changelog, "BEECH 200 Super King Air", date).map(|x| &x.to)
+        };
+        assert_eq!(last("2022-12-31"), None);
+        assert_eq!(last("2023-12-31").unwrap(), "air-taxi turboprop");
+        assert_eq!(last("2024-12-31").unwrap(), "private jet");
+        assert!(last_reclassification(&changelog, "GULFSTREAM 5", "2024-12-31").is_none());
+    }
+
+    #[test]
+    fn phases() {
+        assert!(load_model_phases().is_ok());
+
+        let data = b"model,climb,cruise,descent,source,date
+GULFSTREAM 5,1.4,0.95,0.3,https://example.com/g5,2024-05-01
+";
+        let phases = parse_model_phases(data).unwrap();
+        assert_eq!(phases["GULFSTREAM 5"].cruise, 0.95);
+        assert!(!phases.contains_key("BEECH 400 Beechjet"));
+    }
+
+    #[test]
+    fn categories() {
+        let data = b"model,gph,fuel,source,date
+EUROCOPTER EC-135,60,jet-a,https://example.com/a,2024-05-01
+EUROCOPTER EC-135,70,jet-a,https://example.com/b,2024-05-01
+";
+        let models = models_of(data, Category::Helicopter).unwrap();
+        let model = models.get("EUROCOPTER EC-135").unwrap();
+        assert_eq!((model.gph, model.category), (65, Category::Helicopter));
+
+        let jets = load_models(&[Category::Jet]).unwrap();
+        assert!(jets.values().all(|x| x.category == Category::Jet));
+        assert_eq!(jets.len(), load_private_jet_models().unwrap().len());
+        assert!(load_models(&[Category::Jet, Category::Helicopter, Category::Turboprop]).is_ok());
+        assert_eq!("helicopters".parse(), Ok(Category::Helicopter));
+        assert!("cars".parse::<Category>().is_err());
+    }
 }
@@ -0,0 +1,465 @@
+//! Contains the implementation of overlays (`M-overlays`): arbitrary areas (e.g. Natura 2000 sites or national parks)
+//! from a GeoJSON `FeatureCollection`, indexed in an R-tree, with which legs and their positions are annotated
+//! (the overlays they fly over, the time spent inside them and their distance to the closest one).
+use std::{collections::BTreeSet, sync::Arc};
+
+use serde_json::Value;
+
+use crate::{
+    aircraft::Aircraft,
+    enrich::{Enricher, Enrichment},
+    geo::EARTH_RADIUS,
+    legs::Leg,
+    region::{distance_to_segment, ring_contains},
+    Position,
+};
+
+/// The maximum distance in km between a position and an overlay for the distance to be computed
+pub static MAX_DISTANCE: f64 = 50.0;
+/// The maximum number of children of a node of the R-tree
+static NODE_CAPACITY: usize = 8;
+
+/// A ring of `(longitude, latitude)` points in degrees
+type Ring = Vec<(f64, f64)>;
+
+/// A rectangle in degrees
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rect {
+    min_lon: f64,
+    min_lat: f64,
+    max_lon: f64,
+    max_lat: f64,
+}
+
+impl Rect {
+    const EMPTY: Self = Self {
+        min_lon: f64::MAX,
+        min_lat: f64::MAX,
+        max_lon: f64::MIN,
+        max_lat: f64::MIN,
+    };
+
+    fn of_point((lon, lat): (f64, f64)) -> Self {
+        Self {
+            min_lon: lon,
+            min_lat: lat,
+            max_lon: lon,
+            max_lat: lat,
+        }
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            min_lon: self.min_lon.min(other.min_lon),
+            min_lat: self.min_lat.min(other.min_lat),
+            max_lon: self.max_lon.max(other.max_lon),
+            max_lat: self.max_lat.max(other.max_lat),
+        }
+    }
+
+    fn intersects(&self, other: &Self) -> bool {
+        self.min_lon <= other.max_lon
+            && other.min_lon <= self.max_lon
+            && self.min_lat <= other.max_lat
+            && other.min_lat <= self.max_lat
+    }
+
+    /// Returns the rectangle enlarged by `distance` km on every side
+    fn expand(self, distance: f64) -> Self {
+        let lat = (distance / EARTH_RADIUS).to_degrees();
+        let latitude = self.min_lat.abs().max(self.max_lat.abs()).min(89.0);
+        let lon = lat / latitude.to_radians().cos();
+        Self {
+            min_lon: self.min_lon - lon,
+            min_lat: self.min_lat - lat,
+            max_lon: self.max_lon + lon,
+            max_lat: self.max_lat + lat,
+        }
+    }
+
+    fn center(&self) -> (f64, f64) {
+        (
+            (self.min_lon + self.max_lon) / 2.0,
+            (self.min_lat + self.max_lat) / 2.0,
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Entry {
+    /// The index of an item
+    Item(usize),
+    Children(Vec<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    rect: Rect,
+    entry: Entry,
+}
+
+/// Returns the parents of `nodes`, packed with the sort-tile-recursive algorithm: nodes are sorted by longitude
+/// into vertical slices, and each slice is sorted by latitude into parents of [`NODE_CAPACITY`] nodes
+fn pack(mut nodes: Vec<Node>) -> Vec<Node> {
+    let parents = nodes.len().div_ceil(NODE_CAPACITY);
+    let slices = (parents as f64).sqrt().ceil() as usize;
+    let slice_size = slices * NODE_CAPACITY;
+    nodes.sort_unstable_by(|a, b| a.rect.center().0.total_cmp(&b.rect.center().0));
+
+    let mut packed = Vec::with_capacity(parents);
+    let mut nodes = nodes.into_iter().peekable();
+    while nodes.peek().is_some() {
+        let mut slice = nodes.by_ref().take(slice_size).collect::<Vec<_>>();
+        slice.sort_unstable_by(|a, b| a.rect.center().1.total_cmp(&b.rect.center().1));
+        let mut slice = slice.into_iter().peekable();
+        while slice.peek().is_some() {
+            let children = slice.by_ref().take(NODE_CAPACITY).collect::<Vec<_>>();
+            let rect = children
+                .iter()
+                .fold(Rect::EMPTY, |rect, child| rect.union(child.rect));
+            packed.push(Node {
+                rect,
+                entry: Entry::Children(children),
+            });
+        }
+    }
+    packed
+}
+
+/// A static R-tree of the rectangles of items, bulk-loaded on creation
+#[derive(Debug, Clone, PartialEq)]
+struct RTree {
+    root: Node,
+}
+
+impl RTree {
+    fn new(rects: impl Iterator<Item = Rect>) -> Self {
+        let mut nodes = rects
+            .enumerate()
+            .map(|(index, rect)| Node {
+                rect,
+                entry: Entry::Item(index),
+            })
+            .collect::<Vec<_>>();
+        while nodes.len() > NODE_CAPACITY {
+            nodes = pack(nodes);
+        }
+        let rect = nodes
+            .iter()
+            .fold(Rect::EMPTY, |rect, node| rect.union(node.rect));
+        Self {
+            root: Node {
+                rect,
+                entry: Entry::Children(nodes),
+            },
+        }
+    }
+
+    /// Calls `visit` with the index of every item whose rectangle intersects `rect`
+    fn search(&self, rect: &Rect, visit: &mut impl FnMut(usize)) {
+        fn search(node: &Node, rect: &Rect, visit: &mut impl FnMut(usize)) {
+            if !node.rect.intersects(rect) {
+                return;
+            }
+            match &node.entry {
+                Entry::Item(index) => visit(*index),
+                Entry::Children(children) => {
+                    for child in children {
+                        search(child, rect, visit)
+                    }
+                }
+            }
+        }
+        search(&self.root, rect, visit)
+    }
+}
+
+/// A polygon of an overlay
+#[derive(Debug, Clone, PartialEq)]
+struct Polygon {
+    /// the index of its overlay in [`Overlays::names`]
+    overlay: usize,
+    /// the first ring is its exterior; the others are its holes
+    rings: Vec<Ring>,
+}
+
+impl Polygon {
+    fn contains(&self, point: (f64, f64)) -> bool {
+        let mut rings = self.rings.iter();
+        rings
+            .next()
+            .is_some_and(|exterior| ring_contains(exterior, point))
+            && !rings.any(|hole| ring_contains(hole, point))
+    }
+
+    /// Returns the distance in km from `point` to the polygon (zero when inside it)
+    fn distance(&self, point: (f64, f64)) -> f64 {
+        if self.contains(point) {
+            return 0.0;
+        }
+        self.rings
+            .iter()
+            .flat_map(|ring| ring.windows(2))
+            .map(|segment| distance_to_segment(point, segment[0], segment[1]))
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+/// The annotations of a sequence of positions (e.g. of a leg) by [`Overlays`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Annotation {
+    /// The names of the overlays any position is inside of
+    pub names: BTreeSet<Arc<str>>,
+    /// The time inside any overlay
+    pub duration: time::Duration,
+    /// The lowest altitude in feet of the positions inside any overlay, when any is
+    pub min_altitude: Option<f64>,
+    /// The distance in km between the positions and the closest overlay (zero when any is inside one), when within
+    /// [`MAX_DISTANCE`]
+    pub distance: Option<f64>,
+}
+
+/// A set of named overlays, indexed in an R-tree of the bounding boxes of their polygons
+#[derive(Debug, Clone, PartialEq)]
+pub struct Overlays {
+    names: Vec<Arc<str>>,
+    polygons: Vec<Polygon>,
+    tree: RTree,
+}
+
+impl Overlays {
+    /// Returns [`Overlays`] from a GeoJSON `FeatureCollection` whose features are (multi)polygons, named after
+    /// their property `name_property` (e.g. `SITENAME` of Natura 2000 sites); features without it are named after
+    /// their index in the collection
+    pub fn from_geojson(data: &[u8], name_property: &str) -> Result<Self, String> {
+        let value = serde_json::from_slice::<Value>(data).map_err(|e| e.to_string())?;
+        let features = value
+            .get("features")
+            .and_then(|x| x.as_array())
+            .ok_or_else(|| "GeoJSON must be a FeatureCollection".to_string())?;
+
+        let mut names = vec![];
+        let mut polygons = vec![];
+        for (index, feature) in features.iter().enumerate() {
+            let name = match feature.get("properties").and_then(|x| x.get(name_property)) {
+                Some(Value::String(name)) => name.clone(),
+                Some(Value::Number(name)) => name.to_string(),
+                _ => index.to_string(),
+            };
+            let geometry = feature
+                .get("geometry")
+                .ok_or_else(|| format!("feature without geometry: {feature}"))?;
+            polygons.extend(
+                crate::region::parse_geojson(geometry)?
+                    .into_iter()
+                    .map(|rings| Polygon {
+                        overlay: names.len(),
+                        rings,
+                    }),
+            );
+            names.push(name.into());
+        }
+        let tree = RTree::new(polygons.iter().map(|polygon| {
+            polygon
+                .rings
+                .first()
+                .into_iter()
+                .flatten()
+                .fold(Rect::EMPTY, |rect, point| {
+                    rect.union(Rect::of_point(*point))
+                })
+        }));
+        Ok(Self {
+            names,
+            polygons,
+            tree,
+        })
+    }
+
+    /// The number of overlays
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether there are no overlays
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// Returns the names of the overlays containing `(latitude, longitude)`
+    pub fn containing(&self, (latitude, longitude): (f64, f64)) -> BTreeSet<Arc<str>> {
+        let point = (longitude, latitude);
+        let mut names = BTreeSet::new();
+        self.tree.search(&Rect::of_point(point), &mut |index| {
+            let polygon = &self.polygons[index];
+            if polygon.contains(point) {
+                names.insert(self.names[polygon.overlay].clone());
+            }
+        });
+        names
+    }
+
+    /// Returns the distance in km between `(latitude, longitude)` and the closest overlay (zero when inside one),
+    /// or `None` when none is within [`MAX_DISTANCE`]
+    pub fn distance(&self, (latitude, longitude): (f64, f64)) -> Option<f64> {
+        let point = (longitude, latitude);
+        let mut distance = None::<f64>;
+        let rect = Rect::of_point(point).expand(MAX_DISTANCE);
+        self.tree.search(&rect, &mut |index| {
+            let candidate = self.polygons[index].distance(point);
+            distance = Some(distance.map_or(candidate, |distance| distance.min(candidate)));
+        });
+        distance.filter(|distance| *distance <= MAX_DISTANCE)
+    }
+
+    /// Returns the [`Annotation`] of the flying `positions`: the time between two consecutive positions is inside
+    /// an overlay when the first of them is
+    pub fn annotate(&self, positions: &[Position]) -> Annotation {
+        let mut annotation = Annotation::default();
+        let flying = positions.iter().filter(|p| p.flying()).collect::<Vec<_>>();
+        for (index, position) in flying.iter().enumerate() {
+            let names = self.containing(position.pos());
+            if !names.is_empty() {
+                if let Some(next) = flying.get(index + 1) {
+                    annotation.duration += next.datetime() - position.datetime();
+                }
+                let altitude = position.altitude();
+                annotation.min_altitude = Some(
+                    annotation
+                        .min_altitude
+                        .map_or(altitude, |min| min.min(altitude)),
+                );
+                annotation.names.extend(names);
+                annotation.distance = Some(0.0);
+            } else if annotation.distance != Some(0.0) {
+                if let Some(distance) = self.distance(position.pos()) {
+                    annotation.distance = Some(
+                        annotation
+                            .distance
+                            .map_or(distance, |min| min.min(distance)),
+                    );
+                }
+            }
+        }
+        annotation
+    }
+}
+
+/// Adds the overlays flown over, the time inside them and the distance to the closest one (see `M-overlays`)
+impl Enricher for Overlays {
+    fn name(&self) -> &'static str {
+        "overlays"
+    }
+
+    fn enrich(&self, leg: &Leg, _: Option<&Aircraft>, enrichment: &mut Enrichment) {
+        let annotation = self.annotate(leg.positions());
+        enrichment.overlays = (!annotation.names.is_empty()).then(|| {
+            let names = annotation.names.iter().map(|x| x.as_ref());
+            names.collect::<Vec<_>>().join(";").into()
+        });
+        enrichment.overlay_minutes = Some(annotation.duration.as_seconds_f64() / 60.0);
+        enrichment.overlay_min_altitude = annotation.min_altitude;
+        enrichment.overlay_distance = annotation.distance;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn rtree() {
+        // a grid of 20x20 cells of 1 degree
+        let rects = (0..400)
+            .map(|i| {
+                let (lon, lat) = ((i % 20) as f64, (i / 20) as f64);
+                Rect {
+                    min_lon: lon,
+                    min_lat: lat,
+                    max_lon: lon + 0.5,
+                    max_lat: lat + 0.5,
+                }
+            })
+            .collect::<Vec<_>>();
+        let tree = RTree::new(rects.iter().copied());
+        let query = Rect {
+            min_lon: 3.2,
+            min_lat: 4.7,
+            max_lon: 6.1,
+            max_lat: 5.2,
+        };
+        let mut found = vec![];
+        tree.search(&query, &mut |index| found.push(index));
+        found.sort_unstable();
+        let expected = (0..rects.len())
+            .filter(|index| rects[*index].intersects(&query))
+            .collect::<Vec<_>>();
+        assert_eq!(found, expected);
+        assert_eq!(found.len(), 4);
+    }
+
+    #[test]
+    fn work() {
+        let data = br#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {"SITENAME": "Park"},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[10.0, 55.0], [11.0, 55.0], [11.0, 56.0], [10.0, 56.0], [10.0, 55.0]]]
+                }
+            }, {
+                "type": "Feature",
+                "properties": {},
+                "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [[[[20.0, 55.0], [21.0, 55.0], [21.0, 56.0], [20.0, 56.0], [20.0, 55.0]]]]
+                }
+            }]
+        }"#;
+        let overlays = Overlays::from_geojson(data, "SITENAME").unwrap();
+        assert_eq!(overlays.len(), 2);
+        assert_eq!(
+            overlays.containing((55.5, 10.5)),
+            BTreeSet::from(["Park".into()])
+        );
+        assert_eq!(
+            overlays.containing((55.5, 20.5)),
+            BTreeSet::from(["1".into()])
+        );
+        assert!(overlays.containing((55.5, 15.0)).is_empty());
+        // 0.1 degrees of longitude east of the park at 55.5N
+        let distance = overlays.distance((55.5, 11.1)).unwrap();
+        assert!((distance - 6.3).abs() < 0.1, "{distance}");
+        assert_eq!(overlays.distance((55.5, 15.0)), None);
+
+        let position = |datetime, longitude, altitude| Position {
+            datetime,
+            latitude: 55.5,
+            longitude,
+            altitude,
+            altitude_source: None,
+        };
+        let annotation = overlays.annotate(&[
+            position(datetime!(2023 - 01 - 01 10:00 UTC), 10.5, None),
+            position(datetime!(2023 - 01 - 01 10:01 UTC), 10.6, Some(900.0)),
+            position(datetime!(2023 - 01 - 01 10:03 UTC), 10.9, Some(1500.0)),
+            position(datetime!(2023 - 01 - 01 10:10 UTC), 12.0, Some(3000.0)),
+        ]);
+        assert_eq!(annotation.names, BTreeSet::from(["Park".into()]));
+        assert_eq!(annotation.duration, time::Duration::minutes(9));
+        assert_eq!(annotation.min_altitude, Some(900.0));
+        assert_eq!(annotation.distance, Some(0.0));
+
+        let annotation = overlays.annotate(&[
+            position(datetime!(2023 - 01 - 01 10:00 UTC), 11.1, Some(900.0)),
+            position(datetime!(2023 - 01 - 01 10:05 UTC), 12.0, Some(900.0)),
+        ]);
+        assert!(annotation.names.is_empty());
+        assert_eq!(annotation.duration, time::Duration::ZERO);
+        assert!((annotation.distance.unwrap() - 6.3).abs() < 0.1);
+    }
+}
@@ -0,0 +1,154 @@
+//! Contains the implementation of the curated dataset of owners and operators of aircrafts,
+//! stored at `owner/v1/data.json` (see `M-owners`).
+use std::{collections::HashMap, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::fs::BlobStorageProvider;
+
+static DATABASE: &str = "owner/v1/data.json";
+
+/// The type of an owner or operator
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OwnerType {
+    /// A natural person
+    Individual,
+    /// A company using the aircraft for its own purposes
+    Corporate,
+    /// A company renting the aircraft (with crew) to third parties
+    Charter,
+}
+
+impl OwnerType {
+    /// The name of the type (e.g. `charter`)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Individual => "individual",
+            Self::Corporate => "corporate",
+            Self::Charter => "charter",
+        }
+    }
+}
+
+/// An entry of the dataset of owners, identifying an aircraft by its ICAO number and/or tail number
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Owner {
+    /// The ICAO number of the aircraft (e.g. `45d2ed`)
+    #[serde(default)]
+    pub icao_number: Option<Arc<str>>,
+    /// The tail number of the aircraft (e.g. `OY-GFS`)
+    #[serde(default)]
+    pub tail_number: Option<Arc<str>>,
+    /// The name of the owner
+    #[serde(default)]
+    pub owner: Option<Arc<str>>,
+    #[serde(default)]
+    pub owner_type: Option<OwnerType>,
+    /// The name of the operator
+    #[serde(default)]
+    pub operator: Option<Arc<str>>,
+    #[serde(default)]
+    pub operator_type: Option<OwnerType>,
+    /// The region of the registered address of the owner, in ISO 3166-2 (e.g. `DK-81`) or in ISO 3166-1 alpha-2
+    /// for regions without subdivisions (e.g. `GL`), see `M-registry-filters`
+    #[serde(default)]
+    pub region: Option<Arc<str>>,
+    /// The source of the entry (e.g. a registry)
+    pub source: String,
+    /// The date of when the source was retrieved
+    pub date: String,
+}
+
+/// The dataset of [`Owner`]s, indexed by ICAO number and tail number
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Owners {
+    owners: Vec<Owner>,
+    by_icao_number: HashMap<Arc<str>, usize>,
+    by_tail_number: HashMap<Arc<str>, usize>,
+}
+
+impl Owners {
+    /// Returns [`Owners`] from a JSON array of [`Owner`]
+    pub fn from_json(data: &[u8]) -> Result<Self, String> {
+        let owners = serde_json::from_slice::<Vec<Owner>>(data).map_err(|e| e.to_string())?;
+        let mut by_icao_number = HashMap::new();
+        let mut by_tail_number = HashMap::new();
+        for (i, owner) in owners.iter().enumerate() {
+            if owner.icao_number.is_none() && owner.tail_number.is_none() {
+                return Err(format!(
+                    "owner {i} must have an icao_number or a tail_number"
+                ));
+            }
+            if let Some(icao_number) = &owner.icao_number {
+                by_icao_number.insert(icao_number.to_ascii_lowercase().into(), i);
+            }
+            if let Some(tail_number) = &owner.tail_number {
+                by_tail_number.insert(tail_number.to_ascii_uppercase().into(), i);
+            }
+        }
+        Ok(Self {
+            owners,
+            by_icao_number,
+            by_tail_number,
+        })
+    }
+
+    /// Returns the [`Owner`] of the aircraft with `icao_number`, or otherwise with `tail_number`
+    pub fn get(&self, icao_number: &str, tail_number: &str) -> Option<&Owner> {
+        self.by_icao_number
+            .get(icao_number.to_ascii_lowercase().as_str())
+            .or_else(|| {
+                self.by_tail_number
+                    .get(tail_number.to_ascii_uppercase().as_str())
+            })
+            .map(|i| &self.owners[*i])
+    }
+
+    /// The number of entries
+    pub fn len(&self) -> usize {
+        self.owners.len()
+    }
+
+    /// Whether there are no entries
+    pub fn is_empty(&self) -> bool {
+        self.owners.is_empty()
+    }
+}
+
+/// Returns the [`Owners`] in `owner/v1/data.json` of `client`, or none when it does not exist
+/// # Error
+/// Errors if the dataset cannot be read or is not valid
+pub async fn owners(client: &dyn BlobStorageProvider) -> Result<Owners, std::io::Error> {
+    match client.maybe_get(DATABASE).await? {
+        Some(data) => Owners::from_json(&data).map_err(std::io::Error::other),
+        None => {
+            log::warn!("{DATABASE} does not exist; no owners are known");
+            Ok(Owners::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn work() {
+        let data = br#"[
+            {"icao_number": "45D2ED", "owner": "Some Company A/S", "owner_type": "corporate", "source": "registry", "date": "2024-01-01"},
+            {"tail_number": "oy-abc", "operator": "Some Charter", "operator_type": "charter", "source": "website", "date": "2024-01-01"}
+        ]"#;
+        let owners = Owners::from_json(data).unwrap();
+        assert_eq!(owners.len(), 2);
+
+        let owner = owners.get("45d2ed", "OY-GFS").unwrap();
+        assert_eq!(owner.owner.as_deref(), Some("Some Company A/S"));
+        assert_eq!(owner.owner_type, Some(OwnerType::Corporate));
+        let owner = owners.get("45860d", "OY-ABC").unwrap();
+        assert_eq!(owner.operator_type.map(|x| x.as_str()), Some("charter"));
+        assert!(owners.get("45860e", "OY-ABD").is_none());
+
+        assert!(Owners::from_json(br#"[{"source": "a", "date": "b"}]"#).is_err());
+    }
+}
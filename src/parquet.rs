@@ -0,0 +1,397 @@
+//! Contains the implementation to serialize records to [Apache Parquet](https://parquet.apache.org/),
+//! an alternative to [`crate::csv`] with column types and compression for large datasets.
+use std::sync::Arc;
+
+use ::parquet::{
+    basic::Compression,
+    data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, Int64Type},
+    file::{
+        properties::WriterProperties,
+        reader::{FileReader, SerializedFileReader},
+        writer::SerializedFileWriter,
+    },
+    record::Field,
+    schema::{parser::parse_message_type, types::ColumnPath},
+};
+use time::OffsetDateTime;
+
+use crate::Position;
+
+/// Number of rows of each row group
+static ROW_GROUP_SIZE: usize = 1_000_000;
+
+/// The type of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// UTF-8 text
+    Text,
+    /// UTF-8 text with few distinct values (e.g. ICAO numbers), dictionary-encoded
+    Dictionary,
+    /// 64-bit float
+    Float,
+    /// 64-bit integer
+    Integer,
+    /// Timestamp in microseconds since the epoch, in UTC
+    Timestamp,
+    /// Boolean
+    Boolean,
+}
+
+/// A column of a [`Record`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column {
+    pub name: &'static str,
+    pub kind: Kind,
+    /// whether values can be null
+    pub nullable: bool,
+}
+
+impl Column {
+    pub fn new(name: &'static str, kind: Kind, nullable: bool) -> Self {
+        Self {
+            name,
+            kind,
+            nullable,
+        }
+    }
+}
+
+/// A value of a [`Column`] of a [`Record`] (`None` is null)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value<'a> {
+    /// A value of [`Kind::Text`] or [`Kind::Dictionary`]
+    Text(Option<&'a str>),
+    Float(Option<f64>),
+    Integer(Option<i64>),
+    Timestamp(Option<OffsetDateTime>),
+    Boolean(Option<bool>),
+}
+
+/// A type that can be converted from a [`Field`] of a Parquet row
+pub trait FromField: Sized {
+    fn from_field(field: Field) -> Result<Self, String>;
+}
+
+impl<T: FromField> FromField for Option<T> {
+    fn from_field(field: Field) -> Result<Self, String> {
+        match field {
+            Field::Null => Ok(None),
+            field => T::from_field(field).map(Some),
+        }
+    }
+}
+
+impl FromField for String {
+    fn from_field(field: Field) -> Result<Self, String> {
+        match field {
+            Field::Str(value) => Ok(value),
+            other => Err(format!("expected text, got {other}")),
+        }
+    }
+}
+
+impl FromField for Arc<str> {
+    fn from_field(field: Field) -> Result<Self, String> {
+        String::from_field(field).map(|x| x.into())
+    }
+}
+
+impl FromField for f64 {
+    fn from_field(field: Field) -> Result<Self, String> {
+        match field {
+            Field::Double(value) => Ok(value),
+            other => Err(format!("expected a float, got {other}")),
+        }
+    }
+}
+
+impl FromField for u64 {
+    fn from_field(field: Field) -> Result<Self, String> {
+        match field {
+            Field::Long(value) => u64::try_from(value).map_err(|e| e.to_string()),
+            other => Err(format!("expected an integer, got {other}")),
+        }
+    }
+}
+
+impl FromField for bool {
+    fn from_field(field: Field) -> Result<Self, String> {
+        match field {
+            Field::Bool(value) => Ok(value),
+            other => Err(format!("expected a boolean, got {other}")),
+        }
+    }
+}
+
+impl FromField for OffsetDateTime {
+    fn from_field(field: Field) -> Result<Self, String> {
+        match field {
+            Field::TimestampMicros(value) => {
+                OffsetDateTime::from_unix_timestamp_nanos(value as i128 * 1000)
+                    .map_err(|e| e.to_string())
+            }
+            other => Err(format!("expected a timestamp, got {other}")),
+        }
+    }
+}
+
+/// The fields of a Parquet row, in the order of [`Record::columns`]
+pub struct Fields(std::vec::IntoIter<(String, Field)>);
+
+impl Fields {
+    /// Returns the next field converted to `T`
+    pub fn next_field<T: FromField>(&mut self) -> Result<T, std::io::Error> {
+        let (name, field) = self
+            .0
+            .next()
+            .ok_or_else(|| std::io::Error::other("row has fewer columns than the schema"))?;
+        T::from_field(field).map_err(|e| std::io::Error::other(format!("column `{name}`: {e}")))
+    }
+}
+
+/// A type that can be serialized to and deserialized from Parquet
+pub trait Record: Sized {
+    /// The columns of the record
+    fn columns() -> Vec<Column>;
+    /// The values of the record, in the order of [`Record::columns`]
+    fn values(&self) -> Vec<Value<'_>>;
+    /// Returns the record from the values of a row, in the order of [`Record::columns`]
+    fn from_fields(fields: &mut Fields) -> Result<Self, std::io::Error>;
+}
+
+fn schema(columns: &[Column]) -> String {
+    let fields = columns
+        .iter()
+        .map(|column| {
+            let repetition = if column.nullable {
+                "OPTIONAL"
+            } else {
+                "REQUIRED"
+            };
+            let name = column.name;
+            let r#type = match column.kind {
+                Kind::Text | Kind::Dictionary => format!("BYTE_ARRAY {name} (UTF8)"),
+                Kind::Float => format!("DOUBLE {name}"),
+                Kind::Integer => format!("INT64 {name}"),
+                Kind::Timestamp => format!("INT64 {name} (TIMESTAMP(MICROS,true))"),
+                Kind::Boolean => format!("BOOLEAN {name}"),
+            };
+            format!("{repetition} {type};")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!("message schema {{\n{fields}\n}}")
+}
+
+/// Returns the non-null values of a column and, when nullable, its definition levels
+fn definition<T>(values: impl Iterator<Item = Option<T>>, nullable: bool) -> (Vec<T>, Vec<i16>) {
+    let mut levels = vec![];
+    let values = values
+        .filter_map(|value| {
+            if nullable {
+                levels.push(value.is_some() as i16);
+            }
+            value
+        })
+        .collect();
+    (values, levels)
+}
+
+/// Serializes `items` to Parquet
+/// # Implementation
+/// Items are compressed with snappy. Only columns of [`Kind::Dictionary`] are dictionary-encoded.
+pub fn serialize<R: Record>(items: impl Iterator<Item = R>) -> Result<Vec<u8>, std::io::Error> {
+    let columns = R::columns();
+    let schema = Arc::new(parse_message_type(&schema(&columns)).map_err(std::io::Error::other)?);
+    let properties = columns
+        .iter()
+        .filter(|column| column.kind == Kind::Dictionary)
+        .fold(
+            WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .set_dictionary_enabled(false),
+            |properties, column| {
+                properties.set_column_dictionary_enabled(ColumnPath::from(column.name), true)
+            },
+        )
+        .build();
+    let mut writer = SerializedFileWriter::new(vec![], schema, Arc::new(properties))
+        .map_err(std::io::Error::other)?;
+
+    let items = items.collect::<Vec<_>>();
+    for chunk in items.chunks(ROW_GROUP_SIZE) {
+        let rows = chunk.iter().map(|item| item.values()).collect::<Vec<_>>();
+        let mut row_group = writer.next_row_group().map_err(std::io::Error::other)?;
+        for (i, column) in columns.iter().enumerate() {
+            let mut writer = row_group
+                .next_column()
+                .map_err(std::io::Error::other)?
+                .ok_or_else(|| std::io::Error::other("schema has fewer columns than the record"))?;
+            let values = rows.iter().map(|row| row[i]);
+            let nullable = column.nullable;
+            let invalid = || std::io::Error::other(format!("invalid value of `{}`", column.name));
+            match column.kind {
+                Kind::Text | Kind::Dictionary => {
+                    let values = values
+                        .map(|value| match value {
+                            Value::Text(value) => {
+                                Ok(value.map(|x| ByteArray::from(x.as_bytes().to_vec())))
+                            }
+                            _ => Err(invalid()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let (values, levels) = definition(values.into_iter(), nullable);
+                    writer.typed::<ByteArrayType>().write_batch(
+                        &values,
+                        nullable.then_some(&levels),
+                        None,
+                    )
+                }
+                Kind::Float => {
+                    let values = values
+                        .map(|value| match value {
+                            Value::Float(value) => Ok(value),
+                            _ => Err(invalid()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let (values, levels) = definition(values.into_iter(), nullable);
+                    writer.typed::<DoubleType>().write_batch(
+                        &values,
+                        nullable.then_some(&levels),
+                        None,
+                    )
+                }
+                Kind::Integer => {
+                    let values = values
+                        .map(|value| match value {
+                            Value::Integer(value) => Ok(value),
+                            _ => Err(invalid()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let (values, levels) = definition(values.into_iter(), nullable);
+                    writer.typed::<Int64Type>().write_batch(
+                        &values,
+                        nullable.then_some(&levels),
+                        None,
+                    )
+                }
+                Kind::Timestamp => {
+                    let values = values
+                        .map(|value| match value {
+                            Value::Timestamp(value) => {
+                                Ok(value.map(|x| (x.unix_timestamp_nanos() / 1000) as i64))
+                            }
+                            _ => Err(invalid()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let (values, levels) = definition(values.into_iter(), nullable);
+                    writer.typed::<Int64Type>().write_batch(
+                        &values,
+                        nullable.then_some(&levels),
+                        None,
+                    )
+                }
+                Kind::Boolean => {
+                    let values = values
+                        .map(|value| match value {
+                            Value::Boolean(value) => Ok(value),
+                            _ => Err(invalid()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let (values, levels) = definition(values.into_iter(), nullable);
+                    writer.typed::<BoolType>().write_batch(
+                        &values,
+                        nullable.then_some(&levels),
+                        None,
+                    )
+                }
+            }
+            .map_err(std::io::Error::other)?;
+            writer.close().map_err(std::io::Error::other)?;
+        }
+        row_group.close().map_err(std::io::Error::other)?;
+    }
+    writer.into_inner().map_err(std::io::Error::other)
+}
+
+/// Deserializes records from Parquet written by [`serialize`]
+pub fn deserialize<R: Record>(data: &[u8]) -> Result<Vec<R>, std::io::Error> {
+    let reader = SerializedFileReader::new(bytes::Bytes::copy_from_slice(data))
+        .map_err(std::io::Error::other)?;
+    reader
+        .get_row_iter(None)
+        .map_err(std::io::Error::other)?
+        .map(|row| {
+            let row = row.map_err(std::io::Error::other)?;
+            R::from_fields(&mut Fields(row.into_columns().into_iter()))
+        })
+        .collect()
+}
+
+impl Record for Position {
+    fn columns() -> Vec<Column> {
+        vec![
+            Column::new("datetime", Kind::Timestamp, false),
+            Column::new("latitude", Kind::Float, false),
+            Column::new("longitude", Kind::Float, false),
+            Column::new("altitude", Kind::Float, true),
+            Column::new("altitude_source", Kind::Dictionary, true),
+        ]
+    }
+
+    fn values(&self) -> Vec<Value<'_>> {
+        vec![
+            Value::Timestamp(Some(self.datetime)),
+            Value::Float(Some(self.latitude)),
+            Value::Float(Some(self.longitude)),
+            Value::Float(self.altitude),
+            Value::Text(self.altitude_source.as_ref().map(|x| x.as_str())),
+        ]
+    }
+
+    fn from_fields(fields: &mut Fields) -> Result<Self, std::io::Error> {
+        Ok(Self {
+            datetime: fields.next_field()?,
+            latitude: fields.next_field()?,
+            longitude: fields.next_field()?,
+            altitude: fields.next_field()?,
+            altitude_source: fields
+                .next_field::<Option<String>>()?
+                .map(|x| x.parse())
+                .transpose()
+                .map_err(std::io::Error::other)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let positions = vec![
+            Position {
+                datetime: datetime!(2023-01-01 10:00:00.123456 UTC),
+                latitude: 55.6,
+                longitude: 12.6,
+                altitude: None,
+                altitude_source: None,
+            },
+            Position {
+                datetime: datetime!(2023-01-01 10:01:00 UTC),
+                latitude: 55.7,
+                longitude: 12.7,
+                altitude: Some(1500.0),
+                altitude_source: None,
+            },
+        ];
+
+        let data = serialize(positions.clone().into_iter()).unwrap();
+        assert_eq!(&data[..4], b"PAR1");
+        assert_eq!(deserialize::<Position>(&data).unwrap(), positions);
+        assert!(deserialize::<Position>(b"not parquet").is_err());
+    }
+}
@@ -1,14 +1,37 @@
-use std::{collections::HashMap, error::Error, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    sync::Arc,
+};
 
 use itertools::Itertools;
 use time::macros::date;
 use time::Date;
 
-use crate::{aircraft::Aircraft, fs::BlobStorageProvider, model::AircraftModel};
+use crate::{
+    aircraft::{Aircraft, Aircrafts, LazyAircrafts},
+    fs::BlobStorageProvider,
+    model::{AircraftModel, AircraftModels},
+    owners::Owners,
+    registry::filter::Filter,
+};
 
 pub type RequiredTasks = HashMap<(Arc<str>, time::Date), (Arc<Aircraft>, Arc<AircraftModel>)>;
 
-/// Returns the map `(icao_number, month) -> `[`Aircraft`] for the given set of years and (optionally) countries.
+/// A change of the aircraft (e.g. its tail number) of an ICAO number within a month (see `M-aircraft-swaps`)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Swap {
+    /// the date of the snapshot of aircrafts from which the ICAO number is of `aircraft`
+    pub date: Date,
+    pub aircraft: Arc<Aircraft>,
+    pub model: Arc<AircraftModel>,
+}
+
+/// The [`Swap`]s of `(icao_number, month)`, in order of their date, of the months with swaps
+pub type Swaps = HashMap<(Arc<str>, time::Date), Vec<Swap>>;
+
+/// Returns the map `(icao_number, month) -> `[`Aircraft`] for the given set of years and (optionally) a [`Filter`]
+/// of their registration (e.g. by country, region or tail number, see `M-registry-filters`).
 /// The key is the specific `(icao_number, month)`, the value is the [`Aircraft`] associated with that icao_number at that month.
 ///
 /// ## Background
@@ -26,62 +49,148 @@ pub type RequiredTasks = HashMap<(Arc<str>, time::Date), (Arc<Aircraft>, Arc<Air
 /// It leverages these snapshots and the set of aircraft models to return the normalized set of months, aircrafts.
 pub async fn private_jets_in_month(
     years: impl Iterator<Item = i32>,
-    maybe_country: Option<&str>,
+    maybe_filter: Option<&Filter>,
     client: &dyn BlobStorageProvider,
 ) -> Result<RequiredTasks, Box<dyn Error>> {
     let models = crate::model::load_private_jet_models()?;
+    private_jets_in_month_with_models(months_of_years(years), maybe_filter, &models, client)
+        .await
+        .map(|(required, _)| required)
+}
+
+/// Returns the first day of every month of `years`
+pub fn months_of_years(years: impl Iterator<Item = i32>) -> impl Iterator<Item = Date> {
+    years.cartesian_product(1..=12u8).map(|(year, month)| {
+        Date::from_calendar_date(year, time::Month::try_from(month).unwrap(), 1)
+            .expect("day 1 never errors")
+    })
+}
+
+/// Returns the first day of every month from `from` to `to` (inclusive)
+pub fn months_between(from: Date, to: Date) -> impl Iterator<Item = Date> {
+    let start = from.year() * 12 + from.month() as i32 - 1;
+    let end = to.year() * 12 + to.month() as i32 - 1;
+    (start..=end).map(|month| {
+        Date::from_calendar_date(
+            month.div_euclid(12),
+            time::Month::try_from((month.rem_euclid(12) + 1) as u8).unwrap(),
+            1,
+        )
+        .expect("day 1 never errors")
+    })
+}
+
+/// Same as [`private_jets_in_month`] but for a given set of `months` (first day of each month) and `models`
+/// instead of `src/models.csv` (e.g. those of a [`crate::replay::Snapshot`]), together with the [`Swaps`]
+/// of aircrafts within the months.
+pub async fn private_jets_in_month_with_models(
+    months: impl Iterator<Item = Date>,
+    maybe_filter: Option<&Filter>,
+    models: &AircraftModels,
+    client: &dyn BlobStorageProvider,
+) -> Result<(RequiredTasks, Swaps), Box<dyn Error>> {
+    let owners = match maybe_filter.filter(|filter| filter.needs_owners()) {
+        Some(_) => Some(crate::owners::owners(client).await?),
+        None => None,
+    };
     let aircrafts = crate::aircraft::read_all(client).await?;
+    let filter = maybe_filter.map(|filter| (filter, owners.as_ref()));
+    Ok(private_jets(aircrafts, months, filter, models))
+}
+
+/// Same as [`private_jets_in_month_with_models`] but restricted to `icao_numbers`, whose aircrafts are
+/// looked up on demand (see [`LazyAircrafts`]) instead of reading every snapshot of aircrafts.
+pub async fn private_jets_in_month_of(
+    icao_numbers: &HashSet<Arc<str>>,
+    months: impl Iterator<Item = Date>,
+    models: &AircraftModels,
+    client: &dyn BlobStorageProvider,
+) -> Result<(RequiredTasks, Swaps), Box<dyn Error>> {
+    let aircrafts = LazyAircrafts::new(client).await?.read(icao_numbers).await?;
+    Ok(private_jets(aircrafts, months, None, models))
+}
 
+fn private_jets(
+    aircrafts: HashMap<Date, Aircrafts>,
+    months: impl Iterator<Item = Date>,
+    maybe_filter: Option<(&Filter, Option<&Owners>)>,
+    models: &AircraftModels,
+) -> (RequiredTasks, Swaps) {
     // set of icao numbers that are private jets, for each date
     let private_jets = aircrafts
         .into_iter()
         .map(|(date, a)| {
-            (
-                date,
-                a.into_iter()
-                    // filter by optional country
-                    .filter(|(_, a)| {
-                        maybe_country
-                            .map(|country| a.country.as_deref() == Some(country))
-                            .unwrap_or(true)
-                    })
-                    // filter for private jet models and optionally country
-                    .filter_map(|(icao_number, a)| {
-                        models
-                            .get(&a.model)
-                            .map(|m| (icao_number, (Arc::new(a), m.clone())))
-                    })
-                    .collect::<HashMap<_, _>>(),
-            )
+            let jets = a
+                .into_iter()
+                // filter by optional registration
+                .filter(|(_, a)| {
+                    maybe_filter
+                        .map(|(filter, owners)| filter.matches(a, owners))
+                        .unwrap_or(true)
+                })
+                // filter for private jet models
+                .filter_map(|(icao_number, a)| {
+                    models
+                        .get(&a.model)
+                        .map(|m| (icao_number, (Arc::new(a), m.clone())))
+                })
+                .collect::<HashMap<_, _>>();
+            // cross-check the country of the tail number against the allocation of the ICAO number
+            let conflicts = jets.values().filter(|(a, _)| a.country_conflict()).count();
+            if conflicts > 0 {
+                log::warn!("{date}: {conflicts} private jets with conflicting countries");
+            }
+            (date, jets)
         })
         .collect::<HashMap<_, _>>();
 
-    // set of all months for requested years
+    // requested months that already ended
     let now = time::OffsetDateTime::now_utc().date();
     let now =
         time::Date::from_calendar_date(now.year(), now.month(), 1).expect("day 1 never errors");
-    let months = years
-        .cartesian_product(1..=12u8)
-        .map(|(year, month)| {
-            time::Date::from_calendar_date(year, time::Month::try_from(month).unwrap(), 1)
-                .expect("day 1 never errors")
-        })
-        .filter(|month| month < &now);
+    let months = months.filter(|month| month < &now);
 
-    // for each month, get the list of private jets closest from the start of month
-    let private_jets = months
-        .map(|month| {
-            let closest_date = closest_date(private_jets.keys().copied(), month);
-            private_jets
-                .get(&closest_date)
-                .unwrap()
-                .iter()
-                .map(move |(icao, aircraft)| ((icao.clone(), month), aircraft.clone()))
-        })
-        .flatten()
-        .collect::<HashMap<_, _>>();
+    let mut dates = private_jets.keys().copied().collect::<Vec<_>>();
+    dates.sort_unstable();
 
-    Ok(private_jets)
+    // for each month, get the list of private jets closest from the start of month
+    let mut required = RequiredTasks::new();
+    let mut swaps = Swaps::new();
+    for month in months {
+        let closest_date = closest_date(dates.iter().copied(), month);
+        // no snapshot of aircrafts => no private jets
+        let Some(jets) = private_jets.get(&closest_date) else {
+            continue;
+        };
+        // the later snapshots within the month
+        let next_month = crate::trace_month::first_of_next_month(&month);
+        let later = dates
+            .iter()
+            .filter(|date| **date > closest_date.max(month) && **date < next_month)
+            .map(|date| (*date, &private_jets[date]))
+            .collect::<Vec<_>>();
+        for (icao, jet) in jets {
+            let key = (icao.clone(), month);
+            let mut current = &jet.0;
+            for (date, later) in &later {
+                let Some((aircraft, model)) = later.get(icao) else {
+                    continue;
+                };
+                if (&aircraft.tail_number, &aircraft.model)
+                    != (&current.tail_number, &current.model)
+                {
+                    swaps.entry(key.clone()).or_default().push(Swap {
+                        date: *date,
+                        aircraft: aircraft.clone(),
+                        model: model.clone(),
+                    });
+                    current = aircraft;
+                }
+            }
+            required.insert(key, jet.clone());
+        }
+    }
+    (required, swaps)
 }
 
 fn closest_date(dates: impl Iterator<Item = Date>, target: Date) -> Date {
@@ -116,4 +225,82 @@ mod test {
             date!(2010 - 02 - 01)
         );
     }
+
+    #[test]
+    fn months() {
+        let months =
+            months_between(date!(2022 - 11 - 01), date!(2023 - 02 - 01)).collect::<Vec<_>>();
+        assert_eq!(
+            months,
+            vec![
+                date!(2022 - 11 - 01),
+                date!(2022 - 12 - 01),
+                date!(2023 - 01 - 01),
+                date!(2023 - 02 - 01)
+            ]
+        );
+        assert_eq!(
+            months_between(date!(2023 - 02 - 01), date!(2023 - 01 - 01)).count(),
+            0
+        );
+        assert_eq!(months_of_years(2019..2025).count(), 72);
+
+        assert_eq!(
+            crate::serde::try_parse_month("2023-02"),
+            Ok(date!(2023 - 02 - 01))
+        );
+        assert!(crate::serde::try_parse_month("2023-13").is_err());
+        assert!(crate::serde::try_parse_month("2023").is_err());
+    }
+
+    #[test]
+    fn swaps() {
+        let aircraft = |tail_number: &str| Aircraft {
+            icao_number: "459cd3".into(),
+            tail_number: tail_number.to_string(),
+            type_designator: "F2TH".to_string(),
+            model: "Falcon 2000".to_string(),
+            country: Some("DK".into()),
+            manufacture_year: None,
+        };
+        let snapshot = |tail_number| Aircrafts::from([("459cd3".into(), aircraft(tail_number))]);
+        let model = Arc::new(AircraftModel {
+            model: "Falcon 2000".to_string(),
+            gph: 300,
+            fuel: Default::default(),
+            category: Default::default(),
+            source: "test".to_string(),
+            date: "2023-01-01".to_string(),
+        });
+        let models = AircraftModels::from([("Falcon 2000".to_string(), model.clone())]);
+        let aircrafts = HashMap::from([
+            (date!(2023 - 01 - 01), snapshot("OY-GFS")),
+            // the tail number moves to another airframe mid-month
+            (date!(2023 - 01 - 15), snapshot("OY-CKK")),
+            (date!(2023 - 02 - 01), snapshot("OY-CKK")),
+        ]);
+        let months = [date!(2023 - 01 - 01), date!(2023 - 02 - 01)].into_iter();
+
+        let (required, swaps) = private_jets(aircrafts, months, None, &models);
+        let key = |month| ("459cd3".into(), month);
+        assert_eq!(
+            required[&key(date!(2023 - 01 - 01))].0.tail_number,
+            "OY-GFS"
+        );
+        assert_eq!(
+            required[&key(date!(2023 - 02 - 01))].0.tail_number,
+            "OY-CKK"
+        );
+        assert_eq!(
+            swaps,
+            Swaps::from([(
+                key(date!(2023 - 01 - 01)),
+                vec![Swap {
+                    date: date!(2023 - 01 - 15),
+                    aircraft: Arc::new(aircraft("OY-CKK")),
+                    model,
+                }]
+            )])
+        );
+    }
 }
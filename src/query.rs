@@ -0,0 +1,186 @@
+//! Contains the queries of the database of legs (`M-identify-legs`), so that consumers read its partitions
+//! without reimplementing their layout (`leg/v2/data/month={month}/icao_number={icao}/data.csv`) nor their schema.
+use futures::{Stream, StreamExt, TryStreamExt};
+use time::Date;
+
+use crate::{
+    compression::Compression,
+    etl::legs::{LegOut, Roots, DEFAULT_VERSION},
+    format::Format,
+    fs::BlobStorageProvider,
+    Error,
+};
+
+/// Returns the legs of `icao_number` starting from `from` to `to` (inclusive, in UTC), ordered by their start,
+/// from the database of legs of the published version (see [`Roots::published`] and [`legs_of`]).
+pub fn legs<'a>(
+    icao_number: &'a str,
+    from: Date,
+    to: Date,
+    client: &'a dyn BlobStorageProvider,
+) -> impl Stream<Item = Result<LegOut, Error>> + 'a {
+    futures::stream::once(Roots::published(client))
+        .map_ok(move |roots| legs_of(roots, icao_number, from, to, client))
+        .try_flatten()
+}
+
+/// Returns the legs of `icao_number` starting from `from` to `to` (inclusive, in UTC), ordered by their start,
+/// from the database of legs at `roots`.
+/// # Implementation
+/// Only the partitions of the months from `from` to `to` are read, one at a time, in any [`Format`] and
+/// [`Compression`]. Months without a partition in the default version are read from the legacy database
+/// (see [`crate::legacy`]); months without a partition in either (e.g. without positions) have no legs.
+pub fn legs_of<'a>(
+    roots: Roots,
+    icao_number: &'a str,
+    from: Date,
+    to: Date,
+    client: &'a dyn BlobStorageProvider,
+) -> impl Stream<Item = Result<LegOut, Error>> + 'a {
+    let icao_number = icao_number.to_ascii_lowercase();
+    futures::stream::iter(crate::months_between(from, to))
+        .then(move |month| {
+            let key = |format| {
+                crate::etl::legs::pk_to_blob_name(
+                    &roots,
+                    &icao_number,
+                    month,
+                    format,
+                    Compression::None,
+                )
+            };
+            let keys = [Format::Csv, Format::Parquet].map(|format| (format, key(format)));
+            let legacy = (&*roots.version == DEFAULT_VERSION)
+                .then(|| crate::legacy::pk_to_blob_name(&icao_number, month));
+            async move {
+                for (format, key) in keys {
+                    if let Some(data) = crate::io::maybe_get(&key, client).await? {
+                        return Ok(crate::etl::legs::deserialize_legs(&data, format)?);
+                    }
+                }
+                if let Some(key) = legacy {
+                    return Ok(crate::legacy::read(&key, client).await?.unwrap_or_default());
+                }
+                Ok::<_, Error>(vec![])
+            }
+        })
+        .map_ok(move |mut legs| {
+            legs.retain(|leg| (from..=to).contains(&leg.start.date()));
+            legs.sort_unstable_by_key(|leg| leg.start);
+            futures::stream::iter(legs.into_iter().map(Ok))
+        })
+        .try_flatten()
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+
+    fn leg(icao_number: &str, start: &str) -> LegOut {
+        serde_json::from_value(serde_json::json!({
+            "icao_number": icao_number,
+            "start": start,
+            "start_lat": 55.6,
+            "start_lon": 12.6,
+            "start_altitude": 0.0,
+            "end": start,
+            "end_lat": 49.0,
+            "end_lon": 2.5,
+            "end_altitude": 0.0,
+            "duration": 1.5,
+            "distance": 1000.0,
+            "great_circle_distance": 1000.0,
+            "midpoint_lat": 52.3,
+            "midpoint_lon": 7.5,
+            "hours_above_30000": 1.0,
+            "hours_above_40000": 0.0,
+            "diverted": false,
+            "start_on_ground": true,
+            "end_on_ground": true,
+            "taxi_out_minutes": 0.0,
+            "taxi_in_minutes": 0.0,
+            "commercial_alternative_exists": true,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn work() {
+        let root = std::env::temp_dir().join("test_query");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let roots = Roots::default();
+        let write = |month, legs: Vec<LegOut>, compression| {
+            let key = crate::etl::legs::pk_to_blob_name(
+                &roots,
+                "459cd3",
+                month,
+                Format::Csv,
+                Compression::None,
+            );
+            let data = crate::csv::serialize(legs.into_iter());
+            let disk = &disk;
+            async move { crate::io::put(&key, data, compression, disk).await }
+        };
+        write(
+            date!(2023 - 01 - 01),
+            vec![
+                leg("459cd3", "2023-01-20T10:00:00Z"),
+                leg("459cd3", "2023-01-02T10:00:00Z"),
+            ],
+            Compression::None,
+        )
+        .await
+        .unwrap();
+        write(
+            date!(2023 - 03 - 01),
+            vec![
+                leg("459cd3", "2023-03-01T10:00:00Z"),
+                leg("459cd3", "2023-03-31T10:00:00Z"),
+            ],
+            Compression::Zstd,
+        )
+        .await
+        .unwrap();
+
+        let starts = |from, to| {
+            legs("459CD3", from, to, &disk)
+                .map_ok(|leg| leg.start.date())
+                .try_collect::<Vec<_>>()
+        };
+        assert_eq!(
+            starts(date!(2023 - 01 - 10), date!(2023 - 03 - 01))
+                .await
+                .unwrap(),
+            vec![date!(2023 - 01 - 20), date!(2023 - 03 - 01)]
+        );
+        assert_eq!(
+            starts(date!(2023 - 01 - 01), date!(2023 - 12 - 31))
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+        assert!(starts(date!(2022 - 01 - 01), date!(2022 - 12 - 31))
+            .await
+            .unwrap()
+            .is_empty());
+
+        // months not migrated are read from the legacy database
+        let legacy = b"icao_number,start,from_lat,from_lon,from_altitude,end,to_lat,to_lon,to_altitude,duration,distance,hours_above_30000,hours_above_40000
+459cd3,2019-05-02 10:00:00,55.6,12.6,0,2019-05-02 11:30:00,49.0,2.5,0,1.5,1100,1.0,0.0
+";
+        let key = crate::legacy::pk_to_blob_name("459cd3", date!(2019 - 05 - 01));
+        crate::io::put(&key, legacy.to_vec(), Compression::None, &disk)
+            .await
+            .unwrap();
+        assert_eq!(
+            starts(date!(2019 - 01 - 01), date!(2023 - 01 - 10))
+                .await
+                .unwrap(),
+            vec![date!(2019 - 05 - 02), date!(2023 - 01 - 02)]
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Contains the implementation of geographic regions used to restrict which legs are processed.
+use serde_json::Value;
+
+use crate::{geo::EARTH_RADIUS, Position};
+
+/// A ring of `(longitude, latitude)` points in degrees
+type Ring = Vec<(f64, f64)>;
+
+/// A geographic region
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    /// A bounding box in degrees
+    BoundingBox {
+        min_lon: f64,
+        min_lat: f64,
+        max_lon: f64,
+        max_lat: f64,
+    },
+    /// A set of polygons. The first ring of each polygon is its exterior; the others are its holes
+    Polygons(Vec<Vec<Ring>>),
+    /// All points within `width / 2` km of a path of `(longitude, latitude)` points
+    /// (e.g. a fjord or an airway)
+    Corridor { path: Ring, width: f64 },
+}
+
+/// Returns the angular distance and the initial bearing from `from` to `to`, all `(longitude, latitude)` in radians
+pub(crate) fn angular_distance_bearing(
+    (lon1, lat1): (f64, f64),
+    (lon2, lat2): (f64, f64),
+) -> (f64, f64) {
+    let d_lat = lat2 - lat1;
+    let d_lon = lon2 - lon1;
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+    let distance = 2.0 * a.sqrt().atan2((1.0 - a).sqrt());
+    let bearing = (d_lon.sin() * lat2.cos())
+        .atan2(lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * d_lon.cos());
+    (distance, bearing)
+}
+
+/// Returns the distance in km from `point` to the great-circle segment from `from` to `to`,
+/// all `(longitude, latitude)` in degrees
+pub(crate) fn distance_to_segment(point: (f64, f64), from: (f64, f64), to: (f64, f64)) -> f64 {
+    let radians = |(lon, lat): (f64, f64)| (lon.to_radians(), lat.to_radians());
+    let (point, from, to) = (radians(point), radians(from), radians(to));
+    let (d13, bearing13) = angular_distance_bearing(from, point);
+    let (d12, bearing12) = angular_distance_bearing(from, to);
+    let cross_track = (d13.sin() * (bearing13 - bearing12).sin()).asin();
+    let distance = if (bearing13 - bearing12).cos() < 0.0 {
+        // behind the start of the segment
+        d13
+    } else {
+        let along_track = (d13.cos() / cross_track.cos()).clamp(-1.0, 1.0).acos();
+        if along_track > d12 {
+            angular_distance_bearing(to, point).0
+        } else {
+            cross_track.abs()
+        }
+    };
+    distance * EARTH_RADIUS
+}
+
+/// Returns whether `(lon, lat)` is inside `ring` (even-odd rule)
+pub(crate) fn ring_contains(ring: &[(f64, f64)], (lon, lat): (f64, f64)) -> bool {
+    let mut inside = false;
+    let mut j = ring.len().wrapping_sub(1);
+    for i in 0..ring.len() {
+        let (xi, yi) = ring[i];
+        let (xj, yj) = ring[j];
+        if (yi > lat) != (yj > lat) && lon < (xj - xi) * (lat - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+fn parse_ring(value: &Value) -> Option<Ring> {
+    value
+        .as_array()?
+        .iter()
+        .map(|point| {
+            let point = point.as_array()?;
+            Some((point.first()?.as_f64()?, point.get(1)?.as_f64()?))
+        })
+        .collect()
+}
+
+fn parse_polygon(value: &Value) -> Option<Vec<Ring>> {
+    value.as_array()?.iter().map(parse_ring).collect()
+}
+
+/// Returns all polygons of a GeoJSON object
+pub(crate) fn parse_geojson(value: &Value) -> Result<Vec<Vec<Ring>>, String> {
+    let invalid = || format!("invalid GeoJSON object: {value}");
+    match value.get("type").and_then(|x| x.as_str()) {
+        Some("FeatureCollection") => Ok(value
+            .get("features")
+            .and_then(|x| x.as_array())
+            .ok_or_else(invalid)?
+            .iter()
+            .map(parse_geojson)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect()),
+        Some("Feature") => parse_geojson(value.get("geometry").ok_or_else(invalid)?),
+        Some("Polygon") => Ok(vec![value
+            .get("coordinates")
+            .and_then(parse_polygon)
+            .ok_or_else(invalid)?]),
+        Some("MultiPolygon") => value
+            .get("coordinates")
+            .and_then(|x| x.as_array())
+            .and_then(|polygons| polygons.iter().map(parse_polygon).collect())
+            .ok_or_else(invalid),
+        Some(other) => Err(format!("GeoJSON type `{other}` is not a polygon")),
+        None => Err(invalid()),
+    }
+}
+
+impl Region {
+    /// Returns a [`Region::BoundingBox`] from `"min_lon,min_lat,max_lon,max_lat"`
+    pub fn from_bbox(bbox: &str) -> Result<Self, String> {
+        let values = bbox
+            .split(',')
+            .map(|x| x.trim().parse::<f64>().map_err(|e| format!("{bbox}: {e}")))
+            .collect::<Result<Vec<_>, _>>()?;
+        let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+            return Err(format!("{bbox} must be min_lon,min_lat,max_lon,max_lat"));
+        };
+        if min_lon > max_lon || min_lat > max_lat {
+            return Err(format!("{bbox} must have min values lower than max values"));
+        }
+        Ok(Self::BoundingBox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+
+    /// Returns a [`Region::Polygons`] from a GeoJSON `Polygon`, `MultiPolygon`, `Feature` or `FeatureCollection`
+    pub fn from_geojson(data: &[u8]) -> Result<Self, String> {
+        let value = serde_json::from_slice::<Value>(data).map_err(|e| e.to_string())?;
+        Ok(Self::Polygons(parse_geojson(&value)?))
+    }
+
+    /// Returns whether the point `(latitude, longitude)` is inside the region
+    pub fn contains(&self, (latitude, longitude): (f64, f64)) -> bool {
+        match self {
+            Self::BoundingBox {
+                min_lon,
+                min_lat,
+                max_lon,
+                max_lat,
+            } => {
+                (*min_lon..=*max_lon).contains(&longitude)
+                    && (*min_lat..=*max_lat).contains(&latitude)
+            }
+            Self::Polygons(polygons) => polygons.iter().any(|rings| {
+                let mut rings = rings.iter();
+                rings
+                    .next()
+                    .map(|exterior| ring_contains(exterior, (longitude, latitude)))
+                    .unwrap_or(false)
+                    && !rings.any(|hole| ring_contains(hole, (longitude, latitude)))
+            }),
+            Self::Corridor { path, width } => match path[..] {
+                [] => false,
+                [point] => distance_to_segment((longitude, latitude), point, point) <= width / 2.0,
+                _ => path.windows(2).any(|segment| {
+                    distance_to_segment((longitude, latitude), segment[0], segment[1])
+                        <= width / 2.0
+                }),
+            },
+        }
+    }
+
+    /// Returns whether any of the `positions` is inside the region
+    pub fn touches(&self, positions: &[Position]) -> bool {
+        positions.iter().any(|p| self.contains(p.pos()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bbox() {
+        let region = Region::from_bbox("8.0,54.5,15.2,57.8").unwrap();
+        // Copenhagen
+        assert!(region.contains((55.68, 12.57)));
+        // Berlin
+        assert!(!region.contains((52.52, 13.40)));
+
+        assert!(Region::from_bbox("8.0,54.5,15.2").is_err());
+        assert!(Region::from_bbox("15.2,54.5,8.0,57.8").is_err());
+        assert!(Region::from_bbox("a,54.5,15.2,57.8").is_err());
+    }
+
+    #[test]
+    fn geojson() {
+        let data = br#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [
+                        [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0], [0.0, 0.0]],
+                        [[4.0, 4.0], [6.0, 4.0], [6.0, 6.0], [4.0, 6.0], [4.0, 4.0]]
+                    ]
+                }
+            }]
+        }"#;
+        let region = Region::from_geojson(data).unwrap();
+        assert!(region.contains((1.0, 2.0)));
+        assert!(!region.contains((5.0, 5.0)));
+        assert!(!region.contains((11.0, 5.0)));
+
+        assert!(Region::from_geojson(br#"{"type": "Point", "coordinates": [0, 0]}"#).is_err());
+    }
+
+    #[test]
+    fn corridor() {
+        // 20 km wide corridor along the equator
+        let region = Region::Corridor {
+            path: vec![(0.0, 0.0), (1.0, 0.0)],
+            width: 20.0,
+        };
+        // ~5.6 km north of the path
+        assert!(region.contains((0.05, 0.5)));
+        // ~22 km north of the path
+        assert!(!region.contains((0.2, 0.5)));
+        // ~11 km after the end of the path
+        assert!(!region.contains((0.0, 1.1)));
+        // ~5.6 km before the start of the path
+        assert!(region.contains((0.0, -0.05)));
+    }
+}
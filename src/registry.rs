@@ -0,0 +1,2 @@
+//! Contains the selection of aircrafts by their registration (see `M-registry-filters`).
+pub mod filter;
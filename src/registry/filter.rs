@@ -0,0 +1,173 @@
+//! Contains the filters of aircrafts by their registration (`M-registry-filters`), e.g. to process the aircrafts
+//! registered in Greenland (`GL`) separately from those registered in Denmark (`Denmark,!GL`).
+use std::sync::Arc;
+
+use crate::{aircraft::Aircraft, owners::Owners};
+
+/// A rule of a [`Filter`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rule {
+    /// The country of registration of the aircraft (see `M-country-of-registration`), e.g. `Denmark`
+    Country(Arc<str>),
+    /// The region (ISO 3166-1 alpha-2 or ISO 3166-2) of the owner of the aircraft (see `M-owners`),
+    /// e.g. `GL` or `DK-81`; a region matches its subdivisions (`DK` matches `DK-81`)
+    Region(Arc<str>),
+    /// A prefix of the tail number of the aircraft, written with a trailing `*`, e.g. `OY-H*`
+    TailNumber(Arc<str>),
+}
+
+impl std::str::FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("a rule must not be empty".to_string());
+        }
+        if let Some(prefix) = s.strip_suffix('*') {
+            return Ok(Self::TailNumber(prefix.to_ascii_uppercase().into()));
+        }
+        let (country, subdivision) = s.split_once('-').unwrap_or((s, "a"));
+        let is_region = country.len() == 2
+            && country.chars().all(|c| c.is_ascii_alphabetic())
+            && (1..=3).contains(&subdivision.len())
+            && subdivision.chars().all(|c| c.is_ascii_alphanumeric());
+        Ok(if is_region {
+            Self::Region(s.to_ascii_uppercase().into())
+        } else {
+            Self::Country(s.into())
+        })
+    }
+}
+
+impl Rule {
+    fn matches(&self, aircraft: &Aircraft, region: Option<&str>) -> bool {
+        match self {
+            Self::Country(country) => aircraft.country_or_allocation().as_ref() == Some(country),
+            Self::Region(expected) => region.is_some_and(|region| {
+                region.eq_ignore_ascii_case(expected)
+                    || region
+                        .to_ascii_uppercase()
+                        .strip_prefix(expected.as_ref())
+                        .is_some_and(|rest| rest.starts_with('-'))
+            }),
+            Self::TailNumber(prefix) => aircraft
+                .tail_number
+                .to_ascii_uppercase()
+                .starts_with(prefix.as_ref()),
+        }
+    }
+
+    fn kind(&self) -> usize {
+        match self {
+            Self::Country(_) => 0,
+            Self::Region(_) => 1,
+            Self::TailNumber(_) => 2,
+        }
+    }
+}
+
+/// A filter of aircrafts, written as comma-separated [`Rule`]s, each optionally prefixed by `!` to exclude the
+/// aircrafts matching it (e.g. `Denmark,!GL` or `GL,OY-H*`). An aircraft passes the filter when:
+/// * for each kind of rule that is included, it matches one of the included rules of that kind, and
+/// * it matches none of the excluded rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    include: Vec<Rule>,
+    exclude: Vec<Rule>,
+}
+
+impl std::str::FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut include = vec![];
+        let mut exclude = vec![];
+        for rule in s.split(',').map(str::trim) {
+            match rule.strip_prefix('!') {
+                Some(rule) => exclude.push(rule.parse()?),
+                None => include.push(rule.parse()?),
+            }
+        }
+        Ok(Self { include, exclude })
+    }
+}
+
+impl Filter {
+    /// Whether the filter has [`Rule::Region`]s, whose regions are those of the owners of the aircrafts
+    pub fn needs_owners(&self) -> bool {
+        self.include
+            .iter()
+            .chain(self.exclude.iter())
+            .any(|rule| matches!(rule, Rule::Region(_)))
+    }
+
+    /// Whether `aircraft` passes the filter, with the regions of its owner in `owners`
+    /// (aircrafts without an owner or region match no [`Rule::Region`])
+    pub fn matches(&self, aircraft: &Aircraft, owners: Option<&Owners>) -> bool {
+        let region = owners
+            .and_then(|owners| owners.get(&aircraft.icao_number, &aircraft.tail_number))
+            .and_then(|owner| owner.region.as_deref());
+        let included = (0..3).all(|kind| {
+            let mut rules = self
+                .include
+                .iter()
+                .filter(|rule| rule.kind() == kind)
+                .peekable();
+            rules.peek().is_none() || rules.any(|rule| rule.matches(aircraft, region))
+        });
+        included
+            && !self
+                .exclude
+                .iter()
+                .any(|rule| rule.matches(aircraft, region))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn work() {
+        assert_eq!("Denmark".parse(), Ok(Rule::Country("Denmark".into())));
+        assert_eq!("gl".parse(), Ok(Rule::Region("GL".into())));
+        assert_eq!("DK-81".parse(), Ok(Rule::Region("DK-81".into())));
+        assert_eq!("oy-h*".parse(), Ok(Rule::TailNumber("OY-H".into())));
+        assert!("Denmark,,GL".parse::<Filter>().is_err());
+
+        let aircraft = |icao_number: &str, tail_number: &str| Aircraft {
+            icao_number: icao_number.into(),
+            tail_number: tail_number.to_string(),
+            type_designator: "F2TH".to_string(),
+            model: "Falcon 2000".to_string(),
+            country: Some("Denmark".into()),
+            manufacture_year: None,
+        };
+        let owners = Owners::from_json(
+            br#"[
+            {"tail_number": "OY-HGA", "region": "GL", "source": "registry", "date": "2024-01-01"},
+            {"tail_number": "OY-GFS", "region": "DK-81", "source": "registry", "date": "2024-01-01"}
+        ]"#,
+        )
+        .unwrap();
+        let owners = Some(&owners);
+        let greenland = aircraft("458d6b", "OY-HGA");
+        let jutland = aircraft("459cd3", "OY-GFS");
+        let unknown = aircraft("45d2ed", "OY-CKK");
+        let matches = |filter: &str| {
+            let filter = filter.parse::<Filter>().unwrap();
+            [&greenland, &jutland, &unknown].map(|x| filter.matches(x, owners))
+        };
+
+        assert_eq!(matches("Denmark"), [true, true, true]);
+        assert_eq!(matches("Denmark,!GL"), [false, true, true]);
+        assert_eq!(matches("GL"), [true, false, false]);
+        assert_eq!(matches("DK"), [false, true, false]);
+        assert_eq!(matches("DK-81,GL"), [true, true, false]);
+        assert_eq!(matches("GL,OY-H*"), [true, false, false]);
+        assert_eq!(matches("OY-H*,OY-C*"), [true, false, true]);
+        assert!(matches("Sweden").iter().all(|x| !x));
+        assert!("GL".parse::<Filter>().unwrap().needs_owners());
+        assert!(!"Denmark".parse::<Filter>().unwrap().needs_owners());
+    }
+}
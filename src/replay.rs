@@ -0,0 +1,236 @@
+//! Contains the implementation to replay a run of the pipeline against a stored snapshot, to verify that
+//! it reproduces previously published datasets bit-for-bit (e.g. after refactoring the ETL).
+//!
+//! A snapshot is a directory with the blobs a run reads (database of aircrafts, positions, airports, etc.),
+//! the published datasets it is compared against, and the model table the datasets were computed with at
+//! `replay/models.csv` and `replay/models_overrides.csv` (copies of `src/models.csv` and `src/models_overrides.csv`).
+use std::{collections::HashMap, error::Error, path::PathBuf, sync::Mutex};
+
+use async_trait::async_trait;
+
+use crate::{
+    fs::BlobStorageProvider,
+    fs_local::LocalDisk,
+    model::{AircraftModels, ModelOverride},
+};
+
+static MODELS: &str = "replay/models.csv";
+static MODELS_OVERRIDES: &str = "replay/models_overrides.csv";
+
+/// Whether a blob changes on every run (e.g. `status.json` contains when it was written)
+/// and is thus not compared
+fn is_volatile(blob_name: &str) -> bool {
+    blob_name.ends_with("status.json")
+}
+
+/// A difference between a blob written by a replay and the snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The blob was written but is not in the snapshot
+    Missing(String),
+    /// The blob was written with different contents than in the snapshot
+    Different(String),
+}
+
+impl std::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(blob_name) => write!(f, "{blob_name}: not in the snapshot"),
+            Self::Different(blob_name) => write!(f, "{blob_name}: differs from the snapshot"),
+        }
+    }
+}
+
+/// A [`BlobStorageProvider`] that reads from a snapshot on local disk and keeps writes in memory,
+/// so that the snapshot is never modified by a replay.
+#[derive(Debug)]
+pub struct Snapshot {
+    snapshot: LocalDisk,
+    /// blobs written (`Some`) or deleted (`None`) by the replay
+    written: Mutex<HashMap<String, Option<Vec<u8>>>>,
+}
+
+impl Snapshot {
+    /// Returns a new [`Snapshot`] of the directory `root`
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            snapshot: LocalDisk::new(root),
+            written: Default::default(),
+        }
+    }
+
+    /// Returns the [`ModelOverride`]s of the snapshot (none when it has no `replay/models_overrides.csv`)
+    /// # Error
+    /// Errors if the file cannot be read or is not a valid CSV
+    pub async fn model_overrides(&self) -> Result<Vec<ModelOverride>, Box<dyn Error>> {
+        match self.snapshot.maybe_get(MODELS_OVERRIDES).await? {
+            Some(data) => crate::model::parse_model_overrides(&data),
+            None => Ok(vec![]),
+        }
+    }
+
+    /// Returns the private jet models of the snapshot, as in [`crate::model::load_private_jet_models`]
+    /// # Error
+    /// Errors if the snapshot has no `replay/models.csv` or it is not a valid CSV
+    pub async fn models(&self) -> Result<AircraftModels, Box<dyn Error>> {
+        let models = self
+            .snapshot
+            .maybe_get(MODELS)
+            .await?
+            .ok_or_else(|| format!("the snapshot has no {MODELS}"))?;
+        crate::model::private_jet_models(&models, &self.model_overrides().await?)
+    }
+
+    /// Compares the blobs written by the replay whose name starts with any of `prefixes` to the snapshot.
+    /// Returns the [`Mismatch`]es, ordered by blob name; blobs that change on every run (`status.json`) are ignored.
+    /// # Error
+    /// Errors if the snapshot cannot be read
+    pub async fn verify(&self, prefixes: &[&str]) -> Result<Vec<Mismatch>, std::io::Error> {
+        let mut written = self
+            .written
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(key, _)| prefixes.iter().any(|prefix| key.starts_with(prefix)))
+            .filter(|(key, _)| !is_volatile(key))
+            .filter_map(|(key, data)| Some((key.clone(), data.clone()?)))
+            .collect::<Vec<_>>();
+        written.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let mut mismatches = vec![];
+        for (key, data) in written {
+            match self.snapshot.maybe_get(&key).await? {
+                None => mismatches.push(Mismatch::Missing(key)),
+                Some(expected) if expected != data => mismatches.push(Mismatch::Different(key)),
+                Some(_) => {}
+            }
+        }
+        Ok(mismatches)
+    }
+}
+
+#[async_trait]
+impl BlobStorageProvider for Snapshot {
+    #[must_use]
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let written = self.written.lock().unwrap().get(blob_name).cloned();
+        match written {
+            Some(data) => Ok(data),
+            None => self.snapshot.maybe_get(blob_name).await,
+        }
+    }
+
+    #[must_use]
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        self.written
+            .lock()
+            .unwrap()
+            .insert(blob_name.to_string(), Some(contents));
+        Ok(())
+    }
+
+    #[must_use]
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        let mut keys = self.snapshot.list(prefix).await?;
+        let written = self.written.lock().unwrap();
+        keys.extend(
+            written
+                .iter()
+                .filter(|(key, data)| key.starts_with(prefix) && data.is_some())
+                .map(|(key, _)| key.clone()),
+        );
+        keys.sort_unstable();
+        keys.dedup();
+        keys.retain(|key| !matches!(written.get(key), Some(None)));
+        Ok(keys)
+    }
+
+    #[must_use]
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        self.written
+            .lock()
+            .unwrap()
+            .insert(blob_name.to_string(), None);
+        Ok(())
+    }
+
+    /// Blobs written during the replay have no entity tag
+    async fn etag(&self, blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        if self.written.lock().unwrap().contains_key(blob_name) {
+            return Ok(None);
+        }
+        self.snapshot.etag(blob_name).await
+    }
+
+    fn can_put(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn work() {
+        let root = std::env::temp_dir().join("test_replay");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = LocalDisk::new(&root);
+        disk.put("leg/v2/all/year=2023/data.csv", b"a".to_vec())
+            .await
+            .unwrap();
+        disk.put("leg/v2/all/year=2022/data.csv", b"b".to_vec())
+            .await
+            .unwrap();
+        disk.put("position/a.json", b"c".to_vec()).await.unwrap();
+
+        let snapshot = Snapshot::new(&root);
+        assert!(snapshot.models().await.is_err());
+        assert_eq!(snapshot.model_overrides().await.unwrap(), vec![]);
+
+        snapshot
+            .put("leg/v2/all/year=2023/data.csv", b"a".to_vec())
+            .await
+            .unwrap();
+        snapshot
+            .put("leg/v2/all/year=2022/data.csv", b"changed".to_vec())
+            .await
+            .unwrap();
+        snapshot
+            .put("leg/v2/all/year=2021/data.csv", b"d".to_vec())
+            .await
+            .unwrap();
+        snapshot
+            .put("leg/v2/status.json", b"{}".to_vec())
+            .await
+            .unwrap();
+        snapshot.delete("position/a.json").await.unwrap();
+
+        // the snapshot is not modified
+        assert_eq!(
+            disk.maybe_get("leg/v2/all/year=2022/data.csv")
+                .await
+                .unwrap(),
+            Some(b"b".to_vec())
+        );
+        assert_eq!(
+            snapshot
+                .maybe_get("leg/v2/all/year=2022/data.csv")
+                .await
+                .unwrap(),
+            Some(b"changed".to_vec())
+        );
+        assert_eq!(snapshot.maybe_get("position/a.json").await.unwrap(), None);
+        assert_eq!(snapshot.list("position/").await.unwrap().len(), 0);
+        assert_eq!(snapshot.list("leg/v2/all/").await.unwrap().len(), 3);
+
+        assert_eq!(
+            snapshot.verify(&["leg/v2/"]).await.unwrap(),
+            vec![
+                Mismatch::Missing("leg/v2/all/year=2021/data.csv".to_string()),
+                Mismatch::Different("leg/v2/all/year=2022/data.csv".to_string()),
+            ]
+        );
+        assert_eq!(snapshot.verify(&["activity/v1/"]).await.unwrap(), vec![]);
+    }
+}
@@ -0,0 +1,180 @@
+//! Contains the implementation of the dataset of retired aircrafts (`M-retired`): aircrafts that disappeared from the
+//! database of aircrafts and stopped flying.
+use std::{collections::HashMap, error::Error, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{aircraft::Aircrafts, fs::BlobStorageProvider};
+
+static ROOT: &str = "retired/v1/";
+static DATABASE: &str = "retired/v1/data.csv";
+
+/// A retired aircraft
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Retired {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The tail number, from the last snapshot of aircrafts containing the ICAO number
+    pub tail_number: String,
+    /// The model, from the last snapshot of aircrafts containing the ICAO number
+    pub model: String,
+    /// The last month with positions of the aircraft (e.g. `2021-03`)
+    pub last_flight_month: String,
+    /// The date of the last snapshot of aircrafts containing the ICAO number (e.g. `2022-01-01`)
+    pub last_registry: String,
+    /// The date of the first snapshot of aircrafts after `last_registry`, the first without the ICAO number
+    pub deregistered: String,
+    /// The number of months between `last_flight_month` and the month of the most recent snapshot of aircrafts
+    pub months_inactive: u32,
+}
+
+crate::schema!(Retired {
+    icao_number: "The ICAO number",
+    tail_number: "The tail number, from the last snapshot of aircrafts containing the ICAO number",
+    model: "The model, from the last snapshot of aircrafts containing the ICAO number",
+    last_flight_month: "The last month with positions of the aircraft (e.g. 2021-03)",
+    last_registry: "The date of the last snapshot of aircrafts containing the ICAO number (e.g. 2022-01-01)",
+    deregistered: "The date of the first snapshot of aircrafts without the ICAO number",
+    months_inactive("months"): "The number of months between `last_flight_month` and the most recent snapshot of aircrafts",
+});
+
+/// Returns the number of months from `from` to `to` (zero when `to` is not after `from`)
+fn months_between(from: Date, to: Date) -> u32 {
+    let month = |date: Date| date.year() * 12 + date.month() as i32;
+    (month(to) - month(from)).max(0) as u32
+}
+
+/// Returns the [`Retired`] aircrafts from the set of `(icao_number, month)` with positions and the snapshots of
+/// aircrafts, ordered by ICAO number.
+/// An aircraft is retired when it is not in the most recent snapshot of aircrafts and it has no positions
+/// on the `min_months` months before it.
+/// ICAO numbers in no snapshot are not retired (see `M-backfill`).
+pub fn retired(
+    months: impl Iterator<Item = (Arc<str>, Date)>,
+    aircrafts: &HashMap<Date, Aircrafts>,
+    min_months: u32,
+) -> Vec<Retired> {
+    let last_months = months.fold(
+        HashMap::<Arc<str>, Date>::new(),
+        |mut acc, (icao_number, month)| {
+            acc.entry(icao_number)
+                .and_modify(|last| *last = (*last).max(month))
+                .or_insert(month);
+            acc
+        },
+    );
+
+    let mut snapshots = aircrafts.iter().collect::<Vec<_>>();
+    // most recent first
+    snapshots.sort_unstable_by(|a, b| b.0.cmp(a.0));
+    let Some((latest, _)) = snapshots.first() else {
+        return vec![];
+    };
+
+    let mut retired = last_months
+        .into_iter()
+        .filter_map(|(icao_number, last_month)| {
+            let position = snapshots
+                .iter()
+                .position(|(_, aircrafts)| aircrafts.contains_key(&icao_number))?;
+            // in the most recent snapshot
+            let deregistered = *snapshots[position.checked_sub(1)?].0;
+            let (last_registry, aircrafts) = snapshots[position];
+            let aircraft = aircrafts.get(&icao_number)?;
+
+            let months_inactive = months_between(last_month, **latest);
+            (months_inactive >= min_months).then(|| Retired {
+                tail_number: aircraft.tail_number.clone(),
+                model: aircraft.model.clone(),
+                last_flight_month: crate::serde::month_to_part(last_month),
+                last_registry: last_registry.to_string(),
+                deregistered: deregistered.to_string(),
+                months_inactive,
+                icao_number,
+            })
+        })
+        .collect::<Vec<_>>();
+    retired.sort_unstable_by(|a, b| a.icao_number.cmp(&b.icao_number));
+    retired
+}
+
+/// Computes the [`Retired`] aircrafts from the database of positions and aircrafts and writes them to `client`.
+pub async fn etl_retired(
+    client: &dyn BlobStorageProvider,
+    min_months: u32,
+) -> Result<(), Box<dyn Error>> {
+    let months = crate::icao_to_trace::list_months_positions(client).await?;
+    let aircrafts = crate::aircraft::read_all(client).await?;
+
+    let retired = retired(months.into_iter(), &aircrafts, min_months);
+    log::info!("retired aircrafts: {}", retired.len());
+    client
+        .put(DATABASE, crate::csv::serialize(retired.into_iter()))
+        .await?;
+    log::info!("Written {DATABASE}");
+    crate::schema::write::<Retired>(ROOT, crate::units::Units::Metric, client).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+    use crate::aircraft::Aircraft;
+
+    #[test]
+    fn work() {
+        let aircraft = |icao_number: &str| {
+            (
+                icao_number.into(),
+                Aircraft {
+                    icao_number: icao_number.into(),
+                    tail_number: "OY-GFS".to_string(),
+                    type_designator: "F2TH".to_string(),
+                    model: "FALCON 2000".to_string(),
+                    country: None,
+                    manufacture_year: Some(2007),
+                },
+            )
+        };
+        let aircrafts = HashMap::from([
+            (
+                date!(2022 - 01 - 01),
+                HashMap::from([aircraft("aa"), aircraft("bb"), aircraft("cc")]),
+            ),
+            (
+                date!(2023 - 01 - 01),
+                HashMap::from([aircraft("aa"), aircraft("bb")]),
+            ),
+            (date!(2024 - 01 - 01), HashMap::from([aircraft("aa")])),
+        ]);
+        let months = [
+            // registered
+            ("aa".into(), date!(2019 - 02 - 01)),
+            // deregistered and inactive
+            ("bb".into(), date!(2021 - 03 - 01)),
+            ("bb".into(), date!(2020 - 01 - 01)),
+            // deregistered but recently active
+            ("cc".into(), date!(2023 - 10 - 01)),
+            // in no snapshot
+            ("dd".into(), date!(2020 - 01 - 01)),
+        ];
+
+        let retired = retired(months.into_iter(), &aircrafts, 6);
+
+        assert_eq!(
+            retired,
+            vec![Retired {
+                icao_number: "bb".into(),
+                tail_number: "OY-GFS".to_string(),
+                model: "FALCON 2000".to_string(),
+                last_flight_month: "2021-03".to_string(),
+                last_registry: "2023-01-01".to_string(),
+                deregistered: "2024-01-01".to_string(),
+                months_inactive: 34,
+            }]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Contains the records of runs of the ETL of legs (`M-runs`): one JSON line per run with its outcome, its tasks,
+//! the bytes it read and wrote and the completeness of each year, so that the history of runs is kept
+//! even when a run fails or its status is overwritten by a later run.
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicUsize, Ordering},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use futures::{stream::BoxStream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{checkpoint::Progress, fs::BlobStorageProvider};
+
+/// How a run ended
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The run executed its tasks and aggregated the datasets
+    Completed,
+    /// The run was asked to stop before executing all its tasks
+    Interrupted,
+    /// The run executed its tasks but did not aggregate the datasets because of soft warnings (see `M-strict`)
+    Strict,
+    /// The run failed with an error
+    Failed,
+}
+
+/// The tasks of a year of a run
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Completeness {
+    /// The number of months of aircrafts of the year to process
+    pub tasks: usize,
+    /// The number of them completed by the run (or by the run it resumed)
+    pub completed: usize,
+    /// `completed / tasks` (1 when there are no tasks)
+    pub ratio: f64,
+}
+
+/// Returns the [`Completeness`] of each year of `tasks` according to `progress`
+pub fn completeness<'a>(
+    tasks: impl Iterator<Item = (&'a Arc<str>, time::Date)>,
+    progress: &Progress,
+) -> BTreeMap<i32, Completeness> {
+    let mut years = BTreeMap::<i32, (usize, usize)>::new();
+    for (icao_number, month) in tasks {
+        let (tasks, completed) = years.entry(month.year()).or_default();
+        *tasks += 1;
+        *completed += progress.is_completed(icao_number, month) as usize;
+    }
+    years
+        .into_iter()
+        .map(|(year, (tasks, completed))| {
+            let ratio = match tasks {
+                0 => 1.0,
+                _ => completed as f64 / tasks as f64,
+            };
+            let completeness = Completeness {
+                tasks,
+                completed,
+                ratio,
+            };
+            (year, completeness)
+        })
+        .collect()
+}
+
+/// The record of a run of the ETL of legs
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Run {
+    /// The identifier of the run (see [`Progress::run_id`])
+    pub run_id: String,
+    pub outcome: Outcome,
+    /// When the run started
+    #[serde(with = "time::serde::rfc3339")]
+    pub started: time::OffsetDateTime,
+    /// When the run ended
+    #[serde(with = "time::serde::rfc3339")]
+    pub finished: time::OffsetDateTime,
+    /// The number of months of aircrafts processed
+    pub tasks_succeeded: usize,
+    /// The number of months of aircrafts that failed
+    pub tasks_failed: usize,
+    /// The number of months of aircrafts that timed out (or were rate limited) on every attempt
+    pub tasks_timed_out: usize,
+    /// The number of months of aircrafts skipped because the resumed run completed them
+    pub tasks_skipped: usize,
+    /// The number of bytes read from the storage
+    pub bytes_read: usize,
+    /// The number of bytes written to the storage
+    pub bytes_written: usize,
+    /// The [`Completeness`] of each year of the run
+    pub years: BTreeMap<i32, Completeness>,
+    /// The error of a failed run
+    pub error: Option<String>,
+}
+
+impl Run {
+    /// Returns a new [`Run`] of `run_id` started now
+    pub fn new(run_id: String) -> Self {
+        let now = time::OffsetDateTime::now_utc();
+        Self {
+            run_id,
+            outcome: Outcome::Completed,
+            started: now,
+            finished: now,
+            tasks_succeeded: 0,
+            tasks_failed: 0,
+            tasks_timed_out: 0,
+            tasks_skipped: 0,
+            bytes_read: 0,
+            bytes_written: 0,
+            years: Default::default(),
+            error: None,
+        }
+    }
+
+    /// Sets the outcome to `outcome` and writes the record, as a JSON line, to `key`
+    /// # Error
+    /// Errors if the blob cannot be written
+    pub async fn write(
+        &mut self,
+        outcome: Outcome,
+        key: &str,
+        client: &dyn BlobStorageProvider,
+    ) -> Result<(), std::io::Error> {
+        self.outcome = outcome;
+        self.finished = time::OffsetDateTime::now_utc();
+        let mut data = serde_json::to_vec(self).map_err(std::io::Error::other)?;
+        data.push(b'\n');
+        client.put(key, data).await
+    }
+}
+
+/// Returns the records of runs written under `prefix` (e.g. `leg/v2/runs/`), oldest first
+/// # Error
+/// Errors if a blob cannot be read or is not a valid record
+pub async fn runs(
+    prefix: &str,
+    client: &dyn BlobStorageProvider,
+) -> Result<Vec<Run>, crate::Error> {
+    let mut keys = client.list(prefix).await?;
+    keys.sort_unstable();
+    let mut runs = vec![];
+    for key in keys {
+        let Some(data) = client.maybe_get(&key).await? else {
+            continue;
+        };
+        for line in data.split(|x| *x == b'\n').filter(|x| !x.is_empty()) {
+            runs.push(serde_json::from_slice::<Run>(line)?);
+        }
+    }
+    runs.sort_by_key(|run| run.started);
+    Ok(runs)
+}
+
+/// A [`BlobStorageProvider`] counting the bytes read from and written to `client`
+pub struct Metered<C> {
+    client: C,
+    read: AtomicUsize,
+    written: AtomicUsize,
+}
+
+impl<C> Metered<C> {
+    /// Returns a new [`Metered`] of `client`
+    pub fn new(client: C) -> Self {
+        Self {
+            client,
+            read: Default::default(),
+            written: Default::default(),
+        }
+    }
+
+    /// The number of bytes read so far
+    pub fn bytes_read(&self) -> usize {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    /// The number of bytes written so far
+    pub fn bytes_written(&self) -> usize {
+        self.written.load(Ordering::Relaxed)
+    }
+}
+
+#[async_trait]
+impl<C: BlobStorageProvider + Send> BlobStorageProvider for Metered<C> {
+    async fn maybe_get(&self, blob_name: &str) -> Result<Option<Vec<u8>>, std::io::Error> {
+        let data = self.client.maybe_get(blob_name).await?;
+        let read = data.as_ref().map_or(0, |x| x.len());
+        self.read.fetch_add(read, Ordering::Relaxed);
+        Ok(data)
+    }
+
+    async fn put(&self, blob_name: &str, contents: Vec<u8>) -> Result<(), std::io::Error> {
+        let written = contents.len();
+        self.client.put(blob_name, contents).await?;
+        self.written.fetch_add(written, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>, std::io::Error> {
+        self.client.list(prefix).await
+    }
+
+    async fn delete(&self, blob_name: &str) -> Result<(), std::io::Error> {
+        self.client.delete(blob_name).await
+    }
+
+    async fn put_stream(
+        &self,
+        blob_name: &str,
+        chunks: BoxStream<'_, Result<Vec<u8>, std::io::Error>>,
+    ) -> Result<(), std::io::Error> {
+        let chunks = chunks
+            .inspect_ok(|chunk| {
+                self.written.fetch_add(chunk.len(), Ordering::Relaxed);
+            })
+            .boxed();
+        self.client.put_stream(blob_name, chunks).await
+    }
+
+    async fn etag(&self, blob_name: &str) -> Result<Option<String>, std::io::Error> {
+        self.client.etag(blob_name).await
+    }
+
+    fn can_put(&self) -> bool {
+        self.client.can_put()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::date;
+
+    use super::*;
+
+    #[test]
+    fn years() {
+        let mut progress = Progress::new("1".to_string());
+        progress.complete("45d2ed".into(), date!(2023 - 01 - 01));
+        let (a, b) = ("45d2ed".into(), "45860d".into());
+        let tasks = [
+            (&a, date!(2023 - 01 - 01)),
+            (&b, date!(2023 - 01 - 01)),
+            (&a, date!(2024 - 01 - 01)),
+        ];
+        let years = completeness(tasks.into_iter(), &progress);
+        assert_eq!(years[&2023].ratio, 0.5);
+        assert_eq!(years[&2024].completed, 0);
+        assert_eq!(years[&2024].ratio, 0.0);
+    }
+
+    #[tokio::test]
+    async fn work() {
+        let root = std::env::temp_dir().join("test_runs");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = Metered::new(crate::fs_local::LocalDisk::new(&root));
+        disk.put("a", b"abc".to_vec()).await.unwrap();
+        disk.maybe_get("a").await.unwrap();
+        disk.maybe_get("b").await.unwrap();
+        assert_eq!((disk.bytes_read(), disk.bytes_written()), (3, 3));
+
+        let mut first = Run::new("1".to_string());
+        first.tasks_failed = 1;
+        first
+            .write(Outcome::Failed, "leg/v2/runs/1.jsonl", &disk)
+            .await
+            .unwrap();
+        let mut second = Run::new("1".to_string());
+        second.tasks_succeeded = 2;
+        second
+            .write(Outcome::Completed, "leg/v2/runs/2.jsonl", &disk)
+            .await
+            .unwrap();
+
+        // a failed run keeps its record
+        let runs = runs("leg/v2/runs/", &disk).await.unwrap();
+        assert_eq!(runs, vec![first, second]);
+    }
+}
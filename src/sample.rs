@@ -0,0 +1,132 @@
+//! Contains the implementation of reproducible random samples of the public dataset of legs
+//! (`leg/v2/all/year={year}/data.csv`), stratified by year and country (`M-sample`).
+use std::{collections::BTreeMap, error::Error};
+
+use rand::SeedableRng;
+
+use crate::fs::BlobStorageProvider;
+
+static DATABASE_ROOT: &str = "leg/v2/";
+
+/// The column of the dataset of legs whose values define the strata of a year
+pub static STRATUM_COLUMN: &str = "from_country";
+
+/// Returns the number of rows to sample out of `rows` rows of a stratum: `fraction` of them, rounded,
+/// and at least one so that every stratum is represented
+fn stratum_size(rows: usize, fraction: f64) -> usize {
+    ((rows as f64 * fraction).round() as usize).clamp(1, rows.max(1))
+}
+
+/// Returns `fraction` of the rows of the CSV `data` of the legs of `year`, sampled at random within each
+/// stratum of [`STRATUM_COLUMN`] (see [`stratum_size`]), in their original order and with the header.
+/// # Implementation
+/// The sample only depends on `data`, `fraction`, `year` and `seed`: the strata are sampled in their order,
+/// with a random number generator seeded by `seed` and `year`, so that the sample of a year does not depend
+/// on which other years are sampled.
+pub fn sample_year(
+    data: &[u8],
+    year: i32,
+    fraction: f64,
+    seed: u64,
+) -> Result<Vec<u8>, std::io::Error> {
+    let mut reader = csv::Reader::from_reader(data);
+    let headers = reader.headers()?.clone();
+    let column = headers
+        .iter()
+        .position(|x| x == STRATUM_COLUMN)
+        .ok_or_else(|| std::io::Error::other(format!("the dataset has no `{STRATUM_COLUMN}`")))?;
+    let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+
+    let mut strata = BTreeMap::<&str, Vec<usize>>::new();
+    for (row, record) in records.iter().enumerate() {
+        strata
+            .entry(record.get(column).unwrap_or_default())
+            .or_default()
+            .push(row);
+    }
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed.wrapping_add(year as u64));
+    let mut selected = strata
+        .values()
+        .flat_map(|rows| {
+            let amount = stratum_size(rows.len(), fraction);
+            rand::seq::index::sample(&mut rng, rows.len(), amount)
+                .into_iter()
+                .map(|index| rows[index])
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    selected.sort_unstable();
+
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(&headers)?;
+    for row in selected {
+        writer.write_record(&records[row])?;
+    }
+    writer.into_inner().map_err(|e| e.into_error())
+}
+
+/// Returns `fraction` of the legs of each of `years` of the public dataset of legs, sampled with [`sample_year`],
+/// as a single CSV.
+pub async fn sample(
+    years: impl Iterator<Item = i32>,
+    fraction: f64,
+    seed: u64,
+    client: &dyn BlobStorageProvider,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if !(fraction > 0.0 && fraction <= 1.0) {
+        return Err(format!("the fraction {fraction} must be in (0, 1]").into());
+    }
+    let mut result: Vec<u8> = vec![];
+    for year in years {
+        let key = format!("{DATABASE_ROOT}all/year={year}/data.csv");
+        let Some(data) = crate::io::maybe_get(&key, client).await? else {
+            log::warn!("{key} does not exist; skipping");
+            continue;
+        };
+        let sample = sample_year(&data, year, fraction, seed)?;
+        log::info!("sampled year={year}");
+        // the header is only written once
+        let body = if result.is_empty() {
+            &sample[..]
+        } else {
+            &sample[sample.iter().position(|x| *x == b'\n').map_or(0, |x| x + 1)..]
+        };
+        result.extend_from_slice(body);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stratified() {
+        let mut data = "icao_number,from_country\n".to_string();
+        for i in 0..1000 {
+            data.push_str(&format!("{i},DK\n"));
+        }
+        data.push_str("1000,FO\n");
+        data.push_str("1001,\n");
+
+        let sample = sample_year(data.as_bytes(), 2023, 0.01, 42).unwrap();
+        let rows = crate::csv::deserialize::<(String, String)>(&sample)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        // 1% of DK and at least one of each other country
+        assert_eq!(rows.iter().filter(|x| x.1 == "DK").count(), 10);
+        assert_eq!(rows.iter().filter(|x| x.1 == "FO").count(), 1);
+        assert_eq!(rows.iter().filter(|x| x.1.is_empty()).count(), 1);
+
+        // reproducible
+        assert_eq!(
+            sample,
+            sample_year(data.as_bytes(), 2023, 0.01, 42).unwrap()
+        );
+        assert_ne!(
+            sample,
+            sample_year(data.as_bytes(), 2023, 0.01, 43).unwrap()
+        );
+    }
+}
@@ -0,0 +1,249 @@
+//! Contains the schemas of the datasets (`M-schemas`): the name, type, unit, nullability and description of each
+//! of their columns, written to `schema.json` at the root of each dataset so that loaders do not need to guess them.
+//!
+//! The schema of a row is declared next to its struct with [`schema!`](crate::schema!), which takes the type and
+//! nullability of each column from the type of its field.
+use serde::Serialize;
+
+use crate::{fs::BlobStorageProvider, units::Units};
+
+/// A type of the fields of the rows of a dataset
+pub trait Type {
+    /// The name of the type of the column (e.g. `f64`), as in the column specifications of the methodology
+    const NAME: &'static str;
+    /// Whether values can be null
+    const NULLABLE: bool = false;
+}
+
+impl<T: Type> Type for Option<T> {
+    const NAME: &'static str = T::NAME;
+    const NULLABLE: bool = true;
+}
+
+macro_rules! impl_type {
+    ($name:literal: $($type:ty),*) => {
+        $(impl Type for $type {
+            const NAME: &'static str = $name;
+        })*
+    };
+}
+
+impl_type!("string": std::sync::Arc<str>, String);
+impl_type!("f64": f64);
+impl_type!("bool": bool);
+impl_type!("i32": i32);
+impl_type!("u8": u8);
+impl_type!("u16": u16);
+impl_type!("u32": u32);
+impl_type!("u64": u64, usize);
+impl_type!("datetime": time::OffsetDateTime);
+
+/// The unit of a column
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// A unit that does not depend on [`Units`] (e.g. `h`)
+    Fixed(&'static str),
+    /// The unit of distances of the [`Units`] of the dataset (e.g. `km`)
+    Distance,
+    /// The unit of masses of the [`Units`] of the dataset (e.g. `kg`)
+    Mass,
+}
+
+impl From<&'static str> for Unit {
+    fn from(unit: &'static str) -> Self {
+        Self::Fixed(unit)
+    }
+}
+
+impl Unit {
+    fn name(&self, units: Units) -> &'static str {
+        match self {
+            Self::Fixed(name) => name,
+            Self::Distance => units.distance_unit(),
+            Self::Mass => units.mass_unit(),
+        }
+    }
+}
+
+/// A column of the schema of a dataset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    /// The name of the type (see [`Type::NAME`])
+    pub type_: &'static str,
+    pub unit: Option<Unit>,
+    pub nullable: bool,
+    pub description: &'static str,
+}
+
+impl Field {
+    /// Returns the [`Field`] of the field of rows `R` of type `T` returned by `field`
+    pub fn of<R, T: Type>(
+        _field: fn(&R) -> &T,
+        name: &'static str,
+        unit: Option<Unit>,
+        description: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            type_: T::NAME,
+            unit,
+            nullable: T::NULLABLE,
+            description,
+        }
+    }
+}
+
+/// A row of a dataset with a schema, implemented with [`schema!`](crate::schema!)
+pub trait Schema {
+    /// The columns of the row, in the order they are serialized
+    fn fields() -> Vec<Field>;
+}
+
+/// Implements [`Schema`] for a struct from the description (and optional [`Unit`]) of each of its fields,
+/// in the order of the struct, e.g.
+/// ```ignore
+/// flights::schema!(GroundTime {
+///     airport: "The airport",
+///     hours_on_ground("h"): "The time on the ground",
+/// });
+/// ```
+/// Fields that do not exist fail to compile.
+#[macro_export]
+macro_rules! schema {
+    ($type:ty { $($field:ident $(($unit:expr))?: $description:literal),* $(,)? }) => {
+        impl $crate::schema::Schema for $type {
+            fn fields() -> Vec<$crate::schema::Field> {
+                vec![$(
+                    $crate::schema::Field::of(
+                        |row: &$type| &row.$field,
+                        stringify!($field),
+                        None$(.or(Some($crate::schema::Unit::from($unit))))?,
+                        $description,
+                    ),
+                )*]
+            }
+        }
+    };
+}
+
+#[derive(Serialize)]
+struct Column {
+    name: &'static str,
+    #[serde(rename = "type")]
+    type_: &'static str,
+    unit: Option<&'static str>,
+    nullable: bool,
+    description: &'static str,
+}
+
+#[derive(Serialize)]
+struct Document {
+    version: String,
+    columns: Vec<Column>,
+}
+
+/// Returns the version of the schema of rows `S`: a fingerprint (FNV-1a, in hexadecimal) of the name, type and
+/// nullability of its columns, in order. It changes when a column is added, removed, renamed, reordered or retyped,
+/// and does not depend on the units or descriptions of the columns.
+pub fn version<S: Schema>() -> String {
+    let hash = S::fields()
+        .into_iter()
+        .flat_map(|field| {
+            let nullable = if field.nullable { "?" } else { "" };
+            format!("{}:{}{nullable};", field.name, field.type_).into_bytes()
+        })
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{hash:016x}")
+}
+
+/// Returns the `schema.json` of datasets of rows `S` in `units`
+pub fn to_json<S: Schema>(units: Units) -> Vec<u8> {
+    let columns = S::fields()
+        .into_iter()
+        .map(|field| Column {
+            name: field.name,
+            type_: field.type_,
+            unit: field.unit.map(|unit| unit.name(units)),
+            nullable: field.nullable,
+            description: field.description,
+        })
+        .collect();
+    let document = Document {
+        version: version::<S>(),
+        columns,
+    };
+    serde_json::to_vec_pretty(&document).expect("serialization of a schema never fails")
+}
+
+/// Writes the schema of datasets of rows `S` in `units` to `{root}schema.json`
+pub async fn write<S: Schema>(
+    root: &str,
+    units: Units,
+    client: &dyn BlobStorageProvider,
+) -> Result<(), std::io::Error> {
+    let key = format!("{root}schema.json");
+    client.put(&key, to_json::<S>(units)).await?;
+    log::info!("Written {key}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Row {
+        a: std::sync::Arc<str>,
+        b: Option<f64>,
+        c: f64,
+    }
+
+    crate::schema!(Row {
+        a: "The a",
+        b(Unit::Distance): "The b",
+        c("h"): "The c",
+    });
+
+    #[test]
+    fn work() {
+        let fields = Row::fields();
+        assert_eq!(
+            fields[1],
+            Field {
+                name: "b",
+                type_: "f64",
+                unit: Some(Unit::Distance),
+                nullable: true,
+                description: "The b",
+            }
+        );
+
+        let value =
+            serde_json::from_slice::<serde_json::Value>(&to_json::<Row>(Units::Aviation)).unwrap();
+        let columns = value["columns"].as_array().unwrap();
+        assert_eq!(columns.len(), 3);
+        assert_eq!(value["version"], version::<Row>());
+        // the version is stable across runs, and changes with the columns
+        assert_eq!(version::<Row>(), "316553ae08a33846");
+        assert_ne!(version::<Row>(), version::<crate::Position>());
+        assert_eq!(columns[0]["type"], "string");
+        assert_eq!(columns[0]["unit"], serde_json::Value::Null);
+        assert_eq!(columns[1]["unit"], "nm");
+        assert_eq!(columns[2]["unit"], "h");
+        assert_eq!(columns[2]["nullable"], false);
+
+        // the columns are those of the serialized rows
+        let row = Row {
+            a: "x".into(),
+            b: None,
+            c: 1.0,
+        };
+        let header = crate::csv::serialize(std::iter::once(row));
+        let header = String::from_utf8(header).unwrap();
+        let names = fields.iter().map(|x| x.name).collect::<Vec<_>>();
+        assert_eq!(header.lines().next().unwrap(), names.join(","));
+    }
+}
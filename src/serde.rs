@@ -19,6 +19,19 @@ pub fn parse_month(date: &str) -> time::Date {
     .unwrap()
 }
 
+/// Parses a "2022-01" to a date at first of month, or an error when it is not a valid month
+pub fn try_parse_month(date: &str) -> Result<time::Date, String> {
+    let invalid = || format!("{date} must be a month (YYYY-MM)");
+    let (year, month) = date.split_once('-').ok_or_else(invalid)?;
+    let year = year.parse::<i32>().map_err(|_| invalid())?;
+    let month = month
+        .parse::<u8>()
+        .ok()
+        .and_then(|month| time::Month::try_from(month).ok())
+        .ok_or_else(invalid)?;
+    time::Date::from_calendar_date(year, month, 1).map_err(|_| invalid())
+}
+
 pub fn hive_to_map<'a>(mut blob: &'a str) -> HashMap<&'a str, &'a str> {
     let mut a = HashMap::new();
     while !blob.is_empty() {
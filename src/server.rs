@@ -0,0 +1,435 @@
+//! Contains the read-only HTTP API over the datasets in a [`BlobStorageProvider`] (feature `server`, `M-api`),
+//! so that consumers (e.g. a newsroom) can embed live numbers without downloading the yearly datasets:
+//! * `GET /aircraft/{icao_number}/legs?from={date}&to={date}`: the legs of an aircraft (see [`crate::query`])
+//! * `GET /legs/year/{year}?from=&to=&airport=&model=&cursor=&limit=`: a page of the legs of a year, in JSON or CSV
+//!   (see [`crate::dataset::LegsQuery`])
+//! * `GET /stats/year/{year}`: the totals of the yearly statistics of the aircrafts (see [`crate::stats`])
+//! * `GET /status`: the status of the datasets of legs
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use crate::{
+    dataset::{ContentType, Cursor, LegsQuery},
+    etl::legs::{LegOut, Roots},
+    fs::BlobStorageProvider,
+    stats::AircraftYear,
+};
+
+/// The maximum number of days of legs returned by a request
+pub static MAX_DAYS: i64 = 366;
+/// The maximum number of legs of a page of `GET /legs/year/{year}`
+pub static MAX_LIMIT: usize = 10_000;
+/// The header with the cursor of the next page of `GET /legs/year/{year}`, when there is one
+pub static NEXT_CURSOR: HeaderName = HeaderName::from_static("x-next-cursor");
+
+/// The state shared by the requests
+struct Api {
+    client: Arc<dyn BlobStorageProvider + Send + Sync>,
+    /// the roots of the datasets of legs, or `None` for those of the published version
+    roots: Option<Roots>,
+}
+
+impl Api {
+    /// Returns the roots of the datasets of legs, resolving the published version on every request so that a
+    /// promotion (see `M-versions`) is served without a restart
+    async fn roots(&self) -> Result<Roots, crate::Error> {
+        match &self.roots {
+            Some(roots) => Ok(roots.clone()),
+            None => Roots::published(self.client.as_ref()).await,
+        }
+    }
+}
+
+/// An error of a request, returned with its status code and `{"error": ...}`
+#[derive(Debug)]
+enum ApiError {
+    BadRequest(String),
+    NotFound(String),
+    NotAcceptable(String),
+    Internal(crate::Error),
+}
+
+impl From<crate::Error> for ApiError {
+    fn from(error: crate::Error) -> Self {
+        match error {
+            crate::Error::MissingData(blob_name) => Self::NotFound(blob_name),
+            error => Self::Internal(error),
+        }
+    }
+}
+
+impl From<std::io::Error> for ApiError {
+    fn from(error: std::io::Error) -> Self {
+        crate::Error::from(error).into()
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, error) = match self {
+            Self::BadRequest(error) => (StatusCode::BAD_REQUEST, error),
+            Self::NotFound(error) => (StatusCode::NOT_FOUND, error),
+            Self::NotAcceptable(error) => (StatusCode::NOT_ACCEPTABLE, error),
+            Self::Internal(error) => {
+                log::error!("{error}");
+                (StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+            }
+        };
+        (status, Json(serde_json::json!({ "error": error }))).into_response()
+    }
+}
+
+/// The range of dates of `GET /aircraft/{icao_number}/legs`, in `yyyy-mm-dd` (inclusive)
+#[derive(Deserialize)]
+struct Range {
+    from: String,
+    to: String,
+}
+
+fn parse_date(name: &str, value: &str) -> Result<Date, ApiError> {
+    Date::parse(
+        value,
+        time::macros::format_description!("[year]-[month]-[day]"),
+    )
+    .map_err(|e| ApiError::BadRequest(format!("`{name}` must be a date (yyyy-mm-dd): {e}")))
+}
+
+async fn aircraft_legs(
+    State(api): State<Arc<Api>>,
+    Path(icao_number): Path<String>,
+    Query(range): Query<Range>,
+) -> Result<Json<Vec<LegOut>>, ApiError> {
+    let from = parse_date("from", &range.from)?;
+    let to = parse_date("to", &range.to)?;
+    if to < from || (to - from).whole_days() >= MAX_DAYS {
+        return Err(ApiError::BadRequest(format!(
+            "`to` must be after `from` and at most {MAX_DAYS} days apart"
+        )));
+    }
+    let legs = crate::query::legs_of(
+        api.roots().await?,
+        &icao_number,
+        from,
+        to,
+        api.client.as_ref(),
+    )
+    .try_collect::<Vec<_>>()
+    .await?;
+    Ok(Json(legs))
+}
+
+/// The filters and page of `GET /legs/year/{year}` (see [`LegsQuery`])
+#[derive(Deserialize)]
+struct LegsParams {
+    from: Option<String>,
+    to: Option<String>,
+    airport: Option<String>,
+    model: Option<String>,
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+impl LegsParams {
+    fn query(self) -> Result<LegsQuery, ApiError> {
+        let date =
+            |name, value: Option<String>| value.map(|value| parse_date(name, &value)).transpose();
+        Ok(LegsQuery {
+            from: date("from", self.from)?,
+            to: date("to", self.to)?,
+            airport: self.airport,
+            model: self.model,
+            cursor: self
+                .cursor
+                .map(|cursor| cursor.parse::<Cursor>().map_err(ApiError::BadRequest))
+                .transpose()?,
+            limit: self
+                .limit
+                .unwrap_or(crate::dataset::DEFAULT_LIMIT)
+                .min(MAX_LIMIT),
+        })
+    }
+}
+
+async fn year_legs(
+    State(api): State<Arc<Api>>,
+    Path(year): Path<i32>,
+    headers: HeaderMap,
+    Query(params): Query<LegsParams>,
+) -> Result<Response, ApiError> {
+    let accept = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok());
+    let content_type = ContentType::negotiate(accept).ok_or_else(|| {
+        ApiError::NotAcceptable("`Accept` must be `application/json` or `text/csv`".to_string())
+    })?;
+    let query = params.query()?;
+    let roots = api.roots().await?;
+    let page = crate::dataset::query_legs(&query, year, &roots, api.client.as_ref()).await?;
+
+    let mut response = (
+        [(header::CONTENT_TYPE, content_type.media_type())],
+        content_type.serialize(&page),
+    )
+        .into_response();
+    if let Some(next) = &page.next {
+        let next = HeaderValue::try_from(next.to_string())
+            .map_err(|e| crate::Error::Invalid(format!("cursor `{next}`: {e}")))?;
+        response.headers_mut().insert(NEXT_CURSOR.clone(), next);
+    }
+    Ok(response)
+}
+
+/// The totals of the statistics of the aircrafts on a year, returned by `GET /stats/year/{year}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct YearTotals {
+    pub year: i32,
+    /// The number of aircrafts with legs
+    pub aircrafts: usize,
+    /// The number of legs
+    pub legs: usize,
+    /// The total duration of the legs in hours
+    pub hours: f64,
+    /// The total flown distance of the legs in km
+    pub distance: f64,
+    /// The total CO2 emissions of the legs in kg
+    pub co2_emissions: f64,
+}
+
+/// Returns the [`YearTotals`] of the statistics of the aircrafts of `year`
+pub fn year_totals(year: i32, stats: &[AircraftYear]) -> YearTotals {
+    YearTotals {
+        year,
+        aircrafts: stats.len(),
+        legs: stats.iter().map(|x| x.legs).sum(),
+        hours: stats.iter().map(|x| x.hours).sum(),
+        distance: stats.iter().map(|x| x.distance).sum(),
+        co2_emissions: stats.iter().map(|x| x.co2_emissions).sum(),
+    }
+}
+
+async fn year_stats(
+    State(api): State<Arc<Api>>,
+    Path(year): Path<i32>,
+) -> Result<Json<YearTotals>, ApiError> {
+    let key = crate::stats::aircraft_year_blob_name(year);
+    let Some(data) = crate::io::maybe_get(&key, api.client.as_ref()).await? else {
+        return Err(ApiError::NotFound(format!("{key} does not exist")));
+    };
+    let stats = crate::csv::deserialize::<AircraftYear>(&data)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(crate::Error::from)?;
+    Ok(Json(year_totals(year, &stats)))
+}
+
+async fn status(State(api): State<Arc<Api>>) -> Result<Response, ApiError> {
+    let key = format!("{}status.json", api.roots().await?.legs);
+    let Some(data) = crate::io::maybe_get(&key, api.client.as_ref()).await? else {
+        return Err(ApiError::NotFound(format!("{key} does not exist")));
+    };
+    Ok(([(header::CONTENT_TYPE, "application/json")], data).into_response())
+}
+
+/// Returns the [`Router`] of the API over the datasets in `client`, with the datasets of legs at `roots`
+/// (`None` for those of the published version, see [`Roots::published`])
+pub fn router(client: Arc<dyn BlobStorageProvider + Send + Sync>, roots: Option<Roots>) -> Router {
+    Router::new()
+        .route("/aircraft/{icao_number}/legs", get(aircraft_legs))
+        .route("/legs/year/{year}", get(year_legs))
+        .route("/stats/year/{year}", get(year_stats))
+        .route("/status", get(status))
+        .with_state(Arc::new(Api { client, roots }))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{compression::Compression, format::Format};
+
+    use super::*;
+
+    fn leg(start: &str) -> LegOut {
+        serde_json::from_value::<LegOut>(serde_json::json!({
+            "icao_number": "459cd3",
+            "start": start,
+            "start_lat": 55.6,
+            "start_lon": 12.6,
+            "start_altitude": 0.0,
+            "end": "2023-01-20T11:30:00Z",
+            "end_lat": 49.0,
+            "end_lon": 2.5,
+            "end_altitude": 0.0,
+            "duration": 1.5,
+            "distance": 1000.0,
+            "great_circle_distance": 1000.0,
+            "midpoint_lat": 52.3,
+            "midpoint_lon": 7.5,
+            "hours_above_30000": 1.0,
+            "hours_above_40000": 0.0,
+            "diverted": false,
+            "start_on_ground": true,
+            "end_on_ground": true,
+            "taxi_out_minutes": 0.0,
+            "taxi_in_minutes": 0.0,
+            "commercial_alternative_exists": true,
+        }))
+        .unwrap()
+    }
+
+    /// Serves the API over `disk` on a local port and returns its address
+    async fn serve(disk: Arc<crate::fs_local::LocalDisk>) -> std::net::SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = axum::serve(listener, router(disk, None));
+        tokio::spawn(async move { server.await });
+        address
+    }
+
+    #[tokio::test]
+    async fn work() {
+        let root = std::env::temp_dir().join("test_server");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let roots = Roots::default();
+        let leg = leg("2023-01-20T10:00:00Z");
+        let key = crate::etl::legs::pk_to_blob_name(
+            &roots,
+            "459cd3",
+            time::macros::date!(2023 - 01 - 01),
+            Format::Csv,
+            Compression::None,
+        );
+        disk.put(&key, crate::csv::serialize(std::iter::once(leg)))
+            .await
+            .unwrap();
+        disk.put("leg/v2/status.json", br#"{"2023": {}}"#.to_vec())
+            .await
+            .unwrap();
+
+        let disk = Arc::new(disk);
+        let address = serve(disk.clone()).await;
+
+        let get = |path: &str| {
+            let url = format!("http://{address}{path}");
+            async move { reqwest::get(url).await.unwrap() }
+        };
+        let response = get("/aircraft/459cd3/legs?from=2023-01-01&to=2023-01-31").await;
+        assert_eq!(response.status(), 200);
+        let legs = response.bytes().await.unwrap();
+        let legs = serde_json::from_slice::<serde_json::Value>(&legs).unwrap();
+        assert_eq!(legs.as_array().unwrap().len(), 1);
+        assert_eq!(legs[0]["start"], "2023-01-20T10:00:00Z");
+
+        let response = get("/aircraft/459cd3/legs?from=2023-01-01&to=2022-01-31").await;
+        assert_eq!(response.status(), 400);
+        let response = get("/status").await;
+        assert_eq!(response.text().await.unwrap(), r#"{"2023": {}}"#);
+        assert_eq!(get("/stats/year/2023").await.status(), 404);
+
+        // a promoted version is served without a restart
+        let promotion = crate::validate::Promotion {
+            version: "v3".into(),
+            status: "leg/v3/status.json".to_string(),
+            previous: Some("v2".into()),
+            promoted: time::OffsetDateTime::now_utc(),
+        };
+        disk.put(
+            crate::validate::POINTER,
+            serde_json::to_vec(&promotion).unwrap(),
+        )
+        .await
+        .unwrap();
+        disk.put("leg/v3/status.json", br#"{"2024": {}}"#.to_vec())
+            .await
+            .unwrap();
+        let response = get("/status").await;
+        assert_eq!(response.text().await.unwrap(), r#"{"2024": {}}"#);
+        let response = get("/aircraft/459cd3/legs?from=2023-01-01&to=2023-01-31").await;
+        assert_eq!(response.text().await.unwrap(), "[]");
+    }
+
+    #[tokio::test]
+    async fn year_legs() {
+        let root = std::env::temp_dir().join("test_server_year_legs");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let legs = [
+            leg("2023-01-20T10:00:00.5Z"),
+            leg("2023-02-20T10:00:00Z"),
+            leg("2023-03-20T10:00:00Z"),
+        ];
+        disk.put(
+            "leg/v2/all/year=2023/data.csv",
+            crate::csv::serialize(legs.iter()),
+        )
+        .await
+        .unwrap();
+        let address = serve(Arc::new(disk)).await;
+
+        let client = reqwest::Client::new();
+        let get = |path: String, accept: &'static str| {
+            let request = client
+                .get(format!("http://{address}{path}"))
+                .header(header::ACCEPT, accept);
+            async move { request.send().await.unwrap() }
+        };
+
+        // pages of JSON, followed with the cursor of the previous page
+        let mut starts = vec![];
+        let mut cursor = None::<String>;
+        loop {
+            let path = match &cursor {
+                Some(cursor) => format!("/legs/year/2023?limit=1&cursor={cursor}"),
+                None => "/legs/year/2023?limit=1".to_string(),
+            };
+            let response = get(path, "application/json").await;
+            assert_eq!(response.status(), 200);
+            let page = response.bytes().await.unwrap();
+            let page = serde_json::from_slice::<serde_json::Value>(&page).unwrap();
+            starts.extend(page["legs"].as_array().unwrap().clone());
+            match page["next"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => break,
+            }
+        }
+        let starts = starts
+            .iter()
+            .map(|leg| leg["start"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            starts,
+            [
+                "2023-01-20T10:00:00.5Z",
+                "2023-02-20T10:00:00Z",
+                "2023-03-20T10:00:00Z"
+            ]
+        );
+
+        // CSV with the cursor in a header, and filters
+        let response = get("/legs/year/2023?from=2023-02-01&limit=1".into(), "text/csv").await;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/csv");
+        assert!(response.headers().contains_key(&NEXT_CURSOR));
+        let data = response.bytes().await.unwrap();
+        let legs = crate::csv::deserialize::<crate::dataset::DatasetLeg>(&data)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(legs.len(), 1);
+        assert_eq!(legs[0].start, time::macros::datetime!(2023-02-20 10:00 UTC));
+        let response = get("/legs/year/2023?model=unknown".into(), "text/csv").await;
+        assert!(!response.headers().contains_key(&NEXT_CURSOR));
+
+        let response = get("/legs/year/2023".into(), "image/png").await;
+        assert_eq!(response.status(), 406);
+        let response = get("/legs/year/2023?cursor=459cd3".into(), "*/*").await;
+        assert_eq!(response.status(), 400);
+    }
+}
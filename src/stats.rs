@@ -0,0 +1,832 @@
+//! Contains the implementation of the yearly statistics of each aircraft (`M-aircraft-year`), of each country
+//! (`M-country-year`) and of each model (`M-model-year`), and of the quarterly statistics of each aircraft
+//! (`M-aircraft-quarter`), computed from the public dataset of legs of the published version (e.g. `leg/v2/all/year={year}/data.csv`).
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use time::{Date, Month, OffsetDateTime};
+
+use crate::{
+    etl::legs::Roots,
+    fs::BlobStorageProvider,
+    model::{last_reclassification, ModelReclassification},
+};
+
+static DATABASE_ROOT: &str = "stats/v1/aircraft_year/";
+static COUNTRY_DATABASE_ROOT: &str = "stats/v1/country_year/";
+static QUARTER_DATABASE_ROOT: &str = "stats/v1/aircraft_quarter/";
+static MODEL_DATABASE_ROOT: &str = "stats/v1/model_year/";
+
+/// Legs whose great-circle distance is shorter than this (in km) are counted as short legs
+pub static SHORT_LEG_DISTANCE: f64 = 50.0;
+
+/// A leg of the public dataset of legs, restricted to the columns needed to compute [`AircraftYear`]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct StatsLeg {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The tail number, when known
+    #[serde(default)]
+    pub tail_number: Option<Arc<str>>,
+    /// The aircraft model, when known
+    #[serde(default)]
+    pub aircraft_model: Option<Arc<str>>,
+    /// The start timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    /// The end timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: OffsetDateTime,
+    /// The duration of the leg in hours
+    pub duration: f64,
+    /// The total flown distance of the leg in km
+    pub distance: f64,
+    /// The great-circle distance of the leg in km
+    pub great_circle_distance: f64,
+    /// The CO2 emissions in kg, when known
+    #[serde(default)]
+    pub co2_emissions: Option<f64>,
+    /// The identifier of the departure airport, when known
+    #[serde(default)]
+    pub from_airport_icao: Option<Arc<str>>,
+    /// The identifier of the arrival airport, when known
+    #[serde(default)]
+    pub to_airport_icao: Option<Arc<str>>,
+    /// The country (ISO 3166-1 alpha-2) of the start of the leg, when known
+    #[serde(default)]
+    pub from_country: Option<Arc<str>>,
+    /// The country (ISO 3166-1 alpha-2) of the end of the leg, when known
+    #[serde(default)]
+    pub to_country: Option<Arc<str>>,
+}
+
+/// How the operating country of an aircraft was inferred (see `M-operating-country`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OperatingCountryMethod {
+    /// The country of the airport where the aircraft spent the most time on the ground between legs
+    HomeBase,
+    /// The country most departed from and arrived at, when no time on the ground is known
+    MostVisited,
+}
+
+impl crate::schema::Type for OperatingCountryMethod {
+    const NAME: &'static str = "string";
+}
+
+/// The statistics of the legs of an aircraft on a year
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AircraftYear {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    pub year: i32,
+    /// The number of legs
+    pub legs: usize,
+    /// The total duration of the legs in hours
+    pub hours: f64,
+    /// The total flown distance of the legs in km
+    pub distance: f64,
+    /// The total CO2 emissions of the legs in kg (legs without emissions count as zero)
+    pub co2_emissions: f64,
+    /// The number of distinct departure and arrival airports of the legs
+    pub airports: usize,
+    /// The number of legs shorter than [`SHORT_LEG_DISTANCE`]
+    pub short_legs: usize,
+    /// The country of registration (see `M-country-of-registration`)
+    pub registration_country: Option<Arc<str>>,
+    /// The country (ISO 3166-1 alpha-2) the aircraft was operated from (see `M-operating-country`)
+    pub operating_country: Option<Arc<str>>,
+    /// How `operating_country` was inferred
+    pub operating_country_method: Option<OperatingCountryMethod>,
+}
+
+crate::schema!(AircraftYear {
+    icao_number: "The ICAO number",
+    year: "The year",
+    legs: "The number of legs",
+    hours("h"): "The total duration of the legs",
+    distance("km"): "The total flown distance of the legs",
+    co2_emissions("kg"): "The total CO2 emissions of the legs (legs without emissions count as zero)",
+    airports: "The number of distinct departure and arrival airports of the legs",
+    short_legs: "The number of legs shorter than 50 km of great-circle distance",
+    registration_country: "The country of registration (see `M-country-of-registration`)",
+    operating_country: "The country (ISO 3166-1 alpha-2) the aircraft was operated from (see `M-operating-country`)",
+    operating_country_method: "How `operating_country` was inferred (`home-base` or `most-visited`)",
+});
+
+/// Returns the blob name of the [`AircraftYear`]s of `year` (e.g. `stats/v1/aircraft_year/year=2023/data.csv`)
+pub fn aircraft_year_blob_name(year: i32) -> String {
+    format!("{DATABASE_ROOT}year={year}/data.csv")
+}
+
+/// Returns the operating country of an aircraft given its `legs` ordered by start, and how it was inferred
+fn operating_country(legs: &[StatsLeg]) -> Option<(Arc<str>, OperatingCountryMethod)> {
+    // hours on the ground between consecutive legs per (airport, country)
+    let mut ground = HashMap::<(&Arc<str>, &Arc<str>), f64>::new();
+    for w in legs.windows(2) {
+        let (Some(airport), Some(country)) = (&w[0].to_airport_icao, &w[0].to_country) else {
+            continue;
+        };
+        if w[1].from_airport_icao.as_ref() == Some(airport) {
+            *ground.entry((airport, country)).or_default() +=
+                (w[1].start - w[0].end).as_seconds_f64() / 60.0 / 60.0;
+        }
+    }
+    let home_base = ground
+        .into_iter()
+        // ties are broken by the identifier of the airport, for reproducibility
+        .max_by(|(a, a_hours), (b, b_hours)| a_hours.total_cmp(b_hours).then(b.0.cmp(a.0)))
+        .map(|((_, country), _)| (country.clone(), OperatingCountryMethod::HomeBase));
+    home_base.or_else(|| {
+        let mut visits = BTreeMap::<&Arc<str>, usize>::new();
+        for leg in legs {
+            for country in leg.from_country.iter().chain(leg.to_country.iter()) {
+                *visits.entry(country).or_default() += 1;
+            }
+        }
+        visits
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, visits)| *visits)
+            .map(|(country, _)| (country.clone(), OperatingCountryMethod::MostVisited))
+    })
+}
+
+/// Returns the [`AircraftYear`]s of the `legs` of `year`, ordered by ICAO number.
+pub fn aircraft_year(year: i32, legs: impl Iterator<Item = StatsLeg>) -> Vec<AircraftYear> {
+    let mut by_icao_number = BTreeMap::<Arc<str>, Vec<StatsLeg>>::new();
+    for leg in legs {
+        by_icao_number
+            .entry(leg.icao_number.clone())
+            .or_default()
+            .push(leg);
+    }
+    by_icao_number
+        .into_iter()
+        .map(|(icao_number, mut legs)| {
+            legs.sort_unstable_by_key(|leg| leg.start);
+            let airports = legs
+                .iter()
+                .flat_map(|leg| {
+                    leg.from_airport_icao
+                        .iter()
+                        .chain(leg.to_airport_icao.iter())
+                })
+                .collect::<HashSet<_>>()
+                .len();
+            let tail_number = legs.iter().rev().find_map(|leg| leg.tail_number.as_deref());
+            let operating_country = operating_country(&legs);
+            AircraftYear {
+                registration_country: crate::country::registration_country(
+                    &icao_number,
+                    tail_number,
+                ),
+                icao_number,
+                year,
+                legs: legs.len(),
+                hours: legs.iter().map(|leg| leg.duration).sum(),
+                distance: legs.iter().map(|leg| leg.distance).sum(),
+                co2_emissions: legs.iter().filter_map(|leg| leg.co2_emissions).sum(),
+                airports,
+                short_legs: legs
+                    .iter()
+                    .filter(|leg| leg.great_circle_distance < SHORT_LEG_DISTANCE)
+                    .count(),
+                operating_country_method: operating_country.as_ref().map(|x| x.1),
+                operating_country: operating_country.map(|x| x.0),
+            }
+        })
+        .collect()
+}
+
+/// Whether a [`CountryYear`] aggregates the aircrafts registered or operated in the country
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Basis {
+    /// Aircrafts whose country of registration is the country
+    Registration,
+    /// Aircrafts whose operating country is the country
+    Operation,
+}
+
+impl crate::schema::Type for Basis {
+    const NAME: &'static str = "string";
+}
+
+/// The statistics of the aircrafts of a country on a year
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CountryYear {
+    /// The country; its name for [`Basis::Registration`] and ISO 3166-1 alpha-2 for [`Basis::Operation`]
+    pub country: Arc<str>,
+    pub year: i32,
+    pub basis: Basis,
+    /// The number of aircrafts
+    pub aircrafts: usize,
+    /// The number of legs of the aircrafts
+    pub legs: usize,
+    /// The total duration of the legs in hours
+    pub hours: f64,
+    /// The total CO2 emissions of the legs in kg
+    pub co2_emissions: f64,
+}
+
+crate::schema!(CountryYear {
+    country: "The country; its name for the basis `registration` and ISO 3166-1 alpha-2 for `operation`",
+    year: "The year",
+    basis: "Whether the aircrafts are those registered (`registration`) or operated (`operation`) in the country",
+    aircrafts: "The number of aircrafts",
+    legs: "The number of legs of the aircrafts",
+    hours("h"): "The total duration of the legs",
+    co2_emissions("kg"): "The total CO2 emissions of the legs",
+});
+
+/// Returns the [`CountryYear`]s of `stats` of a year, ordered by basis and country.
+/// Aircrafts without a country on a basis are not included on that basis.
+pub fn country_year(year: i32, stats: &[AircraftYear]) -> Vec<CountryYear> {
+    let mut countries = BTreeMap::<(Basis, Arc<str>), CountryYear>::new();
+    for aircraft in stats {
+        let bases = [
+            (Basis::Registration, &aircraft.registration_country),
+            (Basis::Operation, &aircraft.operating_country),
+        ];
+        for (basis, country) in bases {
+            let Some(country) = country else {
+                continue;
+            };
+            let entry = countries
+                .entry((basis, country.clone()))
+                .or_insert_with(|| CountryYear {
+                    country: country.clone(),
+                    year,
+                    basis,
+                    aircrafts: 0,
+                    legs: 0,
+                    hours: 0.0,
+                    co2_emissions: 0.0,
+                });
+            entry.aircrafts += 1;
+            entry.legs += aircraft.legs;
+            entry.hours += aircraft.hours;
+            entry.co2_emissions += aircraft.co2_emissions;
+        }
+    }
+    countries.into_values().collect()
+}
+
+/// The rule suppressing the [`CountryYear`]s of few aircrafts or legs from publication (see `M-suppression`),
+/// so that the statistics of a country do not identify the owner of an aircraft
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Suppression {
+    /// Countries with fewer aircrafts than this on a basis are suppressed
+    pub min_aircrafts: usize,
+    /// Countries with fewer legs than this on a basis are suppressed
+    pub min_legs: usize,
+}
+
+impl Suppression {
+    /// Whether `country` is suppressed
+    pub fn suppresses(&self, country: &CountryYear) -> bool {
+        country.aircrafts < self.min_aircrafts || country.legs < self.min_legs
+    }
+}
+
+/// The report of the [`Suppression`] of the [`CountryYear`]s of a year
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SuppressionReport {
+    #[serde(flatten)]
+    pub rule: Suppression,
+    /// The number of suppressed countries on the basis `registration`
+    pub registration: usize,
+    /// The number of suppressed countries on the basis `operation`
+    pub operation: usize,
+}
+
+/// Returns `countries` without those suppressed by `rule`, and the report of the suppressed ones
+pub fn suppress(
+    countries: Vec<CountryYear>,
+    rule: Suppression,
+) -> (Vec<CountryYear>, SuppressionReport) {
+    let mut report = SuppressionReport {
+        rule,
+        registration: 0,
+        operation: 0,
+    };
+    let countries = countries
+        .into_iter()
+        .filter(|country| {
+            let suppressed = rule.suppresses(country);
+            if suppressed {
+                *match country.basis {
+                    Basis::Registration => &mut report.registration,
+                    Basis::Operation => &mut report.operation,
+                } += 1;
+            }
+            !suppressed
+        })
+        .collect();
+    (countries, report)
+}
+
+/// The emissions per distance of the legs of a model on a year
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelYear {
+    /// The position of the model in the year, from the lowest `co2_per_km` (1)
+    pub rank: usize,
+    /// The aircraft model (e.g. `GULFSTREAM 5`)
+    pub model: Arc<str>,
+    pub year: i32,
+    /// The number of aircrafts of the model with legs
+    pub aircrafts: usize,
+    /// The number of legs with emissions
+    pub legs: usize,
+    /// The median over legs of the CO2 emissions in kg per flown km
+    pub co2_per_km: f64,
+    /// The passenger capacity of the model, when known
+    pub seats: Option<u32>,
+    /// `co2_per_km` divided by `seats`, i.e. the CO2 emissions in kg per seat-km
+    pub co2_per_seat_km: Option<f64>,
+    /// The date of the most recent reclassification of the model until the end of the year, when any
+    /// (see [`crate::model::ModelReclassification`])
+    pub reclassified_on: Option<String>,
+}
+
+crate::schema!(ModelYear {
+    rank: "The position of the model in the year, from the lowest `co2_per_km` (1)",
+    model: "The aircraft model",
+    year: "The year",
+    aircrafts: "The number of aircrafts of the model with legs",
+    legs: "The number of legs with emissions",
+    co2_per_km("kg/km"): "The median over legs of the CO2 emissions per flown km",
+    seats: "The passenger capacity of the model, when known",
+    co2_per_seat_km("kg/km"): "`co2_per_km` divided by `seats`",
+    reclassified_on: "The date of the most recent reclassification of the model until the end of the year, when any",
+});
+
+/// Returns the median of `values`, or `None` when empty
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    values.sort_unstable_by(|a, b| a.total_cmp(b));
+    let middle = values.len() / 2;
+    match values.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((values[middle - 1] + values[middle]) / 2.0),
+        _ => Some(values[middle]),
+    }
+}
+
+/// Returns the [`ModelYear`]s of the `legs` of `year` ranked by `co2_per_km` (ties by model), where `seats` is the
+/// passenger capacity of each model (see [`crate::model::load_model_seats`]) and `changelog` the reclassifications
+/// of models (see [`crate::model::load_model_changelog`]).
+/// Legs without model, emissions or flown distance are ignored.
+pub fn model_year<'a>(
+    year: i32,
+    legs: impl Iterator<Item = &'a StatsLeg>,
+    seats: &HashMap<String, u32>,
+    changelog: &[ModelReclassification],
+) -> Vec<ModelYear> {
+    let end_of_year = format!("{year}-12-31");
+    let mut by_model = BTreeMap::<&Arc<str>, (HashSet<&Arc<str>>, Vec<f64>)>::new();
+    for leg in legs {
+        let (Some(model), Some(co2_emissions)) = (&leg.aircraft_model, leg.co2_emissions) else {
+            continue;
+        };
+        if leg.distance <= 0.0 {
+            continue;
+        }
+        let (aircrafts, co2_per_km) = by_model.entry(model).or_default();
+        aircrafts.insert(&leg.icao_number);
+        co2_per_km.push(co2_emissions / leg.distance);
+    }
+    let mut models = by_model
+        .into_iter()
+        .filter_map(|(model, (aircrafts, co2_per_km))| {
+            let legs = co2_per_km.len();
+            let co2_per_km = median(co2_per_km)?;
+            let seats = seats.get(model.as_ref()).copied().filter(|x| *x > 0);
+            Some(ModelYear {
+                rank: 0,
+                model: model.clone(),
+                year,
+                aircrafts: aircrafts.len(),
+                legs,
+                co2_per_km,
+                seats,
+                co2_per_seat_km: seats.map(|seats| co2_per_km / seats as f64),
+                reclassified_on: last_reclassification(changelog, model, &end_of_year)
+                    .map(|x| x.date.clone()),
+            })
+        })
+        .collect::<Vec<_>>();
+    models.sort_by(|a, b| a.co2_per_km.total_cmp(&b.co2_per_km));
+    for (i, model) in models.iter_mut().enumerate() {
+        model.rank = i + 1;
+    }
+    models
+}
+
+/// The statistics of the legs of an aircraft on a calendar quarter (in UTC)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AircraftQuarter {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    pub year: i32,
+    /// The quarter of the year, from 1 to 4
+    pub quarter: u8,
+    /// The number of legs starting in the quarter
+    pub legs: usize,
+    /// The duration in hours of the legs within the quarter
+    pub hours: f64,
+    /// The flown distance in km of the legs, proportional to their duration within the quarter
+    pub distance: f64,
+    /// The CO2 emissions in kg of the legs, proportional to their duration within the quarter
+    pub co2_emissions: f64,
+}
+
+crate::schema!(AircraftQuarter {
+    icao_number: "The ICAO number",
+    year: "The year",
+    quarter: "The quarter of the year, from 1 to 4",
+    legs: "The number of legs starting in the quarter",
+    hours("h"): "The duration of the legs within the quarter",
+    distance("km"): "The flown distance of the legs, proportional to their duration within the quarter",
+    co2_emissions("kg"): "The CO2 emissions of the legs, proportional to their duration within the quarter",
+});
+
+/// Returns the (year, quarter) of `datetime`
+fn quarter_of(datetime: OffsetDateTime) -> (i32, u8) {
+    (datetime.year(), (datetime.month() as u8 - 1) / 3 + 1)
+}
+
+/// Returns the start of the quarter after `(year, quarter)`
+fn next_quarter(year: i32, quarter: u8) -> OffsetDateTime {
+    let (year, month) = match quarter {
+        4 => (year + 1, Month::January),
+        quarter => (year, Month::try_from(quarter * 3 + 1).unwrap()),
+    };
+    Date::from_calendar_date(year, month, 1)
+        .unwrap()
+        .midnight()
+        .assume_utc()
+}
+
+/// Returns the quarters `leg` was airborne in and the fraction of its duration in each of them
+fn quarter_fractions(leg: &StatsLeg) -> Vec<((i32, u8), f64)> {
+    let duration = (leg.end - leg.start).as_seconds_f64();
+    if duration <= 0.0 {
+        return vec![(quarter_of(leg.start), 1.0)];
+    }
+    let mut fractions = vec![];
+    let mut from = leg.start;
+    loop {
+        let quarter = quarter_of(from);
+        let next = next_quarter(quarter.0, quarter.1);
+        let to = next.min(leg.end);
+        fractions.push((quarter, (to - from).as_seconds_f64() / duration));
+        if leg.end <= next {
+            return fractions;
+        }
+        from = next;
+    }
+}
+
+/// Returns the [`AircraftQuarter`]s of the quarters of `year` from `legs`, ordered by ICAO number and quarter.
+/// Legs crossing quarters are split proportionally to their duration in each quarter; only the parts within
+/// `year` are included (so that `legs` should contain the legs of the previous year ending in `year`).
+pub fn aircraft_quarter<'a>(
+    year: i32,
+    legs: impl Iterator<Item = &'a StatsLeg>,
+) -> Vec<AircraftQuarter> {
+    let mut quarters = BTreeMap::<(Arc<str>, u8), AircraftQuarter>::new();
+    for leg in legs {
+        let start = quarter_of(leg.start);
+        for ((leg_year, quarter), fraction) in quarter_fractions(leg) {
+            if leg_year != year {
+                continue;
+            }
+            let entry = quarters
+                .entry((leg.icao_number.clone(), quarter))
+                .or_insert_with(|| AircraftQuarter {
+                    icao_number: leg.icao_number.clone(),
+                    year,
+                    quarter,
+                    legs: 0,
+                    hours: 0.0,
+                    distance: 0.0,
+                    co2_emissions: 0.0,
+                });
+            entry.legs += (start == (year, quarter)) as usize;
+            entry.hours += leg.duration * fraction;
+            entry.distance += leg.distance * fraction;
+            entry.co2_emissions += leg.co2_emissions.unwrap_or(0.0) * fraction;
+        }
+    }
+    quarters.into_values().collect()
+}
+
+async fn read_legs(
+    year: i32,
+    roots: &Roots,
+    client: &dyn BlobStorageProvider,
+) -> Result<Option<Vec<StatsLeg>>, Box<dyn Error>> {
+    let key = format!("{}all/year={year}/data.csv", roots.legs);
+    let Some(data) = crate::io::maybe_get(&key, client).await? else {
+        log::warn!("{key} does not exist");
+        return Ok(None);
+    };
+    Ok(Some(
+        crate::csv::deserialize::<StatsLeg>(&data).collect::<Result<Vec<_>, _>>()?,
+    ))
+}
+
+/// Returns the `legs` of `year` that end after it
+fn crossing(year: i32, legs: &[StatsLeg]) -> Vec<StatsLeg> {
+    legs.iter()
+        .filter(|leg| leg.end.year() > year)
+        .cloned()
+        .collect()
+}
+
+/// Computes the [`AircraftYear`]s, [`CountryYear`]s, [`ModelYear`]s and [`AircraftQuarter`]s of each of `years`
+/// from the public dataset of legs of the published version (see [`Roots::published`]) and writes them to
+/// `stats/v1/aircraft_year/year={year}/data.csv`, `stats/v1/country_year/year={year}/data.csv`,
+/// `stats/v1/model_year/year={year}/data.csv` and `stats/v1/aircraft_quarter/year={year}/data.csv`, with `seats`
+/// the passenger capacity of each model and `changelog` the reclassifications of models.
+/// The [`CountryYear`]s suppressed by `suppression` are not written; the report of the suppressed ones is written
+/// to `stats/v1/country_year/year={year}/suppression.json`.
+/// Years without a dataset of legs are skipped.
+pub async fn etl_aircraft_stats(
+    years: impl Iterator<Item = i32>,
+    seats: &HashMap<String, u32>,
+    changelog: &[ModelReclassification],
+    suppression: Suppression,
+    client: &dyn BlobStorageProvider,
+) -> Result<(), Box<dyn Error>> {
+    // so that loaders do not need to guess the types and units of the columns (see `M-schemas`)
+    let metric = crate::units::Units::Metric;
+    crate::schema::write::<AircraftYear>(DATABASE_ROOT, metric, client).await?;
+    crate::schema::write::<CountryYear>(COUNTRY_DATABASE_ROOT, metric, client).await?;
+    crate::schema::write::<ModelYear>(MODEL_DATABASE_ROOT, metric, client).await?;
+    crate::schema::write::<AircraftQuarter>(QUARTER_DATABASE_ROOT, metric, client).await?;
+    let roots = Roots::published(client).await?;
+    log::info!("reading the legs of {}", roots.legs);
+    // the legs of a year that end in the next year, carried over to the quarters of the next year
+    let mut carried = None::<(i32, Vec<StatsLeg>)>;
+    for year in years {
+        let Some(legs) = read_legs(year, &roots, client).await? else {
+            log::warn!("skipping year={year}");
+            continue;
+        };
+        log::info!("legs of year={year}: {}", legs.len());
+
+        let previous = match carried.take() {
+            Some((carried_year, legs)) if carried_year == year - 1 => legs,
+            _ => read_legs(year - 1, &roots, client)
+                .await?
+                .map(|legs| crossing(year - 1, &legs))
+                .unwrap_or_default(),
+        };
+        let quarters = aircraft_quarter(year, previous.iter().chain(legs.iter()));
+        let key = format!("{QUARTER_DATABASE_ROOT}year={year}/data.csv");
+        client
+            .put(&key, crate::csv::serialize(quarters.into_iter()))
+            .await?;
+        log::info!("Written {key}");
+        carried = Some((year, crossing(year, &legs)));
+
+        let key = format!("{MODEL_DATABASE_ROOT}year={year}/data.csv");
+        client
+            .put(
+                &key,
+                crate::csv::serialize(model_year(year, legs.iter(), seats, changelog).into_iter()),
+            )
+            .await?;
+        log::info!("Written {key}");
+
+        let stats = aircraft_year(year, legs.into_iter());
+        let key = aircraft_year_blob_name(year);
+        client
+            .put(&key, crate::csv::serialize(stats.iter()))
+            .await?;
+        log::info!("Written {key}");
+
+        let (countries, report) = suppress(country_year(year, &stats), suppression);
+        let key = format!("{COUNTRY_DATABASE_ROOT}year={year}/data.csv");
+        client
+            .put(&key, crate::csv::serialize(countries.into_iter()))
+            .await?;
+        log::info!("Written {key}");
+        let key = format!("{COUNTRY_DATABASE_ROOT}year={year}/suppression.json");
+        client.put(&key, serde_json::to_vec(&report)?).await?;
+        log::info!(
+            "Written {key}: {} countries suppressed",
+            report.registration + report.operation
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn leg(icao_number: &str, distance: f64, from: &str, to: Option<&str>) -> StatsLeg {
+        StatsLeg {
+            icao_number: icao_number.into(),
+            tail_number: None,
+            aircraft_model: Some("GULFSTREAM 5".into()),
+            start: datetime!(2023-01-01 10:00 UTC),
+            end: datetime!(2023-01-01 10:30 UTC),
+            duration: 0.5,
+            distance: distance * 1.1,
+            great_circle_distance: distance,
+            co2_emissions: (icao_number == "aa").then_some(100.0),
+            from_airport_icao: Some(from.into()),
+            to_airport_icao: to.map(Into::into),
+            from_country: None,
+            to_country: None,
+        }
+    }
+
+    #[test]
+    fn work() {
+        let legs = vec![
+            leg("bb", 10.0, "EKRK", None),
+            leg("aa", 300.0, "EKCH", Some("EDDB")),
+            leg("aa", 300.0, "EDDB", Some("EKCH")),
+            leg("aa", 20.0, "EKCH", Some("EKRK")),
+        ];
+
+        let stats = aircraft_year(2023, legs.into_iter());
+
+        assert_eq!(stats.len(), 2);
+        let aa = &stats[0];
+        assert_eq!(aa.icao_number.as_ref(), "aa");
+        assert_eq!(aa.legs, 3);
+        assert_eq!(aa.hours, 1.5);
+        assert_eq!(aa.co2_emissions, 300.0);
+        assert_eq!(aa.airports, 3);
+        assert_eq!(aa.short_legs, 1);
+        let bb = &stats[1];
+        assert_eq!((bb.legs, bb.co2_emissions, bb.airports), (1, 0.0, 1));
+        assert_eq!(bb.short_legs, 1);
+    }
+
+    #[test]
+    fn quarters() {
+        let at = |leg: StatsLeg, start: OffsetDateTime, end: OffsetDateTime| StatsLeg {
+            start,
+            end,
+            duration: (end - start).as_seconds_f64() / 60.0 / 60.0,
+            ..leg
+        };
+        let legs = [
+            // within Q1
+            at(
+                leg("aa", 100.0, "EKCH", None),
+                datetime!(2023-02-01 10:00 UTC),
+                datetime!(2023-02-01 11:00 UTC),
+            ),
+            // 1/4 in Q1 and 3/4 in Q2
+            at(
+                leg("aa", 400.0, "EKCH", None),
+                datetime!(2023-03-31 23:45 UTC),
+                datetime!(2023-04-01 00:45 UTC),
+            ),
+            // 1/2 in Q4 of 2022, carried over from the previous year
+            at(
+                leg("bb", 200.0, "EKCH", None),
+                datetime!(2022-12-31 23:00 UTC),
+                datetime!(2023-01-01 01:00 UTC),
+            ),
+        ];
+        let quarters = aircraft_quarter(2023, legs.iter());
+        let keys = quarters
+            .iter()
+            .map(|x| (x.icao_number.as_ref(), x.quarter, x.legs))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, vec![("aa", 1, 2), ("aa", 2, 0), ("bb", 1, 0)]);
+
+        assert_eq!(quarters[0].hours, 1.0 + 0.25);
+        assert_eq!(quarters[0].co2_emissions, 100.0 + 25.0);
+        assert_eq!(quarters[1].hours, 0.75);
+        assert_eq!(quarters[1].co2_emissions, 75.0);
+        assert!((quarters[2].distance - 220.0 / 2.0).abs() < 1e-9);
+
+        // the other half is in the previous year
+        let quarters = aircraft_quarter(2022, legs[2..].iter());
+        assert_eq!((quarters[0].quarter, quarters[0].legs), (4, 1));
+        assert_eq!(quarters[0].hours, 1.0);
+    }
+
+    #[test]
+    fn models() {
+        let legs = [
+            leg("aa", 100.0, "EKCH", None),
+            leg("aa", 200.0, "EKCH", None),
+            leg("aa", 300.0, "EKCH", None),
+            StatsLeg {
+                aircraft_model: Some("BEECH 400 Beechjet".into()),
+                co2_emissions: Some(50.0),
+                ..leg("bb", 100.0, "EKCH", None)
+            },
+            // without emissions
+            leg("cc", 100.0, "EKCH", None),
+        ];
+        let seats = HashMap::from([("BEECH 400 Beechjet".to_string(), 8)]);
+
+        let changelog = vec![ModelReclassification {
+            model: "BEECH 400 Beechjet".to_string(),
+            date: "2024-01-01".to_string(),
+            from: "private jet".to_string(),
+            to: "air-taxi".to_string(),
+            reason: "".to_string(),
+            source: "".to_string(),
+        }];
+
+        let models = model_year(2023, legs.iter(), &seats, &changelog);
+        assert_eq!(models.len(), 2);
+        assert_eq!(
+            (models[0].rank, models[0].model.as_ref()),
+            (1, "BEECH 400 Beechjet")
+        );
+        assert!((models[0].co2_per_km - 50.0 / 110.0).abs() < 1e-9);
+        assert!((models[0].co2_per_seat_km.unwrap() - 50.0 / 110.0 / 8.0).abs() < 1e-9);
+        assert_eq!(
+            (models[1].rank, models[1].aircrafts, models[1].legs),
+            (2, 1, 3)
+        );
+        assert!((models[1].co2_per_km - 100.0 / 220.0).abs() < 1e-9);
+        assert_eq!(models[1].co2_per_seat_km, None);
+        // reclassified after the year
+        assert_eq!(models[0].reclassified_on, None);
+        let models = model_year(2024, legs.iter(), &seats, &changelog);
+        assert_eq!(models[0].reclassified_on.as_deref(), Some("2024-01-01"));
+    }
+
+    #[test]
+    fn operating_country() {
+        let at = |leg: StatsLeg, day: u8, from: &str, to: &str| StatsLeg {
+            start: leg.start.replace_day(day).unwrap(),
+            end: leg.end.replace_day(day).unwrap(),
+            from_country: Some(from.into()),
+            to_country: Some(to.into()),
+            tail_number: Some("M-ABCD".into()),
+            ..leg
+        };
+        // registered in the Isle of Man, based in Denmark: a long stay at EKCH and a short one at LSGG
+        let legs = vec![
+            at(leg("43e8c1", 900.0, "EKCH", Some("LSGG")), 1, "DK", "CH"),
+            at(leg("43e8c1", 900.0, "LSGG", Some("EKCH")), 2, "CH", "DK"),
+            at(leg("43e8c1", 900.0, "EKCH", Some("LSGG")), 9, "DK", "CH"),
+        ];
+        let stats = aircraft_year(2023, legs.into_iter());
+        assert_eq!(stats[0].registration_country, Some("Isle of Man".into()));
+        assert_eq!(stats[0].operating_country, Some("DK".into()));
+        assert_eq!(
+            stats[0].operating_country_method,
+            Some(OperatingCountryMethod::HomeBase)
+        );
+
+        let countries = country_year(2023, &stats);
+        assert_eq!(countries.len(), 2);
+        assert_eq!(
+            (countries[0].basis, countries[0].country.as_ref()),
+            (Basis::Registration, "Isle of Man")
+        );
+        assert_eq!(
+            (countries[1].basis, countries[1].country.as_ref()),
+            (Basis::Operation, "DK")
+        );
+        assert_eq!(countries[1].legs, 3);
+
+        // a single aircraft is suppressed on both bases
+        let rule = Suppression {
+            min_aircrafts: 2,
+            min_legs: 0,
+        };
+        let (suppressed, report) = suppress(countries.clone(), rule);
+        assert!(suppressed.is_empty());
+        assert_eq!((report.registration, report.operation), (1, 1));
+        let rule = Suppression {
+            min_aircrafts: 1,
+            min_legs: 4,
+        };
+        assert!(suppress(countries.clone(), rule).0.is_empty());
+        // by default, nothing is suppressed
+        assert_eq!(suppress(countries, Suppression::default()).0.len(), 2);
+
+        // without time on the ground, the country most departed from and arrived at
+        let legs = vec![at(leg("aa", 900.0, "EKCH", None), 1, "DK", "DK")];
+        let stats = aircraft_year(2023, legs.into_iter());
+        assert_eq!(stats[0].operating_country, Some("DK".into()));
+        assert_eq!(
+            stats[0].operating_country_method,
+            Some(OperatingCountryMethod::MostVisited)
+        );
+    }
+}
@@ -0,0 +1,241 @@
+//! Contains the implementation to look up the time zone of positions using the boundaries of the
+//! [IANA time zones](https://www.iana.org/time-zones) of
+//! [timezone-boundary-builder](https://github.com/evansiroky/timezone-boundary-builder), and the local time at them.
+use std::{io::Read, sync::Arc};
+
+use serde_json::Value;
+use time::OffsetDateTime;
+use time_tz::{OffsetDateTimeExt, TimeZone, Tz};
+
+use crate::{
+    fs::{self, BlobStorageProvider},
+    region::Region,
+};
+
+static DATABASE: &str = "timezone/boundary-builder/data.geojson";
+
+fn url() -> &'static str {
+    "https://github.com/evansiroky/timezone-boundary-builder/releases/download/2024b/timezones-with-oceans-now.geojson.zip"
+}
+
+/// A set of IANA time zones and their borders, including the time zones of the oceans (`Etc/GMT+1`, ...)
+pub struct TimeZones {
+    /// each time zone, its borders and their bounding box
+    zones: Vec<(&'static Tz, Region, Region)>,
+}
+
+/// Returns the time zone and its polygons from a GeoJSON `Feature` of timezone-boundary-builder,
+/// or `None` when the time zone is not in the tz database of this build
+fn parse_feature(feature: &Value) -> Result<Option<(&'static Tz, Region, Region)>, String> {
+    let tzid = feature
+        .get("properties")
+        .and_then(|x| x.get("tzid"))
+        .and_then(|x| x.as_str())
+        .ok_or_else(|| format!("feature without tzid: {feature}"))?;
+    let Some(tz) = time_tz::timezones::get_by_name(tzid) else {
+        log::warn!("time zone {tzid} is not in the tz database; ignoring it");
+        return Ok(None);
+    };
+    let polygons = crate::region::parse_geojson(feature)?;
+    let bbox = crate::geo::bounding_box(&polygons);
+    Ok(Some((tz, Region::Polygons(polygons), bbox)))
+}
+
+impl TimeZones {
+    /// Returns [`TimeZones`] from a GeoJSON `FeatureCollection` of timezone-boundary-builder
+    pub fn from_geojson(data: &[u8]) -> Result<Self, String> {
+        let value = serde_json::from_slice::<Value>(data).map_err(|e| e.to_string())?;
+        let zones = value
+            .get("features")
+            .and_then(|x| x.as_array())
+            .ok_or_else(|| "GeoJSON must be a FeatureCollection".to_string())?
+            .iter()
+            .map(parse_feature)
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        Ok(Self { zones })
+    }
+
+    /// Returns the time zone containing `(latitude, longitude)`, or `None` when it is not in any
+    pub fn time_zone_of(&self, latitude: f64, longitude: f64) -> Option<&'static Tz> {
+        self.zones
+            .iter()
+            .find(|(_, borders, bbox)| {
+                bbox.contains((latitude, longitude)) && borders.contains((latitude, longitude))
+            })
+            .map(|(tz, _, _)| *tz)
+    }
+
+    /// Returns `datetime` in the local time (with its UTC offset) at `(latitude, longitude)`,
+    /// or `None` when it is not in any time zone
+    pub fn local(
+        &self,
+        datetime: OffsetDateTime,
+        latitude: f64,
+        longitude: f64,
+    ) -> Option<OffsetDateTime> {
+        self.time_zone_of(latitude, longitude)
+            .map(|tz| datetime.to_timezone(tz))
+    }
+}
+
+/// Returns the first file of the zip archive `data`
+fn unzip(data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    let mut archive =
+        zip::ZipArchive::new(std::io::Cursor::new(data)).map_err(std::io::Error::other)?;
+    let mut file = archive.by_index(0).map_err(std::io::Error::other)?;
+    let mut contents = vec![];
+    file.read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+async fn extract() -> Result<Vec<u8>, std::io::Error> {
+    let data = reqwest::get(url())
+        .await
+        .map_err(std::io::Error::other)?
+        .bytes()
+        .await
+        .map_err(std::io::Error::other)?;
+    unzip(&data)
+}
+
+/// Returns [`TimeZones`] from [timezone-boundary-builder](https://github.com/evansiroky/timezone-boundary-builder).
+/// # Implementation
+/// The dataset is cached in `client` (or on local disk when `client` cannot be written to)
+/// the first time it is used.
+pub async fn time_zones(client: &dyn BlobStorageProvider) -> Result<TimeZones, std::io::Error> {
+    let data =
+        fs::cached_call(DATABASE, extract(), client, fs::CacheAction::ReadFetchWrite).await?;
+    TimeZones::from_geojson(&data).map_err(std::io::Error::other)
+}
+
+/// Returns `datetime` formatted in RFC 3339 with its UTC offset (e.g. `2023-07-01T12:00:00+02:00`)
+pub fn to_rfc3339(datetime: OffsetDateTime) -> Arc<str> {
+    datetime
+        .format(&time::format_description::well_known::Rfc3339)
+        .expect("rfc3339 of years between 0 and 9999 never errors")
+        .into()
+}
+
+/// The calendar in which legs are assigned to months and years when aggregated (see `M-local-months`)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Calendar {
+    /// Coordinated Universal Time
+    #[default]
+    Utc,
+    /// an IANA time zone (e.g. `America/New_York`)
+    Zone(&'static Tz),
+    /// the local time at the start of each leg (its `start_local`), or UTC when it is unknown
+    Departure,
+}
+
+impl std::str::FromStr for Calendar {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "utc" | "UTC" => Ok(Self::Utc),
+            "local" => Ok(Self::Departure),
+            name => time_tz::timezones::get_by_name(name)
+                .map(Self::Zone)
+                .ok_or_else(|| {
+                    format!("time zone `{name}` must be `utc`, `local` or an IANA time zone")
+                }),
+        }
+    }
+}
+
+impl Calendar {
+    /// The name of the calendar: `UTC`, the name of its time zone or `local`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Utc => "UTC",
+            Self::Zone(tz) => tz.name(),
+            Self::Departure => "local",
+        }
+    }
+
+    /// Returns the date in this calendar of `datetime`, whose local time is `local` (in RFC 3339) when known
+    pub fn date(&self, datetime: OffsetDateTime, local: Option<&str>) -> time::Date {
+        use time::format_description::well_known::Rfc3339;
+        match self {
+            Self::Utc => datetime.date(),
+            Self::Zone(tz) => datetime.to_timezone(*tz).date(),
+            Self::Departure => local
+                .and_then(|local| OffsetDateTime::parse(local, &Rfc3339).ok())
+                .unwrap_or(datetime)
+                .date(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    #[test]
+    fn local() {
+        let data = br#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {"tzid": "Europe/Copenhagen"},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[8.0, 54.5], [13.0, 54.5], [13.0, 57.8], [8.0, 57.8], [8.0, 54.5]]]
+                }
+            }, {
+                "type": "Feature",
+                "properties": {"tzid": "Etc/GMT+1"},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[-22.5, -10.0], [-7.5, -10.0], [-7.5, 10.0], [-22.5, 10.0], [-22.5, -10.0]]]
+                }
+            }]
+        }"#;
+        let zones = TimeZones::from_geojson(data).unwrap();
+
+        // summer time
+        let local = zones.local(datetime!(2023-07-01 10:00 UTC), 55.68, 12.57);
+        assert_eq!(
+            to_rfc3339(local.unwrap()).as_ref(),
+            "2023-07-01T12:00:00+02:00"
+        );
+        let local = zones.local(datetime!(2023-01-01 10:00 UTC), 55.68, 12.57);
+        assert_eq!(
+            to_rfc3339(local.unwrap()).as_ref(),
+            "2023-01-01T11:00:00+01:00"
+        );
+        // the ocean
+        let local = zones.local(datetime!(2023-01-01 10:00 UTC), 0.0, -15.0);
+        assert_eq!(
+            to_rfc3339(local.unwrap()).as_ref(),
+            "2023-01-01T09:00:00-01:00"
+        );
+        assert!(zones
+            .local(datetime!(2023-01-01 10:00 UTC), 0.0, 0.0)
+            .is_none());
+    }
+
+    #[test]
+    fn calendar() {
+        let new_york = "America/New_York".parse::<Calendar>().unwrap();
+        assert_eq!(new_york.name(), "America/New_York");
+        let datetime = datetime!(2023-01-01 03:00 UTC);
+        assert_eq!(Calendar::Utc.date(datetime, None), datetime.date());
+        assert_eq!(
+            new_york.date(datetime, None),
+            time::macros::date!(2022 - 12 - 31)
+        );
+        assert_eq!(
+            Calendar::Departure.date(datetime, Some("2022-12-31T19:00:00-08:00")),
+            time::macros::date!(2022 - 12 - 31)
+        );
+        assert_eq!(Calendar::Departure.date(datetime, None), datetime.date());
+        assert!("Mars/Olympus_Mons".parse::<Calendar>().is_err());
+    }
+}
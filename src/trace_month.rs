@@ -4,11 +4,11 @@ use futures::{StreamExt, TryStreamExt};
 use time::Date;
 
 use super::Position;
-use crate::{fs, icao_to_trace::cached_aircraft_positions};
+use crate::{altitude::Preference, fs, fs_index, icao_to_trace::cached_aircraft_positions};
 
 static DATABASE: &'static str = "position/";
 
-fn pk_to_blob_name(icao: &str, date: time::Date) -> String {
+pub(crate) fn pk_to_blob_name(icao: &str, date: time::Date) -> String {
     let month = crate::serde::month_to_part(date);
     format!("{DATABASE}icao_number={icao}/month={month}/data.json",)
 }
@@ -33,14 +33,26 @@ pub fn first_of_next_month(month: &time::Date) -> time::Date {
         })
 }
 
-/// Returns the positions of an aircraft at a given month, ordered by timestamp
+/// Returns the positions of an aircraft at a given month, ordered by timestamp, with barometric altitudes
+/// (see [`month_positions_with`])
+pub async fn month_positions(
+    icao_number: &str,
+    month: time::Date,
+    client: &dyn fs::BlobStorageProvider,
+) -> Result<Vec<Position>, std::io::Error> {
+    month_positions_with(icao_number, month, Preference::Barometric, client).await
+}
+
+/// Returns the positions of an aircraft at a given month, ordered by timestamp, with the altitude of `altitude`
+/// (see `M-altitudes`)
 /// # Implementation
 /// This function is idempotent but not pure:
 /// * the data is retrieved from `https://globe.adsbexchange.com`
 /// * the call is cached on local disk or Remote Blob (depending on `client` configuration)
-pub async fn month_positions(
+pub async fn month_positions_with(
     icao_number: &str,
     month: time::Date,
+    altitude: Preference,
     client: &dyn fs::BlobStorageProvider,
 ) -> Result<Vec<Position>, std::io::Error> {
     log::info!("month_positions({icao_number},{month})");
@@ -52,7 +64,7 @@ pub async fn month_positions(
 
     let fetch = async {
         // fetch all positions for the month for icao
-        let tasks = cached_aircraft_positions(icao_number, month, to, client);
+        let tasks = cached_aircraft_positions(icao_number, month, to, altitude, client);
         let mut positions = futures::stream::iter(tasks)
             // limit concurrent tasks
             .buffered(5)
@@ -82,6 +94,17 @@ pub async fn aircraft_positions(
     to: Date,
     icao_number: &str,
     client: &dyn fs::BlobStorageProvider,
+) -> Result<Vec<Position>, Box<dyn Error>> {
+    aircraft_positions_with(from, to, icao_number, Preference::Barometric, client).await
+}
+
+/// Same as [`aircraft_positions`], with the altitude of `altitude` (see `M-altitudes`)
+pub async fn aircraft_positions_with(
+    from: Date,
+    to: Date,
+    icao_number: &str,
+    altitude: Preference,
+    client: &dyn fs::BlobStorageProvider,
 ) -> Result<Vec<Position>, Box<dyn Error>> {
     let dates = super::DateIter {
         from,
@@ -96,9 +119,9 @@ pub async fn aircraft_positions(
         })
         .collect::<HashSet<_>>();
 
-    let tasks = months
-        .into_iter()
-        .map(|month| async move { month_positions(icao_number, month, client).await });
+    let tasks = months.into_iter().map(|month| async move {
+        month_positions_with(icao_number, month, altitude, client).await
+    });
 
     let positions = futures::stream::iter(tasks)
         // limit concurrent tasks
@@ -126,13 +149,213 @@ pub async fn get_month_positions(
     assert_eq!(month.day(), 1);
     let blob_name = pk_to_blob_name(&icao_number, month);
 
-    let r = client
-        .maybe_get(&blob_name)
-        .await?
-        .ok_or_else(|| std::io::Error::other(format!("{blob_name} does not exist")))?;
+    let r = client.maybe_get(&blob_name).await?.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{blob_name} does not exist"),
+        )
+    })?;
     Ok(serde_json::from_slice(&r)?)
 }
 
+/// Writes the positions of an aircraft at a given month to the database, e.g. positions of another source
+/// (see `M-source-selection`)
+pub async fn put_month_positions(
+    icao_number: &str,
+    month: time::Date,
+    positions: &[Position],
+    client: &dyn fs::BlobStorageProvider,
+) -> Result<(), std::io::Error> {
+    assert_eq!(month.day(), 1);
+    let blob_name = pk_to_blob_name(icao_number, month);
+    client.put(&blob_name, serde_json::to_vec(positions)?).await
+}
+
+/// Returns the raw (JSON) positions of an aircraft at a given month from the database.
+/// Use [`positions_from_json`] to lazily deserialize them.
+pub async fn get_month_positions_json(
+    icao_number: &str,
+    month: time::Date,
+    client: &dyn fs::BlobStorageProvider,
+) -> Result<Vec<u8>, std::io::Error> {
+    log::info!("get_month_positions_json({icao_number},{month})");
+    assert_eq!(month.day(), 1);
+    let blob_name = pk_to_blob_name(icao_number, month);
+
+    client.maybe_get(&blob_name).await?.ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("{blob_name} does not exist"),
+        )
+    })
+}
+
+/// Returns an iterator of [`Position`] over a JSON array of positions, deserializing one position at a time.
+/// # Implementation
+/// Contrarily to `serde_json::from_slice::<Vec<Position>>`, this does not hold all positions in memory.
+pub fn positions_from_json(
+    data: &[u8],
+) -> impl Iterator<Item = Result<Position, std::io::Error>> + '_ {
+    let skip_whitespace = |offset: usize| {
+        offset
+            + data[offset..]
+                .iter()
+                .take_while(|c| c.is_ascii_whitespace())
+                .count()
+    };
+    let mut offset = skip_whitespace(0);
+    let mut state = match data.get(offset) {
+        Some(b'[') => {
+            offset = skip_whitespace(offset + 1);
+            (data.get(offset) != Some(&b']')).then_some(Ok(()))
+        }
+        _ => Some(Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "positions must be a JSON array",
+        ))),
+    };
+
+    std::iter::from_fn(move || {
+        if let Err(e) = state.take()? {
+            return Some(Err(e));
+        };
+        let mut stream =
+            serde_json::Deserializer::from_slice(&data[offset..]).into_iter::<Position>();
+        let position = match stream.next()? {
+            Ok(position) => position,
+            Err(e) => return Some(Err(e.into())),
+        };
+        offset = skip_whitespace(offset + stream.byte_offset());
+        state = match data.get(offset) {
+            Some(b',') => {
+                offset = skip_whitespace(offset + 1);
+                Some(Ok(()))
+            }
+            Some(b']') => None,
+            _ => Some(Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "positions must be a JSON array",
+            ))),
+        };
+        Some(Ok(position))
+    })
+}
+
+/// Returns the ranges of the elements of a JSON array in `data`.
+/// # Implementation
+/// Elements are delimited by scanning the bytes for commas outside of strings and nested values, without
+/// deserializing them, so that they can be deserialized independently.
+#[cfg(feature = "parallel")]
+fn json_array_elements(data: &[u8]) -> Result<Vec<std::ops::Range<usize>>, std::io::Error> {
+    let error = || {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "positions must be a JSON array",
+        )
+    };
+    let trim = |range: std::ops::Range<usize>| {
+        let start = range.start
+            + data[range.clone()]
+                .iter()
+                .take_while(|c| c.is_ascii_whitespace())
+                .count();
+        let end = range.end
+            - data[start..range.end]
+                .iter()
+                .rev()
+                .take_while(|c| c.is_ascii_whitespace())
+                .count();
+        start..end
+    };
+
+    let Some(open) = data.iter().position(|c| !c.is_ascii_whitespace()) else {
+        return Err(error());
+    };
+    if data[open] != b'[' {
+        return Err(error());
+    }
+    let mut elements = vec![];
+    let mut start = open + 1;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in data.iter().enumerate().skip(open + 1) {
+        if in_string {
+            match (escaped, c) {
+                (true, _) => escaped = false,
+                (false, b'\\') => escaped = true,
+                (false, b'"') => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (depth, c) {
+            (_, b'"') => in_string = true,
+            (_, b'{' | b'[') => depth += 1,
+            (0, b',') => {
+                elements.push(trim(start..i));
+                start = i + 1;
+            }
+            (0, b']') => {
+                let last = trim(start..i);
+                if !last.is_empty() || !elements.is_empty() {
+                    elements.push(last);
+                }
+                return Ok(elements);
+            }
+            (_, b'}' | b']') => depth -= 1,
+            _ => {}
+        }
+    }
+    Err(error())
+}
+
+/// Returns the positions of a JSON array of positions, deserialized in parallel on rayon's thread pool.
+/// # Implementation
+/// Contrarily to [`positions_from_json`], this holds all positions in memory.
+#[cfg(feature = "parallel")]
+pub fn positions_from_json_parallel(data: &[u8]) -> Result<Vec<Position>, std::io::Error> {
+    use rayon::prelude::*;
+    json_array_elements(data)?
+        .into_par_iter()
+        // so that each task deserializes enough positions to amortize its scheduling
+        .with_min_len(1024)
+        .map(|range| serde_json::from_slice(&data[range]).map_err(std::io::Error::from))
+        .collect()
+}
+
+/// Returns an iterator of [`Position`] over a JSON array of positions, deserialized in parallel when `parallel`
+/// (see [`positions_from_json_parallel`]; requires feature `parallel`) and one at a time otherwise
+/// (see [`positions_from_json`])
+pub fn decode_positions(
+    data: &[u8],
+    parallel: bool,
+) -> Box<dyn Iterator<Item = Result<Position, std::io::Error>> + Send + '_> {
+    if !parallel {
+        return Box::new(positions_from_json(data));
+    }
+    #[cfg(feature = "parallel")]
+    let positions = positions_from_json_parallel(data);
+    #[cfg(not(feature = "parallel"))]
+    let positions: Result<Vec<Position>, _> = Err(std::io::Error::other(
+        "this build does not support parallel decoding; compile with feature `parallel`",
+    ));
+    match positions {
+        Ok(positions) => Box::new(positions.into_iter().map(Ok)),
+        Err(e) => Box::new(std::iter::once(Err(e))),
+    }
+}
+
+/// Returns an [`IndexedClient`](fs_index::IndexedClient) over the positions in `client`, so that
+/// [`list_months_positions`] does not list all positions. See [`IndexedClient::new`](fs_index::IndexedClient::new)
+/// for `refresh`.
+pub async fn indexed_client<C: fs::BlobStorageProvider>(
+    client: C,
+    refresh: bool,
+) -> Result<fs_index::IndexedClient<C>, std::io::Error> {
+    fs_index::IndexedClient::new(client, DATABASE, refresh).await
+}
+
 /// Returns the set of (icao, month) that exists in the db
 pub async fn list_months_positions(
     client: &dyn fs::BlobStorageProvider,
@@ -174,6 +397,57 @@ mod test {
         );
     }
 
+    #[test]
+    fn positions_from_json() {
+        let positions = vec![
+            Position {
+                datetime: time::macros::datetime!(2022 - 02 - 01 10:00 UTC),
+                latitude: 1.0,
+                longitude: 2.0,
+                altitude: None,
+                altitude_source: None,
+            },
+            Position {
+                datetime: time::macros::datetime!(2022 - 02 - 01 10:01 UTC),
+                latitude: 1.5,
+                longitude: 2.5,
+                altitude: Some(1000.0),
+                altitude_source: None,
+            },
+        ];
+        let data = serde_json::to_vec_pretty(&positions).unwrap();
+        let result = super::positions_from_json(&data)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(result, positions);
+
+        assert_eq!(super::positions_from_json(b" [ ] ").count(), 0);
+        assert!(super::positions_from_json(b"{}").next().unwrap().is_err());
+        assert!(super::positions_from_json(b"[{}]").next().unwrap().is_err());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn positions_from_json_parallel() {
+        let data = br#" [ {"datetime": "2022-02-01T10:00:00Z", "latitude": 1.0, "longitude": 2.0},
+            {"datetime":"2022-02-01T10:01:00Z","latitude":1.5,"longitude":2.5,"altitude":1000.0} ] "#;
+        let expected = super::positions_from_json(data)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(super::positions_from_json_parallel(data).unwrap(), expected);
+
+        assert_eq!(
+            super::json_array_elements(br#"[{"a": ",]"}, [1]]"#).unwrap(),
+            vec![1..12, 14..17]
+        );
+        assert!(super::positions_from_json_parallel(b" [ ] ")
+            .unwrap()
+            .is_empty());
+        assert!(super::positions_from_json_parallel(b"{}").is_err());
+        assert!(super::positions_from_json_parallel(b"[{}]").is_err());
+        assert!(super::positions_from_json_parallel(b"[{}").is_err());
+    }
+
     #[tokio::test]
     async fn list_months_positions() {
         let a = super::list_months_positions(&LocalDisk).await.unwrap();
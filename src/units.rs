@@ -0,0 +1,77 @@
+//! Contains the units in which datasets can be emitted.
+use serde::{Deserialize, Serialize};
+
+static NM_PER_KM: f64 = 1.0 / 1.852;
+static LB_PER_KG: f64 = 2.204623;
+
+/// A system of units of distances and masses
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    /// Distances in km and masses in kg
+    #[default]
+    Metric,
+    /// Distances in nautical miles and masses in lb
+    Aviation,
+}
+
+impl std::str::FromStr for Units {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "metric" => Ok(Self::Metric),
+            "aviation" => Ok(Self::Aviation),
+            other => Err(format!("units `{other}` must be `metric` or `aviation`")),
+        }
+    }
+}
+
+impl Units {
+    /// The name of the unit of distances (e.g. `km`)
+    pub fn distance_unit(&self) -> &'static str {
+        match self {
+            Self::Metric => "km",
+            Self::Aviation => "nm",
+        }
+    }
+
+    /// The name of the unit of masses (e.g. `kg`)
+    pub fn mass_unit(&self) -> &'static str {
+        match self {
+            Self::Metric => "kg",
+            Self::Aviation => "lb",
+        }
+    }
+
+    /// Converts a distance in km to these units
+    pub fn distance(&self, km: f64) -> f64 {
+        match self {
+            Self::Metric => km,
+            Self::Aviation => km * NM_PER_KM,
+        }
+    }
+
+    /// Converts a mass in kg to these units
+    pub fn mass(&self, kg: f64) -> f64 {
+        match self {
+            Self::Metric => kg,
+            Self::Aviation => kg * LB_PER_KG,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn work() {
+        let units = "aviation".parse::<Units>().unwrap();
+        assert_eq!(units.distance(1.852), 1.0);
+        assert!((units.mass(1000.0) - 2204.623).abs() < 1e-6);
+        assert_eq!(Units::Metric.distance(10.0), 10.0);
+        assert_eq!(units.distance_unit(), "nm");
+        assert!("imperial".parse::<Units>().is_err());
+    }
+}
@@ -0,0 +1,423 @@
+//! Contains the implementation of the validation of the database of legs (`leg/v2/data/`), which reports
+//! anomalies that point to bugs in their ingestion (`M-quality-report`), and of the promotion of a validated
+//! version of the datasets of legs to the published one (`M-versions`).
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{etl::legs::Roots, fs::BlobStorageProvider, geo::Countries, Error};
+
+/// The blob name of the pointer to the published version of the datasets of legs
+pub static POINTER: &str = "leg/status.json";
+
+/// The speed of sound at sea level in km/h; legs faster than this are reported as [`Anomaly::Supersonic`]
+pub static MACH_1: f64 = 1225.0;
+
+/// A leg of the database of legs, restricted to the columns needed to validate it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ValidationLeg {
+    /// The ICAO number
+    pub icao_number: Arc<str>,
+    /// The start timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub start: OffsetDateTime,
+    /// The start latitude
+    pub start_lat: f64,
+    /// The start longitude
+    pub start_lon: f64,
+    /// The start altitude in feet
+    pub start_altitude: f64,
+    /// The end timestamp
+    #[serde(with = "time::serde::rfc3339")]
+    pub end: OffsetDateTime,
+    /// The end latitude
+    pub end_lat: f64,
+    /// The end longitude
+    pub end_lon: f64,
+    /// The end altitude in feet
+    pub end_altitude: f64,
+    /// The duration of the flight in hours
+    pub duration: f64,
+    /// The great-circle distance of the leg in km
+    pub great_circle_distance: f64,
+}
+
+/// An anomaly of a leg
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anomaly {
+    /// The leg starts before the end of a previous leg of the same aircraft
+    Overlapping,
+    /// The leg ends before it starts
+    NegativeDuration,
+    /// The leg starts or ends at zero altitude outside of any country (e.g. over the ocean)
+    OceanAtZeroAltitude,
+    /// The great-circle speed of the leg is above [`MACH_1`]
+    Supersonic,
+    /// The leg has the same aircraft, start and end of another leg
+    Duplicate,
+}
+
+/// A partition of the database of legs with legs of an [`Anomaly`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Offending {
+    /// The blob name of the partition (e.g. `leg/v2/data/month=2023-01/icao_number=459cd3/data.csv`)
+    pub partition: Arc<str>,
+    pub anomaly: Anomaly,
+    /// The number of legs of the partition with the anomaly
+    pub legs: usize,
+}
+
+/// The report of the validation of the legs of a year
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityReport {
+    pub year: i32,
+    /// The number of validated partitions
+    pub partitions: usize,
+    /// The number of validated legs
+    pub legs: usize,
+    /// The number of legs of each anomaly
+    pub anomalies: BTreeMap<Anomaly, usize>,
+    /// The partitions with anomalies, ordered by partition and anomaly
+    pub offending: Vec<Offending>,
+}
+
+/// The published version of the datasets of legs, written to [`POINTER`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Promotion {
+    /// The published version (e.g. `v3`)
+    pub version: Arc<str>,
+    /// The blob name of the status of the published version (e.g. `leg/v3/status.json`)
+    pub status: String,
+    /// The version published before, if any
+    pub previous: Option<Arc<str>>,
+    /// When the version was published
+    #[serde(with = "time::serde::rfc3339")]
+    pub promoted: OffsetDateTime,
+}
+
+/// Returns the [`Anomaly`]s of `leg` that do not depend on other legs
+fn leg_anomalies(leg: &ValidationLeg, countries: &Countries) -> Vec<Anomaly> {
+    let mut anomalies = vec![];
+    if leg.end < leg.start || leg.duration < 0.0 {
+        anomalies.push(Anomaly::NegativeDuration);
+    }
+    let at_ocean = |lat: f64, lon: f64, altitude: f64| {
+        altitude <= 0.0 && countries.country_of(lat, lon).is_none()
+    };
+    if at_ocean(leg.start_lat, leg.start_lon, leg.start_altitude)
+        || at_ocean(leg.end_lat, leg.end_lon, leg.end_altitude)
+    {
+        anomalies.push(Anomaly::OceanAtZeroAltitude);
+    }
+    if leg.duration > 0.0 && leg.great_circle_distance / leg.duration > MACH_1 {
+        anomalies.push(Anomaly::Supersonic);
+    }
+    anomalies
+}
+
+/// Returns the [`Offending`] partitions of `legs`, each with the blob name of its partition, ordered by
+/// partition and anomaly.
+/// Legs of an aircraft are compared across partitions, so that legs overlapping the turn of a month are reported.
+pub fn validate(legs: &[(Arc<str>, ValidationLeg)], countries: &Countries) -> Vec<Offending> {
+    let mut offending = BTreeMap::<(Arc<str>, Anomaly), usize>::new();
+    let mut report = |partition: &Arc<str>, anomaly| {
+        *offending.entry((partition.clone(), anomaly)).or_default() += 1;
+    };
+
+    for (partition, leg) in legs {
+        for anomaly in leg_anomalies(leg, countries) {
+            report(partition, anomaly);
+        }
+    }
+
+    let mut by_icao_number = BTreeMap::<&str, Vec<&(Arc<str>, ValidationLeg)>>::new();
+    for leg in legs {
+        by_icao_number
+            .entry(leg.1.icao_number.as_ref())
+            .or_default()
+            .push(leg);
+    }
+    for mut legs in by_icao_number.into_values() {
+        legs.sort_by_key(|(partition, leg)| (leg.start, leg.end, partition.clone()));
+        let mut seen = HashSet::new();
+        let mut end = None;
+        for (partition, leg) in legs {
+            if !seen.insert((leg.start, leg.end)) {
+                // duplicates are not also reported as overlapping
+                report(partition, Anomaly::Duplicate);
+                continue;
+            }
+            if end.is_some_and(|end| leg.start < end) {
+                report(partition, Anomaly::Overlapping);
+            }
+            end = end.max(Some(leg.end));
+        }
+    }
+
+    offending
+        .into_iter()
+        .map(|((partition, anomaly), legs)| Offending {
+            partition,
+            anomaly,
+            legs,
+        })
+        .collect()
+}
+
+/// Returns the legs of the partitions of `year` under `roots`, each with the blob name of its partition,
+/// and the number of partitions
+async fn read_legs(
+    year: i32,
+    roots: &Roots,
+    client: &dyn BlobStorageProvider,
+) -> Result<(Vec<(Arc<str>, ValidationLeg)>, usize), Error> {
+    let mut legs = vec![];
+    let mut partitions = 0;
+    for month in 1..=12 {
+        let prefix = format!("{}data/month={year}-{month:02}/", roots.legs);
+        for key in client.list(&prefix).await? {
+            if !crate::compression::Compression::of(&key)
+                .1
+                .ends_with(".csv")
+            {
+                log::warn!("{key} is not a csv; skipping");
+                continue;
+            }
+            let Some(data) = client.maybe_get(&key).await? else {
+                continue;
+            };
+            let data = crate::compression::decompress(data)?;
+            let partition: Arc<str> = key.into();
+            for leg in crate::csv::deserialize::<ValidationLeg>(&data) {
+                legs.push((partition.clone(), leg?));
+            }
+            partitions += 1;
+        }
+    }
+    Ok((legs, partitions))
+}
+
+/// Validates the legs of each of `years` of the database of legs under `roots` and writes a [`QualityReport`]
+/// of each to `{roots}year={year}/quality_report.json` (e.g. `leg/v2/year=2023/quality_report.json`).
+/// Returns the reports.
+pub async fn etl_validate(
+    years: impl Iterator<Item = i32>,
+    roots: &Roots,
+    client: &dyn BlobStorageProvider,
+) -> Result<Vec<QualityReport>, Error> {
+    let countries = crate::geo::countries(client).await?;
+    let mut reports = vec![];
+    for year in years {
+        let (legs, partitions) = read_legs(year, roots, client).await?;
+        log::info!("legs of year={year}: {}", legs.len());
+
+        let offending = validate(&legs, &countries);
+        let mut anomalies = BTreeMap::<Anomaly, usize>::new();
+        for offending in &offending {
+            *anomalies.entry(offending.anomaly).or_default() += offending.legs;
+        }
+        let report = QualityReport {
+            year,
+            partitions,
+            legs: legs.len(),
+            anomalies,
+            offending,
+        };
+
+        let key = format!("{}year={year}/quality_report.json", roots.legs);
+        let data = serde_json::to_vec(&report).map_err(std::io::Error::other)?;
+        client.put(&key, data).await?;
+        log::info!(
+            "Written {key} ({} offending partitions)",
+            report.offending.len()
+        );
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+/// Returns an error describing why `reports` do not pass the validation required to promote a version:
+/// every year must have legs, and the share of legs with anomalies of each year must be at most `max_anomalies`.
+pub fn check(reports: &[QualityReport], max_anomalies: f64) -> Result<(), String> {
+    if reports.is_empty() {
+        return Err("no year was validated".to_string());
+    }
+    for report in reports {
+        if report.legs == 0 {
+            return Err(format!("year={} has no legs", report.year));
+        }
+        let anomalies = report.anomalies.values().sum::<usize>();
+        let share = anomalies as f64 / report.legs as f64;
+        if share > max_anomalies {
+            return Err(format!(
+                "year={} has {anomalies} legs with anomalies out of {} ({share:.4} > {max_anomalies})",
+                report.year, report.legs
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns the published version of the datasets of legs, if any was promoted
+pub async fn published(client: &dyn BlobStorageProvider) -> Result<Option<Promotion>, Error> {
+    Ok(match client.maybe_get(POINTER).await? {
+        Some(data) => Some(serde_json::from_slice(&data)?),
+        None => None,
+    })
+}
+
+/// Promotes the version of `roots` to the published one by writing [`POINTER`], once its `status.json` has
+/// every year of `reports` and `reports` pass [`check`].
+/// The pointer is a single blob, so readers either see the previous version or the promoted one.
+pub async fn promote(
+    roots: &Roots,
+    reports: &[QualityReport],
+    max_anomalies: f64,
+    client: &dyn BlobStorageProvider,
+) -> Result<Promotion, Error> {
+    let status = format!("{}status.json", roots.legs);
+    let Some(data) = client.maybe_get(&status).await? else {
+        return Err(Error::MissingData(format!(
+            "{status} does not exist; aggregate the version first"
+        )));
+    };
+    let years = serde_json::from_slice::<BTreeMap<String, serde_json::Value>>(&data)?;
+    if let Some(report) = reports
+        .iter()
+        .find(|report| !years.contains_key(&report.year.to_string()))
+    {
+        return Err(Error::MissingData(format!(
+            "{status} has no year={}",
+            report.year
+        )));
+    }
+    check(reports, max_anomalies).map_err(Error::Invalid)?;
+
+    let promotion = Promotion {
+        version: roots.version.clone(),
+        status,
+        previous: published(client).await?.map(|x| x.version),
+        promoted: OffsetDateTime::now_utc(),
+    };
+    let data = serde_json::to_vec(&promotion).map_err(std::io::Error::other)?;
+    client.put(POINTER, data).await?;
+    log::info!(
+        "Promoted {} (previous: {:?})",
+        promotion.version,
+        promotion.previous
+    );
+    Ok(promotion)
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn leg(start: OffsetDateTime, end: OffsetDateTime) -> ValidationLeg {
+        ValidationLeg {
+            icao_number: "459cd3".into(),
+            start,
+            start_lat: 55.6,
+            start_lon: 12.6,
+            start_altitude: 0.0,
+            end,
+            end_lat: 55.6,
+            end_lon: 12.6,
+            end_altitude: 0.0,
+            duration: (end - start).as_seconds_f64() / 60.0 / 60.0,
+            great_circle_distance: 500.0,
+        }
+    }
+
+    #[test]
+    fn work() {
+        let countries = Countries::from_geojson(
+            br#"{
+            "type": "FeatureCollection",
+            "features": [{
+                "type": "Feature",
+                "properties": {"ISO_A2": "DK", "NAME": "Denmark", "CONTINENT": "Europe"},
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[8.0, 54.5], [13.0, 54.5], [13.0, 57.8], [8.0, 57.8], [8.0, 54.5]]]
+                }
+            }]
+        }"#,
+        )
+        .unwrap();
+        let january: Arc<str> = "month=2023-01".into();
+        let february: Arc<str> = "month=2023-02".into();
+
+        let ok = leg(
+            datetime!(2023-01-31 23:00 UTC),
+            datetime!(2023-02-01 01:00 UTC),
+        );
+        let overlapping = leg(
+            datetime!(2023-02-01 00:30 UTC),
+            datetime!(2023-02-01 02:30 UTC),
+        );
+        let negative = leg(
+            datetime!(2023-02-02 10:00 UTC),
+            datetime!(2023-02-02 09:00 UTC),
+        );
+        let supersonic = ValidationLeg {
+            great_circle_distance: 5000.0,
+            ..leg(
+                datetime!(2023-02-03 10:00 UTC),
+                datetime!(2023-02-03 11:00 UTC),
+            )
+        };
+        let ocean = ValidationLeg {
+            end_lon: 0.0,
+            ..leg(
+                datetime!(2023-02-04 10:00 UTC),
+                datetime!(2023-02-04 11:00 UTC),
+            )
+        };
+        let legs = vec![
+            (january.clone(), ok),
+            (february.clone(), overlapping.clone()),
+            (february.clone(), overlapping),
+            (february.clone(), negative),
+            (february.clone(), supersonic),
+            (february.clone(), ocean),
+        ];
+
+        let offending = validate(&legs, &countries)
+            .into_iter()
+            .map(|x| (x.partition, x.anomaly, x.legs))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            offending,
+            vec![
+                (february.clone(), Anomaly::Overlapping, 1),
+                (february.clone(), Anomaly::NegativeDuration, 1),
+                (february.clone(), Anomaly::OceanAtZeroAltitude, 1),
+                (february.clone(), Anomaly::Supersonic, 1),
+                (february, Anomaly::Duplicate, 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_reports() {
+        let report = |legs, anomalies| QualityReport {
+            year: 2023,
+            partitions: 1,
+            legs,
+            anomalies: [(Anomaly::Supersonic, anomalies)].into_iter().collect(),
+            offending: vec![],
+        };
+        assert!(check(&[report(100, 1)], 0.01).is_ok());
+        assert!(check(&[report(100, 2)], 0.01).is_err());
+        assert!(check(&[report(0, 0)], 0.01).is_err());
+        assert!(check(&[], 0.01).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+//! Contains the weekly aggregates of legs (`M-weekly`): the number of legs and aircrafts, and the duration, distance
+//! and emissions of the legs of each ISO 8601 week, so that recent activity can be reported before the datasets of
+//! its month are closed.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    sync::Arc,
+};
+
+use serde::{Deserialize, Serialize};
+use time::{Date, OffsetDateTime, Weekday};
+
+use crate::{etl::legs::LegOut, schema::Unit, units::Units};
+
+/// A week in ISO 8601, as its year and number (e.g. `(2024, 23)`)
+pub type IsoWeek = (i32, u8);
+
+/// Returns the ISO 8601 week of `date`
+pub fn week_of(date: Date) -> IsoWeek {
+    let (year, week, _) = date.to_iso_week_date();
+    (year, week)
+}
+
+/// Returns the first day (Monday) of `week`
+pub fn week_start((year, week): IsoWeek) -> Date {
+    Date::from_iso_week_date(year, week, Weekday::Monday).expect("week to be valid")
+}
+
+/// Returns the name of `week` (e.g. `2024-W23`)
+pub fn week_name((year, week): IsoWeek) -> String {
+    format!("{year}-W{week:02}")
+}
+
+/// Returns the weeks with at least one day on the month starting at `month`
+pub fn weeks_of_month(month: Date) -> impl Iterator<Item = IsoWeek> {
+    crate::DateIter {
+        from: month,
+        to: crate::trace_month::first_of_next_month(&month),
+        increment: time::Duration::days(1),
+    }
+    .map(week_of)
+    .collect::<BTreeSet<_>>()
+    .into_iter()
+}
+
+/// The legs of a week
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Week {
+    /// The week in ISO 8601 (e.g. `2024-W23`)
+    pub week: String,
+    /// The first day (Monday) of the week in ISO 8601 (e.g. `2024-06-03`)
+    pub start: String,
+    /// Whether the week had ended when it was aggregated
+    pub complete: bool,
+    /// The number of legs starting on the week
+    pub legs: usize,
+    /// The number of aircrafts with legs starting on the week
+    pub aircrafts: usize,
+    /// The total duration of the legs in hours
+    pub hours: f64,
+    /// The total flown distance of the legs
+    pub distance: f64,
+    /// The total CO2 emissions of the legs (of known models)
+    pub co2_emissions: f64,
+    /// When the week was aggregated, in RFC 3339
+    pub updated: String,
+}
+
+crate::schema!(Week {
+    week: "The week in ISO 8601 (e.g. `2024-W23`)",
+    start: "The first day (Monday) of the week",
+    complete: "Whether the week had ended when it was aggregated",
+    legs: "The number of legs starting on the week",
+    aircrafts: "The number of aircrafts with legs starting on the week",
+    hours("h"): "The total duration of the legs",
+    distance(Unit::Distance): "The total flown distance of the legs",
+    co2_emissions(Unit::Mass): "The total CO2 emissions of the legs of known models",
+    updated: "When the week was aggregated",
+});
+
+/// Returns the [`Week`] of each of `weeks` from `legs` (in metric units) in `units`, aggregated at `now`.
+/// Legs are of the week of their start (in UTC); weeks without legs have no legs.
+pub fn weeks<'a>(
+    weeks: &HashSet<IsoWeek>,
+    legs: impl Iterator<Item = &'a LegOut>,
+    units: Units,
+    now: OffsetDateTime,
+) -> Vec<Week> {
+    let mut by_week = weeks
+        .iter()
+        .map(|week| (*week, (HashSet::<Arc<str>>::new(), 0, 0.0, 0.0, 0.0)))
+        .collect::<BTreeMap<_, _>>();
+    for leg in legs {
+        let Some((aircrafts, legs, hours, distance, co2)) =
+            by_week.get_mut(&week_of(leg.start.date()))
+        else {
+            continue;
+        };
+        aircrafts.insert(leg.icao_number.clone());
+        *legs += 1;
+        *hours += leg.duration;
+        *distance += leg.distance;
+        *co2 += leg.co2_emissions.unwrap_or_default();
+    }
+    let updated = crate::timezone::to_rfc3339(now).to_string();
+    by_week
+        .into_iter()
+        .map(|(week, (aircrafts, legs, hours, distance, co2))| {
+            let start = week_start(week);
+            Week {
+                week: week_name(week),
+                start: start.to_string(),
+                complete: now.date() >= start + time::Duration::days(7),
+                legs,
+                aircrafts: aircrafts.len(),
+                hours,
+                distance: units.distance(distance),
+                co2_emissions: units.mass(co2),
+                updated: updated.clone(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::{date, datetime};
+
+    use super::*;
+
+    #[test]
+    fn work() {
+        assert_eq!(week_of(date!(2024 - 01 - 01)), (2024, 1));
+        // belongs to the last week of 2020
+        assert_eq!(week_of(date!(2021 - 01 - 03)), (2020, 53));
+        assert_eq!(week_start((2024, 23)), date!(2024 - 06 - 03));
+        assert_eq!(week_name((2024, 3)), "2024-W03");
+        let weeks_of = weeks_of_month(date!(2024 - 06 - 01)).collect::<Vec<_>>();
+        assert_eq!(weeks_of.first(), Some(&(2024, 22)));
+        assert_eq!(weeks_of.last(), Some(&(2024, 26)));
+
+        let leg = |icao_number: &str, start: &str| -> LegOut {
+            serde_json::from_value(serde_json::json!({
+                "icao_number": icao_number,
+                "start": start,
+                "start_lat": 55.6,
+                "start_lon": 12.6,
+                "start_altitude": 0.0,
+                "end": start,
+                "end_lat": 49.0,
+                "end_lon": 2.5,
+                "end_altitude": 0.0,
+                "duration": 1.5,
+                "distance": 1000.0,
+                "great_circle_distance": 1000.0,
+                "midpoint_lat": 52.3,
+                "midpoint_lon": 7.5,
+                "hours_above_30000": 1.0,
+                "hours_above_40000": 0.0,
+                "co2_emissions": 100.0,
+                "diverted": false,
+                "start_on_ground": true,
+                "end_on_ground": true,
+                "taxi_out_minutes": 0.0,
+                "taxi_in_minutes": 0.0,
+                "commercial_alternative_exists": true,
+            }))
+            .unwrap()
+        };
+        let legs = [
+            leg("459cd3", "2024-06-03T10:00:00Z"),
+            leg("459cd3", "2024-06-09T10:00:00Z"),
+            leg("45d2ed", "2024-06-05T10:00:00Z"),
+            // another week
+            leg("45d2ed", "2024-06-10T10:00:00Z"),
+        ];
+        let now = datetime!(2024-06-12 12:00 UTC);
+        let result = weeks(
+            &[(2024, 23), (2024, 24), (2024, 25)].into(),
+            legs.iter(),
+            Units::Metric,
+            now,
+        );
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].week, "2024-W23");
+        assert_eq!(result[0].start, "2024-06-03");
+        assert!(result[0].complete);
+        assert_eq!((result[0].legs, result[0].aircrafts), (3, 2));
+        assert_eq!(result[0].co2_emissions, 300.0);
+        assert!(!result[1].complete);
+        assert_eq!(result[1].legs, 1);
+        assert_eq!(result[2].legs, 0);
+    }
+}
@@ -0,0 +1,330 @@
+//! Contains the implementation to enrich legs with winds aloft from
+//! [ERA5](https://cds.climate.copernicus.eu/datasets/reanalysis-era5-pressure-levels) reanalysis.
+//!
+//! ERA5 is not retrieved by this crate: subsets of it (e.g. a region at 1° resolution and a few pressure levels)
+//! are expected to be stored, per month, as CSV at `wind/era5/month={month}/data.csv` with the columns of [`WindRecord`].
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::OnceCell;
+
+use crate::{fs::BlobStorageProvider, legs::Leg, Position};
+
+static DATABASE: &str = "wind/era5/";
+
+static KNOTS_PER_METER_PER_SECOND: f64 = 1.943844;
+
+fn pk_to_blob_name(month: time::Date) -> String {
+    let month = crate::serde::month_to_part(month);
+    format!("{DATABASE}month={month}/data.csv")
+}
+
+/// Returns the hour since epoch closest to `datetime`, the time key of [`WindGrid`]
+fn hour(datetime: time::OffsetDateTime) -> i64 {
+    (datetime.unix_timestamp() as f64 / 3600.0).round() as i64
+}
+
+/// A wind vector of the reanalysis at a given time, pressure level and location
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindRecord {
+    #[serde(with = "time::serde::rfc3339")]
+    pub datetime: time::OffsetDateTime,
+    /// The pressure level in hPa
+    pub level: u32,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// The eastward component of the wind in m/s
+    pub u: f64,
+    /// The northward component of the wind in m/s
+    pub v: f64,
+}
+
+/// A set of [`WindRecord`]s on a regular grid, indexed for nearest-neighbour lookups
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindGrid {
+    /// resolution of the grid in degrees
+    resolution: f64,
+    /// available pressure levels in hPa
+    levels: Vec<u32>,
+    /// (hour since epoch, level, latitude index, longitude index) -> (u, v)
+    winds: HashMap<(i64, u32, i64, i64), (f64, f64)>,
+}
+
+/// Returns the pressure in hPa at `altitude` (in feet) in the international standard atmosphere
+fn pressure(altitude: f64) -> f64 {
+    let meters = altitude * 0.3048;
+    1013.25 * (1.0 - 6.5e-3 * meters / 288.15).max(0.0).powf(5.2559)
+}
+
+impl WindGrid {
+    /// Returns a new [`WindGrid`] from records on a regular grid of `resolution` degrees and hourly (or coarser) times
+    pub fn new(records: impl Iterator<Item = WindRecord>, resolution: f64) -> Self {
+        let mut levels = vec![];
+        let winds = records
+            .map(|r| {
+                if !levels.contains(&r.level) {
+                    levels.push(r.level)
+                }
+                let key = (
+                    hour(r.datetime),
+                    r.level,
+                    (r.latitude / resolution).round() as i64,
+                    (r.longitude / resolution).round() as i64,
+                );
+                (key, (r.u, r.v))
+            })
+            .collect();
+        Self {
+            resolution,
+            levels,
+            winds,
+        }
+    }
+
+    /// Returns the `(u, v)` wind in m/s closest to `position` in space, pressure level and time (up to 6 hours)
+    pub fn wind(&self, position: &Position) -> Option<(f64, f64)> {
+        let pressure = pressure(position.altitude());
+        let level = *self.levels.iter().min_by(|a, b| {
+            (**a as f64 - pressure)
+                .abs()
+                .total_cmp(&(**b as f64 - pressure).abs())
+        })?;
+        let lat = (position.latitude() / self.resolution).round() as i64;
+        let lon = (position.longitude() / self.resolution).round() as i64;
+        let hour = hour(position.datetime());
+        // reanalysis subsets are often 3 or 6-hourly => search the closest hour
+        (0..=6)
+            .flat_map(|delta| [hour - delta, hour + delta])
+            .find_map(|hour| self.winds.get(&(hour, level, lat, lon)).copied())
+    }
+}
+
+/// Winds-derived metrics of a leg
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LegWind {
+    /// Time-weighted average of the along-track component of the wind in knots (negative for headwind)
+    pub tailwind: f64,
+    /// Time-weighted average true airspeed in knots
+    pub true_airspeed: f64,
+}
+
+/// Returns the [`LegWind`] of `leg`, computed over its airborne segments with available winds.
+/// Returns `None` when no segment has winds available.
+pub fn leg_wind(leg: &Leg, grid: &WindGrid) -> Option<LegWind> {
+    let (hours, tailwind, true_airspeed) = leg
+        .positions()
+        .windows(2)
+        .filter(|w| w[0].flying() && w[1].flying())
+        .filter_map(|w| {
+            let hours = (w[1].datetime() - w[0].datetime()).as_seconds_f64() / 3600.0;
+            if hours <= 0.0 {
+                return None;
+            }
+            let (u, v) = grid.wind(&w[0])?;
+            let (u, v) = (
+                u * KNOTS_PER_METER_PER_SECOND,
+                v * KNOTS_PER_METER_PER_SECOND,
+            );
+            // ground speed in knots
+            let ground_speed = w[0].distace(&w[1]) / 1.852 / hours;
+            // positions at the same point have no ground speed; their bearing is taken as north
+            let bearing = crate::geo::initial_bearing(w[0].pos(), w[1].pos())
+                .unwrap_or_default()
+                .to_radians();
+            let (east, north) = (ground_speed * bearing.sin(), ground_speed * bearing.cos());
+
+            let tailwind = u * bearing.sin() + v * bearing.cos();
+            let true_airspeed = (east - u).hypot(north - v);
+            Some((hours, tailwind, true_airspeed))
+        })
+        .fold((0.0, 0.0, 0.0), |acc, (hours, tailwind, true_airspeed)| {
+            (
+                acc.0 + hours,
+                acc.1 + tailwind * hours,
+                acc.2 + true_airspeed * hours,
+            )
+        });
+    (hours > 0.0).then(|| LegWind {
+        tailwind: tailwind / hours,
+        true_airspeed: true_airspeed / hours,
+    })
+}
+
+/// The [`WindGrid`] of a month, read once
+type Month = Arc<OnceCell<Option<Arc<WindGrid>>>>;
+
+/// In-memory cache of the [`WindGrid`] of each month
+pub struct Winds {
+    resolution: f64,
+    months: Mutex<HashMap<time::Date, Month>>,
+}
+
+impl Winds {
+    /// Returns a new [`Winds`] whose subsets are on a grid of `resolution` degrees
+    pub fn new(resolution: f64) -> Self {
+        Self {
+            resolution,
+            months: Default::default(),
+        }
+    }
+
+    /// Returns the [`WindGrid`] of `month`, or `None` if no subset exists for it.
+    /// # Implementation
+    /// The subset is read from `client` on the first call for a given month, and kept in memory.
+    /// Concurrent calls for the same month wait for a single read; calls for other months are not blocked by it.
+    /// Failed reads are not kept, so that they are retried by the next call.
+    pub async fn month(
+        &self,
+        month: time::Date,
+        client: &dyn BlobStorageProvider,
+    ) -> Result<Option<Arc<WindGrid>>, std::io::Error> {
+        let cell = self
+            .months
+            .lock()
+            .unwrap()
+            .entry(month)
+            .or_default()
+            .clone();
+        cell.get_or_try_init(|| async {
+            let Some(data) = client.maybe_get(&pk_to_blob_name(month)).await? else {
+                log::warn!("no winds for month {month}");
+                return Ok(None);
+            };
+            let records =
+                crate::csv::deserialize::<WindRecord>(&data).collect::<Result<Vec<_>, _>>()?;
+            Ok(Some(Arc::new(WindGrid::new(
+                records.into_iter(),
+                self.resolution,
+            ))))
+        })
+        .await
+        .cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn position(datetime: time::OffsetDateTime, latitude: f64, altitude: f64) -> Position {
+        Position {
+            datetime,
+            latitude,
+            longitude: 10.0,
+            altitude: Some(altitude),
+            altitude_source: None,
+        }
+    }
+
+    #[test]
+    fn standard_atmosphere() {
+        assert_eq!(pressure(0.0), 1013.25);
+        assert!((pressure(34000.0) - 250.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn tailwind() {
+        // 50 m/s wind blowing to the north at 250 hPa
+        let records = [
+            datetime!(2023 - 01 - 01 10:00 UTC),
+            datetime!(2023 - 01 - 01 11:00 UTC),
+        ]
+        .into_iter()
+        .flat_map(|datetime| {
+            (54..=57).map(move |latitude| WindRecord {
+                datetime,
+                level: 250,
+                latitude: latitude as f64,
+                longitude: 10.0,
+                u: 0.0,
+                v: 50.0,
+            })
+        });
+        let grid = WindGrid::new(records, 1.0);
+
+        // flying north 2 degrees of latitude (~222 km) in 30 minutes
+        let positions = vec![
+            position(datetime!(2023 - 01 - 01 10:00 UTC), 55.0, 34000.0),
+            position(datetime!(2023 - 01 - 01 10:15 UTC), 56.0, 34000.0),
+            position(datetime!(2023 - 01 - 01 10:30 UTC), 57.0, 34000.0),
+        ];
+        let leg = crate::legs::legs_with_elevation(
+            std::iter::once(Position {
+                altitude: None,
+                altitude_source: None,
+                ..positions[0].clone()
+            })
+            .chain(positions),
+            |_| 0.0,
+        )
+        .next()
+        .unwrap();
+
+        let wind = leg_wind(&leg, &grid).unwrap();
+        assert!((wind.tailwind - 50.0 * KNOTS_PER_METER_PER_SECOND).abs() < 0.1);
+        // ground speed ~ 240 knots
+        assert!((wind.true_airspeed - (240.0 - 97.2)).abs() < 2.0);
+    }
+
+    #[test]
+    fn hours() {
+        // a record at half past is found from positions of the closest hour
+        let record = WindRecord {
+            datetime: datetime!(2023 - 01 - 01 10:40 UTC),
+            level: 250,
+            latitude: 55.0,
+            longitude: 10.0,
+            u: 1.0,
+            v: 2.0,
+        };
+        let grid = WindGrid::new(std::iter::once(record), 1.0);
+        let at = |datetime| position(datetime, 55.0, 34000.0);
+        assert_eq!(
+            grid.wind(&at(datetime!(2023 - 01 - 01 11:00 UTC))),
+            Some((1.0, 2.0))
+        );
+        // up to 6 hours away
+        assert!(grid
+            .wind(&at(datetime!(2023 - 01 - 01 17:00 UTC)))
+            .is_some());
+        assert!(grid
+            .wind(&at(datetime!(2023 - 01 - 01 17:40 UTC)))
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn months() {
+        let root = std::env::temp_dir().join("test_winds_months");
+        let _ = std::fs::remove_dir_all(&root);
+        let disk = crate::fs_local::LocalDisk::new(&root);
+        let month = time::macros::date!(2023 - 01 - 01);
+        let record = WindRecord {
+            datetime: datetime!(2023 - 01 - 01 10:00 UTC),
+            level: 250,
+            latitude: 55.0,
+            longitude: 10.0,
+            u: 1.0,
+            v: 2.0,
+        };
+        disk.put(
+            &pk_to_blob_name(month),
+            crate::csv::serialize(std::iter::once(record)),
+        )
+        .await
+        .unwrap();
+
+        let winds = Winds::new(1.0);
+        let next = time::macros::date!(2023 - 02 - 01);
+        let (grid, other) = futures::join!(winds.month(month, &disk), winds.month(next, &disk));
+        assert!(grid.unwrap().is_some());
+        assert!(other.unwrap().is_none());
+        // read once
+        let grid = winds.month(month, &disk).await.unwrap().unwrap();
+        assert_eq!(grid.levels, vec![250]);
+    }
+}